@@ -13,16 +13,17 @@ use knotcoin::crypto::dilithium::PublicKey;
 
 use colored::*;
 
-fn resolve_data_dir() -> String {
-    if let Ok(d) = std::env::var("KNOTCOIN_DATA_DIR") {
-        return d;
-    }
-    let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
-    format!("{}/{}", home, knotcoin::config::DATA_DIR)
+/// Same resolution as the daemon (env + config file), so the CLI finds the
+/// cookie and port of a node configured either way.
+fn resolve_config() -> knotcoin::config::Config {
+    knotcoin::config::Config::load(&[]).unwrap_or_else(|e| {
+        eprintln!("{} {e}", "error:".bright_red().bold());
+        std::process::exit(1);
+    })
 }
 
-fn read_rpc_cookie() -> Option<String> {
-    let cookie_path = std::path::Path::new(&resolve_data_dir()).join(knotcoin::config::RPC_COOKIE_FILE);
+fn read_rpc_cookie(data_dir: &str) -> Option<String> {
+    let cookie_path = std::path::Path::new(data_dir).join(knotcoin::config::RPC_COOKIE_FILE);
     std::fs::read_to_string(cookie_path).ok().map(|s| s.trim().to_string()).filter(|s| !s.is_empty())
 }
 
@@ -83,6 +84,12 @@ fn print_usage() {
         "getblockcount".bright_green(),
         "Get current chain height".white()
     );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "getblockchaininfo".bright_green(),
+        "Chain summary: tip, difficulty, sync, disk".white()
+    );
     println!(
         "  {} {:<38} {}",
         "❯".bright_black(),
@@ -92,8 +99,74 @@ fn print_usage() {
     println!(
         "  {} {:<38} {}",
         "❯".bright_black(),
-        "getblock <hash>".bright_green(),
-        "Get full block data".white()
+        "getblock <hash> [verbosity]".bright_green(),
+        "Get full block data (0 = raw hex)".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "getrawblock <hash>".bright_green(),
+        "Serialized block bytes in hex".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "getblocks <start_height> [count]".bright_green(),
+        "Summaries of up to 200 blocks".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "getblockheader <hash> [verbose]".bright_green(),
+        "Get block header only".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "getblockstats <height|hash>".bright_green(),
+        "Get fee, size and reward totals".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "getblocktxids <height|hash>".bright_green(),
+        "List the txids in a block".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "verifychain [check_pow]".bright_green(),
+        "Re-check stored chain integrity".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "reindex".bright_green(),
+        "Rebuild tx/referral indices and supply".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "compactdb".bright_green(),
+        "Compact the database (e.g. after pruning)".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "getrawtransaction <txid>".bright_green(),
+        "Transaction and its confirming block".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "getconfirmations <txid>".bright_green(),
+        "Confirmation depth (0 pending, -1 unknown)".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "getstatesnapshothash [height]".bright_green(),
+        "Hash of the account/governance state".white()
     );
     println!(
         "  {} {:<38} {}",
@@ -101,18 +174,114 @@ fn print_usage() {
         "getbalance <address>".bright_green(),
         "Get balance (accepts KOT1 or hex)".white()
     );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "sendmany <mnemonic> '{\"addr\":kot,...}'".bright_green(),
+        "One payment per recipient, batched".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "getaddresstransactions <addr> [from] [cursor]".bright_green(),
+        "Page through an address's full history".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "getreferraltree <address> [depth]".bright_green(),
+        "Miners an address referred, by level".white()
+    );
     println!(
         "  {} {:<38} {}",
         "❯".bright_black(),
         "getmininginfo".bright_green(),
         "Get mining stats".white()
     );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "getmininghashps [window_secs]".bright_green(),
+        "Local miner nonce rate over a window".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "optimizemining [secs_per_count]".bright_green(),
+        "Benchmark 1-8 threads, suggest one".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "getdifficulty".bright_green(),
+        "Get current difficulty".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "getsupply".bright_green(),
+        "Get total coins minted so far".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "getaccountsroot".bright_green(),
+        "Merkle root of all account balances".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "getblocksubsidy <height>".bright_green(),
+        "Mining reward at a height".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "getrewardschedule <start> <count> [step]".bright_green(),
+        "Sample the emission curve".white()
+    );
     println!(
         "  {} {:<38} {}",
         "❯".bright_black(),
         "getmempoolinfo".bright_green(),
         "Get mempool stats".white()
     );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "getmempoolentry <txid>".bright_green(),
+        "Get one pending transaction".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "feehistogram".bright_green(),
+        "Pending fee levels, best first".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "estimatefee [conf_target] [tx_size]".bright_green(),
+        "Fee to confirm within N blocks".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "decoderawtransaction <hex>".bright_green(),
+        "Show a raw transaction's fields".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "testmempoolaccept <hex>".bright_green(),
+        "Dry-run a raw transaction".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "getmempoolconflicts <hex>".bright_green(),
+        "Pending tx it clashes with, fee to replace".white()
+    );
     println!(
         "  {} {:<38} {}",
         "❯".bright_black(),
@@ -122,8 +291,20 @@ fn print_usage() {
     println!(
         "  {} {:<38} {}",
         "❯".bright_black(),
-        "addnode <ip:port>".bright_green(),
-        "Add a new P2P peer".white()
+        "getblocktemplate [payout_address]".bright_green(),
+        "Get work for an external miner".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "submitblock <block_hex>".bright_green(),
+        "Submit a solved block".white()
+    );
+    println!(
+        "  {} {:<38} {}",
+        "❯".bright_black(),
+        "addnode <ip:port> [add|onetry|remove]".bright_green(),
+        "Connect to, persist or remove a P2P peer".white()
     );
     println!(
         "  {} {:<38} {}",
@@ -190,9 +371,22 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 return serde_json::json!(arg);
             }
 
+            // JSON objects, e.g. the recipient map for sendmany
+            if arg.starts_with('{')
+                && let Ok(obj @ serde_json::Value::Object(_)) = serde_json::from_str(arg)
+            {
+                return obj;
+            }
+
+            if let Ok(b) = arg.parse::<bool>() {
+                return serde_json::json!(b);
+            }
+
             // Try to parse as number
             if let Ok(n) = arg.parse::<u64>() {
                 serde_json::json!(n)
+            } else if let Ok(n) = arg.parse::<i64>() {
+                serde_json::json!(n)
             } else {
                 serde_json::json!(arg)
             }
@@ -203,10 +397,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     use tokio::io::{AsyncReadExt, AsyncWriteExt};
     use tokio::net::TcpStream;
 
-    let rpc_port = std::env::var("KNOTCOIN_RPC_PORT")
-        .ok()
-        .and_then(|p| p.parse().ok())
-        .unwrap_or(knotcoin::config::RPC_PORT);
+    let config = resolve_config();
+    let rpc_port = config.rpc_port;
 
     let addr = format!("127.0.0.1:{}", rpc_port);
     let mut stream = match TcpStream::connect(&addr).await {
@@ -234,7 +426,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let body = serde_json::to_string(&request_body)?;
 
-    let auth = read_rpc_cookie();
+    let auth = read_rpc_cookie(&config.data_dir);
     let auth_header = auth
         .as_deref()
         .map(|t| format!("Authorization: Bearer {}\r\n", t))
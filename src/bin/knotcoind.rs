@@ -2,12 +2,13 @@ use std::path::PathBuf;
 use std::sync::{Arc, atomic::AtomicBool};
 use tokio::sync::Mutex;
 
-use knotcoin::config::NetworkConfig;
-use knotcoin::consensus::genesis::create_genesis_block;
+use knotcoin::config::Config;
+use knotcoin::consensus::genesis::{genesis_block, genesis_hash};
 use knotcoin::consensus::state::apply_block;
-use knotcoin::net::mempool::Mempool;
+use knotcoin::net::mempool::{Mempool, MEMPOOL_FILE};
 use knotcoin::net::node::P2PNode;
 use knotcoin::node::ChainDB;
+use knotcoin::node::lockfile::DataDirLock;
 use knotcoin::rpc::server::{RpcState, start_rpc_server};
 
 use colored::*;
@@ -65,52 +66,43 @@ fn banner() {
     println!();
 }
 
-/// Parse a CLI flag like `--rpc-port=9001` from args.
-fn parse_cli_flag(args: &[String], flag: &str) -> Option<String> {
-    for arg in args {
-        if let Some(val) = arg.strip_prefix(flag) {
-            if let Some(v) = val.strip_prefix('=') {
-                return Some(v.to_string());
-            }
-        }
-    }
-    None
-}
-
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     banner();
 
-    let args: Vec<String> = std::env::args().collect();
-    let mut config = NetworkConfig::mainnet();
-
-    // Priority: CLI args > env vars > defaults (from config.rs)
-    if let Some(p) = parse_cli_flag(&args, "--rpc-port") {
-        if let Ok(port) = p.parse() { config.rpc_port = port; }
-    } else if let Ok(p) = std::env::var("KNOTCOIN_RPC_PORT") {
-        if let Ok(port) = p.parse() { config.rpc_port = port; }
-    }
+    // Node logs go to stderr at `info` unless RUST_LOG says otherwise,
+    // e.g. RUST_LOG=knotcoin::net=debug.
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
-    if let Some(p) = parse_cli_flag(&args, "--p2p-port") {
-        if let Ok(port) = p.parse() { config.p2p_port = port; }
-    } else if let Ok(p) = std::env::var("KNOTCOIN_P2P_PORT") {
-        if let Ok(port) = p.parse() { config.p2p_port = port; }
-    }
+    let args: Vec<String> = std::env::args().collect();
 
-    if let Some(d) = parse_cli_flag(&args, "--data-dir") {
-        config.data_dir = d;
-    } else if let Ok(d) = std::env::var("KNOTCOIN_DATA_DIR") {
-        config.data_dir = d;
-    }
+    // Priority: CLI args > env vars > config file > defaults (from config.rs)
+    let config = match Config::load(&args) {
+        Ok(c) => Arc::new(c),
+        Err(e) => {
+            eprintln!("{} {e}", "[init]".bright_red().bold());
+            std::process::exit(1);
+        }
+    };
 
     println!(
-        "{} data dir: {}",
+        "{} network: {}, data dir: {}",
         "[init]".bright_blue().bold(),
+        config.network,
         config.data_dir
     );
     std::fs::create_dir_all(&config.data_dir)?;
+    let lock = match DataDirLock::acquire(std::path::Path::new(&config.data_dir)) {
+        Ok(l) => l,
+        Err(e) => {
+            eprintln!("{} {e}", "[init]".bright_red().bold());
+            std::process::exit(1);
+        }
+    };
 
-    let db = ChainDB::open(&PathBuf::from(&config.data_dir).join("chaindata"))?;
+    let db = ChainDB::open(&PathBuf::from(&config.data_dir).join("chaindata"))?
+        .with_tx_index(config.txindex)
+        .with_network(config.network);
     println!("{} chain database opened", "[init]".bright_blue().bold());
 
     if db.get_tip()?.is_none() {
@@ -118,7 +110,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
             "{} empty chain — applying genesis block",
             "[init]".bright_blue().bold()
         );
-        apply_block(&db, &create_genesis_block())?;
+        apply_block(&db, &genesis_block(config.network))?;
+    }
+    if db.get_block_hash_by_height(0)? != Some(genesis_hash(config.network)) {
+        eprintln!(
+            "{} {} holds a chain with a different genesis block; refusing to start",
+            "[init]".bright_red().bold(),
+            config.data_dir
+        );
+        drop(lock);
+        std::process::exit(1);
     }
 
     println!(
@@ -141,27 +142,50 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         format!("           Full token saved to: {}/.cookie", config.data_dir).yellow()
     );
 
+    let mempool_path = PathBuf::from(&config.data_dir).join(MEMPOOL_FILE);
+    let mut mempool = Mempool::with_max_bytes(config.mempool_max_bytes)
+        .with_ttl_secs(config.mempool_ttl_secs)
+        .with_max_per_sender(config.max_txs_per_sender)
+        .with_min_relay_fee(config.min_relay_fee);
+    match mempool.load_from_disk(&mempool_path, &db) {
+        Ok(0) => {}
+        Ok(n) => println!("{} restored {n} mempool transaction(s)", "[init]".bright_blue().bold()),
+        Err(e) => eprintln!("{} could not read {}: {e}", "[init]".bright_yellow().bold(), mempool_path.display()),
+    }
+
+    let (shutdown_tx, _) = tokio::sync::broadcast::channel(1);
     let state = Arc::new(RpcState {
         db,
-        mempool: Arc::new(Mutex::new(Mempool::new())),
+        mempool: Arc::new(Mutex::new(mempool)),
         shutdown: AtomicBool::new(false),
+        shutdown_tx,
         p2p_tx,
         auth_token,
         data_dir: config.data_dir.clone(),
+        config: config.clone(),
         mining_active: AtomicBool::new(false),
         mining_blocks_found: Arc::new(std::sync::atomic::AtomicU64::new(0)),
         mining_start_time: Arc::new(std::sync::atomic::AtomicU64::new(0)),
         mining_stop: Arc::new(AtomicBool::new(false)),
         connected_peers: Arc::new(std::sync::atomic::AtomicUsize::new(0)),
+        known_peers: Arc::new(Mutex::new(knotcoin::net::node::load_known_peers(std::path::Path::new(&config.data_dir), config.dev_allow_local))),
+        peers: Arc::new(Mutex::new(std::collections::HashMap::new())),
+        time_data: Arc::new(Mutex::new(knotcoin::net::timedata::TimeData::new(config.refuse_mining_on_skew))),
         wallet_keys: Arc::new(Mutex::new(std::collections::HashMap::new())),
         mining_nonces_total: Arc::new(std::sync::atomic::AtomicU64::new(0)),
+        mining_hashrate_samples: Arc::new(Mutex::new(knotcoin::miner::miner::HashrateSamples::new())),
         mining_address: Arc::new(Mutex::new(None)),
         mining_referrer: Arc::new(Mutex::new(None)),
+        block_events: knotcoin::rpc::ws::block_events(),
+        block_stats_cache: Arc::new(Mutex::new(std::collections::HashMap::new())),
+        last_tip_update: Arc::new(std::sync::atomic::AtomicU64::new(
+            std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs(),
+        )),
     });
 
     let p2p_state = state.clone();
     let p2p_port = config.p2p_port;
-    tokio::spawn(async move {
+    let p2p_task = tokio::spawn(async move {
         let node = P2PNode::new_from_rpc_state(p2p_state);
 
         // Bootstrap in the background so unreachable seeds can't block the P2P event loop.
@@ -179,10 +203,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     });
 
     println!(
-        "{} RPC server listening on {}:{}",
+        "{} RPC server listening on {}",
         "[rpc] ".bright_magenta().bold(),
-        knotcoin::config::RPC_BIND_ADDRESS,
-        config.rpc_port
+        std::net::SocketAddr::new(knotcoin::rpc::server::rpc_bind_ip(config.rpc_bind), config.rpc_port)
     );
     println!(
         "{} P2P server listening on {}:{}",
@@ -222,7 +245,52 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     println!("  {} knotcoin-cli stop", "❯".bright_black());
     println!();
 
-    start_rpc_server(state, config.rpc_port).await?;
+    let signal_state = state.clone();
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        println!("{} signal received, stopping", "[shutdown]".bright_red().bold());
+        signal_state.request_shutdown();
+    });
+
+    start_rpc_server(state.clone(), config.rpc_bind, config.rpc_port).await?;
+
+    // Already done by `stop` or the signal handler; repeated in case the
+    // RPC loop ended some other way.
+    state.request_shutdown();
+    if tokio::time::timeout(std::time::Duration::from_secs(5), p2p_task).await.is_err() {
+        eprintln!("{} p2p did not stop within 5s", "[shutdown]".bright_red().bold());
+    }
+
+    let pool = state.mempool.lock().await;
+    match pool.save_to_disk(&mempool_path) {
+        Ok(()) => println!("{} saved {} mempool transaction(s)", "[shutdown]".bright_red().bold(), pool.size()),
+        Err(e) => eprintln!("{} could not write {}: {e}", "[shutdown]".bright_red().bold(), mempool_path.display()),
+    }
+    drop(pool);
+    match state.db.flush() {
+        Ok(()) => println!("{} database flushed", "[shutdown]".bright_red().bold()),
+        Err(e) => eprintln!("{} database flush failed: {e}", "[shutdown]".bright_red().bold()),
+    }
+    drop(lock);
     println!("{} done", "[shutdown]".bright_red().bold());
     Ok(())
 }
+
+/// Resolves on Ctrl-C, or on SIGTERM where there is one.
+async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = term.recv() => {}
+                }
+                return;
+            }
+            Err(e) => eprintln!("{} no SIGTERM handler: {e}", "[shutdown]".bright_red().bold()),
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
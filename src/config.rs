@@ -10,6 +10,7 @@ pub const MAX_PEERS: usize = 12;
 pub const MAX_MESSAGE_SIZE: usize = 1_048_576;
 
 /// Bind address for RPC — set to 127.0.0.1 for local-only access (Security)
+/// Default for `Config::rpc_bind`; override with `KNOTCOIN_RPC_BIND`.
 pub const RPC_BIND_ADDRESS: &str = "127.0.0.1";
 
 /// RPC authentication cookie filename
@@ -23,6 +24,62 @@ pub const P2P_BIND_ADDRESS: &str = "0.0.0.0";
 /// Data directory name
 pub const DATA_DIR: &str = ".knotcoin/mainnet";
 
+/// Fewest recent blocks a pruning node keeps in full (two days of one-minute blocks).
+pub const MIN_PRUNE_KEEP: u32 = 2880;
+
+/// Which chain the node runs (`KNOTCOIN_NETWORK`). Each network has its own
+/// genesis block, P2P magic, default ports and data directory, so nodes on
+/// different networks can neither share data nor complete a handshake.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    #[default]
+    Mainnet,
+    /// Public test network: trivial genesis target, normal retargeting, no built-in seeds.
+    Testnet,
+    /// Local regression testing: every block's target is trivial, so
+    /// `generatetoaddress` mines instantly. No built-in seeds.
+    Regtest,
+}
+
+impl Network {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
+            Network::Regtest => "regtest",
+        }
+    }
+
+    /// First four bytes of every P2P frame.
+    pub fn magic(self) -> [u8; 4] {
+        match self {
+            Network::Mainnet => crate::net::protocol::MAGIC,
+            Network::Testnet => *b"KNTT",
+            Network::Regtest => *b"KNRT",
+        }
+    }
+}
+
+impl std::fmt::Display for Network {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Network {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "mainnet" | "main" => Ok(Network::Mainnet),
+            "testnet" | "test" => Ok(Network::Testnet),
+            "regtest" => Ok(Network::Regtest),
+            _ => Err(()),
+        }
+    }
+}
+
 pub struct NetworkConfig {
     pub p2p_port: u16,
     pub rpc_port: u16,
@@ -31,11 +88,633 @@ pub struct NetworkConfig {
 
 impl NetworkConfig {
     pub fn mainnet() -> Self {
+        Self::for_network(Network::Mainnet)
+    }
+
+    /// Default ports and data dir (`~/.knotcoin/<network>`) for `network`.
+    pub fn for_network(network: Network) -> Self {
         let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+        let (p2p_port, rpc_port) = match network {
+            Network::Mainnet => (P2P_PORT, RPC_PORT),
+            Network::Testnet => (19000, 19001),
+            Network::Regtest => (29000, 29001),
+        };
         NetworkConfig {
-            p2p_port: P2P_PORT,
-            rpc_port: RPC_PORT,
-            data_dir: format!("{}/{}", home, DATA_DIR),
+            p2p_port,
+            rpc_port,
+            data_dir: format!("{home}/.knotcoin/{network}"),
+        }
+    }
+}
+
+/// Config file looked up in the data dir when no explicit path is given.
+pub const CONFIG_FILE: &str = "knotcoin.json";
+
+/// The structured node configuration, as read from `knotcoin.json`.
+pub type NodeConfig = Config;
+
+/// Node configuration, resolved once at startup and handed to the RPC and
+/// P2P components.
+///
+/// Precedence: CLI flags > environment variables > config file > defaults.
+/// Every field can be set from a JSON file; the historical `KNOTCOIN_*`
+/// variables keep working as overrides.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// `KNOTCOIN_NETWORK` / `--network=`: mainnet, testnet or regtest. Ports and
+    /// data dir left at their defaults follow the network.
+    pub network: Network,
+    pub p2p_port: u16,
+    pub rpc_port: u16,
+    pub data_dir: String,
+    /// `KNOTCOIN_BOOTSTRAP_PEERS` (comma-separated). Empty = built-in seeds.
+    pub bootstrap_peers: Vec<String>,
+    /// `KNOTCOIN_DEV_ALLOW_LOCAL`: accept private/loopback peers (testing only).
+    pub dev_allow_local: bool,
+    /// `KNOTCOIN_TX_RELAY_DELAY_MS`: mean Poisson delay before relaying txs. 0 = off.
+    pub tx_relay_delay_ms: u64,
+    /// `KNOTCOIN_REFUSE_MINING_ON_SKEW`: refuse to mine while our clock disagrees with peers.
+    pub refuse_mining_on_skew: bool,
+    /// `KNOTCOIN_MEMPOOL_MAX_BYTES`: mempool size cap; the lowest fee rates are evicted first.
+    pub mempool_max_bytes: usize,
+    /// `KNOTCOIN_MEMPOOL_TTL_SECS`: drop pooled txs this long after their timestamp.
+    pub mempool_ttl_secs: u64,
+    /// `KNOTCOIN_MIN_RELAY_FEE`: lowest fee in knots this node accepts into its
+    /// mempool and relays (at least 1). Not a consensus rule.
+    pub min_relay_fee: u64,
+    /// `KNOTCOIN_PRUNE_KEEP`: keep only this many recent block bodies (at
+    /// least `MIN_PRUNE_KEEP`). 0 = keep everything.
+    pub prune_keep: u32,
+    /// `KNOTCOIN_RPC_BIND`: IP the RPC server listens on. None = `RPC_BIND_ADDRESS`.
+    pub rpc_bind: Option<std::net::IpAddr>,
+    /// `KNOTCOIN_MAX_TXS_PER_SENDER`: most txs we mine from one sender per
+    /// block, 1 to the consensus limit `MAX_TXS_PER_SENDER`.
+    pub max_txs_per_sender: usize,
+    /// `KNOTCOIN_TXINDEX`: index every confirmed txid for `getrawtransaction`.
+    pub txindex: bool,
+    /// `KNOTCOIN_RPC_RATE_PER_SEC`: RPC requests per second allowed from one
+    /// non-loopback IP before HTTP 429. 0 = unlimited.
+    pub rpc_rate_per_sec: u32,
+    /// `KNOTCOIN_RPC_RATE_BURST`: requests such an IP may make back to back.
+    pub rpc_rate_burst: u32,
+    /// `KNOTCOIN_ADVERTISE_ADDRS` (comma-separated): addresses peers can reach
+    /// this node at, gossiped in `Addr`. `ip:port`, `[ipv6]:port` or
+    /// `<v3>.onion:port`.
+    pub advertise_addrs: Vec<String>,
+    /// `KNOTCOIN_VERIFY_THREADS`: threads checking PoW of synced blocks and
+    /// headers, kept off the global rayon pool. Default: all cores but one.
+    pub verify_threads: usize,
+    /// `KNOTCOIN_DNS_CACHE_SECS`: how long addresses from a seed hostname stay
+    /// usable when DNS is unreachable. `0` disables the fallback.
+    pub dns_cache_secs: u64,
+    /// `KNOTCOIN_MAX_INBOUND`: inbound peers accepted at once (1..=1024).
+    pub max_inbound: usize,
+    /// `KNOTCOIN_MAX_OUTBOUND`: outbound peers dialed at once (1..=1024).
+    pub max_outbound: usize,
+    /// `KNOTCOIN_STALL_TIMEOUT_SECS`: how long the tip may sit still while
+    /// peers are ahead before sync is retried. `0` disables the watchdog.
+    pub stall_timeout_secs: u64,
+    /// `KNOTCOIN_MINER_ALLOWLIST` (comma-separated): the only addresses
+    /// `start_mining` and `generatetoaddress` may pay. Empty = any address.
+    /// Local policy; blocks from peers are not checked against it.
+    pub miner_allowlist: Vec<String>,
+    /// `KNOTCOIN_RPC_READONLY`: refuse RPC methods that change the chain,
+    /// mempool, wallets, mining or the node; queries keep working.
+    pub rpc_readonly: bool,
+}
+
+/// One day of cached seed addresses.
+pub const DEFAULT_DNS_CACHE_SECS: u64 = 86_400;
+
+/// One thread fewer than the machine has cores, at least one.
+fn default_verify_threads() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get().saturating_sub(1)).max(1)
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let net = NetworkConfig::mainnet();
+        Config {
+            network: Network::Mainnet,
+            p2p_port: net.p2p_port,
+            rpc_port: net.rpc_port,
+            data_dir: net.data_dir,
+            bootstrap_peers: Vec::new(),
+            dev_allow_local: false,
+            tx_relay_delay_ms: 0,
+            refuse_mining_on_skew: false,
+            mempool_max_bytes: crate::net::mempool::DEFAULT_MEMPOOL_MAX_BYTES,
+            mempool_ttl_secs: crate::net::mempool::DEFAULT_MEMPOOL_TTL_SECS,
+            min_relay_fee: crate::primitives::transaction::MIN_FEE_KNOTS,
+            prune_keep: 0,
+            rpc_bind: None,
+            max_txs_per_sender: crate::consensus::chain::MAX_TXS_PER_SENDER,
+            txindex: false,
+            rpc_rate_per_sec: crate::rpc::ratelimit::DEFAULT_RPC_RATE_PER_SEC,
+            rpc_rate_burst: crate::rpc::ratelimit::DEFAULT_RPC_RATE_BURST,
+            advertise_addrs: Vec::new(),
+            verify_threads: default_verify_threads(),
+            dns_cache_secs: DEFAULT_DNS_CACHE_SECS,
+            max_inbound: crate::net::node::DEFAULT_MAX_INBOUND,
+            max_outbound: crate::net::node::DEFAULT_MAX_OUTBOUND,
+            stall_timeout_secs: 120,
+            miner_allowlist: Vec::new(),
+            rpc_readonly: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io { path: String, err: std::io::Error },
+    Parse { path: String, err: String },
+    InvalidValue { key: String, value: String, expected: &'static str },
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io { path, err } => write!(f, "cannot read config file {path}: {err}"),
+            ConfigError::Parse { path, err } => write!(f, "invalid config file {path}: {err}"),
+            ConfigError::InvalidValue { key, value, expected } => {
+                write!(f, "invalid value {value:?} for {key}: expected {expected}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Parse a CLI flag like `--rpc-port=9001` from args.
+pub fn parse_cli_flag(args: &[String], flag: &str) -> Option<String> {
+    for arg in args {
+        if let Some(val) = arg.strip_prefix(flag)
+            && let Some(v) = val.strip_prefix('=')
+        {
+            return Some(v.to_string());
+        }
+    }
+    None
+}
+
+fn parse_bool(key: &str, v: &str) -> Result<bool, ConfigError> {
+    match v.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" | "" => Ok(false),
+        _ => Err(ConfigError::InvalidValue {
+            key: key.to_string(),
+            value: v.to_string(),
+            expected: "a boolean (1/0/true/false)",
+        }),
+    }
+}
+
+/// A `KOT1` address, or its 32 bytes as hex.
+fn parse_miner_address(s: &str) -> Option<[u8; 32]> {
+    crate::crypto::keys::decode_address_string(s)
+        .ok()
+        .or_else(|| hex::decode(s).ok()?.try_into().ok())
+}
+
+fn check_miner_allowlist(key: &str, list: &[String]) -> Result<(), ConfigError> {
+    match list.iter().find(|a| parse_miner_address(a).is_none()) {
+        Some(bad) => Err(ConfigError::InvalidValue {
+            key: key.to_string(),
+            value: bad.clone(),
+            expected: "KOT1 addresses or 64-char hex",
+        }),
+        None => Ok(()),
+    }
+}
+
+fn parse_num<T: std::str::FromStr>(key: &str, v: &str, expected: &'static str) -> Result<T, ConfigError> {
+    v.trim().parse().map_err(|_| ConfigError::InvalidValue {
+        key: key.to_string(),
+        value: v.to_string(),
+        expected,
+    })
+}
+
+impl Config {
+    pub fn from_json(path: &str, s: &str) -> Result<Self, ConfigError> {
+        let mut cfg: Config =
+            serde_json::from_str(s).map_err(|e| ConfigError::Parse { path: path.to_string(), err: e.to_string() })?;
+        check_miner_allowlist("miner_allowlist", &cfg.miner_allowlist)?;
+        // Fields the file left out were filled with mainnet defaults.
+        cfg.move_defaults_from(Network::Mainnet);
+        Ok(cfg)
+    }
+
+    /// Whether locally mined blocks may pay `addr` under `miner_allowlist`.
+    pub fn miner_allowed(&self, addr: &[u8; 32]) -> bool {
+        self.miner_allowlist.is_empty() || self.miner_allowlist.iter().any(|a| parse_miner_address(a) == Some(*addr))
+    }
+
+    /// Switch ports and data dir still at `old`'s defaults to those of `self.network`.
+    fn move_defaults_from(&mut self, old: Network) {
+        let (from, to) = (NetworkConfig::for_network(old), NetworkConfig::for_network(self.network));
+        if self.p2p_port == from.p2p_port {
+            self.p2p_port = to.p2p_port;
+        }
+        if self.rpc_port == from.rpc_port {
+            self.rpc_port = to.rpc_port;
+        }
+        if self.data_dir == from.data_dir {
+            self.data_dir = to.data_dir;
+        }
+    }
+
+    fn set_network(&mut self, network: Network) {
+        let old = self.network;
+        self.network = network;
+        self.move_defaults_from(old);
+    }
+
+    pub fn load_file(path: &std::path::Path) -> Result<Self, ConfigError> {
+        let p = path.display().to_string();
+        let s = std::fs::read_to_string(path).map_err(|err| ConfigError::Io { path: p.clone(), err })?;
+        Self::from_json(&p, &s)
+    }
+
+    /// Apply `KNOTCOIN_*` overrides. `get` abstracts the environment so tests
+    /// don't have to mutate process-global state.
+    pub fn apply_env<F: Fn(&str) -> Option<String>>(&mut self, get: F) -> Result<(), ConfigError> {
+        // First, so explicit ports and data dir below win over its defaults.
+        if let Some(v) = get("KNOTCOIN_NETWORK") {
+            let network = parse_num("KNOTCOIN_NETWORK", &v, "mainnet, testnet or regtest")?;
+            self.set_network(network);
+        }
+        if let Some(v) = get("KNOTCOIN_DATA_DIR") {
+            self.data_dir = v;
+        }
+        if let Some(v) = get("KNOTCOIN_RPC_PORT") {
+            self.rpc_port = parse_num("KNOTCOIN_RPC_PORT", &v, "a port number")?;
         }
+        if let Some(v) = get("KNOTCOIN_P2P_PORT") {
+            self.p2p_port = parse_num("KNOTCOIN_P2P_PORT", &v, "a port number")?;
+        }
+        if let Some(v) = get("KNOTCOIN_BOOTSTRAP_PEERS") {
+            self.bootstrap_peers = v
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+        }
+        if let Some(v) = get("KNOTCOIN_DEV_ALLOW_LOCAL") {
+            self.dev_allow_local = parse_bool("KNOTCOIN_DEV_ALLOW_LOCAL", &v)?;
+        }
+        if let Some(v) = get("KNOTCOIN_TX_RELAY_DELAY_MS") {
+            self.tx_relay_delay_ms = parse_num("KNOTCOIN_TX_RELAY_DELAY_MS", &v, "milliseconds")?;
+        }
+        if let Some(v) = get("KNOTCOIN_REFUSE_MINING_ON_SKEW") {
+            self.refuse_mining_on_skew = parse_bool("KNOTCOIN_REFUSE_MINING_ON_SKEW", &v)?;
+        }
+        if let Some(v) = get("KNOTCOIN_MEMPOOL_MAX_BYTES") {
+            self.mempool_max_bytes = parse_num("KNOTCOIN_MEMPOOL_MAX_BYTES", &v, "a byte count")?;
+        }
+        if let Some(v) = get("KNOTCOIN_MEMPOOL_TTL_SECS") {
+            self.mempool_ttl_secs = parse_num("KNOTCOIN_MEMPOOL_TTL_SECS", &v, "seconds")?;
+        }
+        if let Some(v) = get("KNOTCOIN_MIN_RELAY_FEE") {
+            let expected = "a fee of at least 1 knot";
+            let fee: u64 = parse_num("KNOTCOIN_MIN_RELAY_FEE", &v, expected)?;
+            if fee < crate::primitives::transaction::MIN_FEE_KNOTS {
+                return Err(ConfigError::InvalidValue { key: "KNOTCOIN_MIN_RELAY_FEE".to_string(), value: v, expected });
+            }
+            self.min_relay_fee = fee;
+        }
+        if let Some(v) = get("KNOTCOIN_PRUNE_KEEP") {
+            let expected = "0 or a block count of at least 2880";
+            let keep: u32 = parse_num("KNOTCOIN_PRUNE_KEEP", &v, expected)?;
+            if keep != 0 && keep < MIN_PRUNE_KEEP {
+                return Err(ConfigError::InvalidValue { key: "KNOTCOIN_PRUNE_KEEP".to_string(), value: v, expected });
+            }
+            self.prune_keep = keep;
+        }
+        if let Some(v) = get("KNOTCOIN_RPC_BIND") {
+            self.rpc_bind = Some(parse_num("KNOTCOIN_RPC_BIND", &v, "an IP address")?);
+        }
+        if let Some(v) = get("KNOTCOIN_MAX_TXS_PER_SENDER") {
+            let expected = "a transaction count from 1 to 25";
+            let n: usize = parse_num("KNOTCOIN_MAX_TXS_PER_SENDER", &v, expected)?;
+            if !(1..=crate::consensus::chain::MAX_TXS_PER_SENDER).contains(&n) {
+                return Err(ConfigError::InvalidValue { key: "KNOTCOIN_MAX_TXS_PER_SENDER".to_string(), value: v, expected });
+            }
+            self.max_txs_per_sender = n;
+        }
+        if let Some(v) = get("KNOTCOIN_TXINDEX") {
+            self.txindex = parse_bool("KNOTCOIN_TXINDEX", &v)?;
+        }
+        if let Some(v) = get("KNOTCOIN_RPC_RATE_PER_SEC") {
+            self.rpc_rate_per_sec = parse_num("KNOTCOIN_RPC_RATE_PER_SEC", &v, "requests per second")?;
+        }
+        if let Some(v) = get("KNOTCOIN_RPC_RATE_BURST") {
+            let expected = "a request count of at least 1";
+            let n: u32 = parse_num("KNOTCOIN_RPC_RATE_BURST", &v, expected)?;
+            if n == 0 {
+                return Err(ConfigError::InvalidValue { key: "KNOTCOIN_RPC_RATE_BURST".to_string(), value: v, expected });
+            }
+            self.rpc_rate_burst = n;
+        }
+        if let Some(v) = get("KNOTCOIN_ADVERTISE_ADDRS") {
+            self.advertise_addrs = v
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+        }
+        if let Some(v) = get("KNOTCOIN_VERIFY_THREADS") {
+            let expected = "a thread count of at least 1";
+            let n: usize = parse_num("KNOTCOIN_VERIFY_THREADS", &v, expected)?;
+            if n == 0 {
+                return Err(ConfigError::InvalidValue { key: "KNOTCOIN_VERIFY_THREADS".to_string(), value: v, expected });
+            }
+            self.verify_threads = n;
+        }
+        if let Some(v) = get("KNOTCOIN_DNS_CACHE_SECS") {
+            self.dns_cache_secs = parse_num("KNOTCOIN_DNS_CACHE_SECS", &v, "a number of seconds")?;
+        }
+        if let Some(v) = get("KNOTCOIN_STALL_TIMEOUT_SECS") {
+            self.stall_timeout_secs = parse_num("KNOTCOIN_STALL_TIMEOUT_SECS", &v, "a number of seconds")?;
+        }
+        if let Some(v) = get("KNOTCOIN_RPC_READONLY") {
+            self.rpc_readonly = parse_bool("KNOTCOIN_RPC_READONLY", &v)?;
+        }
+        if let Some(v) = get("KNOTCOIN_MINER_ALLOWLIST") {
+            let list: Vec<String> = v
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+            check_miner_allowlist("KNOTCOIN_MINER_ALLOWLIST", &list)?;
+            self.miner_allowlist = list;
+        }
+        for (key, field) in [("KNOTCOIN_MAX_INBOUND", &mut self.max_inbound), ("KNOTCOIN_MAX_OUTBOUND", &mut self.max_outbound)] {
+            if let Some(v) = get(key) {
+                let expected = "a connection count from 1 to 1024";
+                let n: usize = parse_num(key, &v, expected)?;
+                if !(1..=crate::net::node::MAX_CONNECTION_LIMIT).contains(&n) {
+                    return Err(ConfigError::InvalidValue { key: key.to_string(), value: v, expected });
+                }
+                *field = n;
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply `--network=`, `--rpc-port=`, `--p2p-port=` and `--data-dir=` flags.
+    pub fn apply_cli(&mut self, args: &[String]) -> Result<(), ConfigError> {
+        if let Some(v) = parse_cli_flag(args, "--network") {
+            let network = parse_num("--network", &v, "mainnet, testnet or regtest")?;
+            self.set_network(network);
+        }
+        if let Some(v) = parse_cli_flag(args, "--rpc-port") {
+            self.rpc_port = parse_num("--rpc-port", &v, "a port number")?;
+        }
+        if let Some(v) = parse_cli_flag(args, "--p2p-port") {
+            self.p2p_port = parse_num("--p2p-port", &v, "a port number")?;
+        }
+        if let Some(d) = parse_cli_flag(args, "--data-dir") {
+            self.data_dir = d;
+        }
+        Ok(())
+    }
+
+    /// Resolve the startup configuration from `args`, the environment and the
+    /// config file (`--config=`, `KNOTCOIN_CONFIG`, or `knotcoin.json` in the
+    /// data dir). An explicitly named file must exist; the default one is optional.
+    pub fn load(args: &[String]) -> Result<Self, ConfigError> {
+        Self::load_with(args, |k| std::env::var(k).ok())
+    }
+
+    /// The configuration for a node in `data_dir`: its `knotcoin.json` if
+    /// there is one, otherwise the defaults, with `KNOTCOIN_*` variables on top.
+    pub fn load_or_default(data_dir: &std::path::Path) -> Result<Self, ConfigError> {
+        Self::load_or_default_with(data_dir, |k| std::env::var(k).ok())
+    }
+
+    fn load_or_default_with<F: Fn(&str) -> Option<String>>(data_dir: &std::path::Path, get: F) -> Result<Self, ConfigError> {
+        Self::load_with(&[format!("--data-dir={}", data_dir.display())], get)
+    }
+
+    pub fn load_with<F: Fn(&str) -> Option<String>>(args: &[String], get: F) -> Result<Self, ConfigError> {
+        let explicit = parse_cli_flag(args, "--config").or_else(|| get("KNOTCOIN_CONFIG"));
+        let path = match explicit {
+            Some(p) => Some(std::path::PathBuf::from(p)),
+            None => {
+                // Locate the data dir first, ignoring the file (it can't move itself).
+                let mut probe = Config::default();
+                probe.apply_env(&get)?;
+                probe.apply_cli(args)?;
+                let p = std::path::Path::new(&probe.data_dir).join(CONFIG_FILE);
+                p.exists().then_some(p)
+            }
+        };
+
+        let mut cfg = match path {
+            Some(p) => Self::load_file(&p)?,
+            None => Config::default(),
+        };
+        cfg.apply_env(&get)?;
+        cfg.apply_cli(args)?;
+        Ok(cfg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let m: HashMap<String, String> = pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        move |k| m.get(k).cloned()
+    }
+
+    #[test]
+    fn test_load_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("node.json");
+        std::fs::write(
+            &path,
+            r#"{"rpc_port": 19001, "bootstrap_peers": ["1.2.3.4:9000"], "tx_relay_delay_ms": 2000}"#,
+        )
+        .unwrap();
+
+        let args = vec![format!("--config={}", path.display())];
+        let cfg = Config::load_with(&args, env(&[])).unwrap();
+        assert_eq!(cfg.rpc_port, 19001);
+        assert_eq!(cfg.p2p_port, P2P_PORT);
+        assert_eq!(cfg.bootstrap_peers, vec!["1.2.3.4:9000".to_string()]);
+        assert_eq!(cfg.tx_relay_delay_ms, 2000);
+    }
+
+    #[test]
+    fn test_default_file_in_data_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(CONFIG_FILE), r#"{"dev_allow_local": true}"#).unwrap();
+        let d = dir.path().display().to_string();
+        let cfg = Config::load_with(&[], env(&[("KNOTCOIN_DATA_DIR", &d)])).unwrap();
+        assert!(cfg.dev_allow_local);
+        assert_eq!(cfg.data_dir, d);
+    }
+
+    #[test]
+    fn test_load_or_default_reads_data_dir_file_under_env() {
+        let dir = tempfile::tempdir().unwrap();
+        let cfg = NodeConfig::load_or_default_with(dir.path(), env(&[])).unwrap();
+        assert_eq!(cfg.bootstrap_peers, Vec::<String>::new());
+        assert_eq!(cfg.data_dir, dir.path().display().to_string());
+
+        std::fs::write(
+            dir.path().join(CONFIG_FILE),
+            r#"{"bootstrap_peers": ["1.2.3.4:9000"], "advertise_addrs": ["203.0.113.5:9000"],
+                "max_inbound": 16, "max_outbound": 4, "prune_keep": 5000}"#,
+        )
+        .unwrap();
+        let cfg = NodeConfig::load_or_default_with(dir.path(), env(&[("KNOTCOIN_MAX_OUTBOUND", "8")])).unwrap();
+        assert_eq!(cfg.bootstrap_peers, vec!["1.2.3.4:9000".to_string()]);
+        assert_eq!(cfg.advertise_addrs, vec!["203.0.113.5:9000".to_string()]);
+        assert_eq!((cfg.max_inbound, cfg.max_outbound, cfg.prune_keep), (16, 8, 5000));
+    }
+
+    #[test]
+    fn test_env_overrides_file_and_cli_overrides_env() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("node.json");
+        std::fs::write(&path, r#"{"rpc_port": 19001, "p2p_port": 19000, "dev_allow_local": false}"#).unwrap();
+        let p = path.display().to_string();
+
+        let cfg = Config::load_with(
+            &["--p2p-port=29000".to_string()],
+            env(&[
+                ("KNOTCOIN_CONFIG", &p),
+                ("KNOTCOIN_RPC_PORT", "29001"),
+                ("KNOTCOIN_P2P_PORT", "39000"),
+                ("KNOTCOIN_DEV_ALLOW_LOCAL", "1"),
+                ("KNOTCOIN_TXINDEX", "yes"),
+                ("KNOTCOIN_BOOTSTRAP_PEERS", " 5.6.7.8:9000, ,9.9.9.9:9000"),
+                ("KNOTCOIN_ADVERTISE_ADDRS", "1.2.3.4:9000,abc.onion:9000"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(cfg.rpc_port, 29001);
+        assert_eq!(cfg.p2p_port, 29000);
+        assert!(cfg.dev_allow_local);
+        assert!(cfg.txindex);
+        assert_eq!(cfg.bootstrap_peers, vec!["5.6.7.8:9000".to_string(), "9.9.9.9:9000".to_string()]);
+        assert_eq!(cfg.advertise_addrs, vec!["1.2.3.4:9000".to_string(), "abc.onion:9000".to_string()]);
+    }
+
+    #[test]
+    fn test_network_moves_default_ports_and_data_dir() {
+        let cfg = Config::load_with(&[], env(&[("KNOTCOIN_NETWORK", "regtest")])).unwrap();
+        let regtest = NetworkConfig::for_network(Network::Regtest);
+        assert_eq!(cfg.network, Network::Regtest);
+        assert_eq!((cfg.p2p_port, cfg.rpc_port), (regtest.p2p_port, regtest.rpc_port));
+        assert!(cfg.data_dir.ends_with(".knotcoin/regtest"), "{}", cfg.data_dir);
+
+        // Explicit settings are kept whichever source sets the network.
+        let cfg = Config::load_with(
+            &["--network=testnet".to_string()],
+            env(&[("KNOTCOIN_RPC_PORT", "5555"), ("KNOTCOIN_DATA_DIR", "/tmp/kt")]),
+        )
+        .unwrap();
+        assert_eq!(cfg.network, Network::Testnet);
+        assert_eq!((cfg.p2p_port, cfg.rpc_port), (19000, 5555));
+        assert_eq!(cfg.data_dir, "/tmp/kt");
+
+        let cfg = Config::from_json("x.json", r#"{"network": "testnet", "p2p_port": 1234}"#).unwrap();
+        assert_eq!((cfg.network, cfg.p2p_port, cfg.rpc_port), (Network::Testnet, 1234, 19001));
+
+        let err = Config::load_with(&[], env(&[("KNOTCOIN_NETWORK", "devnet")])).unwrap_err();
+        assert!(err.to_string().contains("KNOTCOIN_NETWORK"), "{err}");
+        assert_ne!(Network::Testnet.magic(), Network::Mainnet.magic());
+        assert_ne!(Network::Regtest.magic(), Network::Testnet.magic());
+    }
+
+    #[test]
+    fn test_invalid_values_are_errors() {
+        let err = Config::load_with(&[], env(&[("KNOTCOIN_RPC_PORT", "70000")])).unwrap_err();
+        assert!(err.to_string().contains("KNOTCOIN_RPC_PORT"), "{err}");
+
+        let err = Config::load_with(&[], env(&[("KNOTCOIN_DEV_ALLOW_LOCAL", "maybe")])).unwrap_err();
+        assert!(err.to_string().contains("expected a boolean"), "{err}");
+
+        let err = Config::load_with(&["--p2p-port=abc".to_string()], env(&[])).unwrap_err();
+        assert!(err.to_string().contains("--p2p-port"), "{err}");
+
+        let err = Config::load_with(&[], env(&[("KNOTCOIN_PRUNE_KEEP", "100")])).unwrap_err();
+        assert!(err.to_string().contains("KNOTCOIN_PRUNE_KEEP"), "{err}");
+        let cfg = Config::load_with(&[], env(&[("KNOTCOIN_PRUNE_KEEP", "5000")])).unwrap();
+        assert_eq!(cfg.prune_keep, 5000);
+
+        let err = Config::load_with(&[], env(&[("KNOTCOIN_RPC_BIND", "localhost")])).unwrap_err();
+        assert!(err.to_string().contains("KNOTCOIN_RPC_BIND"), "{err}");
+        let cfg = Config::load_with(&[], env(&[("KNOTCOIN_RPC_BIND", "::1")])).unwrap();
+        assert_eq!(cfg.rpc_bind, Some("::1".parse().unwrap()));
+
+        let err = Config::load_with(&[], env(&[("KNOTCOIN_MAX_TXS_PER_SENDER", "26")])).unwrap_err();
+        assert!(err.to_string().contains("KNOTCOIN_MAX_TXS_PER_SENDER"), "{err}");
+        let cfg = Config::load_with(&[], env(&[("KNOTCOIN_MAX_TXS_PER_SENDER", "10")])).unwrap();
+        assert_eq!(cfg.max_txs_per_sender, 10);
+
+        let err = Config::load_with(&[], env(&[("KNOTCOIN_RPC_RATE_BURST", "0")])).unwrap_err();
+        assert!(err.to_string().contains("KNOTCOIN_RPC_RATE_BURST"), "{err}");
+        let cfg = Config::load_with(&[], env(&[("KNOTCOIN_RPC_RATE_PER_SEC", "0"), ("KNOTCOIN_RPC_RATE_BURST", "5")])).unwrap();
+        assert_eq!((cfg.rpc_rate_per_sec, cfg.rpc_rate_burst), (0, 5));
+
+        let err = Config::load_with(&[], env(&[("KNOTCOIN_VERIFY_THREADS", "0")])).unwrap_err();
+        assert!(err.to_string().contains("KNOTCOIN_VERIFY_THREADS"), "{err}");
+        let cfg = Config::load_with(&[], env(&[("KNOTCOIN_VERIFY_THREADS", "3")])).unwrap();
+        assert_eq!(cfg.verify_threads, 3);
+        assert!(Config::default().verify_threads >= 1);
+
+        for bad in ["0", "1025", "many"] {
+            let err = Config::load_with(&[], env(&[("KNOTCOIN_MAX_INBOUND", bad)])).unwrap_err();
+            assert!(err.to_string().contains("KNOTCOIN_MAX_INBOUND"), "{err}");
+            let err = Config::load_with(&[], env(&[("KNOTCOIN_MAX_OUTBOUND", bad)])).unwrap_err();
+            assert!(err.to_string().contains("KNOTCOIN_MAX_OUTBOUND"), "{err}");
+        }
+        let cfg = Config::load_with(&[], env(&[("KNOTCOIN_MAX_INBOUND", "1024"), ("KNOTCOIN_MAX_OUTBOUND", "4")])).unwrap();
+        assert_eq!((cfg.max_inbound, cfg.max_outbound), (1024, 4));
+        assert_eq!((Config::default().max_inbound, Config::default().max_outbound), (128, 32));
+
+        let err = Config::load_with(&[], env(&[("KNOTCOIN_STALL_TIMEOUT_SECS", "2m")])).unwrap_err();
+        assert!(err.to_string().contains("KNOTCOIN_STALL_TIMEOUT_SECS"), "{err}");
+        let cfg = Config::load_with(&[], env(&[("KNOTCOIN_STALL_TIMEOUT_SECS", "0")])).unwrap();
+        assert_eq!(cfg.stall_timeout_secs, 0);
+
+        let err = Config::load_with(&[], env(&[("KNOTCOIN_DNS_CACHE_SECS", "-1")])).unwrap_err();
+        assert!(err.to_string().contains("KNOTCOIN_DNS_CACHE_SECS"), "{err}");
+        let cfg = Config::load_with(&[], env(&[("KNOTCOIN_DNS_CACHE_SECS", "0")])).unwrap();
+        assert_eq!(cfg.dns_cache_secs, 0);
+
+        let err = Config::load_with(&[], env(&[("KNOTCOIN_MIN_RELAY_FEE", "0")])).unwrap_err();
+        assert!(err.to_string().contains("KNOTCOIN_MIN_RELAY_FEE"), "{err}");
+        let cfg = Config::load_with(&[], env(&[("KNOTCOIN_MIN_RELAY_FEE", "100")])).unwrap();
+        assert_eq!(cfg.min_relay_fee, 100);
+
+        let err = Config::load_with(&[], env(&[("KNOTCOIN_RPC_READONLY", "maybe")])).unwrap_err();
+        assert!(err.to_string().contains("KNOTCOIN_RPC_READONLY"), "{err}");
+        assert!(Config::load_with(&[], env(&[("KNOTCOIN_RPC_READONLY", "1")])).unwrap().rpc_readonly);
+
+        let err = Config::load_with(&[], env(&[("KNOTCOIN_MINER_ALLOWLIST", "KOT1nope")])).unwrap_err();
+        assert!(err.to_string().contains("KNOTCOIN_MINER_ALLOWLIST"), "{err}");
+        let pool = crate::crypto::keys::encode_address_string(&[7; 32]);
+        let cfg = Config::load_with(&[], env(&[("KNOTCOIN_MINER_ALLOWLIST", &format!("{pool}, {}", hex::encode([8u8; 32])))])).unwrap();
+        assert!(cfg.miner_allowed(&[7; 32]) && cfg.miner_allowed(&[8; 32]));
+        assert!(!cfg.miner_allowed(&[9; 32]));
+        assert!(Config::default().miner_allowed(&[9; 32]));
+        let err = Config::from_json("f", r#"{"miner_allowlist": ["xyz"]}"#).unwrap_err();
+        assert!(err.to_string().contains("miner_allowlist"), "{err}");
+
+        let err = Config::from_json("x.json", r#"{"rpc_prot": 1}"#).unwrap_err();
+        assert!(matches!(err, ConfigError::Parse { .. }));
+        assert!(err.to_string().contains("rpc_prot"), "{err}");
+
+        let err = Config::load_with(&["--config=/nonexistent/knot.json".to_string()], env(&[])).unwrap_err();
+        assert!(matches!(err, ConfigError::Io { .. }));
     }
 }
@@ -3,8 +3,11 @@ use primitive_types::U256;
 
 const PHASE_1_END: u64 = 262_800;
 const PHASE_2_END: u64 = 525_600;
-const RETARGET_WINDOW: u64 = 60;
-const RETARGET_SECS: u64 = RETARGET_WINDOW * 60;
+/// Intended seconds between blocks; retargeting steers toward it.
+pub const TARGET_BLOCK_SECS: u64 = 60;
+/// Blocks between difficulty adjustments.
+pub const RETARGET_WINDOW: u64 = 60;
+const RETARGET_SECS: u64 = RETARGET_WINDOW * TARGET_BLOCK_SECS;
 pub const REFERRAL_WINDOW: u64 = 2_880;
 const REFERRAL_BONUS_PCT: u64 = 5;
 
@@ -29,6 +32,19 @@ pub const MINING_THREADS_MIN: u64 = 1;
 pub const MINING_THREADS_MAX: u64 = 8;   // Hard cap for fairness
 pub const MINING_THREADS_DEFAULT: u64 = 4;  // Fair for laptops
 
+/// Blocks a mining reward, its fees or a referral bonus waits before it can
+/// be spent: credited at height `h`, it is spendable from block `h + 100`.
+/// A reorg shorter than this can't take back coins that were already spent.
+pub const COINBASE_MATURITY: u64 = 100;
+
+/// Most transactions one sender may have in a single block, so one long
+/// nonce chain can't take all of a block's space.
+pub const MAX_TXS_PER_SENDER: usize = 25;
+
+/// Largest serialized block (`StoredBlock::to_bytes`) a node will accept.
+/// Keeps every valid block well inside the 1 MB P2P frame limit.
+pub const MAX_BLOCK_BYTES: usize = 500 * 1024;
+
 // Phase 1: linear ramp from 0.1 KOT to 1.0 KOT over 262,800 blocks.
 // Formula: reward = 0.1 + (0.9 * height / 262,800) KOT
 // In knots: 10M + (90M * height / 262,800)
@@ -126,11 +142,11 @@ pub fn calculate_new_difficulty(old_target: &[u8; 32], actual_secs: u64) -> [u8;
     let actual = U256::from(clamped);
     let expected = U256::from(RETARGET_SECS);
 
-    let new = if U256::MAX / actual < old {
-        U256::MAX
-    } else {
-        (old * actual / expected).max(U256::one())
-    };
+    // Widen before multiplying: near-maximal targets would otherwise overflow
+    // and saturate even when the window was fast and the target should shrink.
+    let new = U256::try_from(old.full_mul(actual) / expected)
+        .unwrap_or(U256::MAX)
+        .max(U256::one());
 
     let mut out = [0u8; 32];
     let words = new.0;
@@ -140,6 +156,17 @@ pub fn calculate_new_difficulty(old_target: &[u8; 32], actual_secs: u64) -> [u8;
     out
 }
 
+// Expected number of hashes to meet `target`: 2^256 / (target + 1).
+// Summed along a branch this is the fork-choice metric ("sum of 1/target").
+// 2^256 doesn't fit in a U256, so use the identity (~t / (t + 1)) + 1.
+pub fn block_work(target: &[u8; 32]) -> U256 {
+    let t = U256::from_big_endian(target);
+    if t == U256::MAX {
+        return U256::one();
+    }
+    (!t / (t + U256::one())) + U256::one()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -313,6 +340,21 @@ mod tests {
         assert_eq!(calculate_new_difficulty(&target, 10)[31], 25);
     }
 
+    #[test]
+    fn test_retarget_follows_target_block_secs() {
+        assert_eq!(RETARGET_SECS, RETARGET_WINDOW * TARGET_BLOCK_SECS);
+        assert_eq!(crate::primitives::block::TARGET_BLOCK_TIME_SEC, TARGET_BLOCK_SECS);
+
+        let mut target = [0u8; 32];
+        target[31] = 100;
+        // A window mined exactly on schedule keeps the target; blocks at
+        // twice or half the interval move it by the same factor.
+        let on_schedule = RETARGET_WINDOW * TARGET_BLOCK_SECS;
+        assert_eq!(calculate_new_difficulty(&target, on_schedule)[31], 100);
+        assert_eq!(calculate_new_difficulty(&target, on_schedule * 2)[31], 200);
+        assert_eq!(calculate_new_difficulty(&target, on_schedule / 2)[31], 50);
+    }
+
     #[test]
     fn test_difficulty_clamp_ceiling() {
         let mut target = [0u8; 32];
@@ -332,6 +374,14 @@ mod tests {
         assert!(result.iter().any(|&b| b > 0));
     }
 
+    #[test]
+    fn test_difficulty_max_target_still_tightens() {
+        // MAX * 900 overflows U256; the result must still be MAX / 4.
+        let result = calculate_new_difficulty(&[0xFF; 32], 900);
+        assert_eq!(U256::from_big_endian(&result), U256::MAX / 4);
+        assert_eq!(calculate_new_difficulty(&[0xFF; 32], 20000), [0xFF; 32]);
+    }
+
     #[test]
     fn test_difficulty_symmetry() {
         let mut target = [0u8; 32];
@@ -346,6 +396,22 @@ mod tests {
         assert!(halved[31] < target[31]);
     }
 
+    #[test]
+    fn test_block_work_inverse_of_target() {
+        assert_eq!(block_work(&[0xFF; 32]), U256::one());
+
+        let mut half = [0xFF; 32];
+        half[0] = 0x7F;
+        assert_eq!(block_work(&half), U256::from(2));
+
+        // Harder target → strictly more work
+        let mut easy = [0u8; 32];
+        easy[1] = 0x10;
+        let mut hard = [0u8; 32];
+        hard[1] = 0x01;
+        assert!(block_work(&hard) > block_work(&easy));
+    }
+
     // ========== CONSTANTS TESTS ==========
     #[test]
     fn test_phase_boundaries() {
@@ -7,12 +7,17 @@
 // Dilithium3 keypair. The creator must have the corresponding private key to
 // spend the genesis reward.
 
+use crate::config::Network;
 use crate::node::db_common::StoredBlock;
 
 /// Mainnet genesis timestamp: Feb 25 2026 (updated to current time)
 /// Set to current Unix timestamp for mainnet launch
 const MAINNET_GENESIS_TIMESTAMP: u32 = 1772004727;
 
+/// Test network genesis timestamps; distinct so each network has its own root.
+const TESTNET_GENESIS_TIMESTAMP: u32 = 1772004728;
+const REGTEST_GENESIS_TIMESTAMP: u32 = 1772004729;
+
 /// Mainnet genesis difficulty: easy for the first block.
 fn mainnet_genesis_target() -> [u8; 32] {
     let mut target = [0xFF; 32];
@@ -38,6 +43,13 @@ fn genesis_miner_address() -> [u8; 32] {
     [0xadu8, 0xd8u8, 0x30u8, 0x7du8, 0xdbu8, 0x8du8, 0xcfu8, 0xc9u8, 0x24u8, 0x1au8, 0x72u8, 0xf3u8, 0x4bu8, 0xe4u8, 0xe0u8, 0x58u8, 0x67u8, 0x0fu8, 0x31u8, 0x64u8, 0xacu8, 0xc2u8, 0xd2u8, 0x34u8, 0x02u8, 0xfbu8, 0x7eu8, 0xf3u8, 0x6eu8, 0x7au8, 0x25u8, 0x0du8]
 }
 
+/// `block_hash` of `create_genesis_block()`: the root every mainnet node
+/// must share. Changing any genesis field changes this and forks the network.
+pub const GENESIS_HASH: [u8; 32] = [
+    0x70, 0xb8, 0xea, 0x8f, 0x5b, 0xf5, 0x5a, 0xc7, 0x44, 0x62, 0xa1, 0x15, 0xe3, 0x47, 0xab, 0x3e,
+    0x9f, 0x3f, 0x9a, 0x8d, 0x6d, 0x4a, 0x12, 0xe5, 0x1b, 0xa6, 0x84, 0x5e, 0x3a, 0xd1, 0x01, 0x8e,
+];
+
 pub fn create_genesis_block() -> StoredBlock {
     StoredBlock {
         version: [1, 0, 0, 0],
@@ -52,6 +64,31 @@ pub fn create_genesis_block() -> StoredBlock {
     }
 }
 
+/// Genesis block of `network`. Testnet and regtest use a trivial target.
+pub fn genesis_block(network: Network) -> StoredBlock {
+    let mut block = create_genesis_block();
+    match network {
+        Network::Mainnet => {}
+        Network::Testnet => {
+            block.timestamp = TESTNET_GENESIS_TIMESTAMP.to_le_bytes();
+            block.difficulty_target = [0xFF; 32];
+        }
+        Network::Regtest => {
+            block.timestamp = REGTEST_GENESIS_TIMESTAMP.to_le_bytes();
+            block.difficulty_target = [0xFF; 32];
+        }
+    }
+    block
+}
+
+/// Hash every node on `network` must have at height 0.
+pub fn genesis_hash(network: Network) -> [u8; 32] {
+    match network {
+        Network::Mainnet => GENESIS_HASH,
+        _ => crate::consensus::state::block_hash(&genesis_block(network)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -72,4 +109,20 @@ mod tests {
             eprintln!("MUST be replaced with real wallet address before mining!");
         }
     }
+
+    #[test]
+    fn test_genesis_hash_is_pinned() {
+        let genesis = create_genesis_block();
+        assert_eq!(crate::consensus::state::block_hash(&genesis), GENESIS_HASH);
+    }
+
+    #[test]
+    fn test_each_network_has_its_own_genesis() {
+        assert_eq!(genesis_hash(Network::Mainnet), GENESIS_HASH);
+        let testnet = genesis_hash(Network::Testnet);
+        let regtest = genesis_hash(Network::Regtest);
+        assert_ne!(testnet, GENESIS_HASH);
+        assert_ne!(regtest, GENESIS_HASH);
+        assert_ne!(testnet, regtest);
+    }
 }
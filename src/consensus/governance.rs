@@ -0,0 +1,193 @@
+// Governance Proposals
+//
+// A vote is a tx whose `governance_data` names a proposal hash; on its own the
+// hash says nothing about what is being voted on. A proposal tx (version
+// `TX_VERSION_PROPOSAL`) publishes the proposal itself: a short title and the
+// governance parameter it would change. Its hash is SHA3-256 of the encoded
+// proposal, and applying the block records it in the `gov_proposals` column
+// family so nodes can show what a hash means alongside its tally.
+//
+// When a published proposal's tally first reaches `GOVERNANCE_THRESHOLD_BPS`,
+// the block that gets it there also writes the new value into the
+// governance params, in the same batch; the block's undo record keeps the
+// old params so a reorg puts them back.
+//
+// Encoding: param id (u8), value (u64 LE), title (UTF-8, the rest).
+
+use crate::consensus::chain::{
+    GOVERNANCE_CAP_MAX_BPS, GOVERNANCE_CAP_MIN_BPS, MINING_THREADS_MAX, MINING_THREADS_MIN,
+    PONC_ROUNDS_MAX, PONC_ROUNDS_MIN,
+};
+use crate::consensus::state::GovernanceParams;
+use crate::crypto::hash::hash_sha3_256;
+
+/// Transaction version that carries a proposal payload.
+pub const TX_VERSION_PROPOSAL: u8 = 2;
+
+pub const MAX_PROPOSAL_TITLE_BYTES: usize = 128;
+
+/// Tally (in governance weight bps) at which a proposal passes.
+pub const GOVERNANCE_THRESHOLD_BPS: u64 = 5100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GovParam {
+    CapBps,
+    PoncRounds,
+    MiningThreads,
+}
+
+impl GovParam {
+    pub const ALL: [GovParam; 3] = [GovParam::CapBps, GovParam::PoncRounds, GovParam::MiningThreads];
+
+    fn id(self) -> u8 {
+        match self {
+            GovParam::CapBps => 1,
+            GovParam::PoncRounds => 2,
+            GovParam::MiningThreads => 3,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.id() == id)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GovParam::CapBps => "cap_bps",
+            GovParam::PoncRounds => "ponc_rounds",
+            GovParam::MiningThreads => "mining_threads",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.name() == name)
+    }
+
+    /// Inclusive range a proposal may set this parameter to.
+    pub fn bounds(self) -> (u64, u64) {
+        match self {
+            GovParam::CapBps => (GOVERNANCE_CAP_MIN_BPS, GOVERNANCE_CAP_MAX_BPS),
+            GovParam::PoncRounds => (PONC_ROUNDS_MIN, PONC_ROUNDS_MAX),
+            GovParam::MiningThreads => (MINING_THREADS_MIN, MINING_THREADS_MAX),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proposal {
+    pub title: String,
+    pub param: GovParam,
+    pub value: u64,
+}
+
+impl Proposal {
+    /// Build a proposal, checking the title length and the value's bounds.
+    pub fn new(title: &str, param: GovParam, value: u64) -> Result<Self, &'static str> {
+        if title.is_empty() || title.len() > MAX_PROPOSAL_TITLE_BYTES {
+            return Err("proposal title must be 1-128 bytes");
+        }
+        let (min, max) = param.bounds();
+        if !(min..=max).contains(&value) {
+            return Err("proposal value out of range for parameter");
+        }
+        Ok(Proposal { title: title.to_string(), param, value })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut b = Vec::with_capacity(9 + self.title.len());
+        b.push(self.param.id());
+        b.extend_from_slice(&self.value.to_le_bytes());
+        b.extend_from_slice(self.title.as_bytes());
+        b
+    }
+
+    pub fn from_bytes(d: &[u8]) -> Result<Self, &'static str> {
+        if d.len() < 9 {
+            return Err("proposal too short");
+        }
+        let param = GovParam::from_id(d[0]).ok_or("unknown governance parameter")?;
+        let value = u64::from_le_bytes(d[1..9].try_into().unwrap());
+        let title = std::str::from_utf8(&d[9..]).map_err(|_| "proposal title is not UTF-8")?;
+        Self::new(title, param, value)
+    }
+
+    /// Write the proposed value into `params`, clamped to the parameter's bounds.
+    pub fn enact(&self, params: &mut GovernanceParams) {
+        let (min, max) = self.param.bounds();
+        let value = self.value.clamp(min, max);
+        match self.param {
+            GovParam::CapBps => params.cap_bps = value,
+            GovParam::PoncRounds => params.ponc_rounds = value,
+            GovParam::MiningThreads => params.mining_threads = value,
+        }
+    }
+
+    /// The hash voters put in `governance_data`.
+    pub fn hash(&self) -> [u8; 32] {
+        hash_sha3_256(&self.to_bytes())
+    }
+}
+
+/// A proposal as recorded on chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredProposal {
+    pub proposal: Proposal,
+    pub proposer: [u8; 32],
+    /// Height of the block that included the proposal tx.
+    pub height: u32,
+}
+
+impl StoredProposal {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut b = Vec::new();
+        b.extend_from_slice(&self.proposer);
+        b.extend_from_slice(&self.height.to_le_bytes());
+        b.extend_from_slice(&self.proposal.to_bytes());
+        b
+    }
+
+    pub fn from_bytes(d: &[u8]) -> Result<Self, &'static str> {
+        if d.len() < 36 {
+            return Err("stored proposal too short");
+        }
+        Ok(StoredProposal {
+            proposer: d[..32].try_into().unwrap(),
+            height: u32::from_le_bytes(d[32..36].try_into().unwrap()),
+            proposal: Proposal::from_bytes(&d[36..])?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proposal_roundtrip() {
+        let p = Proposal::new("Raise PONC rounds", GovParam::PoncRounds, 1024).unwrap();
+        assert_eq!(Proposal::from_bytes(&p.to_bytes()).unwrap(), p);
+        assert_eq!(p.hash(), hash_sha3_256(&p.to_bytes()));
+
+        let mut params = GovernanceParams::default();
+        p.enact(&mut params);
+        assert_eq!(params.ponc_rounds, 1024);
+        assert_eq!(params.cap_bps, GovernanceParams::default().cap_bps);
+
+        let s = StoredProposal { proposal: p, proposer: [7u8; 32], height: 42 };
+        assert_eq!(StoredProposal::from_bytes(&s.to_bytes()).unwrap(), s);
+    }
+
+    #[test]
+    fn test_proposal_validation() {
+        assert!(Proposal::new("", GovParam::CapBps, 1000).is_err());
+        assert!(Proposal::new(&"x".repeat(129), GovParam::CapBps, 1000).is_err());
+        assert!(Proposal::new("too high", GovParam::CapBps, GOVERNANCE_CAP_MAX_BPS + 1).is_err());
+        assert!(Proposal::new("threads", GovParam::MiningThreads, 0).is_err());
+
+        let mut raw = Proposal::new("ok", GovParam::CapBps, 1000).unwrap().to_bytes();
+        raw[0] = 9;
+        assert!(Proposal::from_bytes(&raw).is_err());
+        assert_eq!(GovParam::from_name("mining_threads"), Some(GovParam::MiningThreads));
+        assert_eq!(GovParam::from_name("nope"), None);
+    }
+}
@@ -1,3 +1,5 @@
 pub mod chain;
 pub mod genesis;
+pub mod governance;
+pub mod pow_cache;
 pub mod state;
@@ -0,0 +1,102 @@
+// PoW Verification Cache
+//
+// Every PONC check builds a fresh engine and scratchpad, and a reorg (or a
+// block arriving from several peers) would otherwise pay that again for blocks
+// already checked. A passing result is remembered per (block hash, PONC
+// rounds): the hash covers every header field the PoW reads, and the rounds
+// come from governance, so a rounds change can't reuse a stale answer.
+// Failures aren't cached. Bounded; the least recently used entry goes first.
+
+use std::collections::{BTreeMap, HashMap};
+
+pub const POW_CACHE_CAPACITY: usize = 4096;
+
+type Key = ([u8; 32], u64);
+
+pub struct PowCache {
+    capacity: usize,
+    /// key -> last-use tick
+    entries: HashMap<Key, u64>,
+    /// last-use tick -> key, oldest first
+    by_use: BTreeMap<u64, Key>,
+    tick: u64,
+}
+
+impl Default for PowCache {
+    fn default() -> Self {
+        Self::with_capacity(POW_CACHE_CAPACITY)
+    }
+}
+
+impl PowCache {
+    pub fn with_capacity(capacity: usize) -> Self {
+        PowCache { capacity, entries: HashMap::new(), by_use: BTreeMap::new(), tick: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Whether `hash` is known to pass PoW at `rounds`; a hit counts as a use.
+    pub fn contains(&mut self, hash: &[u8; 32], rounds: u64) -> bool {
+        self.touch((*hash, rounds))
+    }
+
+    pub fn insert(&mut self, hash: [u8; 32], rounds: u64) {
+        let key = (hash, rounds);
+        if self.touch(key) {
+            return;
+        }
+        if self.entries.len() >= self.capacity
+            && let Some((_, oldest)) = self.by_use.pop_first()
+        {
+            self.entries.remove(&oldest);
+        }
+        self.tick += 1;
+        self.entries.insert(key, self.tick);
+        self.by_use.insert(self.tick, key);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.by_use.clear();
+    }
+
+    fn touch(&mut self, key: Key) -> bool {
+        let Some(last) = self.entries.get_mut(&key) else {
+            return false;
+        };
+        self.by_use.remove(last);
+        self.tick += 1;
+        *last = self.tick;
+        self.by_use.insert(self.tick, key);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = PowCache::with_capacity(2);
+        cache.insert([1u8; 32], 512);
+        cache.insert([2u8; 32], 512);
+        assert!(cache.contains(&[1u8; 32], 512));
+        assert!(!cache.contains(&[1u8; 32], 1024));
+
+        cache.insert([3u8; 32], 512);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.contains(&[1u8; 32], 512));
+        assert!(!cache.contains(&[2u8; 32], 512));
+        assert!(cache.contains(&[3u8; 32], 512));
+
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+}
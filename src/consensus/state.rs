@@ -1,11 +1,18 @@
 use crate::consensus::chain::{
-    calculate_block_reward, calculate_governance_weight, calculate_referral_bonus,
-    GOVERNANCE_CAP_DEFAULT_BPS, PONC_ROUNDS_DEFAULT, MINING_THREADS_DEFAULT,
+    block_work, calculate_block_reward, calculate_governance_weight, calculate_new_difficulty,
+    calculate_referral_bonus, GOVERNANCE_CAP_DEFAULT_BPS, RETARGET_WINDOW, PONC_ROUNDS_DEFAULT, MINING_THREADS_DEFAULT,
+    MAX_TXS_PER_SENDER, MAX_BLOCK_BYTES,
 };
+use crate::consensus::governance::GOVERNANCE_THRESHOLD_BPS;
 use crate::crypto::hash::hash_sha3_256;
 use crate::crypto::ponc::ffi::bridge::new_ponc_engine;
-use crate::node::{ChainDB, db_common::StoredBlock};
-use crate::primitives::transaction::Transaction;
+use crate::node::{ChainDB, db_common::{AccountState, BlockUndo, StoredBlock, StoredTransaction}};
+use crate::config::Network;
+use crate::primitives::transaction::{Transaction, MIN_FEE_KNOTS};
+use primitive_types::U256;
+use log::{info, warn};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicI64, Ordering};
 
 #[derive(Debug, Clone)]
 pub struct GovernanceParams {
@@ -14,6 +21,33 @@ pub struct GovernanceParams {
     pub mining_threads: u64,  // NEW: Governance-controlled thread count
 }
 
+impl GovernanceParams {
+    /// cap_bps, ponc_rounds, mining_threads (LE u64 each)
+    pub fn to_bytes(&self) -> [u8; 24] {
+        let mut buf = [0u8; 24];
+        buf[0..8].copy_from_slice(&self.cap_bps.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.ponc_rounds.to_le_bytes());
+        buf[16..24].copy_from_slice(&self.mining_threads.to_le_bytes());
+        buf
+    }
+
+    /// Accepts the legacy 16-byte form (no mining_threads) too; anything
+    /// shorter yields the defaults.
+    pub fn from_bytes(data: &[u8]) -> Self {
+        if data.len() < 16 {
+            return Self::default();
+        }
+        let cap_bps = u64::from_le_bytes(data[0..8].try_into().unwrap());
+        let ponc_rounds = u64::from_le_bytes(data[8..16].try_into().unwrap());
+        let mining_threads = if data.len() >= 24 {
+            u64::from_le_bytes(data[16..24].try_into().unwrap())
+        } else {
+            MINING_THREADS_DEFAULT
+        };
+        Self { cap_bps, ponc_rounds, mining_threads }
+    }
+}
+
 impl Default for GovernanceParams {
     fn default() -> Self {
         Self {
@@ -37,6 +71,24 @@ pub enum StateError {
     InvalidTransaction(&'static str),
     BlockInPast,
     BlockTooFarInFuture,
+    UnknownParent,
+    InvalidHeight { expected: u32, got: u32 },
+    InvalidDifficulty,
+    /// The block's target is easier than the network's `max_target`.
+    DifficultyBelowMinimum,
+    TooManyTxsPerSender,
+    BlockTooLarge,
+    /// Switching to the branch would disconnect more than `MAX_REORG_DEPTH` blocks.
+    ReorgTooDeep { depth: u32 },
+    /// A transaction pays less than `MIN_FEE_KNOTS`.
+    FeeTooLow { fee: u64 },
+    /// A transaction's amount plus fee is more than has ever been issued.
+    AmountExceedsSupply { debit: u64, supply: u64 },
+    /// A payment to someone else of nothing, with no fee.
+    EmptyTransaction,
+    /// A side block's branch leaves more possible PONC round counts than
+    /// `MAX_POW_ROUNDS_CANDIDATES`, so its PoW can't be checked.
+    PowRoundsUnknown,
 }
 
 impl std::fmt::Display for StateError {
@@ -57,6 +109,25 @@ impl std::fmt::Display for StateError {
             }
             StateError::BlockInPast => write!(f, "block timestamp is before median-time-past"),
             StateError::BlockTooFarInFuture => write!(f, "block timestamp is too far in future"),
+            StateError::UnknownParent => write!(f, "parent block not found"),
+            StateError::InvalidHeight { expected, got } => {
+                write!(f, "bad block height: want {expected}, got {got}")
+            }
+            StateError::InvalidDifficulty => write!(f, "difficulty target does not match retarget schedule"),
+            StateError::DifficultyBelowMinimum => write!(f, "difficulty target is easier than the network minimum"),
+            StateError::TooManyTxsPerSender => {
+                write!(f, "more than {MAX_TXS_PER_SENDER} transactions from one sender")
+            }
+            StateError::BlockTooLarge => write!(f, "block is larger than {MAX_BLOCK_BYTES} bytes"),
+            StateError::ReorgTooDeep { depth } => {
+                write!(f, "reorg of {depth} blocks exceeds the {MAX_REORG_DEPTH}-block limit")
+            }
+            StateError::FeeTooLow { fee } => write!(f, "fee {fee} is below the {MIN_FEE_KNOTS}-knot minimum"),
+            StateError::AmountExceedsSupply { debit, supply } => {
+                write!(f, "amount plus fee {debit} exceeds the {supply} knots issued")
+            }
+            StateError::EmptyTransaction => write!(f, "transaction moves nothing and pays no fee"),
+            StateError::PowRoundsUnknown => write!(f, "PONC rounds for the block's branch can't be determined"),
         }
     }
 }
@@ -80,21 +151,159 @@ impl From<rocksdb::Error> for StateError {
     }
 }
 
+/// Easiest target a mainnet block may carry: the genesis target (difficulty
+/// 2). Without a floor, a target of all 0xFF needs no work at all.
+pub const MAINNET_MAX_TARGET: [u8; 32] = {
+    let mut t = [0xFF; 32];
+    t[0] = 0x7F;
+    t
+};
+
+/// Easiest target allowed on `network`. Testnet and regtest have no floor.
+pub fn max_target(network: Network) -> [u8; 32] {
+    match network {
+        Network::Mainnet => MAINNET_MAX_TARGET,
+        Network::Testnet | Network::Regtest => [0xFF; 32],
+    }
+}
+
+/// Whether a block carries governance votes or proposals, i.e. whether
+/// connecting it could pass a proposal and change the params.
+fn has_governance_txs(block: &StoredBlock) -> bool {
+    block.tx_data.iter().any(|tx| tx.governance_data.is_some() || tx.proposal.is_some())
+}
+
+/// PONC rounds a child of `parent` is mined with: `ponc_rounds` as
+/// connecting `parent` left it. `None` while that can't be known: the parent
+/// is not stored, or it sits on a branch that was never connected behind a
+/// block whose governance txs might have passed a proposal.
+pub fn pow_rounds_after(db: &ChainDB, parent: &[u8; 32]) -> Result<Option<u64>, StateError> {
+    let mut cur = *parent;
+    for _ in 0..=MAX_REORG_DEPTH {
+        if let Some(rounds) = db.get_pow_rounds(&cur)? {
+            return Ok(Some(rounds));
+        }
+        let Some(block) = db.get_block(&cur)? else {
+            return Ok(None);
+        };
+        // Connected before rounds were recorded per block.
+        if db.get_block_hash_by_height(u32::from_le_bytes(block.block_height))? == Some(cur) {
+            return Ok(Some(db.get_governance_params()?.ponc_rounds));
+        }
+        if has_governance_txs(&block) {
+            return Ok(None);
+        }
+        cur = block.previous_hash;
+    }
+    Ok(None)
+}
+
+/// Most PONC round counts a side block is tried against while its branch's
+/// governance leaves the exact count unknown.
+pub const MAX_POW_ROUNDS_CANDIDATES: usize = 4;
+
+/// Every `ponc_rounds` a child of `parent` could be mined with when
+/// `pow_rounds_after` can't tell: the rounds at the branch's last connected
+/// block, plus the value of each PONC-rounds proposal that the branch's
+/// blocks since then vote for or publish, as only those can be enacted on
+/// it. Sorted; empty if `parent` isn't stored.
+pub fn possible_pow_rounds(db: &ChainDB, parent: &[u8; 32]) -> Result<Vec<u64>, StateError> {
+    use crate::consensus::governance::{GovParam, Proposal};
+    let params = db.get_governance_params()?;
+    let enacted = |proposal: &Proposal| {
+        let mut p = params.clone();
+        proposal.enact(&mut p);
+        p.ponc_rounds
+    };
+
+    let mut rounds = Vec::new();
+    let mut cur = *parent;
+    let mut depth = 0;
+    loop {
+        if let Some(r) = db.get_pow_rounds(&cur)? {
+            rounds.push(r);
+            break;
+        }
+        let Some(block) = db.get_block(&cur)? else {
+            return Ok(Vec::new());
+        };
+        if db.get_block_hash_by_height(u32::from_le_bytes(block.block_height))? == Some(cur) {
+            rounds.push(params.ponc_rounds);
+            break;
+        }
+        depth += 1;
+        if depth > MAX_REORG_DEPTH {
+            return Ok(Vec::new());
+        }
+        for tx in &block.tx_data {
+            if let Some(prop) = tx.governance_data
+                && let Some(stored) = db.get_governance_proposal(&prop)?
+                && stored.proposal.param == GovParam::PoncRounds
+            {
+                rounds.push(enacted(&stored.proposal));
+            }
+            if let Some(raw) = &tx.proposal
+                && let Ok(proposal) = Proposal::from_bytes(raw)
+                && proposal.param == GovParam::PoncRounds
+            {
+                rounds.push(enacted(&proposal));
+            }
+        }
+        cur = block.previous_hash;
+    }
+    rounds.sort_unstable();
+    rounds.dedup();
+    Ok(rounds)
+}
+
+/// `pow_rounds_after` for each block of a height-sorted batch, where a
+/// parent may be earlier in the batch rather than stored.
+pub fn pow_rounds_for_batch(db: &ChainDB, blocks: &[StoredBlock]) -> Result<Vec<Option<u64>>, StateError> {
+    let mut after: std::collections::HashMap<[u8; 32], Option<u64>> = std::collections::HashMap::new();
+    let mut out = Vec::with_capacity(blocks.len());
+    for block in blocks {
+        let rounds = match after.get(&block.previous_hash) {
+            Some(r) => *r,
+            None => pow_rounds_after(db, &block.previous_hash)?,
+        };
+        after.insert(block_hash(block), if has_governance_txs(block) { None } else { rounds });
+        out.push(rounds);
+    }
+    Ok(out)
+}
+
+/// Verify block PoW against the rounds in effect at its parent (see
+/// `pow_rounds_after`); fails with `UnknownParent` when those aren't known.
+pub fn verify_block_pow(block: &StoredBlock, db: &ChainDB) -> Result<(), StateError> {
+    if u32::from_le_bytes(block.block_height) == 0 {
+        return Ok(());
+    }
+    let rounds = pow_rounds_after(db, &block.previous_hash)?.ok_or(StateError::UnknownParent)?;
+    verify_block_pow_with_rounds(block, db, rounds)
+}
+
 /// Verify block PoW without state access (stateless, can be parallelized)
 /// This is consensus-safe to call in parallel across multiple blocks
-pub fn verify_block_pow(block: &StoredBlock, db: &ChainDB) -> Result<(), StateError> {
+pub fn verify_block_pow_with_rounds(block: &StoredBlock, db: &ChainDB, rounds: u64) -> Result<(), StateError> {
     let height = u32::from_le_bytes(block.block_height) as u64;
     
     // Skip PoW verification for genesis block
     if height == 0 {
         return Ok(());
     }
+
+    // Targets are big-endian, so byte order is numeric order.
+    if block.difficulty_target > max_target(db.network()) {
+        return Err(StateError::DifficultyBelowMinimum);
+    }
     
+    let hash = block_hash(block);
+    if db.pow_cache.lock().unwrap().contains(&hash, rounds) {
+        return Ok(());
+    }
+
     let mut engine = new_ponc_engine();
-    
-    // Get current PONC rounds from governance params
-    let params = db.get_governance_params()?;
-    engine.pin_mut().set_rounds(params.ponc_rounds as usize);
+    engine.pin_mut().set_rounds(rounds as usize);
     
     engine
         .pin_mut()
@@ -114,206 +323,1028 @@ pub fn verify_block_pow(block: &StoredBlock, db: &ChainDB) -> Result<(), StateEr
     if !engine.compute_and_verify(&prefix, nonce, &block.difficulty_target, &mut out) {
         return Err(StateError::InvalidPoW);
     }
-    
+
+    db.pow_cache.lock().unwrap().insert(hash, rounds);
+    Ok(())
+}
+
+/// Number of ancestor blocks sampled for Median-Time-Past.
+pub const MTP_WINDOW: u32 = 11;
+
+/// Median timestamp of the (up to) `MTP_WINDOW` blocks preceding `height`.
+///
+/// The rule compares against the median, not the parent: a block may carry the
+/// same timestamp as its parent (or even an earlier one) as long as it is
+/// strictly greater than this value. That is the grace a marginally-skewed
+/// miner gets; `block_time == mtp` itself stays rejected, since allowing the
+/// median to stall is what lets a miner majority freeze chain time.
+/// Miners avoid the boundary by clamping their timestamp to `mtp + 1`.
+pub fn median_time_past(db: &ChainDB, height: u32) -> Option<u32> {
+    let mut times = Vec::with_capacity(MTP_WINDOW as usize);
+    for i in 1..=MTP_WINDOW {
+        if height >= i
+            && let Ok(Some(h)) = db.get_block_hash_by_height(height - i)
+            && let Ok(Some(b)) = db.get_block(&h)
+        {
+            times.push(u32::from_le_bytes(b.timestamp));
+        }
+    }
+    if times.is_empty() {
+        return None;
+    }
+    times.sort_unstable();
+    Some(times[times.len() / 2])
+}
+
+/// Target a block at `height` must carry on top of the active chain. Every
+/// `RETARGET_WINDOW` blocks it is rescaled by how long the previous window
+/// (its first to last block) actually took; otherwise the parent's target
+/// carries over, and a retarget never goes easier than `max_target`. On
+/// regtest every block takes the trivial target.
+pub fn next_difficulty_target(db: &ChainDB, height: u32) -> Result<[u8; 32], StateError> {
+    if db.network() == Network::Regtest {
+        return Ok([0xFF; 32]);
+    }
+    let header_at = |h: u32| -> Result<StoredBlock, StateError> {
+        let hash = db.get_block_hash_by_height(h)?.ok_or(StateError::UnknownParent)?;
+        db.get_block_header(&hash)?.ok_or(StateError::UnknownParent)
+    };
+    let parent = header_at(height.checked_sub(1).ok_or(StateError::UnknownParent)?)?;
+    if !(height as u64).is_multiple_of(RETARGET_WINDOW) {
+        return Ok(parent.difficulty_target);
+    }
+
+    let first = header_at(height - RETARGET_WINDOW as u32)?;
+    let start_ts = u32::from_le_bytes(first.timestamp) as u64;
+    let end_ts = u32::from_le_bytes(parent.timestamp) as u64;
+    let elapsed = end_ts.saturating_sub(start_ts).max(1);
+    Ok(calculate_new_difficulty(&parent.difficulty_target, elapsed).min(max_target(db.network())))
+}
+
+/// How far ahead of our clock a block timestamp may be.
+pub const MAX_FUTURE_BLOCK_SECS: u32 = 2 * 60 * 60;
+
+/// Future bound used while catching up. A node whose clock lags the network
+/// would otherwise stall IBD on the most recent historical blocks.
+pub const IBD_MAX_FUTURE_BLOCK_SECS: u32 = 24 * 60 * 60;
+
+/// We consider ourselves in initial block download while the tip is older than this.
+pub const IBD_TIP_AGE_SECS: u32 = 24 * 60 * 60;
+
+fn unix_now() -> u32 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32
+}
+
+/// Peers can move our idea of the time by at most this much (70 minutes, as
+/// in Bitcoin), so a hostile majority can't push the future limit far.
+pub const MAX_TIME_ADJUSTMENT_SECS: i64 = 70 * 60;
+
+/// Median peer clock offset, published by the P2P layer (see `TimeData`).
+static TIME_OFFSET_SECS: AtomicI64 = AtomicI64::new(0);
+
+/// Set the network time offset; clamped to `MAX_TIME_ADJUSTMENT_SECS`.
+pub fn set_time_offset(offset_secs: i64) {
+    TIME_OFFSET_SECS.store(clamp_time_offset(offset_secs), Ordering::Relaxed);
+}
+
+pub fn time_offset() -> i64 {
+    TIME_OFFSET_SECS.load(Ordering::Relaxed)
+}
+
+fn clamp_time_offset(offset_secs: i64) -> i64 {
+    offset_secs.clamp(-MAX_TIME_ADJUSTMENT_SECS, MAX_TIME_ADJUSTMENT_SECS)
+}
+
+/// Our clock corrected by the peer median: the time blocks are judged against.
+pub fn adjusted_now() -> u32 {
+    offset_time(unix_now(), time_offset())
+}
+
+fn offset_time(now: u32, offset_secs: i64) -> u32 {
+    (now as i64).saturating_add(offset_secs).clamp(0, u32::MAX as i64) as u32
+}
+
+/// Timestamp rules for a block extending the active chain: strictly after
+/// MTP, and no more than `MAX_FUTURE_BLOCK_SECS` ahead of `adjusted_now()`.
+pub fn validate_block_timestamp(db: &ChainDB, block: &StoredBlock) -> Result<(), StateError> {
+    validate_block_timestamp_within(db, block, MAX_FUTURE_BLOCK_SECS)
+}
+
+/// `validate_block_timestamp` with a caller-chosen future bound (sync uses
+/// `IBD_MAX_FUTURE_BLOCK_SECS` during IBD). The MTP rule is never relaxed.
+pub fn validate_block_timestamp_within(db: &ChainDB, block: &StoredBlock, max_future_secs: u32) -> Result<(), StateError> {
+    let height = u32::from_le_bytes(block.block_height);
+    let block_time = u32::from_le_bytes(block.timestamp);
+
+    if height > 0
+        && let Some(mtp) = median_time_past(db, height)
+        && block_time <= mtp
+    {
+        return Err(StateError::BlockInPast);
+    }
+
+    if block_time > adjusted_now().saturating_add(max_future_secs) {
+        return Err(StateError::BlockTooFarInFuture);
+    }
     Ok(())
 }
 
+/// True while our tip is more than `IBD_TIP_AGE_SECS` old (or missing).
+pub fn is_initial_block_download(db: &ChainDB) -> bool {
+    let tip_time = db
+        .get_tip()
+        .ok()
+        .flatten()
+        .and_then(|h| db.get_block(&h).ok().flatten())
+        .map(|b| u32::from_le_bytes(b.timestamp));
+    match tip_time {
+        Some(t) => t.saturating_add(IBD_TIP_AGE_SECS) < unix_now(),
+        None => true,
+    }
+}
+
 pub fn apply_block(db: &ChainDB, block: &StoredBlock) -> Result<(), StateError> {
     apply_block_with_referrer(db, block, None)
 }
 
+/// Apply a block received from a peer, with `max_future_secs` in place of
+/// `MAX_FUTURE_BLOCK_SECS`.
+pub fn apply_block_with_time_bound(db: &ChainDB, block: &StoredBlock, max_future_secs: u32) -> Result<(), StateError> {
+    check_block_size(block)?;
+    let _guard = db.chain_lock.lock().unwrap_or_else(|e| e.into_inner());
+    match db.get_tip()? {
+        Some(tip) if block.previous_hash != tip => accept_side_block(db, block),
+        _ => connect_block(db, block, None, max_future_secs),
+    }
+}
+
 /// Apply block with optional referrer registration for the miner's first block
+///
+/// A block extending the tip is connected directly. A block on any other known
+/// parent is stored as a side branch; if that branch now carries more
+/// cumulative work than the active chain, we reorganize onto it.
+///
+/// Everything from reading the tip to writing the new one runs under
+/// `db.chain_lock`, so of two blocks racing for the same height exactly one
+/// is connected and the other lands as a side branch.
 pub fn apply_block_with_referrer(db: &ChainDB, block: &StoredBlock, pending_referrer: Option<[u8; 32]>) -> Result<(), StateError> {
-    let height = u32::from_le_bytes(block.block_height) as u64;
-    let block_time = u32::from_le_bytes(block.timestamp);
+    check_block_size(block)?;
+    let _guard = db.chain_lock.lock().unwrap_or_else(|e| e.into_inner());
+    match db.get_tip()? {
+        Some(tip) if block.previous_hash != tip => accept_side_block(db, block),
+        _ => connect_block(db, block, pending_referrer, MAX_FUTURE_BLOCK_SECS),
+    }
+}
 
-    // 0. Verify Timestamp (MTP + Future Limit)
-    if height > 0 {
-        let mut times = Vec::new();
-        // Look back up to 11 blocks for MTP
-        for i in 1..=11 {
-            if height >= i
-                && let Ok(Some(h)) = db.get_block_hash_by_height((height - i) as u32)
-                && let Ok(Some(b)) = db.get_block(&h)
-            {
-                times.push(u32::from_le_bytes(b.timestamp));
-            }
+/// Oversized blocks are refused before any other work, on the tip and on
+/// side branches alike.
+fn check_block_size(block: &StoredBlock) -> Result<(), StateError> {
+    if block.to_bytes().len() > MAX_BLOCK_BYTES {
+        return Err(StateError::BlockTooLarge);
+    }
+    Ok(())
+}
+
+/// Cumulative work of the chain ending at `hash` (zero for unknown hashes).
+///
+/// O(1) for blocks stored since the work index existed. Blocks connected by
+/// older versions have no entry, so we sum back to the nearest indexed
+/// ancestor; the result is written for the next block and the walk only
+/// happens once per legacy chain.
+pub fn chain_work(db: &ChainDB, hash: &[u8; 32]) -> Result<U256, StateError> {
+    let mut total = U256::zero();
+    let mut cur = *hash;
+    loop {
+        if let Some(w) = db.get_chain_work(&cur)? {
+            return Ok(total.saturating_add(U256::from_big_endian(&w)));
         }
-        if !times.is_empty() {
-            times.sort();
-            let mtp = times[times.len() / 2];
-            if block_time <= mtp {
-                return Err(StateError::BlockInPast);
-            }
+        let Some(b) = db.get_block(&cur)? else {
+            return Ok(total);
+        };
+        total = total.saturating_add(block_work(&b.difficulty_target));
+        if u32::from_le_bytes(b.block_height) == 0 {
+            return Ok(total);
         }
+        cur = b.previous_hash;
     }
+}
 
-    // Future limit: no more than 2 hours (7200s) ahead of now
-    let now = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs() as u32;
-    if block_time > now + 7200 {
-        return Err(StateError::BlockTooFarInFuture);
+/// Knots minted by the active chain: every block reward plus every referral
+/// bonus paid. Fees only move existing coins, so they don't count.
+///
+/// O(1) once the running total is stored. Chains connected by older versions,
+/// or loaded from a state snapshot, have no entry; for those we rebuild it
+/// from the reward schedule and the accounts' bonus totals, and the next
+/// connected block stores it.
+pub fn total_supply(db: &ChainDB) -> Result<u64, StateError> {
+    if let Some(supply) = db.get_total_supply()? {
+        return Ok(supply);
     }
+    recompute_total_supply(db)
+}
 
-    // 1. Verify PoW (Strict Mainnet Requirement)
-    verify_block_pow(block, db)?;
+fn recompute_total_supply(db: &ChainDB) -> Result<u64, StateError> {
+    if db.get_tip()?.is_none() {
+        return Ok(0);
+    }
+    let mut total = 0u64;
+    for h in 0..=db.get_chain_height()? as u64 {
+        total = total.checked_add(calculate_block_reward(h)).ok_or(StateError::MathOverflow)?;
+    }
+    for (_, acc) in db.iter_accounts()? {
+        total = total.checked_add(acc.total_referral_bonus_earned).ok_or(StateError::MathOverflow)?;
+    }
+    Ok(total)
+}
 
-    // 2. Calculate Rewards
-    let base_reward = calculate_block_reward(height);
+/// Leaf of the accounts tree: `SHA3-256(address || balance LE || nonce LE)`.
+/// Immature rewards are not part of `balance` and so not committed.
+fn account_leaf(addr: &[u8; 32], acc: &AccountState) -> [u8; 32] {
+    let mut buf = [0u8; 48];
+    buf[..32].copy_from_slice(addr);
+    buf[32..40].copy_from_slice(&acc.balance.to_le_bytes());
+    buf[40..].copy_from_slice(&acc.nonce.to_le_bytes());
+    hash_sha3_256(&buf)
+}
 
-    let mut account_updates: std::collections::HashMap<[u8; 32], crate::node::db_common::AccountState> = std::collections::HashMap::new();
-    let mut tally_updates: std::collections::HashMap<[u8; 32], u64> = std::collections::HashMap::new();
-    let mut vote_keys = Vec::new();
+/// Levels above the buckets in the accounts tree: one per bit of the
+/// two-byte address prefix that picks a bucket.
+const ACCOUNTS_TREE_LEVELS: u8 = 16;
 
-    let get_account_local = |addr: &[u8; 32], updates: &std::collections::HashMap<[u8; 32], crate::node::db_common::AccountState>, db: &ChainDB| -> crate::node::db_common::AccountState {
-        updates.get(addr).cloned().unwrap_or_else(|| db.get_account(addr).unwrap_or_default())
-    };
+fn account_bucket(addr: &[u8; 32]) -> u16 {
+    u16::from_be_bytes([addr[0], addr[1]])
+}
 
-    // Credit base reward to miner first
-    let mut miner_acc = get_account_local(&block.miner_address, &account_updates, db);
-    miner_acc.balance = miner_acc.balance.checked_add(base_reward).ok_or(StateError::MathOverflow)?;
-    miner_acc.last_mined_height = height;
-    miner_acc.total_blocks_mined = miner_acc.total_blocks_mined.saturating_add(1);
-    miner_acc.governance_weight = calculate_governance_weight(miner_acc.total_blocks_mined);
+fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut buf = [0u8; 64];
+    buf[..32].copy_from_slice(left);
+    buf[32..].copy_from_slice(right);
+    hash_sha3_256(&buf)
+}
 
-    // Auto-register referrer on first block mined (if pending_referrer provided and no referrer set yet)
-    if miner_acc.referrer.is_none() && miner_acc.total_blocks_mined == 1 {
-        if let Some(ref_addr) = pending_referrer {
-            if ref_addr != block.miner_address {
-                miner_acc.referrer = Some(ref_addr);
-                let mut upstream = get_account_local(&ref_addr, &account_updates, db);
-                upstream.total_referred_miners = upstream.total_referred_miners.saturating_add(1);
-                upstream.governance_weight = calculate_governance_weight(upstream.total_referred_miners);
-                account_updates.insert(ref_addr, upstream);
-                println!("[referral] Auto-registered referrer for new miner");
-            }
-        }
+/// Bucket hash for each non-empty bucket in `accounts` (sorted by address):
+/// pairs hashed level by level, an odd last node paired with itself.
+fn bucket_hashes<'a>(accounts: impl IntoIterator<Item = (&'a [u8; 32], &'a AccountState)>) -> BTreeMap<u16, [u8; 32]> {
+    let mut leaves: BTreeMap<u16, Vec<[u8; 32]>> = BTreeMap::new();
+    for (addr, acc) in accounts {
+        leaves.entry(account_bucket(addr)).or_default().push(account_leaf(addr, acc));
     }
+    leaves
+        .into_iter()
+        .map(|(bucket, mut level)| {
+            while level.len() > 1 {
+                level = level.chunks(2).map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0]))).collect();
+            }
+            (bucket, level[0])
+        })
+        .collect()
+}
 
-    // Referral bonus
-    if let Some(ref_addr) = miner_acc.referrer {
-        let mut referrer = get_account_local(&ref_addr, &account_updates, db);
-        let bonus = calculate_referral_bonus(base_reward, referrer.total_blocks_mined, referrer.last_mined_height, height);
-        if bonus > 0 {
-            referrer.balance = referrer.balance.checked_add(bonus).ok_or(StateError::MathOverflow)?;
-            referrer.total_referral_bonus_earned = referrer.total_referral_bonus_earned.checked_add(bonus).ok_or(StateError::MathOverflow)?;
-            referrer.governance_weight = calculate_governance_weight(referrer.total_referred_miners);
-            account_updates.insert(ref_addr, referrer);
+/// Recompute the accounts tree above `changed` bucket hashes. Nodes not
+/// recomputed are read through `stored`. A node whose children are both zero
+/// is zero, so empty subtrees cost nothing. Returns every recomputed node as
+/// `(level, index, hash)`, ending with the root.
+fn fold_accounts_tree(
+    changed: BTreeMap<u16, [u8; 32]>,
+    mut stored: impl FnMut(u8, u16) -> Result<[u8; 32], StateError>,
+) -> Result<Vec<(u8, u16, [u8; 32])>, StateError> {
+    let mut nodes = Vec::new();
+    let mut level_nodes = changed;
+    for level in 0..ACCOUNTS_TREE_LEVELS {
+        let mut parents = BTreeMap::new();
+        for &index in level_nodes.keys() {
+            let parent = index >> 1;
+            if parents.contains_key(&parent) {
+                continue;
+            }
+            let mut child = |i: u16| match level_nodes.get(&i) {
+                Some(h) => Ok(*h),
+                None => stored(level, i),
+            };
+            let (left, right) = (child(parent << 1)?, child((parent << 1) | 1)?);
+            let hash = if left == [0u8; 32] && right == [0u8; 32] { [0u8; 32] } else { hash_pair(&left, &right) };
+            parents.insert(parent, hash);
         }
+        nodes.extend(level_nodes.into_iter().map(|(i, h)| (level, i, h)));
+        level_nodes = parents;
     }
-    account_updates.insert(block.miner_address, miner_acc);
+    nodes.push((ACCOUNTS_TREE_LEVELS, 0, level_nodes.get(&0).copied().unwrap_or([0u8; 32])));
+    Ok(nodes)
+}
 
-    let mut fees = 0u64;
-    let mut seen_txids = std::collections::HashSet::new();
-    
-    for tx in &block.tx_data {
-        let domain_tx = Transaction::try_from(tx).map_err(StateError::InvalidTransaction)?;
-        if !domain_tx.is_structurally_valid() {
-            return Err(StateError::InvalidTransaction("structural or signature failure"));
+/// Merkle root over `accounts`, which must be sorted by address.
+///
+/// Accounts are split into 65536 buckets by the first two address bytes. A
+/// bucket hashes its leaves pairwise as `SHA3-256(left || right)`, an odd
+/// last node paired with itself; an empty bucket is zero. The bucket hashes
+/// are the leaves of a fixed 16-level tree of the same pair hash, except that
+/// two zero children give a zero parent. An empty account set has an
+/// all-zero root.
+///
+/// The fixed shape is what lets `connect_block` keep the tree on disk and
+/// recompute only the buckets a block touches.
+pub fn accounts_merkle_root<'a>(accounts: impl IntoIterator<Item = (&'a [u8; 32], &'a AccountState)>) -> [u8; 32] {
+    let nodes = fold_accounts_tree(bucket_hashes(accounts), |_, _| Ok([0u8; 32]))
+        .expect("in-memory fold cannot fail");
+    nodes.last().map(|n| n.2).unwrap_or([0u8; 32])
+}
+
+/// Apply `changes` (`None` deletes the account) to the accounts tree kept in
+/// the `accounts_tree` CF, as part of `batch`, and tag it with `to`. Returns
+/// the new root.
+///
+/// Only the buckets the changes touch are rescanned, and only their paths to
+/// the root rewritten. If the stored tree is not tagged with the current tip
+/// (first run, or accounts written outside connect/disconnect) it is rebuilt
+/// from a full scan instead.
+fn update_accounts_tree(
+    db: &ChainDB,
+    batch: &mut rocksdb::WriteBatch,
+    to: &[u8; 32],
+    changes: &BTreeMap<[u8; 32], Option<AccountState>>,
+) -> Result<[u8; 32], StateError> {
+    let cf_tree = db.db.cf_handle("accounts_tree").ok_or(StateError::DatabaseError("accounts_tree CF not found".into()))?;
+    let cf_meta = db.db.cf_handle("meta").ok_or(StateError::DatabaseError("meta CF not found".into()))?;
+
+    let current = match db.get_tip()? {
+        Some(tip) => db.get_accounts_tree_tip()? == Some(tip),
+        None => false,
+    };
+    let nodes = if current {
+        let buckets: BTreeSet<u16> = changes.keys().map(account_bucket).collect();
+        let mut changed = BTreeMap::new();
+        for bucket in buckets {
+            let mut accounts: BTreeMap<[u8; 32], AccountState> =
+                db.iter_accounts_with_prefix(&bucket.to_be_bytes())?.into_iter().collect();
+            for (addr, change) in changes.range(bucket_range(bucket)) {
+                match change {
+                    Some(acc) => accounts.insert(*addr, acc.clone()),
+                    None => accounts.remove(addr),
+                };
+            }
+            changed.insert(bucket, bucket_hashes(&accounts).remove(&bucket).unwrap_or([0u8; 32]));
         }
+        fold_accounts_tree(changed, |level, index| Ok(db.get_accounts_tree_node(level, index)?))?
+    } else {
+        let mut accounts: BTreeMap<[u8; 32], AccountState> = db.iter_accounts()?.into_iter().collect();
+        for (addr, change) in changes {
+            match change {
+                Some(acc) => accounts.insert(*addr, acc.clone()),
+                None => accounts.remove(addr),
+            };
+        }
+        batch.delete_range_cf(cf_tree, &[0u8; 3][..], &[0xFFu8; 4][..]);
+        fold_accounts_tree(bucket_hashes(&accounts), |_, _| Ok([0u8; 32]))?
+    };
 
-        // Check for duplicate TXIDs within this block
-        let txid = domain_tx.txid();
-        if !seen_txids.insert(txid) {
-            return Err(StateError::InvalidTransaction("duplicate transaction in block"));
+    for &(level, index, hash) in &nodes {
+        let [hi, lo] = index.to_be_bytes();
+        if hash == [0u8; 32] {
+            batch.delete_cf(cf_tree, [level, hi, lo]);
+        } else {
+            batch.put_cf(cf_tree, [level, hi, lo], hash);
         }
+    }
+    batch.put_cf(cf_meta, crate::node::db_rocksdb::KEY_ACCOUNTS_TREE_TIP, to);
+    Ok(nodes.last().map(|n| n.2).unwrap_or([0u8; 32]))
+}
 
-        fees = fees.checked_add(tx.fee).ok_or(StateError::MathOverflow)?;
+/// Addresses in `bucket`, for ranging over a map keyed by address
+fn bucket_range(bucket: u16) -> std::ops::RangeInclusive<[u8; 32]> {
+    let mut lo = [0u8; 32];
+    lo[..2].copy_from_slice(&bucket.to_be_bytes());
+    let mut hi = [0xFFu8; 32];
+    hi[..2].copy_from_slice(&bucket.to_be_bytes());
+    lo..=hi
+}
 
-        let mut sender = get_account_local(&tx.sender_address, &account_updates, db);
-        let debit = tx.amount.checked_add(tx.fee).ok_or(StateError::MathOverflow)?;
+/// Merkle root of the account set at the tip (see `accounts_merkle_root`).
+///
+/// Stored with each connected or disconnected block, so normally a single
+/// read. After a snapshot load it is recomputed from the accounts; the next
+/// connected block stores it again.
+pub fn accounts_root(db: &ChainDB) -> Result<[u8; 32], StateError> {
+    let tip = db.get_tip()?;
+    if let (Some(stored), Some(tip)) = (db.get_accounts_root()?, tip)
+        && stored.tip == tip
+    {
+        return Ok(stored.root);
+    }
+    let accounts = db.iter_accounts()?;
+    Ok(accounts_merkle_root(accounts.iter().map(|(a, acc)| (a, acc))))
+}
 
-        if sender.balance < debit {
-            return Err(StateError::InsufficientBalance);
-        }
-        let want = sender.nonce + 1;
-        if tx.nonce != want {
-            return Err(StateError::InvalidNonce { expected: want, got: tx.nonce });
-        }
+/// Blocks per `reindex` write batch and progress line.
+const REINDEX_BATCH_BLOCKS: u32 = 1000;
 
-        sender.balance = sender.balance.checked_sub(debit).ok_or(StateError::MathOverflow)?;
-        sender.nonce = tx.nonce;
+/// What `reindex` rebuilt.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReindexStats {
+    /// Active-chain blocks walked for `tx_index` (0 with the index off).
+    pub blocks: u32,
+    /// Of those, blocks whose bodies were pruned and so had no txs to index.
+    pub pruned_blocks: u32,
+    pub txs_indexed: u64,
+    pub referral_entries: u64,
+    pub total_supply: u64,
+}
 
-        // Governance signaling (Move this BEFORE account_updates.insert)
-        if let Some(prop_hash) = tx.governance_data {
-            let mut vote_key = [0u8; 64];
-            vote_key[..32].copy_from_slice(&prop_hash);
-            vote_key[32..].copy_from_slice(&tx.sender_address);
-            
-            if !db.get_governance_vote_exists(&prop_hash, &tx.sender_address)? {
-                let current_tally = tally_updates.get(&prop_hash).cloned().unwrap_or_else(|| db.get_governance_tally(&prop_hash).unwrap_or(0));
-                let new_tally = current_tally.saturating_add(sender.governance_weight);
-                tally_updates.insert(prop_hash, new_tally);
-                vote_keys.push(vote_key);
-            }
-        }
+/// Rebuild the indices derived from the chain without touching accounts:
+/// `tx_index` (if enabled) from every active-chain block, `referral_index`
+/// from the accounts, and the running supply total.
+///
+/// Every write is a plain overwrite of the derived value, so an interrupted
+/// run leaves nothing inconsistent and can simply be started again.
+pub fn reindex(db: &ChainDB) -> Result<ReindexStats, StateError> {
+    let mut stats = ReindexStats::default();
+    let Some(tip_hash) = db.get_tip()? else {
+        return Ok(stats);
+    };
+    let tip = db.get_chain_height()?;
 
-        // Referral registration
-        if tx.nonce == 1 && let Some(ref_addr) = tx.referrer_address {
-            if sender.referrer.is_some() {
-                return Err(StateError::DuplicateReferrer);
+    if db.tx_index_enabled() {
+        let cf_tx_index = db.db.cf_handle("tx_index").ok_or(StateError::DatabaseError("tx_index CF not found".into()))?;
+        let mut batch = rocksdb::WriteBatch::default();
+        for h in 0..=tip {
+            let hash = db.get_block_hash_by_height(h)?
+                .ok_or_else(|| StateError::DatabaseError(format!("no block indexed at height {h}")))?;
+            if db.is_pruned(&hash)? {
+                stats.pruned_blocks += 1;
+            } else {
+                let block = db.get_block(&hash)?
+                    .ok_or_else(|| StateError::DatabaseError(format!("block {} missing", hex::encode(hash))))?;
+                let mut loc = [0u8; 36];
+                loc[..4].copy_from_slice(&block.block_height);
+                loc[4..].copy_from_slice(&hash);
+                for tx in &block.tx_data {
+                    batch.put_cf(cf_tx_index, crate::net::mempool::Mempool::compute_txid_from_stored(tx), loc);
+                    stats.txs_indexed += 1;
+                }
             }
-            if ref_addr == tx.sender_address {
-                return Err(StateError::SelfReferral);
+            stats.blocks += 1;
+            if stats.blocks % REINDEX_BATCH_BLOCKS == 0 || h == tip {
+                db.db.write(std::mem::take(&mut batch))?;
+                info!("[chain] reindex: tx_index {}/{} blocks", stats.blocks, tip + 1);
             }
-            sender.referrer = Some(ref_addr);
-            let mut upstream = get_account_local(&ref_addr, &account_updates, db);
-            upstream.total_referred_miners = upstream.total_referred_miners.checked_add(1).ok_or(StateError::MathOverflow)?;
-            upstream.governance_weight = calculate_governance_weight(upstream.total_referred_miners);
-            account_updates.insert(ref_addr, upstream);
         }
+    }
 
-        account_updates.insert(tx.sender_address, sender);
+    let cf_referral = db.db.cf_handle("referral_index").ok_or(StateError::DatabaseError("referral_index CF not found".into()))?;
+    let mut batch = rocksdb::WriteBatch::default();
+    for (addr, _) in db.iter_accounts()? {
+        batch.put_cf(cf_referral, &hash_sha3_256(&addr)[..8], addr);
+        stats.referral_entries += 1;
+    }
+    db.db.write(batch)?;
+    info!("[chain] reindex: {} referral entries", stats.referral_entries);
 
-        let mut recipient = get_account_local(&tx.recipient_address, &account_updates, db);
-        recipient.balance = recipient.balance.checked_add(tx.amount).ok_or(StateError::MathOverflow)?;
-        account_updates.insert(tx.recipient_address, recipient);
+    // A block connected meanwhile already stored its own total on top of
+    // the old one; only overwrite it if the tip is where we started.
+    stats.total_supply = recompute_total_supply(db)?;
+    if db.get_tip()? == Some(tip_hash) {
+        let cf_meta = db.db.cf_handle("meta").ok_or(StateError::DatabaseError("meta CF not found".into()))?;
+        db.db.put_cf(cf_meta, crate::node::db_rocksdb::KEY_TOTAL_SUPPLY, stats.total_supply.to_le_bytes())?;
     }
+    info!("[chain] reindex: total supply {} knots", stats.total_supply);
+    Ok(stats)
+}
 
-    // 5. Credit accumulated fees to miner
-    let mut miner_with_fees = account_updates.get(&block.miner_address).cloned().unwrap();
-    miner_with_fees.balance = miner_with_fees.balance.checked_add(fees).ok_or(StateError::MathOverflow)?;
-    account_updates.insert(block.miner_address, miner_with_fees);
+fn work_bytes(work: U256) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    work.to_big_endian(&mut out);
+    out
+}
 
-    // 5. Apply all updates atomically using RocksDB batch
-    // Collect all updates
+/// Store a block that does not extend the tip, then switch to its branch if
+/// it has strictly more work. Ties keep the first-seen chain.
+fn accept_side_block(db: &ChainDB, block: &StoredBlock) -> Result<(), StateError> {
     let hash = block_hash(block);
-    
-    // Apply everything in one atomic batch
-    let mut batch = rocksdb::WriteBatch::default();
-    
-    // Get column family handles
-    let cf_blocks = db.db.cf_handle("blocks").ok_or(StateError::DatabaseError("blocks CF not found".into()))?;
-    let cf_heights = db.db.cf_handle("heights").ok_or(StateError::DatabaseError("heights CF not found".into()))?;
-    let cf_accounts = db.db.cf_handle("accounts").ok_or(StateError::DatabaseError("accounts CF not found".into()))?;
-    let cf_referral = db.db.cf_handle("referral_index").ok_or(StateError::DatabaseError("referral_index CF not found".into()))?;
-    let cf_tallies = db.db.cf_handle("gov_tallies").ok_or(StateError::DatabaseError("gov_tallies CF not found".into()))?;
-    let cf_votes = db.db.cf_handle("gov_votes").ok_or(StateError::DatabaseError("gov_votes CF not found".into()))?;
-    let cf_meta = db.db.cf_handle("meta").ok_or(StateError::DatabaseError("meta CF not found".into()))?;
-    
-    // Add block and height
-    batch.put_cf(cf_blocks, &hash, block.to_bytes());
-    batch.put_cf(cf_heights, &block.block_height, &hash);
-    
-    // Add accounts and referral index
-    for (addr, state) in account_updates {
-        batch.put_cf(cf_accounts, &addr, state.to_bytes());
-        let h = crate::crypto::hash::hash_sha3_256(&addr);
-        batch.put_cf(cf_referral, &h[..8], &addr);
+    if db.get_block(&hash)?.is_some() {
+        return Ok(());
     }
-    
-    // Add governance tallies
-    for (prop, tally) in tally_updates {
-        batch.put_cf(cf_tallies, &prop, &tally.to_le_bytes());
+    let parent = db.get_block(&block.previous_hash)?.ok_or(StateError::UnknownParent)?;
+    let expected = u32::from_le_bytes(parent.block_height) + 1;
+    let got = u32::from_le_bytes(block.block_height);
+    if got != expected {
+        return Err(StateError::InvalidHeight { expected, got });
+    }
+
+    // Full validation needs the branch's state and happens when (if) it is
+    // connected; PoW is checked now so junk can't accumulate work for free.
+    // Where the branch's own governance leaves the rounds unknown, the block
+    // must pass with one of the counts that governance could have enacted;
+    // connecting it checks the exact one.
+    let rounds = pow_rounds_after(db, &block.previous_hash)?;
+    match rounds {
+        Some(rounds) => verify_block_pow_with_rounds(block, db, rounds)?,
+        None => {
+            let candidates = possible_pow_rounds(db, &block.previous_hash)?;
+            if candidates.is_empty() || candidates.len() > MAX_POW_ROUNDS_CANDIDATES {
+                return Err(StateError::PowRoundsUnknown);
+            }
+            let mut result = Err(StateError::InvalidPoW);
+            for rounds in candidates {
+                result = verify_block_pow_with_rounds(block, db, rounds);
+                if result.is_ok() {
+                    break;
+                }
+            }
+            result?;
+        }
+    }
+
+    let work = chain_work(db, &block.previous_hash)?.saturating_add(block_work(&block.difficulty_target));
+    let cf_blocks = db.db.cf_handle("blocks").ok_or(StateError::DatabaseError("blocks CF not found".into()))?;
+    let cf_work = db.db.cf_handle("work").ok_or(StateError::DatabaseError("work CF not found".into()))?;
+    let mut batch = rocksdb::WriteBatch::default();
+    batch.put_cf(cf_blocks, hash, block.to_bytes());
+    batch.put_cf(cf_work, hash, work_bytes(work));
+    // Without governance txs it leaves the rounds as it found them, which
+    // keeps `pow_rounds_after` short on long branches.
+    if let Some(rounds) = rounds
+        && !has_governance_txs(block)
+    {
+        let cf_pow_rounds = db.db.cf_handle("pow_rounds").ok_or(StateError::DatabaseError("pow_rounds CF not found".into()))?;
+        batch.put_cf(cf_pow_rounds, hash, rounds.to_le_bytes());
+    }
+    db.db.write(batch)?;
+
+    let tip = db.get_tip()?.ok_or(StateError::DatabaseError("no chain tip".into()))?;
+    if work > chain_work(db, &tip)? {
+        reorganize_to(db, &hash)?;
+    }
+    Ok(())
+}
+
+/// Most blocks a reorg may disconnect (~48h of one-minute blocks). A branch
+/// forking deeper than this is invalid however much work it has, so older
+/// blocks are final.
+pub const MAX_REORG_DEPTH: u32 = 2880;
+
+/// Drop stored side-branch blocks and their work entries.
+fn forget_blocks(db: &ChainDB, blocks: &[StoredBlock]) -> Result<(), StateError> {
+    let cf_blocks = db.db.cf_handle("blocks").ok_or(StateError::DatabaseError("blocks CF not found".into()))?;
+    let cf_work = db.db.cf_handle("work").ok_or(StateError::DatabaseError("work CF not found".into()))?;
+    let mut batch = rocksdb::WriteBatch::default();
+    for b in blocks {
+        let h = block_hash(b);
+        batch.delete_cf(cf_blocks, h);
+        batch.delete_cf(cf_work, h);
+    }
+    db.db.write(batch)?;
+    Ok(())
+}
+
+/// Switch the active chain to the branch ending at `new_tip`.
+///
+/// Walks `new_tip` back to the first ancestor on the active chain, disconnects
+/// the active blocks above it (restoring account, nonce and governance state
+/// from their undo records), then connects the new branch in order. If a
+/// branch block fails validation the old chain is restored, the invalid
+/// block and its descendants are forgotten, and the error is returned. A
+/// branch forking more than `MAX_REORG_DEPTH` blocks below the tip is
+/// forgotten without touching the active chain.
+///
+/// Callers hold `db.chain_lock`; `apply_block*` take it before getting here.
+pub fn reorganize_to(db: &ChainDB, new_tip: &[u8; 32]) -> Result<(), StateError> {
+    let old_tip = db.get_tip()?.ok_or(StateError::DatabaseError("no chain tip".into()))?;
+    if old_tip == *new_tip {
+        return Ok(());
+    }
+
+    let mut branch = Vec::new();
+    let mut cur = *new_tip;
+    let ancestor_height = loop {
+        let b = db.get_block(&cur)?.ok_or(StateError::UnknownParent)?;
+        let h = u32::from_le_bytes(b.block_height);
+        if db.get_block_hash_by_height(h)? == Some(cur) {
+            break h;
+        }
+        if h == 0 {
+            return Err(StateError::DatabaseError("branch does not share our genesis".into()));
+        }
+        cur = b.previous_hash;
+        branch.push(b);
+    };
+    branch.reverse();
+
+    let depth = db.get_chain_height()?.saturating_sub(ancestor_height);
+    if depth > MAX_REORG_DEPTH {
+        forget_blocks(db, &branch)?;
+        warn!("[chain] refusing reorg of {depth} blocks to fork at height {ancestor_height}");
+        return Err(StateError::ReorgTooDeep { depth });
+    }
+
+    let mut disconnected = Vec::new();
+    while db.get_chain_height()? > ancestor_height {
+        disconnected.push(disconnect_tip(db)?);
+    }
+
+    for (i, b) in branch.iter().enumerate() {
+        if let Err(e) = connect_block(db, b, None, MAX_FUTURE_BLOCK_SECS) {
+            for _ in 0..i {
+                disconnect_tip(db)?;
+            }
+            for old in disconnected.iter().rev() {
+                connect_block(db, old, None, MAX_FUTURE_BLOCK_SECS)?;
+            }
+            forget_blocks(db, &branch[i..])?;
+            warn!(
+                "[chain] reorg aborted at height {}: {e}",
+                u32::from_le_bytes(b.block_height)
+            );
+            return Err(e);
+        }
+    }
+
+    info!(
+        "[chain] reorg: {} block(s) disconnected, {} connected, fork at height {}",
+        disconnected.len(),
+        branch.len(),
+        ancestor_height
+    );
+    Ok(())
+}
+
+/// Undo the tip block's state changes and make its parent the tip. The block
+/// itself stays stored (with its work) as a side branch.
+fn disconnect_tip(db: &ChainDB) -> Result<StoredBlock, StateError> {
+    let tip = db.get_tip()?.ok_or(StateError::DatabaseError("no chain tip".into()))?;
+    let block = db.get_block(&tip)?.ok_or(StateError::DatabaseError("tip block missing".into()))?;
+    if u32::from_le_bytes(block.block_height) == 0 {
+        return Err(StateError::DatabaseError("cannot disconnect genesis".into()));
+    }
+    let undo = db
+        .get_block_undo(&tip)?
+        .ok_or(StateError::DatabaseError("no undo data for block (connected before reorg support)".into()))?;
+
+    let cf_heights = db.db.cf_handle("heights").ok_or(StateError::DatabaseError("heights CF not found".into()))?;
+    let cf_accounts = db.db.cf_handle("accounts").ok_or(StateError::DatabaseError("accounts CF not found".into()))?;
+    let cf_referral = db.db.cf_handle("referral_index").ok_or(StateError::DatabaseError("referral_index CF not found".into()))?;
+    let cf_tallies = db.db.cf_handle("gov_tallies").ok_or(StateError::DatabaseError("gov_tallies CF not found".into()))?;
+    let cf_votes = db.db.cf_handle("gov_votes").ok_or(StateError::DatabaseError("gov_votes CF not found".into()))?;
+    let cf_undo = db.db.cf_handle("undo").ok_or(StateError::DatabaseError("undo CF not found".into()))?;
+    let cf_meta = db.db.cf_handle("meta").ok_or(StateError::DatabaseError("meta CF not found".into()))?;
+    let cf_proposals = db.db.cf_handle("gov_proposals").ok_or(StateError::DatabaseError("gov_proposals CF not found".into()))?;
+
+    let mut batch = rocksdb::WriteBatch::default();
+    let changes: BTreeMap<[u8; 32], Option<AccountState>> = undo.accounts.iter().cloned().collect();
+    let mut accounts_root_entry = [0u8; 64];
+    accounts_root_entry[..32].copy_from_slice(&update_accounts_tree(db, &mut batch, &block.previous_hash, &changes)?);
+    accounts_root_entry[32..].copy_from_slice(&block.previous_hash);
+    for (addr, prev) in undo.accounts {
+        match prev {
+            Some(state) => batch.put_cf(cf_accounts, addr, state.to_bytes()),
+            None => {
+                batch.delete_cf(cf_accounts, addr);
+                let h = hash_sha3_256(&addr);
+                batch.delete_cf(cf_referral, &h[..8]);
+            }
+        }
+    }
+    for (prop, tally) in undo.tallies {
+        if tally == 0 {
+            batch.delete_cf(cf_tallies, prop);
+        } else {
+            batch.put_cf(cf_tallies, prop, tally.to_le_bytes());
+        }
+    }
+    for vkey in undo.votes {
+        batch.delete_cf(cf_votes, vkey);
+    }
+    for prop in undo.proposals {
+        batch.delete_cf(cf_proposals, prop);
+    }
+    if let Some(params) = undo.gov_params {
+        batch.put_cf(cf_meta, crate::node::db_rocksdb::KEY_GOV_PARAMS, params.to_bytes());
+    }
+    match undo.total_supply {
+        Some(supply) => batch.put_cf(cf_meta, crate::node::db_rocksdb::KEY_TOTAL_SUPPLY, supply.to_le_bytes()),
+        // Connected before the total was kept; rebuilt on the next read
+        None => batch.delete_cf(cf_meta, crate::node::db_rocksdb::KEY_TOTAL_SUPPLY),
+    }
+    batch.put_cf(cf_meta, crate::node::db_rocksdb::KEY_ACCOUNTS_ROOT, accounts_root_entry);
+    if db.tx_index_enabled() {
+        let cf_tx_index = db.db.cf_handle("tx_index").ok_or(StateError::DatabaseError("tx_index CF not found".into()))?;
+        for tx in &block.tx_data {
+            batch.delete_cf(cf_tx_index, crate::net::mempool::Mempool::compute_txid_from_stored(tx));
+        }
+    }
+    batch.delete_cf(cf_heights, block.block_height);
+    batch.delete_cf(cf_undo, tip);
+    batch.put_cf(cf_meta, crate::node::db_rocksdb::KEY_TIP, block.previous_hash);
+
+    let mut write_opts = rocksdb::WriteOptions::default();
+    write_opts.set_sync(true);
+    db.db.write_opt(batch, &write_opts)?;
+    Ok(block)
+}
+
+/// Validate `block` against the current tip state and make it the new tip.
+fn connect_block(db: &ChainDB, block: &StoredBlock, pending_referrer: Option<[u8; 32]>, max_future_secs: u32) -> Result<(), StateError> {
+    let height = u32::from_le_bytes(block.block_height) as u64;
+
+    if db.get_tip()?.is_some() {
+        let expected = db.get_chain_height()? + 1;
+        if height as u32 != expected {
+            return Err(StateError::InvalidHeight { expected, got: height as u32 });
+        }
+    }
+
+    // 0. Verify Timestamp (MTP + Future Limit)
+    validate_block_timestamp_within(db, block, max_future_secs)?;
+
+    // 1. Verify difficulty follows the retarget schedule, then PoW against it
+    if height > 0 && block.difficulty_target != next_difficulty_target(db, height as u32)? {
+        return Err(StateError::InvalidDifficulty);
+    }
+    verify_block_pow(block, db)?;
+
+    // 2. Calculate Rewards
+    let base_reward = calculate_block_reward(height);
+
+    let mut account_updates: std::collections::HashMap<[u8; 32], crate::node::db_common::AccountState> = std::collections::HashMap::new();
+    let mut tally_updates: std::collections::HashMap<[u8; 32], u64> = std::collections::HashMap::new();
+    let mut vote_keys = Vec::new();
+    let mut new_proposals: Vec<([u8; 32], crate::consensus::governance::StoredProposal)> = Vec::new();
+
+    // Accounts are matured as they are first loaded, so rewards that reach
+    // COINBASE_MATURITY at this height are spendable by its transactions.
+    let get_account_local = |addr: &[u8; 32], updates: &std::collections::HashMap<[u8; 32], crate::node::db_common::AccountState>, db: &ChainDB| -> Result<crate::node::db_common::AccountState, StateError> {
+        if let Some(acc) = updates.get(addr) {
+            return Ok(acc.clone());
+        }
+        let mut acc = db.get_account(addr).unwrap_or_default();
+        acc.mature(height).ok_or(StateError::MathOverflow)?;
+        Ok(acc)
+    };
+
+    // Credit base reward to miner first
+    let mut miner_acc = get_account_local(&block.miner_address, &account_updates, db)?;
+    miner_acc.credit_immature(height, base_reward).ok_or(StateError::MathOverflow)?;
+    miner_acc.last_mined_height = height;
+    miner_acc.total_blocks_mined = miner_acc.total_blocks_mined.saturating_add(1);
+    miner_acc.governance_weight = calculate_governance_weight(miner_acc.total_blocks_mined);
+
+    // Auto-register referrer on first block mined (if pending_referrer provided and no referrer set yet)
+    if miner_acc.referrer.is_none() && miner_acc.total_blocks_mined == 1 {
+        if let Some(ref_addr) = pending_referrer {
+            if ref_addr != block.miner_address {
+                miner_acc.referrer = Some(ref_addr);
+                let mut upstream = get_account_local(&ref_addr, &account_updates, db)?;
+                upstream.total_referred_miners = upstream.total_referred_miners.saturating_add(1);
+                upstream.governance_weight = calculate_governance_weight(upstream.total_referred_miners);
+                account_updates.insert(ref_addr, upstream);
+                info!("[referral] Auto-registered referrer for new miner");
+            }
+        }
+    }
+
+    // Referral bonus
+    let mut minted = base_reward;
+    if let Some(ref_addr) = miner_acc.referrer {
+        let mut referrer = get_account_local(&ref_addr, &account_updates, db)?;
+        let bonus = calculate_referral_bonus(base_reward, referrer.total_blocks_mined, referrer.last_mined_height, height);
+        if bonus > 0 {
+            referrer.credit_immature(height, bonus).ok_or(StateError::MathOverflow)?;
+            referrer.total_referral_bonus_earned = referrer.total_referral_bonus_earned.checked_add(bonus).ok_or(StateError::MathOverflow)?;
+            referrer.governance_weight = calculate_governance_weight(referrer.total_referred_miners);
+            account_updates.insert(ref_addr, referrer);
+            minted = minted.checked_add(bonus).ok_or(StateError::MathOverflow)?;
+        }
+    }
+    account_updates.insert(block.miner_address, miner_acc);
+
+    // One sender's nonce chain may not fill the block.
+    let mut per_sender: std::collections::HashMap<[u8; 32], usize> = std::collections::HashMap::new();
+    for tx in &block.tx_data {
+        let n = per_sender.entry(tx.sender_address).or_default();
+        *n += 1;
+        if *n > MAX_TXS_PER_SENDER {
+            return Err(StateError::TooManyTxsPerSender);
+        }
+    }
+
+    let mut fees = 0u64;
+    let mut seen_txids = std::collections::HashSet::new();
+    let supply_before = total_supply(db)?;
+    // What exists once this block's reward is minted; no tx may move more.
+    let issued = supply_before.checked_add(minted).ok_or(StateError::MathOverflow)?;
+    
+    // Signatures don't depend on state, so check them all up front, in parallel;
+    // the loop below only does the cheap structural checks.
+    let domain_txs = block.tx_data.iter()
+        .map(Transaction::try_from)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(StateError::InvalidTransaction)?;
+    verify_signatures(&domain_txs)?;
+
+    for (tx, domain_tx) in block.tx_data.iter().zip(&domain_txs) {
+        check_amounts(tx, issued)?;
+        if !domain_tx.has_valid_structure() {
+            return Err(StateError::InvalidTransaction("structural or signature failure"));
+        }
+
+        // Check for duplicate TXIDs within this block
+        let txid = domain_tx.txid();
+        if !seen_txids.insert(txid) {
+            return Err(StateError::InvalidTransaction("duplicate transaction in block"));
+        }
+
+        fees = fees.checked_add(tx.fee).ok_or(StateError::MathOverflow)?;
+
+        let mut sender = get_account_local(&tx.sender_address, &account_updates, db)?;
+        check_sender(&sender, tx)?;
+        let debit = tx.amount + tx.fee;
+
+        sender.balance = sender.balance.checked_sub(debit).ok_or(StateError::MathOverflow)?;
+        sender.nonce = tx.nonce;
+
+        // Governance signaling (Move this BEFORE account_updates.insert)
+        if let Some(prop_hash) = tx.governance_data {
+            let mut vote_key = [0u8; 64];
+            vote_key[..32].copy_from_slice(&prop_hash);
+            vote_key[32..].copy_from_slice(&tx.sender_address);
+            
+            if !db.get_governance_vote_exists(&prop_hash, &tx.sender_address)? {
+                let current_tally = tally_updates.get(&prop_hash).cloned().unwrap_or_else(|| db.get_governance_tally(&prop_hash).unwrap_or(0));
+                let new_tally = current_tally.saturating_add(sender.governance_weight);
+                tally_updates.insert(prop_hash, new_tally);
+                vote_keys.push(vote_key);
+            }
+        }
+
+        // Proposal publication: first inclusion of a proposal wins
+        if let Some(raw) = &tx.proposal {
+            let proposal = crate::consensus::governance::Proposal::from_bytes(raw)
+                .map_err(StateError::InvalidTransaction)?;
+            let prop_hash = proposal.hash();
+            if db.get_governance_proposal(&prop_hash)?.is_none()
+                && !new_proposals.iter().any(|(h, _)| *h == prop_hash)
+            {
+                new_proposals.push((prop_hash, crate::consensus::governance::StoredProposal {
+                    proposal,
+                    proposer: tx.sender_address,
+                    height: height as u32,
+                }));
+            }
+        }
+
+        // Referral registration
+        if tx.nonce == 1 && let Some(ref_addr) = tx.referrer_address {
+            sender.referrer = Some(ref_addr);
+            let mut upstream = get_account_local(&ref_addr, &account_updates, db)?;
+            upstream.total_referred_miners = upstream.total_referred_miners.checked_add(1).ok_or(StateError::MathOverflow)?;
+            upstream.governance_weight = calculate_governance_weight(upstream.total_referred_miners);
+            account_updates.insert(ref_addr, upstream);
+        }
+
+        account_updates.insert(tx.sender_address, sender);
+
+        let mut recipient = get_account_local(&tx.recipient_address, &account_updates, db)?;
+        recipient.balance = recipient.balance.checked_add(tx.amount).ok_or(StateError::MathOverflow)?;
+        account_updates.insert(tx.recipient_address, recipient);
+    }
+
+    // 5. Credit accumulated fees to miner
+    let mut miner_with_fees = account_updates.get(&block.miner_address).cloned().unwrap();
+    miner_with_fees.credit_immature(height, fees).ok_or(StateError::MathOverflow)?;
+    account_updates.insert(block.miner_address, miner_with_fees);
+
+    // 6. Enact proposals that became passed in this block: published and at
+    // or over the threshold now, but not both before. Re-applying the block
+    // after a reorg sees the restored state and enacts exactly once again.
+    let mut touched: Vec<[u8; 32]> = tally_updates.keys().copied()
+        .chain(new_proposals.iter().map(|(h, _)| *h))
+        .collect();
+    touched.sort_unstable();
+    touched.dedup();
+    let mut gov_params = db.get_governance_params()?;
+    let mut enacted = false;
+    for prop in touched {
+        let published_before = db.get_governance_proposal(&prop)?;
+        let tally_before = db.get_governance_tally(&prop)?;
+        let tally_after = tally_updates.get(&prop).copied().unwrap_or(tally_before);
+        let was_passed = published_before.is_some() && tally_before >= GOVERNANCE_THRESHOLD_BPS;
+        let proposal = match published_before {
+            Some(stored) => Some(stored.proposal),
+            None => new_proposals.iter().find(|(h, _)| *h == prop).map(|(_, s)| s.proposal.clone()),
+        };
+        if let Some(proposal) = proposal
+            && !was_passed
+            && tally_after >= GOVERNANCE_THRESHOLD_BPS
+        {
+            proposal.enact(&mut gov_params);
+            enacted = true;
+            info!(
+                "[governance] proposal {} passed at height {height}: {} = {}",
+                hex::encode(&prop[..8]),
+                proposal.param.name(),
+                proposal.value
+            );
+        }
+    }
+
+    // 7. Apply all updates atomically using RocksDB batch
+    // Collect all updates
+    let hash = block_hash(block);
+    
+    // Apply everything in one atomic batch
+    let mut batch = rocksdb::WriteBatch::default();
+    
+    // Get column family handles
+    let cf_blocks = db.db.cf_handle("blocks").ok_or(StateError::DatabaseError("blocks CF not found".into()))?;
+    let cf_heights = db.db.cf_handle("heights").ok_or(StateError::DatabaseError("heights CF not found".into()))?;
+    let cf_accounts = db.db.cf_handle("accounts").ok_or(StateError::DatabaseError("accounts CF not found".into()))?;
+    let cf_referral = db.db.cf_handle("referral_index").ok_or(StateError::DatabaseError("referral_index CF not found".into()))?;
+    let cf_tallies = db.db.cf_handle("gov_tallies").ok_or(StateError::DatabaseError("gov_tallies CF not found".into()))?;
+    let cf_votes = db.db.cf_handle("gov_votes").ok_or(StateError::DatabaseError("gov_votes CF not found".into()))?;
+    let cf_meta = db.db.cf_handle("meta").ok_or(StateError::DatabaseError("meta CF not found".into()))?;
+    let cf_work = db.db.cf_handle("work").ok_or(StateError::DatabaseError("work CF not found".into()))?;
+    let cf_undo = db.db.cf_handle("undo").ok_or(StateError::DatabaseError("undo CF not found".into()))?;
+    let cf_proposals = db.db.cf_handle("gov_proposals").ok_or(StateError::DatabaseError("gov_proposals CF not found".into()))?;
+    
+    // Add block and height
+    batch.put_cf(cf_blocks, &hash, block.to_bytes());
+    batch.put_cf(cf_heights, &block.block_height, &hash);
+    
+    // Cumulative work for fork choice
+    let work = chain_work(db, &block.previous_hash)?.saturating_add(block_work(&block.difficulty_target));
+    batch.put_cf(cf_work, hash, work_bytes(work));
+    
+    // Record what this block overwrites so a reorg can disconnect it
+    let mut undo = BlockUndo::default();
+    for addr in account_updates.keys() {
+        undo.accounts.push((*addr, db.get_account_if_exists(addr)?));
+    }
+    for prop in tally_updates.keys() {
+        undo.tallies.push((*prop, db.get_governance_tally(prop)?));
+    }
+    undo.votes = vote_keys.clone();
+    undo.proposals = new_proposals.iter().map(|(h, _)| *h).collect();
+    if enacted {
+        undo.gov_params = Some(db.get_governance_params()?);
+    }
+    undo.total_supply = Some(supply_before);
+    batch.put_cf(cf_undo, hash, undo.to_bytes());
+    
+    // Accounts root as this block leaves it, updating only the touched paths
+    let changes: BTreeMap<[u8; 32], Option<AccountState>> =
+        account_updates.iter().map(|(a, s)| (*a, Some(s.clone()))).collect();
+    let mut accounts_root_entry = [0u8; 64];
+    accounts_root_entry[..32].copy_from_slice(&update_accounts_tree(db, &mut batch, &hash, &changes)?);
+    accounts_root_entry[32..].copy_from_slice(&hash);
+
+    // Add accounts and referral index
+    for (addr, state) in account_updates {
+        batch.put_cf(cf_accounts, &addr, state.to_bytes());
+        let h = crate::crypto::hash::hash_sha3_256(&addr);
+        batch.put_cf(cf_referral, &h[..8], &addr);
+    }
+    
+    // Add governance tallies
+    for (prop, tally) in tally_updates {
+        batch.put_cf(cf_tallies, &prop, &tally.to_le_bytes());
     }
     
     // Add vote records
     for vkey in vote_keys {
         batch.put_cf(cf_votes, &vkey, &[1u8]);
     }
+
+    // Add newly published proposals
+    for (prop, stored) in new_proposals {
+        batch.put_cf(cf_proposals, prop, stored.to_bytes());
+    }
+
+    // Parameter changes from passed proposals
+    if enacted {
+        batch.put_cf(cf_meta, crate::node::db_rocksdb::KEY_GOV_PARAMS, gov_params.to_bytes());
+    }
+    let cf_pow_rounds = db.db.cf_handle("pow_rounds").ok_or(StateError::DatabaseError("pow_rounds CF not found".into()))?;
+    batch.put_cf(cf_pow_rounds, hash, gov_params.ponc_rounds.to_le_bytes());
     
+    // txid -> confirming block, if the node keeps a tx index
+    if db.tx_index_enabled() {
+        let cf_tx_index = db.db.cf_handle("tx_index").ok_or(StateError::DatabaseError("tx_index CF not found".into()))?;
+        let mut loc = [0u8; 36];
+        loc[..4].copy_from_slice(&block.block_height);
+        loc[4..].copy_from_slice(&hash);
+        for tx in &block.tx_data {
+            batch.put_cf(cf_tx_index, crate::net::mempool::Mempool::compute_txid_from_stored(tx), loc);
+        }
+    }
+
+    // Running total of minted coins
+    let supply = supply_before.checked_add(minted).ok_or(StateError::MathOverflow)?;
+    batch.put_cf(cf_meta, crate::node::db_rocksdb::KEY_TOTAL_SUPPLY, supply.to_le_bytes());
+
+    // Accounts root after this block, for `getaccountsroot`
+    batch.put_cf(cf_meta, crate::node::db_rocksdb::KEY_ACCOUNTS_ROOT, accounts_root_entry);
+
     // Update tip
     batch.put_cf(cf_meta, crate::node::db_rocksdb::KEY_TIP, &hash);
     
@@ -325,6 +1356,75 @@ pub fn apply_block_with_referrer(db: &ChainDB, block: &StoredBlock, pending_refe
     Ok(())
 }
 
+/// Verify every transaction's signature across the rayon pool.
+fn verify_signatures(txs: &[Transaction]) -> Result<(), StateError> {
+    use rayon::prelude::*;
+    if txs.par_iter().all(Transaction::has_valid_signature) {
+        Ok(())
+    } else {
+        Err(StateError::InvalidTransaction("structural or signature failure"))
+    }
+}
+
+/// Amount and fee bounds that hold whatever the sender's state: a fee of at
+/// least `MIN_FEE_KNOTS`, no debit larger than the `supply` issued up to and
+/// including the block carrying `tx`, and no empty payment to another address.
+fn check_amounts(tx: &StoredTransaction, supply: u64) -> Result<(), StateError> {
+    if tx.amount == 0 && tx.fee == 0 && tx.recipient_address != tx.sender_address {
+        return Err(StateError::EmptyTransaction);
+    }
+    if tx.fee < MIN_FEE_KNOTS {
+        return Err(StateError::FeeTooLow { fee: tx.fee });
+    }
+    let debit = tx.amount.checked_add(tx.fee).ok_or(StateError::MathOverflow)?;
+    if debit > supply {
+        return Err(StateError::AmountExceedsSupply { debit, supply });
+    }
+    Ok(())
+}
+
+/// Balance, nonce and referral rules for `tx` given its sender's state.
+fn check_sender(sender: &AccountState, tx: &StoredTransaction) -> Result<(), StateError> {
+    let debit = tx.amount.checked_add(tx.fee).ok_or(StateError::MathOverflow)?;
+    if sender.balance < debit {
+        return Err(StateError::InsufficientBalance);
+    }
+    let want = sender.nonce + 1;
+    if tx.nonce != want {
+        return Err(StateError::InvalidNonce { expected: want, got: tx.nonce });
+    }
+    if tx.nonce == 1 && let Some(ref_addr) = tx.referrer_address {
+        if sender.referrer.is_some() {
+            return Err(StateError::DuplicateReferrer);
+        }
+        if ref_addr == tx.sender_address {
+            return Err(StateError::SelfReferral);
+        }
+    }
+    Ok(())
+}
+
+/// Check `tx` the way `connect_block` would if it were mined next, without
+/// writing anything. `pending_nonce` is the sender's highest nonce waiting in
+/// the mempool: a tx that follows or replaces one of those is checked as if
+/// the ones before it had been mined.
+pub fn check_transaction(db: &ChainDB, tx: &StoredTransaction, pending_nonce: Option<u64>) -> Result<(), StateError> {
+    let next_reward = calculate_block_reward(db.get_chain_height()? as u64 + 1);
+    check_amounts(tx, total_supply(db)?.saturating_add(next_reward))?;
+    let domain_tx = Transaction::try_from(tx).map_err(StateError::InvalidTransaction)?;
+    if !domain_tx.is_structurally_valid() {
+        return Err(StateError::InvalidTransaction("structural or signature failure"));
+    }
+    let mut sender = db.get_spendable_account(&tx.sender_address)?;
+    if let Some(pending) = pending_nonce
+        && tx.nonce > sender.nonce + 1
+        && tx.nonce <= pending + 1
+    {
+        sender.nonce = tx.nonce - 1;
+    }
+    check_sender(&sender, tx)
+}
+
 pub fn block_hash(block: &StoredBlock) -> [u8; 32] {
     hash_sha3_256(&block.header_bytes())
 }
@@ -337,7 +1437,7 @@ mod tests {
     use super::*;
     use crate::node::db_common::StoredBlock;
     use std::path::PathBuf;
-    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::atomic::AtomicU64;
 
     static CTR: AtomicU64 = AtomicU64::new(0);
 
@@ -345,7 +1445,8 @@ mod tests {
         let id = CTR.fetch_add(1, Ordering::SeqCst);
         let p = PathBuf::from(format!("/tmp/knot_state_{}_{}", std::process::id(), id));
         let _ = std::fs::remove_dir_all(&p);
-        ChainDB::open(&p).unwrap()
+        // Testnet: mainnet retargeting, but no floor under the trivial test targets.
+        ChainDB::open(&p).unwrap().with_network(Network::Testnet)
     }
 
     #[test]
@@ -365,7 +1466,8 @@ mod tests {
         };
         apply_block(&db, &block).unwrap();
         let s = db.get_account(&miner).unwrap();
-        assert_eq!(s.balance, 10_000_000); // block 0 reward = 0.1 KOT (10M Knots)
+        assert_eq!(s.balance, 0);
+        assert_eq!(s.immature_balance(), 10_000_000); // block 0 reward = 0.1 KOT (10M Knots)
         assert_eq!(s.last_mined_height, 0);
     }
 
@@ -461,4 +1563,949 @@ mod tests {
         
         assert_ne!(block_hash(&block1), block_hash(&block2));
     }
+
+    fn chain_block(prev: &StoredBlock, height: u32, timestamp: u32) -> StoredBlock {
+        StoredBlock {
+            version: [0, 0, 0, 1],
+            previous_hash: block_hash(prev),
+            merkle_root: [0u8; 32],
+            timestamp: timestamp.to_le_bytes(),
+            difficulty_target: [0xFF; 32],
+            nonce: [height as u8; 8],
+            block_height: height.to_le_bytes(),
+            miner_address: [0x03u8; 32],
+            tx_data: vec![],
+        }
+    }
+
+    /// Chain with timestamps 0, 100, 200, 300: MTP for height 4 is 200, parent is 300.
+    fn skewed_chain() -> (ChainDB, StoredBlock) {
+        let db = tmp();
+        let mut tip = StoredBlock {
+            version: [0, 0, 0, 1],
+            previous_hash: [0u8; 32],
+            merkle_root: [0u8; 32],
+            timestamp: 0u32.to_le_bytes(),
+            difficulty_target: [0xFF; 32],
+            nonce: [0u8; 8],
+            block_height: 0u32.to_le_bytes(),
+            miner_address: [0x03u8; 32],
+            tx_data: vec![],
+        };
+        apply_block(&db, &tip).unwrap();
+        for h in 1..=3u32 {
+            let b = chain_block(&tip, h, h * 100);
+            apply_block(&db, &b).unwrap();
+            tip = b;
+        }
+        (db, tip)
+    }
+
+    #[test]
+    fn test_median_time_past_uses_median_not_parent() {
+        let (db, _) = skewed_chain();
+        assert_eq!(median_time_past(&db, 4), Some(200));
+        assert_eq!(median_time_past(&db, 0), None);
+    }
+
+    #[test]
+    fn test_block_time_equal_to_mtp_rejected() {
+        let (db, tip) = skewed_chain();
+        let b = chain_block(&tip, 4, 200);
+        assert!(matches!(apply_block(&db, &b), Err(StateError::BlockInPast)));
+    }
+
+    #[test]
+    fn test_block_time_equal_to_parent_accepted() {
+        let (db, tip) = skewed_chain();
+        let b = chain_block(&tip, 4, 300);
+        apply_block(&db, &b).unwrap();
+        assert_eq!(db.get_chain_height().unwrap(), 4);
+    }
+
+    #[test]
+    fn test_time_offset_is_clamped() {
+        assert_eq!(clamp_time_offset(600), 600);
+        assert_eq!(clamp_time_offset(-600), -600);
+        assert_eq!(clamp_time_offset(5 * 60 * 60), MAX_TIME_ADJUSTMENT_SECS);
+        assert_eq!(clamp_time_offset(-5 * 60 * 60), -MAX_TIME_ADJUSTMENT_SECS);
+        assert_eq!(offset_time(1_000_000, 600), 1_000_600);
+        assert_eq!(offset_time(1_000_000, -600), 999_400);
+        assert_eq!(offset_time(100, -600), 0);
+    }
+
+    #[test]
+    fn test_validate_timestamp_exactly_at_mtp() {
+        let (db, tip) = skewed_chain();
+        let b = chain_block(&tip, 4, 200);
+        assert!(matches!(validate_block_timestamp(&db, &b), Err(StateError::BlockInPast)));
+        // The IBD bound only relaxes the future side
+        assert!(matches!(
+            validate_block_timestamp_within(&db, &b, IBD_MAX_FUTURE_BLOCK_SECS),
+            Err(StateError::BlockInPast)
+        ));
+    }
+
+    #[test]
+    fn test_validate_timestamp_one_second_after_mtp() {
+        let (db, tip) = skewed_chain();
+        let b = chain_block(&tip, 4, 201);
+        validate_block_timestamp(&db, &b).unwrap();
+    }
+
+    #[test]
+    fn test_validate_timestamp_three_hours_ahead() {
+        let (db, tip) = skewed_chain();
+        let b = chain_block(&tip, 4, unix_now() + 3 * 60 * 60);
+        assert!(matches!(validate_block_timestamp(&db, &b), Err(StateError::BlockTooFarInFuture)));
+        validate_block_timestamp_within(&db, &b, IBD_MAX_FUTURE_BLOCK_SECS).unwrap();
+
+        assert!(matches!(apply_block(&db, &b), Err(StateError::BlockTooFarInFuture)));
+        apply_block_with_time_bound(&db, &b, IBD_MAX_FUTURE_BLOCK_SECS).unwrap();
+        assert_eq!(db.get_chain_height().unwrap(), 4);
+    }
+
+    #[test]
+    fn test_stale_tip_is_initial_block_download() {
+        let (db, tip) = skewed_chain();
+        assert!(is_initial_block_download(&db));
+        apply_block(&db, &chain_block(&tip, 4, unix_now())).unwrap();
+        assert!(!is_initial_block_download(&db));
+    }
+
+    #[test]
+    fn test_block_time_behind_parent_but_after_mtp_accepted() {
+        let (db, tip) = skewed_chain();
+        let b = chain_block(&tip, 4, 201);
+        apply_block(&db, &b).unwrap();
+    }
+
+    fn mined_by(prev: &StoredBlock, height: u32, timestamp: u32, miner: u8) -> StoredBlock {
+        let mut b = chain_block(prev, height, timestamp);
+        b.miner_address = [miner; 32];
+        b
+    }
+
+    /// Genesis (miner 0x03) followed by blocks 1a and 2a mined by 0xAA.
+    fn chain_a() -> (ChainDB, StoredBlock, StoredBlock) {
+        let db = tmp();
+        let genesis = StoredBlock {
+            version: [0, 0, 0, 1],
+            previous_hash: [0u8; 32],
+            merkle_root: [0u8; 32],
+            timestamp: 0u32.to_le_bytes(),
+            difficulty_target: [0xFF; 32],
+            nonce: [0u8; 8],
+            block_height: 0u32.to_le_bytes(),
+            miner_address: [0x03u8; 32],
+            tx_data: vec![],
+        };
+        apply_block(&db, &genesis).unwrap();
+        let a1 = mined_by(&genesis, 1, 100, 0xAA);
+        let a2 = mined_by(&a1, 2, 200, 0xAA);
+        apply_block(&db, &a1).unwrap();
+        apply_block(&db, &a2).unwrap();
+        (db, genesis, a2)
+    }
+
+    #[test]
+    fn test_concurrent_applies_at_same_height_connect_one() {
+        for round in 0..10u32 {
+            let (db, _genesis, a2) = chain_a();
+            let x = mined_by(&a2, 3, 300 + round, 0xC1);
+            let y = mined_by(&a2, 3, 300 + round, 0xC2);
+            let handles: Vec<_> = [x.clone(), y.clone()]
+                .into_iter()
+                .map(|b| {
+                    let db = db.clone();
+                    std::thread::spawn(move || apply_block(&db, &b))
+                })
+                .collect();
+            for h in handles {
+                h.join().unwrap().unwrap();
+            }
+
+            let tip = db.get_tip().unwrap().unwrap();
+            assert!(tip == block_hash(&x) || tip == block_hash(&y));
+            assert_eq!(db.get_chain_height().unwrap(), 3);
+            assert_eq!(db.get_block_hash_by_height(3).unwrap(), Some(tip));
+            let credited = [[0xC1u8; 32], [0xC2u8; 32]]
+                .iter()
+                .filter(|m| db.get_account_if_exists(m).unwrap().is_some())
+                .count();
+            assert_eq!(credited, 1, "round {round}: both miners were paid");
+        }
+    }
+
+    #[test]
+    fn test_equal_work_fork_keeps_first_seen() {
+        let (db, genesis, a2) = chain_a();
+        let b1 = mined_by(&genesis, 1, 110, 0xBB);
+        let b2 = mined_by(&b1, 2, 210, 0xBB);
+        apply_block(&db, &b1).unwrap();
+        apply_block(&db, &b2).unwrap();
+
+        assert_eq!(db.get_tip().unwrap(), Some(block_hash(&a2)));
+        assert!(db.get_block(&block_hash(&b2)).unwrap().is_some());
+        assert!(db.get_account_if_exists(&[0xBB; 32]).unwrap().is_none());
+        assert_eq!(chain_work(&db, &block_hash(&b2)).unwrap(), chain_work(&db, &block_hash(&a2)).unwrap());
+    }
+
+    #[test]
+    fn test_heavier_branch_reorgs_and_reverts_state() {
+        let (db, genesis, _) = chain_a();
+        let b1 = mined_by(&genesis, 1, 110, 0xBB);
+        let b2 = mined_by(&b1, 2, 210, 0xBB);
+        let b3 = mined_by(&b2, 3, 310, 0xBB);
+        for b in [&b1, &b2, &b3] {
+            apply_block(&db, b).unwrap();
+        }
+
+        assert_eq!(db.get_tip().unwrap(), Some(block_hash(&b3)));
+        assert_eq!(db.get_chain_height().unwrap(), 3);
+        assert_eq!(db.get_block_hash_by_height(1).unwrap(), Some(block_hash(&b1)));
+        assert_eq!(chain_work(&db, &block_hash(&b3)).unwrap(), U256::from(4));
+
+        // Orphaned miner's rewards are gone, winning miner's are applied
+        assert!(db.get_account_if_exists(&[0xAA; 32]).unwrap().is_none());
+        let b = db.get_account(&[0xBB; 32]).unwrap();
+        assert_eq!(b.total_blocks_mined, 3);
+        assert_eq!(b.last_mined_height, 3);
+    }
+
+    #[test]
+    fn test_reorg_past_max_depth_rejected() {
+        // Regtest keeps every target trivial across retarget boundaries.
+        let db = tmp().with_network(Network::Regtest);
+        let genesis = crate::consensus::genesis::genesis_block(Network::Regtest);
+        apply_block(&db, &genesis).unwrap();
+        // Trivial targets always pass; pre-seeding the cache skips ~5800 PONC runs.
+        let rounds = db.get_governance_params().unwrap().ponc_rounds;
+        *db.pow_cache.lock().unwrap() =
+            crate::consensus::pow_cache::PowCache::with_capacity(2 * MAX_REORG_DEPTH as usize + 8);
+        let passed = |b: &StoredBlock| db.pow_cache.lock().unwrap().insert(block_hash(b), rounds);
+        let mut prev = genesis.clone();
+        for h in 1..=MAX_REORG_DEPTH + 1 {
+            let b = mined_by(&prev, h, 1_772_100_000 + h, 0xAA);
+            passed(&b);
+            apply_block(&db, &b).unwrap();
+            prev = b;
+        }
+        let tip = block_hash(&prev);
+
+        // A heavier branch forking at genesis would disconnect one block too many.
+        let mut side = Vec::new();
+        let mut prev = genesis;
+        for h in 1..=MAX_REORG_DEPTH + 2 {
+            let b = mined_by(&prev, h, 1_772_100_000 + h, 0xBB);
+            passed(&b);
+            side.push(b.clone());
+            prev = b;
+        }
+        let (last, rest) = side.split_last().unwrap();
+        for b in rest {
+            apply_block(&db, b).unwrap();
+        }
+        assert!(matches!(
+            apply_block(&db, last),
+            Err(StateError::ReorgTooDeep { depth }) if depth == MAX_REORG_DEPTH + 1
+        ));
+
+        assert_eq!(db.get_tip().unwrap(), Some(tip));
+        assert!(db.get_account_if_exists(&[0xBB; 32]).unwrap().is_none());
+        assert!(db.get_block(&block_hash(&side[0])).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_invalid_branch_restores_old_chain() {
+        let (db, genesis, a2) = chain_a();
+        let a_before = db.get_account(&[0xAA; 32]).unwrap();
+
+        let far_future = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32 + 100_000;
+        let b1 = mined_by(&genesis, 1, 110, 0xBB);
+        let b2 = mined_by(&b1, 2, far_future, 0xBB);
+        let b3 = mined_by(&b2, 3, far_future + 1, 0xBB);
+        apply_block(&db, &b1).unwrap();
+        apply_block(&db, &b2).unwrap();
+        assert!(matches!(apply_block(&db, &b3), Err(StateError::BlockTooFarInFuture)));
+
+        assert_eq!(db.get_tip().unwrap(), Some(block_hash(&a2)));
+        assert_eq!(db.get_block_hash_by_height(1).unwrap(), Some(a2.previous_hash));
+        let a_after = db.get_account(&[0xAA; 32]).unwrap();
+        assert_eq!(a_after.balance, a_before.balance);
+        assert_eq!(a_after.total_blocks_mined, 2);
+        assert!(db.get_account_if_exists(&[0xBB; 32]).unwrap().is_none());
+
+        // The invalid block and its descendant are forgotten; the valid sibling is kept
+        assert!(db.get_block(&block_hash(&b2)).unwrap().is_none());
+        assert!(db.get_block(&block_hash(&b3)).unwrap().is_none());
+        assert!(db.get_block(&block_hash(&b1)).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_block_with_unknown_parent_rejected() {
+        let (db, genesis, _) = chain_a();
+        let mut stray = chain_block(&genesis, 5, 500);
+        stray.previous_hash = [0x42; 32];
+        assert!(matches!(apply_block(&db, &stray), Err(StateError::UnknownParent)));
+    }
+
+    #[test]
+    fn test_retarget_block_must_carry_new_target() {
+        let db = tmp();
+        let mut prev = StoredBlock {
+            version: [0, 0, 0, 1],
+            previous_hash: [0u8; 32],
+            merkle_root: [0u8; 32],
+            timestamp: 1000u32.to_le_bytes(),
+            difficulty_target: [0xFF; 32],
+            nonce: [0u8; 8],
+            block_height: 0u32.to_le_bytes(),
+            miner_address: [0x03u8; 32],
+            tx_data: vec![],
+        };
+        apply_block(&db, &prev).unwrap();
+        for h in 1..RETARGET_WINDOW as u32 {
+            let b = chain_block(&prev, h, 1000 + h);
+            apply_block(&db, &b).unwrap();
+            prev = b;
+        }
+        assert_eq!(next_difficulty_target(&db, 59).unwrap(), [0xFF; 32]);
+
+        // The window took 59s instead of an hour: the target tightens (clamped to 4x).
+        let expected = next_difficulty_target(&db, 60).unwrap();
+        assert_eq!(expected, calculate_new_difficulty(&[0xFF; 32], 59));
+        assert!(expected < [0xFF; 32]);
+
+        let stale = chain_block(&prev, 60, 1060);
+        assert!(matches!(apply_block(&db, &stale), Err(StateError::InvalidDifficulty)));
+    }
+
+    #[test]
+    fn test_regtest_never_retargets() {
+        let db = tmp().with_network(Network::Regtest);
+        let mut prev = crate::consensus::genesis::genesis_block(Network::Regtest);
+        apply_block(&db, &prev).unwrap();
+        for h in 1..=RETARGET_WINDOW as u32 {
+            assert_eq!(next_difficulty_target(&db, h).unwrap(), [0xFF; 32]);
+            let b = chain_block(&prev, h, u32::from_le_bytes(prev.timestamp) + 1);
+            apply_block(&db, &b).unwrap();
+            prev = b;
+        }
+    }
+
+    fn signed_tx(
+        pk: &crate::crypto::dilithium::PublicKey,
+        sk: &crate::crypto::dilithium::SecretKey,
+        nonce: u64,
+        governance_data: Option<[u8; 32]>,
+        proposal: Option<Vec<u8>>,
+    ) -> crate::node::db_common::StoredTransaction {
+        let addr = crate::crypto::keys::derive_address(pk);
+        let version = if proposal.is_some() { crate::consensus::governance::TX_VERSION_PROPOSAL } else { 1 };
+        let mut tx = Transaction {
+            version,
+            sender_address: addr,
+            sender_pubkey: *pk,
+            recipient_address: addr,
+            amount: 0,
+            fee: 1,
+            nonce,
+            timestamp: 100,
+            referrer_address: None,
+            governance_data,
+            proposal,
+            memo: None,
+            signature: crate::crypto::dilithium::Signature([0u8; 3309]),
+        };
+        tx.signature = crate::crypto::dilithium::sign(&tx.signing_hash(), sk);
+        stored(tx)
+    }
+
+    fn stored(tx: Transaction) -> crate::node::db_common::StoredTransaction {
+        crate::node::db_common::StoredTransaction {
+            version: tx.version,
+            sender_address: tx.sender_address,
+            sender_pubkey: tx.sender_pubkey.0.to_vec(),
+            recipient_address: tx.recipient_address,
+            amount: tx.amount,
+            fee: tx.fee,
+            nonce: tx.nonce,
+            timestamp: tx.timestamp,
+            referrer_address: None,
+            governance_data: tx.governance_data,
+            proposal: tx.proposal,
+            memo: tx.memo,
+            signature: tx.signature.0.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_passed_proposal_enacted_and_reverted_by_reorg() {
+        use crate::consensus::governance::{GovParam, Proposal};
+        let (pk, sk) = crate::crypto::dilithium::generate_keypair(&[3u8; 64]);
+        let voter = crate::crypto::keys::derive_address(&pk);
+
+        let db = tmp();
+        let genesis = StoredBlock {
+            version: [0, 0, 0, 1],
+            previous_hash: [0u8; 32],
+            merkle_root: [0u8; 32],
+            timestamp: 0u32.to_le_bytes(),
+            difficulty_target: [0xFF; 32],
+            nonce: [0u8; 8],
+            block_height: 0u32.to_le_bytes(),
+            miner_address: [0x03u8; 32],
+            tx_data: vec![],
+        };
+        apply_block(&db, &genesis).unwrap();
+        db.put_account(&voter, &crate::node::db_common::AccountState {
+            balance: 100,
+            governance_weight: GOVERNANCE_THRESHOLD_BPS,
+            ..crate::node::db_common::AccountState::empty()
+        }).unwrap();
+
+        let proposal = Proposal::new("Six threads", GovParam::MiningThreads, 6).unwrap();
+        let prop = proposal.hash();
+        let mut a1 = mined_by(&genesis, 1, 100, 0xAA);
+        a1.tx_data = vec![
+            signed_tx(&pk, &sk, 1, None, Some(proposal.to_bytes())),
+            signed_tx(&pk, &sk, 2, Some(prop), None),
+        ];
+        apply_block(&db, &a1).unwrap();
+        assert_eq!(db.get_governance_params().unwrap().mining_threads, 6);
+        assert_eq!(db.get_governance_proposal(&prop).unwrap().unwrap().proposer, voter);
+
+        // A heavier branch without the proposal undoes both.
+        let b1 = mined_by(&genesis, 1, 110, 0xBB);
+        let b2 = mined_by(&b1, 2, 210, 0xBB);
+        apply_block(&db, &b1).unwrap();
+        apply_block(&db, &b2).unwrap();
+        assert_eq!(db.get_tip().unwrap(), Some(block_hash(&b2)));
+        assert_eq!(db.get_governance_params().unwrap().mining_threads, MINING_THREADS_DEFAULT);
+        assert!(db.get_governance_proposal(&prop).unwrap().is_none());
+    }
+
+    /// Find a nonce that meets `block`'s target with `rounds` but, when
+    /// `not_with` is given, not with those rounds.
+    fn mine(db: &ChainDB, block: &mut StoredBlock, rounds: u64, not_with: Option<u64>) {
+        for nonce in 0u64.. {
+            block.nonce = nonce.to_le_bytes();
+            if verify_block_pow_with_rounds(block, db, rounds).is_ok()
+                && not_with.is_none_or(|r| verify_block_pow_with_rounds(block, db, r).is_err())
+            {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn test_batch_across_ponc_rounds_enactment_syncs() {
+        use crate::consensus::governance::{GovParam, Proposal};
+        let (pk, sk) = crate::crypto::dilithium::generate_keypair(&[3u8; 64]);
+        let voter = crate::crypto::keys::derive_address(&pk);
+        let mut target = [0xFF; 32];
+        target[0] = 0x3F;
+
+        let db = tmp();
+        let genesis = StoredBlock {
+            version: [0, 0, 0, 1],
+            previous_hash: [0u8; 32],
+            merkle_root: [0u8; 32],
+            timestamp: 0u32.to_le_bytes(),
+            difficulty_target: target,
+            nonce: [0u8; 8],
+            block_height: 0u32.to_le_bytes(),
+            miner_address: [0x03u8; 32],
+            tx_data: vec![],
+        };
+        apply_block(&db, &genesis).unwrap();
+        db.put_account(&voter, &crate::node::db_common::AccountState {
+            balance: 100,
+            governance_weight: GOVERNANCE_THRESHOLD_BPS,
+            ..crate::node::db_common::AccountState::empty()
+        }).unwrap();
+
+        // Block 1 passes the proposal; its children need the new rounds.
+        let proposal = Proposal::new("More rounds", GovParam::PoncRounds, PONC_ROUNDS_DEFAULT * 2).unwrap();
+        let mut b1 = chain_block(&genesis, 1, 100);
+        b1.difficulty_target = target;
+        b1.tx_data = vec![
+            signed_tx(&pk, &sk, 1, None, Some(proposal.to_bytes())),
+            signed_tx(&pk, &sk, 2, Some(proposal.hash()), None),
+        ];
+        mine(&db, &mut b1, PONC_ROUNDS_DEFAULT, None);
+        let mut batch = vec![b1];
+        for h in 2..=3 {
+            let mut b = chain_block(batch.last().unwrap(), h, h * 100);
+            b.difficulty_target = target;
+            mine(&db, &mut b, PONC_ROUNDS_DEFAULT * 2, Some(PONC_ROUNDS_DEFAULT));
+            batch.push(b);
+        }
+
+        // As `handle_blocks` does: pre-check what can be checked, then apply.
+        let rounds = pow_rounds_for_batch(&db, &batch).unwrap();
+        assert_eq!(rounds, vec![Some(PONC_ROUNDS_DEFAULT), None, None]);
+        verify_block_pow_with_rounds(&batch[0], &db, PONC_ROUNDS_DEFAULT).unwrap();
+        for b in &batch {
+            apply_block(&db, b).unwrap();
+        }
+        assert_eq!(db.get_chain_height().unwrap(), 3);
+        assert_eq!(db.get_governance_params().unwrap().ponc_rounds, PONC_ROUNDS_DEFAULT * 2);
+
+        // Every block re-verifies with the rounds at its own parent.
+        db.clear_pow_cache();
+        for b in &batch {
+            verify_block_pow(b, &db).unwrap();
+        }
+        assert_eq!(pow_rounds_after(&db, &block_hash(&genesis)).unwrap(), Some(PONC_ROUNDS_DEFAULT));
+    }
+
+    #[test]
+    fn test_bogus_target_behind_side_governance_block_does_not_reorg() {
+        use crate::consensus::governance::{GovParam, Proposal};
+        let (pk, sk) = crate::crypto::dilithium::generate_keypair(&[3u8; 64]);
+        let mut target = [0xFF; 32];
+        target[0] = 0x3F;
+
+        let db = tmp();
+        let genesis = StoredBlock {
+            version: [0, 0, 0, 1],
+            previous_hash: [0u8; 32],
+            merkle_root: [0u8; 32],
+            timestamp: 0u32.to_le_bytes(),
+            difficulty_target: target,
+            nonce: [0u8; 8],
+            block_height: 0u32.to_le_bytes(),
+            miner_address: [0x03u8; 32],
+            tx_data: vec![],
+        };
+        apply_block(&db, &genesis).unwrap();
+        let mut tip = genesis.clone();
+        for h in 1..=3 {
+            let mut b = chain_block(&tip, h, h * 100);
+            b.difficulty_target = target;
+            mine(&db, &mut b, PONC_ROUNDS_DEFAULT, None);
+            apply_block(&db, &b).unwrap();
+            tip = b;
+        }
+        let active = block_hash(&tip);
+
+        // A lighter side block whose governance txs might change the rounds.
+        let proposal = Proposal::new("More rounds", GovParam::PoncRounds, PONC_ROUNDS_DEFAULT * 2).unwrap();
+        let mut s1 = chain_block(&genesis, 1, 110);
+        s1.difficulty_target = target;
+        s1.tx_data = vec![
+            signed_tx(&pk, &sk, 1, None, Some(proposal.to_bytes())),
+            signed_tx(&pk, &sk, 2, Some(proposal.hash()), None),
+        ];
+        mine(&db, &mut s1, PONC_ROUNDS_DEFAULT, None);
+        apply_block(&db, &s1).unwrap();
+        assert_eq!(pow_rounds_after(&db, &block_hash(&s1)).unwrap(), None);
+        assert_eq!(
+            possible_pow_rounds(&db, &block_hash(&s1)).unwrap(),
+            vec![PONC_ROUNDS_DEFAULT, PONC_ROUNDS_DEFAULT * 2]
+        );
+
+        // A child claiming a near-zero target without the work is refused
+        // before its work is recorded, so the chain never switches.
+        let mut bogus = chain_block(&s1, 2, 210);
+        bogus.difficulty_target = [0u8; 32];
+        bogus.difficulty_target[31] = 1;
+        assert!(matches!(apply_block(&db, &bogus), Err(StateError::InvalidPoW)));
+        assert_eq!(db.get_tip().unwrap(), Some(active));
+        assert!(db.get_block(&block_hash(&bogus)).unwrap().is_none());
+        assert!(db.get_chain_work(&block_hash(&bogus)).unwrap().is_none());
+
+        // One mined with rounds the branch could have enacted is kept.
+        let mut honest = chain_block(&s1, 2, 220);
+        honest.difficulty_target = target;
+        mine(&db, &mut honest, PONC_ROUNDS_DEFAULT * 2, Some(PONC_ROUNDS_DEFAULT));
+        apply_block(&db, &honest).unwrap();
+        assert!(db.get_block(&block_hash(&honest)).unwrap().is_some());
+        assert_eq!(db.get_tip().unwrap(), Some(active));
+    }
+
+    #[test]
+    fn test_pow_result_cached_only_on_success() {
+        let (db, _, a2) = chain_a();
+        db.clear_pow_cache();
+        let next = mined_by(&a2, 3, 300, 0xAA);
+        verify_block_pow(&next, &db).unwrap();
+        verify_block_pow(&next, &db).unwrap();
+        assert_eq!(db.pow_cache.lock().unwrap().len(), 1);
+
+        let mut hard = next.clone();
+        hard.difficulty_target = [0u8; 32];
+        assert!(matches!(verify_block_pow(&hard, &db), Err(StateError::InvalidPoW)));
+        assert_eq!(db.pow_cache.lock().unwrap().len(), 1);
+
+        db.clear_pow_cache();
+        assert!(db.pow_cache.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_mainnet_difficulty_floor() {
+        let (db, _, a2) = chain_a();
+        let mainnet = db.clone().with_network(Network::Mainnet);
+        assert_eq!(crate::consensus::genesis::create_genesis_block().difficulty_target, MAINNET_MAX_TARGET);
+
+        // Half of all hashes meet the floor target; find a nonce that does.
+        let mut at_floor = mined_by(&a2, 3, 300, 0xAA);
+        at_floor.difficulty_target = MAINNET_MAX_TARGET;
+        let mut nonce = 0u64;
+        loop {
+            at_floor.nonce = nonce.to_le_bytes();
+            if verify_block_pow(&at_floor, &mainnet).is_ok() {
+                break;
+            }
+            nonce += 1;
+            assert!(nonce < 64);
+        }
+
+        // One above the floor: meets the PoW too, but the target is too easy.
+        let mut above = at_floor.clone();
+        above.difficulty_target = [0u8; 32];
+        above.difficulty_target[0] = 0x80;
+        assert!(matches!(verify_block_pow(&above, &mainnet), Err(StateError::DifficultyBelowMinimum)));
+
+        let trivial = mined_by(&a2, 3, 300, 0xAA);
+        assert!(matches!(verify_block_pow(&trivial, &mainnet), Err(StateError::DifficultyBelowMinimum)));
+        verify_block_pow(&trivial, &db).unwrap();
+    }
+
+    #[test]
+    fn test_bad_signature_rejects_block() {
+        let (pk, sk) = crate::crypto::dilithium::generate_keypair(&[4u8; 64]);
+        let sender = crate::crypto::keys::derive_address(&pk);
+        let (db, _, a2) = chain_a();
+        db.put_account(&sender, &crate::node::db_common::AccountState {
+            balance: 100,
+            ..crate::node::db_common::AccountState::empty()
+        }).unwrap();
+
+        let vote = Some([9u8; 32]);
+        let good = vec![signed_tx(&pk, &sk, 1, vote, None), signed_tx(&pk, &sk, 2, vote, None)];
+        let mut b = mined_by(&a2, 3, 300, 0xCC);
+        b.tx_data = good.clone();
+        b.tx_data[1].signature[0] ^= 0xFF;
+        assert!(matches!(apply_block(&db, &b), Err(StateError::InvalidTransaction(_))));
+        assert_eq!(db.get_account(&sender).unwrap().nonce, 0);
+
+        b.tx_data = good;
+        apply_block(&db, &b).unwrap();
+        assert_eq!(db.get_account(&sender).unwrap().nonce, 2);
+    }
+
+    /// `tx` with its amount and fee replaced, signed again.
+    fn with_amounts(
+        tx: &crate::node::db_common::StoredTransaction,
+        sk: &crate::crypto::dilithium::SecretKey,
+        amount: u64,
+        fee: u64,
+    ) -> crate::node::db_common::StoredTransaction {
+        let mut t = Transaction::try_from(tx).unwrap();
+        t.amount = amount;
+        t.fee = fee;
+        t.signature = crate::crypto::dilithium::sign(&t.signing_hash(), sk);
+        stored(t)
+    }
+
+    #[test]
+    fn test_fee_and_amount_bounds_reject_block() {
+        let (pk, sk) = crate::crypto::dilithium::generate_keypair(&[7u8; 64]);
+        let sender = crate::crypto::keys::derive_address(&pk);
+        let (db, _, a2) = chain_a();
+        db.put_account(&sender, &crate::node::db_common::AccountState {
+            balance: u64::MAX,
+            ..crate::node::db_common::AccountState::empty()
+        }).unwrap();
+        let vote = signed_tx(&pk, &sk, 1, Some([9u8; 32]), None);
+        let mut b = mined_by(&a2, 3, 300, 0xCC);
+
+        b.tx_data = vec![with_amounts(&vote, &sk, 0, 0)];
+        assert!(matches!(apply_block(&db, &b), Err(StateError::FeeTooLow { fee: 0 })));
+        assert!(matches!(check_transaction(&db, &b.tx_data[0], None), Err(StateError::FeeTooLow { .. })));
+
+        let supply = total_supply(&db).unwrap() + calculate_block_reward(3);
+        b.tx_data = vec![with_amounts(&vote, &sk, supply, 1)];
+        assert!(matches!(
+            apply_block(&db, &b),
+            Err(StateError::AmountExceedsSupply { debit, supply: s }) if debit == supply + 1 && s == supply
+        ));
+        assert_eq!(db.get_account(&sender).unwrap().nonce, 0);
+
+        b.tx_data = vec![with_amounts(&vote, &sk, supply - 1, 1)];
+        apply_block(&db, &b).unwrap();
+        assert_eq!(db.get_account(&sender).unwrap().nonce, 1);
+
+        let mut empty = vote.clone();
+        empty.fee = 0;
+        empty.recipient_address = [0x42; 32];
+        assert!(matches!(check_amounts(&empty, supply), Err(StateError::EmptyTransaction)));
+        empty.recipient_address = sender;
+        assert!(matches!(check_amounts(&empty, supply), Err(StateError::FeeTooLow { fee: 0 })));
+    }
+
+    #[test]
+    fn test_immature_rewards_cannot_be_spent() {
+        let (pk, sk) = crate::crypto::dilithium::generate_keypair(&[6u8; 64]);
+        let sender = crate::crypto::keys::derive_address(&pk);
+        let (db, _, a2) = chain_a();
+        let miner = db.get_account(&[0xAA; 32]).unwrap();
+        assert_eq!(miner.balance, 0);
+        assert_eq!(miner.immature.iter().map(|(h, _)| *h).collect::<Vec<_>>(), vec![1, 2]);
+
+        db.put_account(&sender, &crate::node::db_common::AccountState {
+            immature: vec![(2, 100)],
+            ..crate::node::db_common::AccountState::empty()
+        }).unwrap();
+        let mut b = mined_by(&a2, 3, 300, 0xCC);
+        b.tx_data = vec![signed_tx(&pk, &sk, 1, Some([9u8; 32]), None)];
+        assert!(matches!(apply_block(&db, &b), Err(StateError::InsufficientBalance)));
+        assert!(matches!(
+            check_transaction(&db, &b.tx_data[0], None),
+            Err(StateError::InsufficientBalance)
+        ));
+
+        db.put_account(&sender, &crate::node::db_common::AccountState {
+            balance: 1,
+            immature: vec![(2, 100)],
+            ..crate::node::db_common::AccountState::empty()
+        }).unwrap();
+        apply_block(&db, &b).unwrap();
+        let after = db.get_account(&sender).unwrap();
+        assert_eq!((after.balance, after.immature_balance()), (0, 100));
+    }
+
+    #[test]
+    fn test_too_many_txs_from_one_sender_rejects_block() {
+        let (pk, sk) = crate::crypto::dilithium::generate_keypair(&[5u8; 64]);
+        let sender = crate::crypto::keys::derive_address(&pk);
+        let (db, _, a2) = chain_a();
+        db.put_account(&sender, &crate::node::db_common::AccountState {
+            balance: 100,
+            ..crate::node::db_common::AccountState::empty()
+        }).unwrap();
+
+        let vote = Some([9u8; 32]);
+        let txs: Vec<_> = (1..=30).map(|n| signed_tx(&pk, &sk, n, vote, None)).collect();
+        let mut b = mined_by(&a2, 3, 300, 0xCC);
+        b.tx_data = txs.clone();
+        assert!(matches!(apply_block(&db, &b), Err(StateError::TooManyTxsPerSender)));
+        assert_eq!(db.get_account(&sender).unwrap().nonce, 0);
+
+        b.tx_data = txs[..MAX_TXS_PER_SENDER].to_vec();
+        apply_block(&db, &b).unwrap();
+        assert_eq!(db.get_account(&sender).unwrap().nonce, MAX_TXS_PER_SENDER as u64);
+    }
+
+    #[test]
+    fn test_block_size_limit_boundary() {
+        let (pk, sk) = crate::crypto::dilithium::generate_keypair(&[5u8; 64]);
+        let (db, genesis, a2) = chain_a();
+        let mut b = mined_by(&a2, 3, 300, 0xCC);
+        b.tx_data = vec![signed_tx(&pk, &sk, 1, Some([9u8; 32]), None)];
+
+        // Pad the signature so the block lands exactly on the cap.
+        let pad = MAX_BLOCK_BYTES - b.to_bytes().len();
+        b.tx_data[0].signature.extend(std::iter::repeat_n(0u8, pad));
+        assert_eq!(b.to_bytes().len(), MAX_BLOCK_BYTES);
+        assert!(!matches!(apply_block(&db, &b), Err(StateError::BlockTooLarge)));
+        assert!(!matches!(apply_block_with_time_bound(&db, &b, 0), Err(StateError::BlockTooLarge)));
+
+        b.tx_data[0].signature.push(0);
+        assert!(matches!(apply_block(&db, &b), Err(StateError::BlockTooLarge)));
+        assert!(matches!(apply_block_with_time_bound(&db, &b, 0), Err(StateError::BlockTooLarge)));
+
+        // Side branches are checked too.
+        let mut side = mined_by(&genesis, 1, 150, 0xDD);
+        side.tx_data = b.tx_data.clone();
+        let tip = db.get_tip().unwrap();
+        assert!(matches!(apply_block(&db, &side), Err(StateError::BlockTooLarge)));
+        assert_eq!(db.get_tip().unwrap(), tip);
+    }
+
+    #[test]
+    fn test_state_snapshot_rolls_back_and_restores() {
+        let (pk, sk) = crate::crypto::dilithium::generate_keypair(&[6u8; 64]);
+        let sender = crate::crypto::keys::derive_address(&pk);
+        let (db, genesis, a2) = chain_a();
+        db.put_account(&sender, &crate::node::db_common::AccountState {
+            balance: 100,
+            ..crate::node::db_common::AccountState::empty()
+        }).unwrap();
+        let at2 = db.state_snapshot(2).unwrap();
+        assert_eq!(at2.accounts[&[0xAA; 32]].total_blocks_mined, 2);
+        assert_eq!(db.state_snapshot(1).unwrap().accounts[&[0xAA; 32]].total_blocks_mined, 1);
+        assert!(matches!(db.state_snapshot(3), Err(crate::node::db_rocksdb::DbError::Snapshot(_))));
+
+        let vote = [9u8; 32];
+        let mut a3 = mined_by(&a2, 3, 300, 0xAA);
+        a3.tx_data = vec![signed_tx(&pk, &sk, 1, Some(vote), None)];
+        apply_block(&db, &a3).unwrap();
+        assert_eq!(db.state_snapshot(2).unwrap().hash(), at2.hash());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.snap");
+        let hash = db.dump_state_snapshot(&path, 3).unwrap();
+
+        // A node that only has the blocks picks up the state from the file.
+        let fresh = tmp();
+        let a1 = mined_by(&genesis, 1, 100, 0xAA);
+        for b in [&genesis, &a1, &a2, &a3] {
+            fresh.store_block(&block_hash(b), b).unwrap();
+        }
+        assert_eq!(fresh.load_state_snapshot(&path).unwrap(), (3, hash));
+        assert_eq!(fresh.get_chain_height().unwrap(), 3);
+        assert_eq!(fresh.get_account(&sender).unwrap().nonce, 1);
+        assert!(fresh.get_governance_vote_exists(&vote, &sender).unwrap());
+        assert_eq!(fresh.get_governance_tally(&vote).unwrap(), db.get_governance_tally(&vote).unwrap());
+        assert_eq!(fresh.state_snapshot(3).unwrap().hash(), hash);
+
+        let a4 = mined_by(&a3, 4, 400, 0xAA);
+        apply_block(&db, &a4).unwrap();
+        apply_block(&fresh, &a4).unwrap();
+        assert_eq!(fresh.state_snapshot(4).unwrap().hash(), db.state_snapshot(4).unwrap().hash());
+        assert_eq!(fresh.get_total_supply().unwrap(), db.get_total_supply().unwrap());
+    }
+
+    #[test]
+    fn test_total_supply_tracks_rewards_bonuses_and_reorgs() {
+        let (db, genesis, a2) = chain_a();
+        let rewards = |tip: u64| (0..=tip).map(calculate_block_reward).sum::<u64>();
+        assert_eq!(db.get_total_supply().unwrap(), Some(rewards(2)));
+
+        // 0xBB's first block pays its referrer, 0xAA, a protocol-minted bonus.
+        let a3 = mined_by(&a2, 3, 300, 0xBB);
+        apply_block_with_referrer(&db, &a3, Some([0xAA; 32])).unwrap();
+        let bonus = db.get_account(&[0xAA; 32]).unwrap().total_referral_bonus_earned;
+        assert!(bonus > 0);
+        assert_eq!(total_supply(&db).unwrap(), rewards(3) + bonus);
+
+        // Without the stored total it is rebuilt to the same value.
+        let cf_meta = db.db.cf_handle("meta").unwrap();
+        db.db.delete_cf(cf_meta, crate::node::db_rocksdb::KEY_TOTAL_SUPPLY).unwrap();
+        assert_eq!(total_supply(&db).unwrap(), rewards(3) + bonus);
+
+        // A heavier branch without the bonus replaces it.
+        let mut prev = genesis;
+        for h in 1..=4 {
+            let b = mined_by(&prev, h, h * 100 + 10, 0xCC);
+            apply_block(&db, &b).unwrap();
+            prev = b;
+        }
+        assert_eq!(db.get_tip().unwrap(), Some(block_hash(&prev)));
+        assert_eq!(total_supply(&db).unwrap(), rewards(4));
+    }
+
+    #[test]
+    fn test_incremental_accounts_root_matches_full_scan() {
+        let full_scan = |db: &ChainDB| {
+            let accounts = db.iter_accounts().unwrap();
+            accounts_merkle_root(accounts.iter().map(|(a, acc)| (a, acc)))
+        };
+        let check = |db: &ChainDB| {
+            let stored = db.get_accounts_root().unwrap().unwrap();
+            let tip = db.get_tip().unwrap().unwrap();
+            assert_eq!(stored.tip, tip);
+            assert_eq!(db.get_accounts_tree_tip().unwrap(), Some(tip));
+            assert_eq!(stored.root, full_scan(db));
+        };
+        let (db, genesis, a2) = chain_a();
+        check(&db);
+
+        // Accounts sharing a bucket with the miners, written outside a
+        // block: the next connect rebuilds, later ones update in place.
+        for i in 0..6u8 {
+            let mut addr = [0xBBu8; 32];
+            addr[2] = i;
+            db.put_account(&addr, &AccountState { balance: 1000 + i as u64, ..AccountState::empty() }).unwrap();
+            db.put_account(&[i; 32], &AccountState { balance: 5, ..AccountState::empty() }).unwrap();
+        }
+        assert_eq!(db.get_accounts_tree_tip().unwrap(), None);
+        let mut prev = a2;
+        for (h, miner) in [(3u32, 0xBBu8), (4, 0xCC), (5, 0xBB)] {
+            let b = mined_by(&prev, h, h * 100, miner);
+            apply_block(&db, &b).unwrap();
+            check(&db);
+            prev = b;
+        }
+
+        // A heavier branch disconnects all of them, removing 0xBB and 0xCC.
+        let mut prev = genesis;
+        for h in 1..=6u32 {
+            let b = mined_by(&prev, h, h * 100 + 10, 0xDD);
+            apply_block(&db, &b).unwrap();
+            prev = b;
+        }
+        assert_eq!(db.get_tip().unwrap(), Some(block_hash(&prev)));
+        assert!(db.get_account_if_exists(&[0xCC; 32]).unwrap().is_none());
+        check(&db);
+    }
+
+    #[test]
+    fn test_reindex_rebuilds_derived_indices_only() {
+        let (pk, sk) = crate::crypto::dilithium::generate_keypair(&[7u8; 64]);
+        let sender = crate::crypto::keys::derive_address(&pk);
+        let (db, _, a2) = chain_a();
+        db.put_account(&sender, &crate::node::db_common::AccountState {
+            balance: 100,
+            ..crate::node::db_common::AccountState::empty()
+        }).unwrap();
+        let mut a3 = mined_by(&a2, 3, 300, 0xCC);
+        let vote = Some([9u8; 32]);
+        a3.tx_data = vec![signed_tx(&pk, &sk, 1, vote, None), signed_tx(&pk, &sk, 2, vote, None)];
+        apply_block(&db, &a3).unwrap();
+
+        // Index turned on after the fact; supply and a referral entry lost.
+        let db = db.with_tx_index(true);
+        let txid = crate::net::mempool::Mempool::compute_txid_from_stored(&a3.tx_data[0]);
+        assert_eq!(db.get_tx_location(&txid).unwrap(), None);
+        let supply = total_supply(&db).unwrap();
+        let code: [u8; 8] = hash_sha3_256(&sender)[..8].try_into().unwrap();
+        db.db.delete_cf(db.db.cf_handle("referral_index").unwrap(), code).unwrap();
+        db.db.delete_cf(db.db.cf_handle("meta").unwrap(), crate::node::db_rocksdb::KEY_TOTAL_SUPPLY).unwrap();
+        let accounts: Vec<_> = db.iter_accounts().unwrap().into_iter().map(|(a, s)| (a, s.to_bytes())).collect();
+
+        let stats = reindex(&db).unwrap();
+        assert_eq!((stats.blocks, stats.pruned_blocks, stats.txs_indexed), (4, 0, 2));
+        assert_eq!(stats.referral_entries, accounts.len() as u64);
+        assert_eq!(stats.total_supply, supply);
+        assert_eq!(db.get_tx_location(&txid).unwrap(), Some((3, block_hash(&a3))));
+        assert_eq!(db.get_address_by_referral_code(&code).unwrap(), Some(sender));
+        assert_eq!(db.get_total_supply().unwrap(), Some(supply));
+        let after: Vec<_> = db.iter_accounts().unwrap().into_iter().map(|(a, s)| (a, s.to_bytes())).collect();
+        assert_eq!(after, accounts);
+
+        // Running it again changes nothing.
+        assert_eq!(reindex(&db).unwrap(), stats);
+    }
+
+    /// `cargo test --release -- --ignored --nocapture bench_block_signature_verification`
+    #[test]
+    #[ignore]
+    fn bench_block_signature_verification() {
+        let (pk, sk) = crate::crypto::dilithium::generate_keypair(&[5u8; 64]);
+        let txs: Vec<Transaction> = (1..=100)
+            .map(|n| Transaction::try_from(&signed_tx(&pk, &sk, n, Some([9u8; 32]), None)).unwrap())
+            .collect();
+
+        let start = std::time::Instant::now();
+        assert!(txs.iter().all(Transaction::is_structurally_valid));
+        let sequential = start.elapsed();
+
+        let start = std::time::Instant::now();
+        verify_signatures(&txs).unwrap();
+        assert!(txs.iter().all(Transaction::has_valid_structure));
+        let parallel = start.elapsed();
+
+        println!(
+            "100 txs: sequential {sequential:?}, parallel {parallel:?} ({} threads)",
+            rayon::current_num_threads()
+        );
+    }
 }
@@ -147,11 +147,36 @@ pub fn derive_account_seed(master_seed: &[u8; 64], account_index: u64) -> [u8; 6
     account_key
 }
 
-/// Helper to derive a full Dilithium3 keypair from a mnemonic (Account 0)
+/// Derives the seed for receiving address `index` under an account.
+/// Index 0 is the account seed itself, so existing wallets keep their address.
+pub fn derive_address_seed(account_seed: &[u8; 64], index: u32) -> [u8; 64] {
+    if index == 0 {
+        return *account_seed;
+    }
+    let mut mac =
+        Hmac::<Sha512>::new_from_slice(b"Knotcoin address").expect("HMAC can take key of any size");
+    mac.update(account_seed);
+    mac.update(&index.to_be_bytes());
+    let result = mac.finalize();
+
+    let mut address_key = [0u8; 64];
+    address_key.copy_from_slice(&result.into_bytes());
+    address_key
+}
+
+/// Helper to derive a full Dilithium3 keypair from a mnemonic (Account 0).
+/// Pure function of the mnemonic: keygen draws from ChaCha20 seeded with the
+/// derived seed, so a lost `wallet_keys.json` regenerates the same address.
 pub fn derive_keypair_from_mnemonic(mnemonic: &str) -> (crate::crypto::dilithium::PublicKey, crate::crypto::dilithium::SecretKey) {
+    derive_keypair_at_index(mnemonic, 0)
+}
+
+/// Derives the keypair for receiving address `index` (Account 0).
+/// Index 0 matches `derive_keypair_from_mnemonic`.
+pub fn derive_keypair_at_index(mnemonic: &str, index: u32) -> (crate::crypto::dilithium::PublicKey, crate::crypto::dilithium::SecretKey) {
     let master = derive_master_seed(mnemonic, "");
     let account = derive_account_seed(&master, 0);
-    crate::crypto::dilithium::generate_keypair(&account)
+    crate::crypto::dilithium::generate_keypair(&derive_address_seed(&account, index))
 }
 
 #[cfg(test)]
@@ -183,4 +208,33 @@ mod tests {
         let s2 = derive_master_seed(&m, "");
         assert_eq!(s, s2, "same mnemonic must produce same seed");
     }
+
+    #[test]
+    fn test_indexed_addresses_distinct_and_stable() {
+        let m = generate_mnemonic();
+        let (pk0, _) = derive_keypair_from_mnemonic(&m);
+        let (pk0_idx, _) = derive_keypair_at_index(&m, 0);
+        assert_eq!(derive_address(&pk0), derive_address(&pk0_idx), "index 0 must keep the legacy address");
+
+        let (pk1, _) = derive_keypair_at_index(&m, 1);
+        let (pk2, _) = derive_keypair_at_index(&m, 2);
+        let (pk1_again, _) = derive_keypair_at_index(&m, 1);
+        assert_ne!(derive_address(&pk1), derive_address(&pk0));
+        assert_ne!(derive_address(&pk1), derive_address(&pk2));
+        assert_eq!(derive_address(&pk1), derive_address(&pk1_again));
+    }
+
+    #[test]
+    fn test_mnemonic_keypair_is_deterministic() {
+        let m = "abandon ".repeat(23) + "art";
+        let (pk, sk) = derive_keypair_from_mnemonic(&m);
+        for _ in 0..5 {
+            let (pk2, sk2) = derive_keypair_from_mnemonic(&m);
+            assert_eq!(pk.0, pk2.0);
+            assert_eq!(sk.0, sk2.0);
+        }
+        // Pinned so a dependency bump that changes keygen output can't
+        // silently move every wallet to a new address.
+        assert_eq!(encode_address_string(&derive_address(&pk)), "KOT1QGXRLYVIXBTDAISODR5SUUN3MBP7SQATC4RLJ3ZIDOTOLITBGMDA2EFUCVA");
+    }
 }
@@ -8,19 +8,74 @@ use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Mutex;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::consensus::chain::calculate_new_difficulty;
-use crate::consensus::state::{apply_block, block_hash};
+use crate::consensus::chain::MAX_BLOCK_BYTES;
+use crate::consensus::state::{apply_block, block_hash, median_time_past, next_difficulty_target};
 use crate::crypto::hash::hash_sha3_256;
 use crate::crypto::ponc::ffi::bridge::new_ponc_engine;
 use crate::net::mempool::Mempool;
 use crate::node::{ChainDB, db_common::{StoredBlock, StoredTransaction}};
 
 pub const MAX_TXS: usize = 6;
-const RETARGET_INTERVAL: u64 = 60;
+
+/// Samples kept in `HashrateSamples`: one a second for an hour.
+pub const MAX_HASHRATE_SAMPLES: usize = 3600;
+
+/// Ring buffer of `(timestamp, cumulative_nonces)` pairs recorded by the
+/// background mining loop, so the hashrate can be measured over a recent
+/// window instead of the miner's whole lifetime.
+#[derive(Debug, Default)]
+pub struct HashrateSamples {
+    samples: std::collections::VecDeque<(u64, u64)>,
+}
+
+impl HashrateSamples {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the nonce counter at `ts`. A second sample in the same second
+    /// replaces the first; the oldest sample is dropped once the buffer is full.
+    pub fn record(&mut self, ts: u64, cumulative_nonces: u64) {
+        if let Some(last) = self.samples.back_mut()
+            && last.0 == ts
+        {
+            last.1 = cumulative_nonces;
+            return;
+        }
+        if self.samples.len() == MAX_HASHRATE_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((ts, cumulative_nonces));
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Nonces per second over the last `window` seconds before `now`, from
+    /// the oldest sample inside the window to the newest. Time after the
+    /// newest sample counts as idle, so a stopped miner decays to 0.
+    pub fn rate(&self, now: u64, window: u64) -> u64 {
+        let start = now.saturating_sub(window);
+        let Some(&(first_ts, first_n)) = self.samples.iter().find(|(ts, _)| *ts >= start) else {
+            return 0;
+        };
+        let (_, last_n) = *self.samples.back().unwrap();
+        let elapsed = now.saturating_sub(first_ts);
+        if elapsed == 0 {
+            return 0;
+        }
+        last_n.saturating_sub(first_n) / elapsed
+    }
+}
 
 // Use shared StoredBlock::header_bytes implementation for PoC/PoW consistency.
 
-fn merkle_root(txs: &[StoredTransaction]) -> [u8; 32] {
+pub fn merkle_root(txs: &[StoredTransaction]) -> [u8; 32] {
     if txs.is_empty() {
         return [0u8; 32];
     }
@@ -46,36 +101,62 @@ fn merkle_root(txs: &[StoredTransaction]) -> [u8; 32] {
     hashes[0]
 }
 
-// Calculate the difficulty target to use for the next block.
-// Reads the actual time taken over the last RETARGET_INTERVAL blocks.
-fn next_difficulty(db: &ChainDB, current_height: u32, current_target: [u8; 32]) -> [u8; 32] {
-    if current_height == 0 || !(current_height as u64).is_multiple_of(RETARGET_INTERVAL) {
-        return current_target;
-    }
-
-    let window_start = current_height.saturating_sub(RETARGET_INTERVAL as u32);
-    let start_hash = match db.get_block_hash_by_height(window_start) {
-        Ok(Some(h)) => h,
-        _ => return current_target,
-    };
-    let start_block = match db.get_block(&start_hash) {
-        Ok(Some(b)) => b,
-        _ => return current_target,
+/// Unsolved block on top of the current tip: next height, retargeted
+/// difficulty, a timestamp past MTP, and the merkle root of `txs`. The nonce
+/// is zero. Transactions that would push the block past `MAX_BLOCK_BYTES`
+/// are left out. Returns `None` before genesis has been applied.
+pub fn block_template(db: &ChainDB, txs: Vec<StoredTransaction>, miner_addr: &[u8; 32]) -> Option<StoredBlock> {
+    let (prev_hash, height) = match db.get_tip().ok()? {
+        Some(h) => {
+            let tip = db.get_block_header(&h).ok()??;
+            (h, u32::from_le_bytes(tip.block_height) + 1)
+        }
+        None => return None, // genesis must be applied before mining
     };
 
-    let start_ts = u32::from_le_bytes(start_block.timestamp) as u64;
-    let tip_hash = match db.get_tip() {
-        Ok(Some(h)) => h,
-        _ => return current_target,
-    };
-    let tip_block = match db.get_block(&tip_hash) {
-        Ok(Some(b)) => b,
-        _ => return current_target,
-    };
-    let end_ts = u32::from_le_bytes(tip_block.timestamp) as u64;
+    let difficulty_target = next_difficulty_target(db, height).ok()?;
 
-    let elapsed = end_ts.saturating_sub(start_ts).max(1);
-    calculate_new_difficulty(&current_target, elapsed)
+    let mut now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32;
+
+    // Ensure timestamp is strictly greater than Median-Time-Past (MTP).
+    // Without this, rapid block generation (tests, fast networks) can produce
+    // blocks with the same timestamp that fail the MTP consensus check.
+    if let Some(mtp) = median_time_past(db, height)
+        && now <= mtp
+    {
+        now = mtp + 1;
+    }
+
+    let txs = fit_block_size(txs);
+    let root = merkle_root(&txs);
+    Some(StoredBlock {
+        version: [1, 0, 0, 0],
+        previous_hash: prev_hash,
+        merkle_root: root,
+        timestamp: now.to_le_bytes(),
+        difficulty_target,
+        nonce: [0u8; 8],
+        block_height: height.to_le_bytes(),
+        miner_address: *miner_addr,
+        tx_data: txs,
+    })
+}
+
+/// Keep transactions in order while the block stays within `MAX_BLOCK_BYTES`.
+/// Stops at the first one that doesn't fit: a later transaction from the same
+/// sender would have a nonce gap without it.
+fn fit_block_size(txs: Vec<StoredTransaction>) -> Vec<StoredTransaction> {
+    // 148-byte header plus the u32 transaction count.
+    let mut size = 148 + 4;
+    txs.into_iter()
+        .take_while(|tx| {
+            size += tx.to_bytes().len();
+            size <= MAX_BLOCK_BYTES
+        })
+        .collect()
 }
 
 pub fn mine_block(
@@ -115,44 +196,9 @@ pub fn mine_block_parallel_with_counter(
     num_threads: usize,
     global_nonce_counter: Option<&AtomicU64>,
 ) -> Option<(StoredBlock, [u8; 32])> {
-    let (prev_hash, height, base_target) = match db.get_tip().ok()? {
-        Some(h) => {
-            let tip = db.get_block(&h).ok()??;
-            let ht = u32::from_le_bytes(tip.block_height);
-            (h, ht + 1, tip.difficulty_target)
-        }
-        None => return None, // genesis must be applied before mining
-    };
-
-    let difficulty_target = next_difficulty(db, height, base_target);
-
-    let mut now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs() as u32;
-
-    // Ensure timestamp is strictly greater than Median-Time-Past (MTP).
-    // Without this, rapid block generation (tests, fast networks) can produce
-    // blocks with the same timestamp that fail the MTP consensus check.
-    {
-        let mut times = Vec::new();
-        for i in 1..=11u32 {
-            if height >= i {
-                if let Ok(Some(h)) = db.get_block_hash_by_height(height - i) {
-                    if let Ok(Some(b)) = db.get_block(&h) {
-                        times.push(u32::from_le_bytes(b.timestamp));
-                    }
-                }
-            }
-        }
-        if !times.is_empty() {
-            times.sort();
-            let mtp = times[times.len() / 2];
-            if now <= mtp {
-                now = mtp + 1;
-            }
-        }
-    }
+    let template = block_template(db, txs, miner_addr)?;
+    let prev_hash = template.previous_hash;
+    let difficulty_target = template.difficulty_target;
 
     // NOTE: Referral binding transactions are NOT auto-inserted by the miner.
     // The miner does not currently have a reliable way to reconstruct the matching Dilithium public
@@ -161,19 +207,6 @@ pub fn mine_block_parallel_with_counter(
     // as the wallet's first outgoing transaction.
     let _ = (referrer, miner_sk);
 
-    let root = merkle_root(&txs);
-    let template = StoredBlock {
-        version: [1, 0, 0, 0],
-        previous_hash: prev_hash,
-        merkle_root: root,
-        timestamp: now.to_le_bytes(),
-        difficulty_target,
-        nonce: [0u8; 8],
-        block_height: height.to_le_bytes(),
-        miner_address: *miner_addr,
-        tx_data: txs,
-    };
-
     // Parallel mining with thread cap
     if num_threads <= 1 {
         // Single-threaded path (for testing/debugging)
@@ -245,6 +278,68 @@ pub fn mine_block_parallel_with_counter(
     result.into_inner().ok()?
 }
 
+/// Highest thread count mining (and `optimizemining`) will use.
+pub const MAX_MINING_THREADS: usize = 8;
+
+/// Nonces per second `num_threads` threads get through on the next block's
+/// template over `duration`. The target is unreachable so every thread runs
+/// the full time; nothing is mined. Scratchpad setup happens before the clock
+/// starts, as a mining session pays it only once per block.
+pub fn measure_hashrate(db: &ChainDB, num_threads: usize, duration: std::time::Duration) -> Option<u64> {
+    let template = block_template(db, Vec::new(), &[0u8; 32])?;
+    let rounds = db.get_governance_params().unwrap_or_default().ponc_rounds as usize;
+    let stop = AtomicBool::new(false);
+    let nonces = AtomicU64::new(0);
+    let num_threads = num_threads.max(1);
+    let ready = std::sync::Barrier::new(num_threads + 1);
+
+    let elapsed = std::thread::scope(|s| {
+        for _ in 0..num_threads {
+            let (template, stop, nonces, ready) = (&template, &stop, &nonces, &ready);
+            s.spawn(move || {
+                let mut engine = new_ponc_engine();
+                engine.pin_mut().set_rounds(rounds);
+                engine.pin_mut().initialize_scratchpad(&template.previous_hash, &template.miner_address);
+                let mut prefix = Vec::with_capacity(140);
+                prefix.extend_from_slice(&template.version);
+                prefix.extend_from_slice(&template.previous_hash);
+                prefix.extend_from_slice(&template.merkle_root);
+                prefix.extend_from_slice(&template.timestamp);
+                prefix.extend_from_slice(&template.difficulty_target);
+                prefix.extend_from_slice(&template.block_height);
+                prefix.extend_from_slice(&template.miner_address);
+                let mut out = [0u8; 32];
+                ready.wait();
+                while !stop.load(Ordering::Relaxed) {
+                    let nonce = nonces.fetch_add(1, Ordering::Relaxed);
+                    engine.compute_and_verify(&prefix, nonce, &[0u8; 32], &mut out);
+                }
+            });
+        }
+        ready.wait();
+        let started = std::time::Instant::now();
+        std::thread::sleep(duration);
+        stop.store(true, Ordering::Relaxed);
+        started.elapsed()
+    });
+
+    let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+    Some((nonces.load(Ordering::Relaxed) as f64 / secs) as u64)
+}
+
+/// The fewest threads within 5% of the best hashrate in `results`
+/// (`(threads, hashrate)` pairs): past that point more threads mostly buy
+/// heat and contention.
+pub fn pick_mining_threads(results: &[(usize, u64)]) -> usize {
+    let best = results.iter().map(|&(_, r)| r).max().unwrap_or(0);
+    results
+        .iter()
+        .filter(|&&(_, r)| r as u128 * 100 >= best as u128 * 95)
+        .map(|&(t, _)| t)
+        .min()
+        .unwrap_or(1)
+}
+
 // Single-threaded mining (original implementation, kept for compatibility)
 fn mine_single_threaded(
     template: &StoredBlock,
@@ -342,6 +437,79 @@ mod tests {
         assert_eq!(u32::from_le_bytes(block.block_height), 1);
 
         apply_block(&db, &block).expect("failed to apply mined block");
-        assert!(db.get_account(&miner).unwrap().balance > 0);
+        assert!(db.get_account(&miner).unwrap().immature_balance() > 0);
+    }
+
+    #[test]
+    fn test_measure_hashrate_mines_nothing() {
+        let db = tmp();
+        apply_block(&db, &create_genesis_block()).unwrap();
+        let rate = measure_hashrate(&db, 2, std::time::Duration::from_millis(300)).unwrap();
+        assert!(rate > 0);
+        assert_eq!(db.get_chain_height().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_pick_mining_threads_prefers_fewer_within_five_percent() {
+        assert_eq!(pick_mining_threads(&[(1, 100), (2, 190), (3, 250), (4, 260), (5, 262)]), 3);
+        assert_eq!(pick_mining_threads(&[(1, 100), (2, 99)]), 1);
+        assert_eq!(pick_mining_threads(&[(1, 10), (2, 20), (4, 40)]), 4);
+        assert_eq!(pick_mining_threads(&[]), 1);
+    }
+
+    #[test]
+    fn test_hashrate_samples_window() {
+        let mut s = HashrateSamples::new();
+        assert_eq!(s.rate(100, 60), 0);
+
+        // 1000 nonces/s for 100 seconds, then idle.
+        for t in 0..=100u64 {
+            s.record(1_000 + t, t * 1_000);
+        }
+        assert_eq!(s.rate(1_100, 10), 1_000);
+        assert_eq!(s.rate(1_100, 60), 1_000);
+        // Ten idle seconds into a 20 second window halves the rate.
+        assert_eq!(s.rate(1_110, 20), 500);
+        // Once the window holds no samples the miner counts as idle.
+        assert_eq!(s.rate(1_200, 60), 0);
+
+        // Same-second samples collapse; the buffer is bounded.
+        s.record(1_100, 200_000);
+        assert_eq!(s.len(), 101);
+        for t in 0..(MAX_HASHRATE_SAMPLES as u64 + 10) {
+            s.record(2_000 + t, t);
+        }
+        assert_eq!(s.len(), MAX_HASHRATE_SAMPLES);
+    }
+
+    #[test]
+    fn test_template_stops_at_block_size_limit() {
+        let db = tmp();
+        apply_block(&db, &create_genesis_block()).unwrap();
+
+        let mut tx = StoredTransaction {
+            version: 1,
+            sender_address: [1u8; 32],
+            sender_pubkey: Vec::new(),
+            recipient_address: [2u8; 32],
+            amount: 1,
+            fee: 1,
+            nonce: 1,
+            timestamp: 0,
+            referrer_address: None,
+            governance_data: None,
+            proposal: None,
+            memo: None,
+            signature: Vec::new(),
+        };
+        // Four of these fill the block to exactly MAX_BLOCK_BYTES.
+        let each = (MAX_BLOCK_BYTES - 148 - 4) / 4;
+        tx.signature = vec![0u8; each - tx.to_bytes().len()];
+        let txs: Vec<_> = (1..=5).map(|n| StoredTransaction { nonce: n, ..tx.clone() }).collect();
+
+        let block = block_template(&db, txs, &[0x55u8; 32]).unwrap();
+        assert_eq!(block.tx_data.len(), 4);
+        assert_eq!(block.to_bytes().len(), MAX_BLOCK_BYTES);
+        assert_eq!(block.merkle_root, merkle_root(&block.tx_data));
     }
 }
@@ -0,0 +1,144 @@
+// Peer Ban List
+//
+// Peers earn misbehavior points for sending data that costs us work and can
+// never be valid (bad PoW, unparseable blocks, oversized gossip). At
+// `BAN_THRESHOLD` the connection is dropped and the IP is refused, both
+// inbound and outbound, for `BAN_DURATION_SECS`. Bans are keyed by IP, not
+// socket address, so reconnecting from a new port doesn't reset them, and
+// they are persisted to `banned.json` so a restart doesn't either.
+
+use std::collections::HashMap;
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+/// Ban score at which a peer is disconnected and banned.
+pub const BAN_THRESHOLD: u32 = 100;
+
+/// How long a ban lasts (24 hours).
+pub const BAN_DURATION_SECS: u64 = 24 * 3600;
+
+pub const BANNED_FILE: &str = "banned.json";
+
+// Misbehavior scores
+pub const MISBEHAVIOR_INVALID_POW: u32 = 50;
+pub const MISBEHAVIOR_MALFORMED_BLOCK: u32 = 20;
+pub const MISBEHAVIOR_OVERSIZED_ADDR: u32 = 10;
+/// A branch forking below `MAX_REORG_DEPTH` can only be an attack: ban outright.
+pub const MISBEHAVIOR_DEEP_REORG: u32 = BAN_THRESHOLD;
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct BanRecord {
+    ip: String,
+    /// Unix time at which the ban lifts.
+    until: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct BanList {
+    /// ip -> unix time the ban expires
+    banned: HashMap<IpAddr, u64>,
+}
+
+impl BanList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn file(data_dir: &Path) -> PathBuf {
+        data_dir.join(BANNED_FILE)
+    }
+
+    /// Load `banned.json`, dropping entries that have already expired.
+    /// A missing or unreadable file yields an empty list.
+    pub fn load(data_dir: &Path, now: u64) -> Self {
+        let mut list = Self::new();
+        let Ok(s) = fs::read_to_string(Self::file(data_dir)) else {
+            return list;
+        };
+        let records: Vec<BanRecord> = serde_json::from_str(&s).unwrap_or_default();
+        for r in records {
+            if let Ok(ip) = r.ip.parse::<IpAddr>()
+                && r.until > now
+            {
+                list.banned.insert(ip, r.until);
+            }
+        }
+        list
+    }
+
+    pub fn save(&self, data_dir: &Path) {
+        let mut records: Vec<BanRecord> = self
+            .banned
+            .iter()
+            .map(|(ip, until)| BanRecord { ip: ip.to_string(), until: *until })
+            .collect();
+        records.sort_by(|a, b| a.ip.cmp(&b.ip));
+        let _ = fs::create_dir_all(data_dir);
+        if let Ok(data) = serde_json::to_string(&records) {
+            let _ = fs::write(Self::file(data_dir), data);
+        }
+    }
+
+    pub fn ban(&mut self, ip: IpAddr, now: u64) {
+        self.banned.insert(ip, now + BAN_DURATION_SECS);
+    }
+
+    pub fn is_banned(&self, ip: IpAddr, now: u64) -> bool {
+        self.banned.get(&ip).is_some_and(|until| *until > now)
+    }
+
+    /// Forget expired bans.
+    pub fn prune(&mut self, now: u64) {
+        self.banned.retain(|_, until| *until > now);
+    }
+
+    pub fn len(&self) -> usize {
+        self.banned.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.banned.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_ban_expires_after_duration() {
+        let mut list = BanList::new();
+        list.ban(ip("8.8.8.8"), 1_000);
+        assert!(list.is_banned(ip("8.8.8.8"), 1_000));
+        assert!(list.is_banned(ip("8.8.8.8"), 1_000 + BAN_DURATION_SECS - 1));
+        assert!(!list.is_banned(ip("8.8.8.8"), 1_000 + BAN_DURATION_SECS));
+        assert!(!list.is_banned(ip("9.9.9.9"), 1_000));
+
+        list.prune(1_000 + BAN_DURATION_SECS);
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn test_save_load_roundtrip_drops_expired() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut list = BanList::new();
+        list.ban(ip("8.8.8.8"), 1_000);
+        list.ban(ip("2001:db8::1"), 50_000);
+        list.save(dir.path());
+
+        let loaded = BanList::load(dir.path(), 1_000 + BAN_DURATION_SECS + 1);
+        assert_eq!(loaded.len(), 1);
+        assert!(loaded.is_banned(ip("2001:db8::1"), 1_000 + BAN_DURATION_SECS + 1));
+    }
+
+    #[test]
+    fn test_load_missing_file_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(BanList::load(dir.path(), 0).is_empty());
+    }
+}
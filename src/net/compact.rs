@@ -0,0 +1,169 @@
+// Compact Blocks
+//
+// A freshly mined block is announced to peers that advertise
+// `SERVICE_COMPACT_BLOCKS` as its 148-byte header plus one short id (the first
+// 8 bytes of the txid) per transaction, instead of the ~5 KB bodies they most
+// likely already hold in their mempool. The receiver fills what it can from
+// its mempool and asks for the rest by index with `GetBlockTxn`. The rebuilt
+// block must match the header's merkle root; if it doesn't (a short id
+// collision, or a peer lying), the receiver falls back to a full `GetBlocks`.
+
+use std::collections::HashMap;
+
+use crate::miner::miner::merkle_root;
+use crate::net::mempool::Mempool;
+use crate::net::protocol::NetworkMessage;
+use crate::node::db_common::{StoredBlock, StoredTransaction};
+
+pub type ShortId = [u8; 8];
+
+pub fn short_id(txid: &[u8; 32]) -> ShortId {
+    txid[..8].try_into().unwrap()
+}
+
+/// Compact announcement for `block`.
+pub fn compact_block(block: &StoredBlock) -> NetworkMessage {
+    NetworkMessage::CompactBlock {
+        header: block.header_bytes(),
+        short_ids: block
+            .tx_data
+            .iter()
+            .map(|tx| short_id(&Mempool::compute_txid_from_stored(tx)))
+            .collect(),
+    }
+}
+
+/// A block being rebuilt from a compact announcement.
+#[derive(Debug)]
+pub struct PartialBlock {
+    pub hash: [u8; 32],
+    header: StoredBlock,
+    slots: Vec<Option<StoredTransaction>>,
+}
+
+impl PartialBlock {
+    /// Fill each slot whose short id matches exactly one of `candidates`
+    /// (txid, tx) pairs; ambiguous ids are left for the peer to send.
+    pub fn new<'a>(
+        header: StoredBlock,
+        hash: [u8; 32],
+        short_ids: &[ShortId],
+        candidates: impl IntoIterator<Item = ([u8; 32], &'a StoredTransaction)>,
+    ) -> Self {
+        let mut by_short: HashMap<ShortId, Option<&StoredTransaction>> = HashMap::new();
+        for (txid, tx) in candidates {
+            by_short
+                .entry(short_id(&txid))
+                .and_modify(|e| *e = None)
+                .or_insert(Some(tx));
+        }
+        let slots = short_ids
+            .iter()
+            .map(|id| by_short.get(id).copied().flatten().cloned())
+            .collect();
+        PartialBlock { hash, header, slots }
+    }
+
+    /// Indexes of the transactions still needed.
+    pub fn missing(&self) -> Vec<u32> {
+        (0..self.slots.len() as u32)
+            .filter(|&i| self.slots[i as usize].is_none())
+            .collect()
+    }
+
+    /// Put `txs` into the missing slots, in order. False if the count is wrong.
+    pub fn fill(&mut self, txs: Vec<StoredTransaction>) -> bool {
+        let missing = self.missing();
+        if txs.len() != missing.len() {
+            return false;
+        }
+        for (i, tx) in missing.into_iter().zip(txs) {
+            self.slots[i as usize] = Some(tx);
+        }
+        true
+    }
+
+    /// The full block, if every slot is filled and the merkle root matches.
+    pub fn finish(self) -> Option<StoredBlock> {
+        let txs: Vec<StoredTransaction> = self.slots.into_iter().collect::<Option<_>>()?;
+        if merkle_root(&txs) != self.header.merkle_root {
+            return None;
+        }
+        Some(StoredBlock { tx_data: txs, ..self.header })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx(nonce: u64) -> StoredTransaction {
+        StoredTransaction {
+            version: 1,
+            sender_address: [1u8; 32],
+            sender_pubkey: vec![2u8; 1952],
+            recipient_address: [3u8; 32],
+            amount: 10,
+            fee: 1,
+            nonce,
+            timestamp: 0,
+            referrer_address: None,
+            governance_data: None,
+            proposal: None,
+            memo: None,
+            signature: vec![4u8; 3309],
+        }
+    }
+
+    fn block(txs: Vec<StoredTransaction>) -> StoredBlock {
+        StoredBlock {
+            version: [0, 0, 0, 1],
+            previous_hash: [9u8; 32],
+            merkle_root: merkle_root(&txs),
+            timestamp: 0u32.to_le_bytes(),
+            difficulty_target: [0xFF; 32],
+            nonce: [0u8; 8],
+            block_height: 1u32.to_le_bytes(),
+            miner_address: [5u8; 32],
+            tx_data: txs,
+        }
+    }
+
+    fn parts(b: &StoredBlock) -> (StoredBlock, Vec<ShortId>) {
+        match compact_block(b) {
+            NetworkMessage::CompactBlock { header, short_ids } => {
+                (StoredBlock::header_only_from_bytes(&header).unwrap(), short_ids)
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_rebuild_from_mempool_and_missing_txs() {
+        let txs = vec![tx(1), tx(2), tx(3)];
+        let full = block(txs.clone());
+        let (header, ids) = parts(&full);
+
+        // The pool has txs 1 and 3 but not 2.
+        let pool: Vec<([u8; 32], StoredTransaction)> = [&txs[0], &txs[2]]
+            .into_iter()
+            .map(|t| (Mempool::compute_txid_from_stored(t), t.clone()))
+            .collect();
+        let mut partial = PartialBlock::new(header, [0u8; 32], &ids, pool.iter().map(|(id, t)| (*id, t)));
+        assert_eq!(partial.missing(), vec![1]);
+        assert!(!partial.fill(vec![]));
+        assert!(partial.fill(vec![txs[1].clone()]));
+
+        let rebuilt = partial.finish().unwrap();
+        assert_eq!(rebuilt.to_bytes(), full.to_bytes());
+    }
+
+    #[test]
+    fn test_wrong_tx_fails_merkle_check() {
+        let full = block(vec![tx(1)]);
+        let (header, ids) = parts(&full);
+        let mut partial = PartialBlock::new(header, [0u8; 32], &ids, std::iter::empty());
+        assert!(partial.fill(vec![tx(7)]));
+        assert!(partial.finish().is_none());
+    }
+}
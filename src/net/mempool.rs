@@ -1,22 +1,124 @@
 // Transaction Mempool
 //
 // In-memory pool of unconfirmed transactions, ordered by fee priority.
-// Supports Replace-by-Fee (10% higher minimum) and reserves one slot
-// per block for Layer 2 dispute transactions.
+// Supports Replace-by-Fee (25% and 1 knot higher minimum) and reserves one slot
+// per block for Layer 2 dispute transactions. When full (by count or by
+// estimated bytes), the lowest fee rates are evicted first.
 
+use crate::consensus::chain::MAX_TXS_PER_SENDER;
+use crate::consensus::state::StateError;
 use crate::crypto::hash::hash_sha3_256;
+use crate::node::ChainDB;
 use crate::node::db_common::StoredTransaction;
-use crate::primitives::transaction::Transaction;
-use std::collections::HashMap;
+use crate::primitives::transaction::{Transaction, MIN_FEE_KNOTS};
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+use std::path::Path;
+use tokio::sync::broadcast;
 
 const MAX_MEMPOOL_SIZE: usize = 5000;
 
+/// Default cap on the summed `estimate_tx_size` of pooled transactions (64 MB).
+pub const DEFAULT_MEMPOOL_MAX_BYTES: usize = 64 * 1024 * 1024;
+
+/// Default lifetime of a pooled transaction, measured from its `timestamp` (3 hours).
+pub const DEFAULT_MEMPOOL_TTL_SECS: u64 = 3 * 3600;
+
+/// Transactions stamped further ahead than this are rejected, so a far-future
+/// timestamp can't keep one alive past the TTL.
+const MAX_TX_FUTURE_SECS: u64 = 2 * 3600;
+
+/// Mempool snapshot file inside the data directory.
+pub const MEMPOOL_FILE: &str = "mempool.dat";
+
+// mempool.dat layout: version byte, then [u32 LE len][StoredTransaction bytes] per entry.
+const MEMPOOL_FILE_VERSION: u8 = 1;
+
 /// A mempool entry wrapping a transaction with its computed hash
 #[derive(Debug, Clone)]
 pub struct MempoolEntry {
     pub tx: StoredTransaction,
     pub txid: [u8; 32],
     pub fee_per_byte_scaled: u64, // fee * 10000 / size for deterministic integer comparison
+    pub size: usize,
+    /// Unix time this node admitted the transaction.
+    pub added_at: u64,
+}
+
+/// Pending transactions sharing one `fee_per_byte_scaled`, with running
+/// totals over this level and every higher-paying one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeeBucket {
+    pub fee_per_byte_scaled: u64,
+    pub count: usize,
+    pub size: usize,
+    pub cumulative_count: usize,
+    pub cumulative_size: usize,
+}
+
+/// Why a transaction was turned away, by `add_transaction` or by the
+/// balance and nonce rules checked before it (see `From<StateError>`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum MempoolError {
+    /// Malformed, or breaks a rule with no variant of its own.
+    Invalid(String),
+    BadSignature,
+    FeeTooLow { fee: u64, min: u64 },
+    TimestampInFuture,
+    DuplicateTxid,
+    /// Same sender and nonce as a pending tx, without the RBF fee bump.
+    ReplacementUnderpriced { fee: u64, min: u64 },
+    /// Bigger than the whole mempool.
+    TooLarge,
+    /// Full, and the tx doesn't pay more per byte than what it would evict.
+    PoolFull,
+    BalanceTooLow,
+    NonceTooLow { expected: u64, got: u64 },
+    /// Earlier nonces are neither confirmed nor pending.
+    NonceGap { expected: u64, got: u64 },
+}
+
+impl std::fmt::Display for MempoolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MempoolError::Invalid(e) => write!(f, "invalid transaction: {e}"),
+            MempoolError::BadSignature => write!(f, "signature validation failed"),
+            MempoolError::FeeTooLow { fee, min } => write!(f, "fee {fee} below minimum relay fee {min}"),
+            MempoolError::TimestampInFuture => write!(f, "timestamp too far in the future"),
+            MempoolError::DuplicateTxid => write!(f, "duplicate transaction"),
+            MempoolError::ReplacementUnderpriced { fee, min } => write!(
+                f,
+                "replacement fee {fee} too low (need {min}: >= 125% of existing and at least 1 knot more)"
+            ),
+            MempoolError::TooLarge => write!(f, "transaction larger than mempool"),
+            MempoolError::PoolFull => write!(f, "mempool full: fee rate too low"),
+            MempoolError::BalanceTooLow => write!(f, "insufficient balance"),
+            MempoolError::NonceTooLow { expected, got } => write!(f, "nonce {got} already used, next is {expected}"),
+            MempoolError::NonceGap { expected, got } => write!(f, "nonce gap: next is {expected}, got {got}"),
+        }
+    }
+}
+
+impl std::error::Error for MempoolError {}
+
+impl From<StateError> for MempoolError {
+    fn from(e: StateError) -> Self {
+        match e {
+            StateError::InsufficientBalance => MempoolError::BalanceTooLow,
+            StateError::InvalidNonce { expected, got } if got < expected => MempoolError::NonceTooLow { expected, got },
+            StateError::InvalidNonce { expected, got } => MempoolError::NonceGap { expected, got },
+            StateError::FeeTooLow { fee } => MempoolError::FeeTooLow { fee, min: MIN_FEE_KNOTS },
+            other => MempoolError::Invalid(other.to_string()),
+        }
+    }
+}
+
+/// What `add_transaction` will do with a tx that passed every check.
+struct Admission {
+    txid: [u8; 32],
+    size: usize,
+    fee_per_byte_scaled: u64,
+    replaced_txid: Option<[u8; 32]>,
+    evictions: Vec<[u8; 32]>,
 }
 
 pub struct Mempool {
@@ -24,6 +126,17 @@ pub struct Mempool {
     entries: HashMap<[u8; 32], MempoolEntry>,
     /// sender_address + nonce -> txid (for Replace-by-Fee lookup)
     by_sender_nonce: HashMap<([u8; 32], u64), [u8; 32]>,
+    /// Sum of `size` over all entries.
+    total_bytes: usize,
+    max_bytes: usize,
+    ttl_secs: u64,
+    /// Most txs `get_top_transactions` takes from one sender.
+    max_per_sender: usize,
+    /// Lowest fee this node admits and relays. Policy only: blocks carrying
+    /// cheaper transactions are still valid.
+    min_relay_fee: u64,
+    /// txid of every newly admitted transaction, for WebSocket subscribers.
+    accepted: broadcast::Sender<[u8; 32]>,
 }
 
 impl Default for Mempool {
@@ -34,12 +147,45 @@ impl Default for Mempool {
 
 impl Mempool {
     pub fn new() -> Self {
+        Self::with_max_bytes(DEFAULT_MEMPOOL_MAX_BYTES)
+    }
+
+    pub fn with_max_bytes(max_bytes: usize) -> Self {
         Mempool {
             entries: HashMap::new(),
             by_sender_nonce: HashMap::new(),
+            total_bytes: 0,
+            max_bytes,
+            ttl_secs: DEFAULT_MEMPOOL_TTL_SECS,
+            max_per_sender: MAX_TXS_PER_SENDER,
+            min_relay_fee: MIN_FEE_KNOTS,
+            accepted: broadcast::channel(256).0,
         }
     }
 
+    pub fn with_ttl_secs(mut self, ttl_secs: u64) -> Self {
+        self.ttl_secs = ttl_secs;
+        self
+    }
+
+    /// Block selection takes at most `n` txs per sender; capped at the
+    /// consensus limit `MAX_TXS_PER_SENDER`.
+    pub fn with_max_per_sender(mut self, n: usize) -> Self {
+        self.max_per_sender = n.clamp(1, MAX_TXS_PER_SENDER);
+        self
+    }
+
+    /// Refuse transactions paying less than `fee` knots; never below the
+    /// consensus minimum `MIN_FEE_KNOTS`.
+    pub fn with_min_relay_fee(mut self, fee: u64) -> Self {
+        self.min_relay_fee = fee.max(MIN_FEE_KNOTS);
+        self
+    }
+
+    pub fn min_relay_fee(&self) -> u64 {
+        self.min_relay_fee
+    }
+
     pub fn compute_txid_from_stored(tx: &StoredTransaction) -> [u8; 32] {
         Self::compute_txid(tx)
     }
@@ -61,6 +207,12 @@ impl Mempool {
         if let Some(gov_data) = tx.governance_data {
             buf.extend_from_slice(&gov_data);
         }
+        if let Some(proposal) = &tx.proposal {
+            buf.extend_from_slice(proposal);
+        }
+        if let Some(memo) = &tx.memo {
+            buf.extend_from_slice(memo);
+        }
         buf.extend_from_slice(&tx.signature);
         hash_sha3_256(&buf)
     }
@@ -74,110 +226,276 @@ impl Mempool {
         if tx.governance_data.is_some() {
             base += 32;
         }
+        if let Some(proposal) = &tx.proposal {
+            base += 4 + proposal.len();
+        }
+        if let Some(memo) = &tx.memo {
+            base += 1 + memo.len();
+        }
         base
     }
 
     /// Add a transaction to the mempool. Returns Ok(true) if added,
     /// Ok(false) if it replaced an existing tx, or Err on rejection.
-    pub fn add_transaction(&mut self, tx: StoredTransaction) -> Result<bool, &'static str> {
+    pub fn add_transaction(&mut self, tx: StoredTransaction) -> Result<bool, MempoolError> {
+        let Admission { txid, size, fee_per_byte_scaled, replaced_txid, evictions } = self.plan_admission(&tx)?;
+
+        if let Some(id) = replaced_txid {
+            self.remove_entry(&id);
+        }
+        for id in &evictions {
+            self.remove_entry(id);
+        }
+
+        let sender_nonce_key = (tx.sender_address, tx.nonce);
+        let added_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let entry = MempoolEntry {
+            tx,
+            txid,
+            fee_per_byte_scaled,
+            size,
+            added_at,
+        };
+        self.by_sender_nonce.insert(sender_nonce_key, txid);
+        self.total_bytes += size;
+        self.entries.insert(txid, entry);
+        let _ = self.accepted.send(txid);
+
+        Ok(replaced_txid.is_none())
+    }
+
+    /// Run every check `add_transaction` would, without touching the pool.
+    pub fn check_transaction(&self, tx: &StoredTransaction) -> Result<(), MempoolError> {
+        self.plan_admission(tx).map(|_| ())
+    }
+
+    fn plan_admission(&self, tx: &StoredTransaction) -> Result<Admission, MempoolError> {
         // 0. Domain Validation (Structural & Signature)
-        let domain_tx = Transaction::try_from(&tx)?;
-        if !domain_tx.is_structurally_valid() {
-            return Err("structural or signature validation failed");
+        let domain_tx = Transaction::try_from(tx).map_err(|e| MempoolError::Invalid(e.to_string()))?;
+        if !domain_tx.has_valid_structure() {
+            // A fee under the consensus floor is the usual culprit; say so.
+            if tx.fee < MIN_FEE_KNOTS {
+                return Err(MempoolError::FeeTooLow { fee: tx.fee, min: self.min_relay_fee });
+            }
+            return Err(MempoolError::Invalid("structural validation failed".to_string()));
+        }
+        if !domain_tx.has_valid_signature() {
+            return Err(MempoolError::BadSignature);
         }
 
-        if tx.fee < 1 {
-            return Err("fee below minimum (1 knot)");
+        if tx.fee < self.min_relay_fee {
+            return Err(MempoolError::FeeTooLow { fee: tx.fee, min: self.min_relay_fee });
+        }
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        if tx.timestamp > now + MAX_TX_FUTURE_SECS {
+            return Err(MempoolError::TimestampInFuture);
         }
 
         // Section 3: Even 0-amount governance signals must pay for network resources.
         if tx.amount == 0 && tx.fee < 1 {
-            return Err("insufficient fee for signaling transaction");
+            return Err(MempoolError::FeeTooLow { fee: tx.fee, min: self.min_relay_fee });
         }
 
-        let txid = Self::compute_txid(&tx);
+        let txid = Self::compute_txid(tx);
 
         // Already in pool?
         if self.entries.contains_key(&txid) {
-            return Err("duplicate transaction");
+            return Err(MempoolError::DuplicateTxid);
         }
 
-        let sender_nonce_key = (tx.sender_address, tx.nonce);
+        let size = Self::estimate_tx_size(tx);
+        // Integer-only fee calculation: (fee * 10000) / size
+        // This ensures deterministic sorting across all platforms
+        let fee_per_byte_scaled = (tx.fee * 10000) / (size as u64).max(1);
 
         // Replace-by-Fee check
-        if let Some(existing_txid) = self.by_sender_nonce.get(&sender_nonce_key) {
-            let existing_txid = *existing_txid;
-            if let Some(existing) = self.entries.get(&existing_txid) {
-                // New fee must be at least 10% higher
-                let min_replacement_fee = existing.tx.fee + (existing.tx.fee / 10).max(1);
-                if tx.fee < min_replacement_fee {
-                    return Err("replacement fee too low (must be >= 110% of existing)");
-                }
-                // Replace it
-                self.entries.remove(&existing_txid);
-                self.by_sender_nonce.remove(&sender_nonce_key);
+        let mut replaced_txid = None;
+        if let Some(existing) = self.conflicting_entry(tx) {
+            let min = Self::min_replacement_fee(existing.tx.fee);
+            if tx.fee < min {
+                return Err(MempoolError::ReplacementUnderpriced { fee: tx.fee, min });
             }
+            replaced_txid = Some(existing.txid);
         }
 
-        // Pool size limit
-        if self.entries.len() >= MAX_MEMPOOL_SIZE {
-            // Evict the lowest-fee transaction
-            let worst_txid = self
-                .entries
-                .iter()
-                .min_by_key(|(_id, entry)| entry.fee_per_byte_scaled)
-                .map(|(&id, _)| id);
-
-            if let Some(id) = worst_txid
-                && let Some(evicted) = self.entries.remove(&id)
-            {
-                let evict_key = (evicted.tx.sender_address, evicted.tx.nonce);
-                self.by_sender_nonce.remove(&evict_key);
-            }
+        // Make room before touching the pool, so a rejected tx leaves it as it was.
+        let evictions = self.plan_evictions(size, fee_per_byte_scaled, replaced_txid)?;
+
+        Ok(Admission { txid, size, fee_per_byte_scaled, replaced_txid, evictions })
+    }
+
+    /// The pending entry `tx` conflicts with, i.e. would have to replace: the
+    /// one with the same sender and nonce.
+    pub fn conflicting_entry(&self, tx: &StoredTransaction) -> Option<&MempoolEntry> {
+        let txid = self.by_sender_nonce.get(&(tx.sender_address, tx.nonce))?;
+        self.entries.get(txid)
+    }
+
+    /// Smallest fee that may replace a pending tx paying `fee`: at least 25%
+    /// more, and at least 1 knot more.
+    pub fn min_replacement_fee(fee: u64) -> u64 {
+        fee.saturating_add(fee.div_ceil(4).max(1))
+    }
+
+    /// Pick the cheapest entries to drop so a `size`-byte tx fits under both
+    /// the byte cap and `MAX_MEMPOOL_SIZE`. Fails if the incoming tx doesn't
+    /// pay a strictly higher fee rate than everything it would displace.
+    fn plan_evictions(
+        &self,
+        size: usize,
+        fee_per_byte_scaled: u64,
+        replacing: Option<[u8; 32]>,
+    ) -> Result<Vec<[u8; 32]>, MempoolError> {
+        if size > self.max_bytes {
+            return Err(MempoolError::TooLarge);
+        }
+        let freed = replacing.and_then(|id| self.entries.get(&id)).map(|e| e.size).unwrap_or(0);
+        let mut bytes = self.total_bytes - freed + size;
+        let mut count = self.entries.len() - usize::from(replacing.is_some()) + 1;
+        if bytes <= self.max_bytes && count <= MAX_MEMPOOL_SIZE {
+            return Ok(Vec::new());
         }
 
-        let size = Self::estimate_tx_size(&tx) as u64;
-        // Integer-only fee calculation: (fee * 10000) / size
-        // This ensures deterministic sorting across all platforms
-        let fee_per_byte_scaled = (tx.fee * 10000) / size.max(1);
+        let mut candidates: Vec<&MempoolEntry> = self
+            .entries
+            .values()
+            .filter(|e| Some(e.txid) != replacing)
+            .collect();
+        candidates.sort_by(|a, b| {
+            a.fee_per_byte_scaled
+                .cmp(&b.fee_per_byte_scaled)
+                .then_with(|| b.txid.cmp(&a.txid))
+        });
 
-        let entry = MempoolEntry {
-            tx,
-            txid,
-            fee_per_byte_scaled,
-        };
-        self.by_sender_nonce.insert(sender_nonce_key, txid);
-        let replaced = self.entries.insert(txid, entry).is_some();
+        let mut evict = Vec::new();
+        for e in candidates {
+            if bytes <= self.max_bytes && count <= MAX_MEMPOOL_SIZE {
+                break;
+            }
+            if e.fee_per_byte_scaled >= fee_per_byte_scaled {
+                return Err(MempoolError::PoolFull);
+            }
+            evict.push(e.txid);
+            bytes -= e.size;
+            count -= 1;
+        }
+        Ok(evict)
+    }
 
-        Ok(!replaced)
+    fn remove_entry(&mut self, txid: &[u8; 32]) -> Option<MempoolEntry> {
+        let entry = self.entries.remove(txid)?;
+        self.by_sender_nonce.remove(&(entry.tx.sender_address, entry.tx.nonce));
+        self.total_bytes -= entry.size;
+        Some(entry)
     }
 
-    /// Get the top N transactions sorted by fee (highest first) for block template
+    /// Get up to N transactions for a block template, best fee-per-byte first.
+    ///
+    /// A sender's transactions are only valid in nonce order, so each sender's
+    /// pool entries form a chain from its lowest pending nonce up to the first
+    /// gap (and at most `max_per_sender` long). Each round takes, across all
+    /// senders, the chain prefix with the highest aggregate fee-per-byte, so a
+    /// cheap tx is included when a richer one behind it pays for both, and a
+    /// tx is never taken without every lower nonce from its sender.
     pub fn get_top_transactions(&self, max_count: usize) -> Vec<StoredTransaction> {
-        let mut entries: Vec<&MempoolEntry> = self.entries.values().collect();
-        // Sort by fee_per_byte_scaled (descending), then by txid for determinism
-        entries.sort_by(|a, b| {
-            b.fee_per_byte_scaled
-                .cmp(&a.fee_per_byte_scaled)
-                .then_with(|| a.txid.cmp(&b.txid))
-        });
-        entries
-            .into_iter()
-            .take(max_count)
-            .map(|e| e.tx.clone())
-            .collect()
+        let mut chains: HashMap<[u8; 32], Vec<&MempoolEntry>> = HashMap::new();
+        for e in self.entries.values() {
+            chains.entry(e.tx.sender_address).or_default().push(e);
+        }
+        let chains: Vec<Vec<&MempoolEntry>> = chains
+            .into_values()
+            .map(|mut chain| {
+                chain.sort_by_key(|e| e.tx.nonce);
+                let contiguous = chain.windows(2).position(|w| w[1].tx.nonce != w[0].tx.nonce + 1);
+                chain.truncate(contiguous.map_or(chain.len(), |i| i + 1).min(self.max_per_sender));
+                chain
+            })
+            .collect();
+        // Next unselected position in each chain.
+        let mut next = vec![0usize; chains.len()];
+
+        let mut out = Vec::new();
+        while out.len() < max_count {
+            // (rate, first txid, chain index, prefix length): highest rate
+            // wins, ties go to the lower txid for determinism.
+            let mut best: Option<(u128, [u8; 32], usize, usize)> = None;
+            for (i, chain) in chains.iter().enumerate() {
+                let (mut fees, mut bytes) = (0u128, 0u128);
+                for (k, e) in chain[next[i]..].iter().take(max_count - out.len()).enumerate() {
+                    fees += e.tx.fee as u128;
+                    bytes += e.size as u128;
+                    let rate = fees * 10000 / bytes.max(1);
+                    let first = chain[next[i]].txid;
+                    let better = match best {
+                        None => true,
+                        Some((r, t, _, _)) => rate > r || (rate == r && first < t),
+                    };
+                    if better {
+                        best = Some((rate, first, i, k + 1));
+                    }
+                }
+            }
+            let Some((_, _, i, len)) = best else {
+                break;
+            };
+            out.extend(chains[i][next[i]..next[i] + len].iter().map(|e| e.tx.clone()));
+            next[i] += len;
+        }
+        out
     }
 
     /// Remove transactions that were included in a mined block
     pub fn remove_confirmed(&mut self, txids: &[[u8; 32]]) {
         for txid in txids {
-            if let Some(entry) = self.entries.remove(txid) {
-                let key = (entry.tx.sender_address, entry.tx.nonce);
-                self.by_sender_nonce.remove(&key);
-            }
+            self.remove_entry(txid);
         }
     }
 
+    pub fn contains(&self, txid: &[u8; 32]) -> bool {
+        self.entries.contains_key(txid)
+    }
+
+    pub fn get(&self, txid: &[u8; 32]) -> Option<&StoredTransaction> {
+        self.entries.get(txid).map(|e| &e.tx)
+    }
+
+    pub fn get_entry(&self, txid: &[u8; 32]) -> Option<&MempoolEntry> {
+        self.entries.get(txid)
+    }
+
+    /// Receive the txid of each transaction admitted from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<[u8; 32]> {
+        self.accepted.subscribe()
+    }
+
+    /// Drop transactions whose `timestamp + ttl_secs` is before `now`.
+    /// Returns how many were removed.
+    pub fn prune_expired(&mut self, now: u64) -> usize {
+        let expired: Vec<[u8; 32]> = self
+            .entries
+            .values()
+            .filter(|e| e.tx.timestamp.saturating_add(self.ttl_secs) < now)
+            .map(|e| e.txid)
+            .collect();
+        for id in &expired {
+            self.remove_entry(id);
+        }
+        expired.len()
+    }
+
+    /// The pending tx occupying `(sender, nonce)`, if any.
+    pub fn get_by_sender_nonce(&self, sender: &[u8; 32], nonce: u64) -> Option<&StoredTransaction> {
+        let txid = self.by_sender_nonce.get(&(*sender, nonce))?;
+        self.get(txid)
+    }
+
     pub fn get_all_txids(&self) -> Vec<[u8; 32]> {
         self.entries.keys().cloned().collect()
     }
@@ -186,6 +504,136 @@ impl Mempool {
         self.entries.len()
     }
 
+    /// Estimated bytes held by pooled transactions.
+    pub fn total_bytes(&self) -> usize {
+        self.total_bytes
+    }
+
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+
+    /// Lowest `fee_per_byte_scaled` in the pool, or `None` when empty.
+    pub fn min_fee_per_byte_scaled(&self) -> Option<u64> {
+        self.entries.values().map(|e| e.fee_per_byte_scaled).min()
+    }
+
+    /// Write every pending transaction to `path`.
+    pub fn save_to_disk(&self, path: &Path) -> std::io::Result<()> {
+        let mut buf = vec![MEMPOOL_FILE_VERSION];
+        for entry in self.entries.values() {
+            let raw = entry.tx.to_bytes();
+            buf.extend_from_slice(&(raw.len() as u32).to_le_bytes());
+            buf.extend_from_slice(&raw);
+        }
+        std::fs::write(path, buf)
+    }
+
+    /// Re-admit transactions saved by `save_to_disk`. Records are deduped by
+    /// txid and by `(sender, nonce)`, keeping the higher fee, since a file
+    /// written mid-replacement can hold both sides of a conflict. Any whose
+    /// nonce the chain has already passed is dropped, and the rest go through
+    /// `add_transaction` again (structure + signature). A missing file is not
+    /// an error; a truncated one loads up to the damaged record.
+    /// Returns the number of transactions admitted.
+    pub fn load_from_disk(&mut self, path: &Path, db: &ChainDB) -> std::io::Result<usize> {
+        let data = match std::fs::read(path) {
+            Ok(d) => d,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        if data.first() != Some(&MEMPOOL_FILE_VERSION) {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "unknown mempool.dat version"));
+        }
+
+        let mut off = 1usize;
+        let mut by_slot: HashMap<([u8; 32], u64), StoredTransaction> = HashMap::new();
+        let mut seen = HashSet::new();
+        let (mut records, mut stale) = (0usize, 0usize);
+        while data.len() >= off + 4 {
+            let len = u32::from_le_bytes(data[off..off + 4].try_into().unwrap()) as usize;
+            off += 4;
+            if data.len() < off + len {
+                break;
+            }
+            let raw = &data[off..off + len];
+            off += len;
+
+            let Ok((tx, _)) = StoredTransaction::from_bytes(raw) else {
+                continue;
+            };
+            records += 1;
+            if !seen.insert(Self::compute_txid_from_stored(&tx)) {
+                continue;
+            }
+            let chain_nonce = db.get_account(&tx.sender_address).map(|a| a.nonce).unwrap_or(0);
+            if tx.nonce <= chain_nonce {
+                stale += 1;
+                continue;
+            }
+            match by_slot.entry((tx.sender_address, tx.nonce)) {
+                Entry::Occupied(mut e) => {
+                    if tx.fee > e.get().fee {
+                        e.insert(tx);
+                    }
+                }
+                Entry::Vacant(e) => {
+                    e.insert(tx);
+                }
+            }
+        }
+
+        let mut txs: Vec<_> = by_slot.into_values().collect();
+        txs.sort_by_key(|tx| (tx.sender_address, tx.nonce));
+        let duplicates = records - stale - txs.len();
+        let candidates = txs.len();
+        let mut loaded = 0;
+        for tx in txs {
+            if self.add_transaction(tx).is_ok() {
+                loaded += 1;
+            }
+        }
+        if loaded < records {
+            log::info!(
+                "[init] mempool.dat: dropped {} of {records} saved transaction(s): {stale} stale, {duplicates} duplicate or conflicting, {} rejected",
+                records - loaded,
+                candidates - loaded
+            );
+        }
+        Ok(loaded)
+    }
+
+    /// Pending transactions grouped by fee rate, highest rate first.
+    pub fn fee_histogram(&self) -> Vec<FeeBucket> {
+        let mut levels: std::collections::BTreeMap<u64, (usize, usize)> = std::collections::BTreeMap::new();
+        for e in self.entries.values() {
+            let level = levels.entry(e.fee_per_byte_scaled).or_default();
+            level.0 += 1;
+            level.1 += e.size;
+        }
+        let (mut cumulative_count, mut cumulative_size) = (0, 0);
+        levels
+            .into_iter()
+            .rev()
+            .map(|(fee_per_byte_scaled, (count, size))| {
+                cumulative_count += count;
+                cumulative_size += size;
+                FeeBucket { fee_per_byte_scaled, count, size, cumulative_count, cumulative_size }
+            })
+            .collect()
+    }
+
+    /// Fee rate (same scale as `fee_per_byte_scaled`) a new transaction needs
+    /// to be among the best `capacity` pending ones: one above the rate of
+    /// the level that fills `capacity`. `None` when everything pending fits,
+    /// so any fee at or above the relay minimum will do.
+    pub fn fee_rate_for_capacity(&self, capacity: usize) -> Option<u64> {
+        self.fee_histogram()
+            .into_iter()
+            .find(|b| b.cumulative_count >= capacity)
+            .map(|b| b.fee_per_byte_scaled + 1)
+    }
+
     pub fn highest_pending_nonce_for_sender(&self, sender: &[u8; 32]) -> Option<u64> {
         let mut max_nonce: Option<u64> = None;
         for ((s, nonce), txid) in &self.by_sender_nonce {
@@ -197,6 +645,17 @@ impl Mempool {
         }
         max_nonce
     }
+
+    /// First nonce after `confirmed_nonce` with no pending tx from `sender`:
+    /// the next one that can extend the sender's contiguous pending chain.
+    /// Pending txs above it are stuck until that nonce is filled.
+    pub fn next_contiguous_nonce(&self, sender: &[u8; 32], confirmed_nonce: u64) -> u64 {
+        let mut nonce = confirmed_nonce + 1;
+        while self.by_sender_nonce.contains_key(&(*sender, nonce)) {
+            nonce += 1;
+        }
+        nonce
+    }
 }
 
 #[cfg(test)]
@@ -211,6 +670,16 @@ mod tests {
         sk: &dilithium::SecretKey,
         nonce: u64,
         fee: u64,
+    ) -> StoredTransaction {
+        mock_stored_tx_at(pk, sk, nonce, fee, 1700000000)
+    }
+
+    fn mock_stored_tx_at(
+        pk: &dilithium::PublicKey,
+        sk: &dilithium::SecretKey,
+        nonce: u64,
+        fee: u64,
+        timestamp: u64,
     ) -> StoredTransaction {
         let addr = crate::crypto::keys::derive_address(pk);
 
@@ -222,9 +691,11 @@ mod tests {
             amount: 1_000_000,
             fee,
             nonce,
-            timestamp: 1700000000,
+            timestamp,
             referrer_address: None,
             governance_data: None,
+            proposal: None,
+            memo: None,
             signature: dilithium::Signature([0u8; 3309]),
         };
         let msg = domain_tx.signing_hash();
@@ -238,9 +709,11 @@ mod tests {
             amount: 1_000_000,
             fee,
             nonce,
-            timestamp: 1700000000,
+            timestamp,
             referrer_address: None,
             governance_data: None,
+            proposal: None,
+            memo: None,
             signature: domain_tx.signature.0.to_vec(),
         }
     }
@@ -262,22 +735,38 @@ mod tests {
     #[test]
     fn test_replace_by_fee() {
         let mut pool = Mempool::new();
-        // same keypair for all three — RBF requires same sender + nonce
+        // same keypair throughout — RBF requires same sender + nonce
         let (pk, sk) = dilithium::generate_keypair(&[0u8; 64]);
 
         let tx1 = mock_stored_tx_with_keys(&pk, &sk, 1, 100);
         pool.add_transaction(tx1).unwrap();
         assert_eq!(pool.size(), 1);
 
-        // >= 110% of 100 → 111 is enough
-        let tx2 = mock_stored_tx_with_keys(&pk, &sk, 1, 111);
-        pool.add_transaction(tx2).unwrap();
+        // 124 is a 24% bump → rejected
+        let tx2 = mock_stored_tx_with_keys(&pk, &sk, 1, 124);
+        assert_eq!(pool.conflicting_entry(&tx2).unwrap().tx.fee, 100);
+        assert!(pool.conflicting_entry(&mock_stored_tx_with_keys(&pk, &sk, 2, 124)).is_none());
+        let err = pool.add_transaction(tx2).unwrap_err();
+        assert_eq!(err, MempoolError::ReplacementUnderpriced { fee: 124, min: 125 });
+        assert!(err.to_string().contains("125%"));
+        assert_eq!(pool.get_by_sender_nonce(&crate::crypto::keys::derive_address(&pk), 1).unwrap().fee, 100);
+
+        // exactly 25% → replaces
+        let tx3 = mock_stored_tx_with_keys(&pk, &sk, 1, 125);
+        assert!(!pool.add_transaction(tx3).unwrap());
         assert_eq!(pool.size(), 1);
+        assert_eq!(pool.get_by_sender_nonce(&crate::crypto::keys::derive_address(&pk), 1).unwrap().fee, 125);
+    }
 
-        // 112 < 111 * 1.1 = 122.1 → must be rejected
-        let tx3 = mock_stored_tx_with_keys(&pk, &sk, 1, 112);
-        let result = pool.add_transaction(tx3);
-        assert!(result.is_err() || pool.size() == 1);
+    #[test]
+    fn test_min_replacement_fee() {
+        // The absolute 1-knot floor dominates for tiny fees.
+        assert_eq!(Mempool::min_replacement_fee(1), 2);
+        assert_eq!(Mempool::min_replacement_fee(3), 4);
+        assert_eq!(Mempool::min_replacement_fee(4), 5);
+        assert_eq!(Mempool::min_replacement_fee(5), 7);
+        assert_eq!(Mempool::min_replacement_fee(100), 125);
+        assert_eq!(Mempool::min_replacement_fee(u64::MAX), u64::MAX);
     }
 
     #[test]
@@ -292,10 +781,283 @@ mod tests {
         assert!(top[0].fee >= top[1].fee);
     }
 
+    #[test]
+    fn test_fee_histogram_cumulates_from_highest_rate() {
+        let mut pool = Mempool::new();
+        for (i, fee) in [100u64, 300, 100, 200].into_iter().enumerate() {
+            pool.add_transaction(mock_stored_tx(1, fee, i as u8 + 1)).unwrap();
+        }
+        let size = pool.entries.values().next().unwrap().size;
+        let hist = pool.fee_histogram();
+        let rates: Vec<u64> = hist.iter().map(|b| b.fee_per_byte_scaled).collect();
+        assert_eq!(rates, vec![300 * 10000 / size as u64, 200 * 10000 / size as u64, 100 * 10000 / size as u64]);
+        let counts: Vec<(usize, usize)> = hist.iter().map(|b| (b.count, b.cumulative_count)).collect();
+        assert_eq!(counts, vec![(1, 1), (1, 2), (2, 4)]);
+        assert_eq!(hist[2].cumulative_size, 4 * size);
+
+        // Two slots: must outbid the 200-fee tx. Five or more: everything fits.
+        assert_eq!(pool.fee_rate_for_capacity(2), Some(hist[1].fee_per_byte_scaled + 1));
+        assert_eq!(pool.fee_rate_for_capacity(3), Some(hist[2].fee_per_byte_scaled + 1));
+        assert_eq!(pool.fee_rate_for_capacity(5), None);
+        assert!(Mempool::new().fee_histogram().is_empty());
+    }
+
+    #[test]
+    fn test_top_transactions_caps_per_sender() {
+        let (pk, sk) = dilithium::generate_keypair(&[7u8; 64]);
+        let mut pool = Mempool::new().with_max_per_sender(2);
+        for nonce in 1..=4 {
+            pool.add_transaction(mock_stored_tx_with_keys(&pk, &sk, nonce, 10)).unwrap();
+        }
+        pool.add_transaction(mock_stored_tx(1, 5, 8)).unwrap();
+
+        let top = pool.get_top_transactions(10);
+        assert_eq!(top.len(), 3);
+        assert_eq!(top.iter().filter(|t| t.sender_pubkey == pk.0.to_vec()).count(), 2);
+    }
+
+    #[test]
+    fn test_top_transactions_keep_nonce_order_per_sender() {
+        let (pk_a, sk_a) = dilithium::generate_keypair(&[7u8; 64]);
+        let (pk_b, sk_b) = dilithium::generate_keypair(&[8u8; 64]);
+        let mut pool = Mempool::new();
+        // Interleaved fees: each sender's higher nonces pay more than its lower ones.
+        for (nonce, fee_a, fee_b) in [(1, 10, 15), (2, 40, 20), (3, 30, 50), (5, 90, 90)] {
+            pool.add_transaction(mock_stored_tx_with_keys(&pk_a, &sk_a, nonce, fee_a)).unwrap();
+            pool.add_transaction(mock_stored_tx_with_keys(&pk_b, &sk_b, nonce, fee_b)).unwrap();
+        }
+
+        for n in 1..=8 {
+            let top = pool.get_top_transactions(n);
+            for pk in [&pk_a, &pk_b] {
+                let nonces: Vec<u64> = top.iter().filter(|t| t.sender_pubkey == pk.0.to_vec()).map(|t| t.nonce).collect();
+                // Contiguous from the lowest pending nonce; 5 sits behind a gap.
+                assert_eq!(nonces, (1..=nonces.len() as u64).collect::<Vec<_>>(), "n={n}");
+            }
+        }
+        assert_eq!(pool.get_top_transactions(10).len(), 6);
+
+        // A's 1+2 package (50) beats B's 1 (15) alone, though B's 1 pays more than A's 1.
+        let top = pool.get_top_transactions(2);
+        assert!(top.iter().all(|t| t.sender_pubkey == pk_a.0.to_vec()));
+    }
+
+    #[test]
+    fn test_next_contiguous_nonce() {
+        let (pk, sk) = dilithium::generate_keypair(&[7u8; 64]);
+        let addr = crate::crypto::keys::derive_address(&pk);
+        let mut pool = Mempool::new();
+        assert_eq!(pool.next_contiguous_nonce(&addr, 5), 6);
+
+        for nonce in [6, 7, 9] {
+            pool.add_transaction(mock_stored_tx_with_keys(&pk, &sk, nonce, 10)).unwrap();
+        }
+        assert_eq!(pool.next_contiguous_nonce(&addr, 5), 8);
+        assert_eq!(pool.highest_pending_nonce_for_sender(&addr), Some(9));
+        // Once 6 and 7 confirm, 8 is still the gap.
+        assert_eq!(pool.next_contiguous_nonce(&addr, 7), 8);
+        assert_eq!(pool.next_contiguous_nonce(&[0u8; 32], 5), 6);
+    }
+
+    #[test]
+    fn test_min_relay_fee() {
+        let mut pool = Mempool::new().with_min_relay_fee(10);
+        assert_eq!(pool.add_transaction(mock_stored_tx(1, 9, 1)), Err(MempoolError::FeeTooLow { fee: 9, min: 10 }));
+        assert!(pool.check_transaction(&mock_stored_tx(1, 9, 1)).is_err());
+        assert!(pool.add_transaction(mock_stored_tx(1, 10, 1)).unwrap());
+
+        // Never below the consensus minimum.
+        assert_eq!(Mempool::new().with_min_relay_fee(0).min_relay_fee(), MIN_FEE_KNOTS);
+    }
+
+    #[test]
+    fn test_byte_cap_evicts_cheapest() {
+        let size = Mempool::estimate_tx_size(&mock_stored_tx(1, 1, 0));
+        let mut pool = Mempool::with_max_bytes(size * 3);
+        for (i, fee) in [(1u8, 400u64), (2, 100), (3, 300), (4, 200), (5, 500)] {
+            pool.add_transaction(mock_stored_tx(1, fee, i)).unwrap();
+            assert!(pool.total_bytes() <= pool.max_bytes());
+        }
+        assert_eq!(pool.size(), 3);
+        assert_eq!(pool.total_bytes(), size * 3);
+        let fees: Vec<u64> = pool.get_top_transactions(10).iter().map(|t| t.fee).collect();
+        assert_eq!(fees, vec![500, 400, 300]);
+
+        // Cheaper than everything left: rejected, pool untouched.
+        assert_eq!(pool.add_transaction(mock_stored_tx(1, 150, 6)), Err(MempoolError::PoolFull));
+        assert_eq!(pool.size(), 3);
+        assert_eq!(pool.total_bytes(), size * 3);
+    }
+
+    #[test]
+    fn test_total_bytes_tracks_add_and_remove() {
+        let mut pool = Mempool::new();
+        let txs: Vec<StoredTransaction> = (0..10u8).map(|i| mock_stored_tx(1, 100 + i as u64, i)).collect();
+        let expected: usize = txs.iter().map(Mempool::estimate_tx_size).sum();
+        let ids: Vec<[u8; 32]> = txs.iter().map(Mempool::compute_txid).collect();
+        for tx in txs {
+            pool.add_transaction(tx).unwrap();
+        }
+        assert_eq!(pool.total_bytes(), expected);
+        assert_eq!(pool.min_fee_per_byte_scaled(), Some(100 * 10000 / Mempool::estimate_tx_size(&mock_stored_tx(1, 1, 0)) as u64));
+
+        // Confirm in several "blocks", including ids already gone.
+        for chunk in ids.chunks(3) {
+            pool.remove_confirmed(chunk);
+            pool.remove_confirmed(chunk);
+        }
+        assert_eq!(pool.size(), 0);
+        assert_eq!(pool.total_bytes(), 0);
+        assert_eq!(pool.min_fee_per_byte_scaled(), None);
+    }
+
+    #[test]
+    fn test_prune_expired_by_ttl() {
+        let mut pool = Mempool::new().with_ttl_secs(3600);
+        // mock txs are stamped 1_700_000_000
+        pool.add_transaction(mock_stored_tx(1, 100, 1)).unwrap();
+        pool.add_transaction(mock_stored_tx(1, 100, 2)).unwrap();
+
+        assert_eq!(pool.prune_expired(1_700_000_000 + 3600), 0);
+        assert_eq!(pool.size(), 2);
+        assert_eq!(pool.prune_expired(1_700_000_000 + 3601), 2);
+        assert_eq!(pool.size(), 0);
+        assert_eq!(pool.total_bytes(), 0);
+    }
+
+    #[test]
+    fn test_far_future_timestamp_rejected() {
+        let (pk, sk) = dilithium::generate_keypair(&[9u8; 64]);
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+        let mut pool = Mempool::new();
+        assert_eq!(
+            pool.add_transaction(mock_stored_tx_at(&pk, &sk, 1, 100, now + MAX_TX_FUTURE_SECS + 60)),
+            Err(MempoolError::TimestampInFuture)
+        );
+        assert!(pool.add_transaction(mock_stored_tx_at(&pk, &sk, 1, 100, now + 60)).is_ok());
+    }
+
+    #[test]
+    fn test_persist_roundtrip_50() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = ChainDB::open(&dir.path().join("chaindata")).unwrap();
+        let path = dir.path().join(MEMPOOL_FILE);
+
+        let mut pool = Mempool::new();
+        for i in 0..50u8 {
+            pool.add_transaction(mock_stored_tx(1, 100 + i as u64, i)).unwrap();
+        }
+        pool.save_to_disk(&path).unwrap();
+
+        let mut restored = Mempool::new();
+        assert_eq!(restored.load_from_disk(&path, &db).unwrap(), 50);
+        let mut a = pool.get_all_txids();
+        let mut b = restored.get_all_txids();
+        a.sort();
+        b.sort();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_load_drops_stale_nonce() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = ChainDB::open(&dir.path().join("chaindata")).unwrap();
+        let path = dir.path().join(MEMPOOL_FILE);
+
+        let (pk, sk) = dilithium::generate_keypair(&[7u8; 64]);
+        let mut pool = Mempool::new();
+        pool.add_transaction(mock_stored_tx_with_keys(&pk, &sk, 1, 100)).unwrap();
+        pool.add_transaction(mock_stored_tx_with_keys(&pk, &sk, 2, 100)).unwrap();
+        pool.save_to_disk(&path).unwrap();
+
+        // Nonce 1 confirmed while we were down
+        let addr = crate::crypto::keys::derive_address(&pk);
+        let mut acc = db.get_account(&addr).unwrap();
+        acc.nonce = 1;
+        db.put_account(&addr, &acc).unwrap();
+
+        let mut restored = Mempool::new();
+        assert_eq!(restored.load_from_disk(&path, &db).unwrap(), 1);
+        assert_eq!(restored.highest_pending_nonce_for_sender(&addr), Some(2));
+    }
+
+    #[test]
+    fn test_load_keeps_higher_fee_of_conflicting_pair() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = ChainDB::open(&dir.path().join("chaindata")).unwrap();
+        let path = dir.path().join(MEMPOOL_FILE);
+
+        // Same sender and nonce, fees too close for replace-by-fee, plus an
+        // exact duplicate: the kind of file a racing save can leave behind.
+        let (pk, sk) = dilithium::generate_keypair(&[8u8; 64]);
+        let low = mock_stored_tx_with_keys(&pk, &sk, 1, 100);
+        let high = mock_stored_tx_with_keys(&pk, &sk, 1, 110);
+        let next = mock_stored_tx_with_keys(&pk, &sk, 2, 100);
+        let mut buf = vec![MEMPOOL_FILE_VERSION];
+        for tx in [&low, &next, &high, &next] {
+            let raw = tx.to_bytes();
+            buf.extend_from_slice(&(raw.len() as u32).to_le_bytes());
+            buf.extend_from_slice(&raw);
+        }
+        std::fs::write(&path, buf).unwrap();
+
+        let mut pool = Mempool::new();
+        assert_eq!(pool.load_from_disk(&path, &db).unwrap(), 2);
+        let addr = crate::crypto::keys::derive_address(&pk);
+        assert_eq!(pool.get_by_sender_nonce(&addr, 1).unwrap().fee, 110);
+        assert_eq!(pool.highest_pending_nonce_for_sender(&addr), Some(2));
+        assert_eq!(pool.size(), 2);
+    }
+
+    #[test]
+    fn test_load_missing_file_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = ChainDB::open(&dir.path().join("chaindata")).unwrap();
+        let mut pool = Mempool::new();
+        assert_eq!(pool.load_from_disk(&dir.path().join(MEMPOOL_FILE), &db).unwrap(), 0);
+    }
+
     #[test]
     fn test_reject_zero_fee() {
         let mut pool = Mempool::new();
         let tx = mock_stored_tx(1, 0, 1);
-        assert!(pool.add_transaction(tx).is_err());
+        assert!(matches!(pool.add_transaction(tx), Err(MempoolError::FeeTooLow { fee: 0, .. })));
+    }
+
+    #[test]
+    fn test_rejection_reasons_are_distinct() {
+        let mut pool = Mempool::new();
+        let tx = mock_stored_tx(1, 100, 1);
+        pool.add_transaction(tx.clone()).unwrap();
+        assert_eq!(pool.add_transaction(tx.clone()), Err(MempoolError::DuplicateTxid));
+
+        let mut forged = mock_stored_tx(1, 100, 2);
+        forged.signature[0] ^= 0xFF;
+        assert_eq!(pool.add_transaction(forged), Err(MempoolError::BadSignature));
+
+        assert_eq!(MempoolError::from(StateError::InsufficientBalance), MempoolError::BalanceTooLow);
+        assert_eq!(
+            MempoolError::from(StateError::InvalidNonce { expected: 5, got: 3 }),
+            MempoolError::NonceTooLow { expected: 5, got: 3 }
+        );
+        assert_eq!(
+            MempoolError::from(StateError::InvalidNonce { expected: 5, got: 7 }),
+            MempoolError::NonceGap { expected: 5, got: 7 }
+        );
+        assert!(matches!(MempoolError::from(StateError::SelfReferral), MempoolError::Invalid(_)));
+    }
+
+    #[test]
+    fn test_memo_counts_toward_txid_and_size() {
+        let plain = mock_stored_tx(1, 100, 1);
+        let mut memo = plain.clone();
+        memo.version = crate::primitives::transaction::TX_VERSION_MEMO;
+        memo.memo = Some(b"order 42".to_vec());
+        let mut other = memo.clone();
+        other.memo = Some(b"order 43".to_vec());
+
+        assert_ne!(Mempool::compute_txid(&memo), Mempool::compute_txid(&other));
+        assert_eq!(Mempool::estimate_tx_size(&memo), Mempool::estimate_tx_size(&plain) + 1 + 8);
+        assert_eq!(Mempool::estimate_tx_size(&memo), memo.to_bytes().len());
     }
 }
@@ -1,3 +1,8 @@
+pub mod banlist;
+pub mod compact;
 pub mod mempool;
 pub mod node;
+pub mod orphans;
 pub mod protocol;
+pub mod seen;
+pub mod timedata;
@@ -1,28 +1,66 @@
 use std::collections::{HashMap, HashSet};
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use std::time::{SystemTime, UNIX_EPOCH};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::fs;
 use serde_json;
+use log::{debug, error, info, warn};
 
 use tokio::net::{TcpListener, TcpStream};
 use tokio::time::timeout;
 
-use crate::config::P2P_BIND_ADDRESS;
-use crate::consensus::state::{apply_block, block_hash};
-use crate::net::protocol::{FramedStream, NetworkMessage};
-use crate::node::{ChainDB, db_common::StoredBlock};
+use crate::config::{Config, Network, P2P_BIND_ADDRESS};
+use crate::consensus::state::{
+    apply_block_with_time_bound, block_hash, is_initial_block_download,
+    validate_block_timestamp_within, StateError, IBD_MAX_FUTURE_BLOCK_SECS, MAX_FUTURE_BLOCK_SECS,
+};
+use crate::net::protocol::{
+    FramedStream, NetworkMessage, OnionAddr, ADDR_V2_VERSION, LOCAL_SERVICES, LOCATOR_VERSION, PROTOCOL_VERSION, SERVICE_BLOCK_HEADERS,
+    SERVICE_COMPACT_BLOCKS, SERVICE_INV_RELAY,
+};
+use crate::node::{ChainDB, db_common::{StoredBlock, StoredTransaction}};
+use crate::net::banlist::{
+    BanList, BAN_THRESHOLD, MISBEHAVIOR_INVALID_POW, MISBEHAVIOR_MALFORMED_BLOCK,
+    MISBEHAVIOR_DEEP_REORG, MISBEHAVIOR_OVERSIZED_ADDR,
+};
+use crate::net::compact::{compact_block, PartialBlock};
 use crate::net::mempool::Mempool;
+use crate::net::orphans::OrphanPool;
+use crate::net::seen::SeenInv;
+use crate::net::timedata::TimeData;
 use crate::rpc::server::RpcState;
+use crate::rpc::ws::{notify_block, BlockEvents};
 
-const MAX_INBOUND: usize = 128;
-const MAX_OUTBOUND: usize = 32;
+/// Default for `KNOTCOIN_MAX_INBOUND`.
+pub const DEFAULT_MAX_INBOUND: usize = 128;
+/// Default for `KNOTCOIN_MAX_OUTBOUND`.
+pub const DEFAULT_MAX_OUTBOUND: usize = 32;
+/// Upper bound on either connection limit.
+pub const MAX_CONNECTION_LIMIT: usize = 1024;
+/// Outbound connections the dialer keeps up regardless of inbound load, so
+/// a flood of inbound peers alone can't eclipse the node.
+const MIN_OUTBOUND: usize = 8;
+/// Dialer period while at or above `MIN_OUTBOUND`.
+const DIAL_INTERVAL_SECS: u64 = 10;
+/// Dialer period while below `MIN_OUTBOUND`.
+const DIAL_REFILL_INTERVAL_SECS: u64 = 2;
 const HANDSHAKE_TIMEOUT_SECS: u64 = 10;
 const MAX_HEADERS_PER_MSG: usize = 500;
 const MAX_BLOCKS_PER_MSG: usize = 50;
 const OUTBOUND_CONNECT_TIMEOUT_SECS: u64 = 3;
+const MEMPOOL_EXPIRY_INTERVAL_SECS: u64 = 60;
+const PRUNE_INTERVAL_SECS: u64 = 600;
+/// How often each connected peer is pinged, to measure round-trip time and
+/// to keep a quiet but healthy connection from hitting `IDLE_TIMEOUT_SECS`.
+const PING_INTERVAL_SECS: u64 = 30;
+/// A connection that delivers nothing at all (not even a `Pong`) for this
+/// long is treated as dead and closed, freeing its slot.
+const IDLE_TIMEOUT_SECS: u64 = 90;
+/// We never send more than 64 addresses at once; anything far beyond that
+/// is a peer trying to flood our known-address table.
+const MAX_ADDR_PER_MSG: usize = 256;
 
 /// Bootstrap seed nodes with automatic phase-out based on blockchain height
 /// Can be overridden with KNOTCOIN_BOOTSTRAP_PEERS environment variable
@@ -36,31 +74,15 @@ const PERMANENT_SEEDS: &[&str] = &[
     "223.185.60.199:9000",  // Founder's seed node (IPv4)
 ];
 
-/// Load bootstrap peers from environment variable or use defaults
-fn load_bootstrap_peers() -> Vec<String> {
-    if let Ok(peers_str) = std::env::var("KNOTCOIN_BOOTSTRAP_PEERS") {
-        peers_str
-            .split(',')
-            .map(|s| s.trim().to_string())
-            .filter(|s| !s.is_empty())
-            .collect()
-    } else {
-        Vec::new() // Return empty if env var not set
-    }
-}
-
-fn dev_allow_local() -> bool {
-    std::env::var("KNOTCOIN_DEV_ALLOW_LOCAL")
-        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
-        .unwrap_or(false)
-}
-
 /// Select seed nodes based on blockchain height
-fn get_bootstrap_peers(current_height: u32) -> Vec<String> {
-    // Priority 1: Environment variable (for privacy)
-    let env_peers = load_bootstrap_peers();
-    if !env_peers.is_empty() {
-        return env_peers;
+fn get_bootstrap_peers(network: Network, current_height: u32, configured: &[String]) -> Vec<String> {
+    // Priority 1: Operator-configured peers (for privacy)
+    if !configured.is_empty() {
+        return configured.to_vec();
+    }
+    // The built-in seeds are mainnet nodes
+    if network != Network::Mainnet {
+        return Vec::new();
     }
     
     // Priority 2: Default seeds based on network maturity
@@ -84,19 +106,323 @@ fn is_private_ip(addr: SocketAddr) -> bool {
     }
 }
 
+/// Network group used for outbound diversity and clock samples: the /16 of an IPv4 address
+/// (IPv4-mapped IPv6 included) or the /32 of an IPv6 address.
+pub(crate) fn netgroup(ip: IpAddr) -> [u8; 5] {
+    match ip.to_canonical() {
+        IpAddr::V4(v4) => {
+            let o = v4.octets();
+            [4, o[0], o[1], 0, 0]
+        }
+        IpAddr::V6(v6) => {
+            let o = v6.octets();
+            [6, o[0], o[1], o[2], o[3]]
+        }
+    }
+}
+
+/// Pick up to `want` known addresses to dial that we are not connected to.
+/// Manually added peers come first; the rest are taken one per network
+/// group not already used by an outbound peer, and only then from groups
+/// we already have.
+fn pick_outbound_candidates(
+    known: &HashMap<SocketAddr, KnownPeer>,
+    connected: &HashSet<SocketAddr>,
+    outbound: &[SocketAddr],
+    want: usize,
+) -> Vec<SocketAddr> {
+    let mut free: Vec<(&SocketAddr, &KnownPeer)> = known
+        .iter()
+        .filter(|(a, _)| !connected.contains(a))
+        .collect();
+    // Least recently tried first, so a dead address doesn't block the rest.
+    free.sort_by_key(|(a, k)| (!k.manual, k.last_attempt, **a));
+
+    let mut groups: HashSet<[u8; 5]> = outbound.iter().map(|a| netgroup(a.ip())).collect();
+    let mut picked = Vec::new();
+    for (a, k) in &free {
+        if picked.len() >= want {
+            return picked;
+        }
+        if k.manual || groups.insert(netgroup(a.ip())) {
+            picked.push(**a);
+        }
+    }
+    for (a, _) in free {
+        if picked.len() >= want {
+            break;
+        }
+        if !picked.contains(a) {
+            picked.push(*a);
+        }
+    }
+    picked
+}
+
+/// Bookkeeping for an address in `peers.json`.
+///
+/// Timestamps are unix seconds; 0 means "never".
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct KnownPeer {
+    /// When the address was first learned (seed, `addnode`, or gossip).
+    #[serde(default)]
+    pub added: u64,
+    /// Last completed handshake with this address.
+    #[serde(default)]
+    pub last_seen: u64,
+    /// Last outbound dial attempt, successful or not.
+    #[serde(default)]
+    pub last_attempt: u64,
+    /// Added with `addnode <addr> add`: dialed first and never pruned.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub manual: bool,
+    /// Last time a seed hostname resolved to this address. Such addresses are
+    /// reused for `KNOTCOIN_DNS_CACHE_SECS` when the lookup itself fails.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub dns_resolved: u64,
+}
+
+fn is_zero(v: &u64) -> bool {
+    *v == 0
+}
+
+impl KnownPeer {
+    pub fn new(now: u64) -> Self {
+        KnownPeer { added: now, last_seen: 0, last_attempt: 0, manual: false, dns_resolved: 0 }
+    }
+
+    /// Stale = neither connected to nor learned about within `max_age_secs`.
+    /// Manually added peers never go stale.
+    pub fn is_stale(&self, now: u64, max_age_secs: u64) -> bool {
+        !self.manual && self.last_seen.max(self.added) + max_age_secs < now
+    }
+}
+
+pub type KnownPeers = Arc<Mutex<HashMap<SocketAddr, KnownPeer>>>;
+pub type BannedPeers = Arc<Mutex<BanList>>;
+/// Live connections, shared with RPC for `getpeerinfo`.
+pub type Peers = Arc<Mutex<HashMap<SocketAddr, PeerInfo>>>;
+
+/// Most addresses dialed from one seed hostname per bootstrap.
+const MAX_ADDRS_PER_SEED: usize = 8;
+const DNS_LOOKUP_TIMEOUT_SECS: u64 = 5;
+
+/// Addresses a seed hostname resolved to within the last `ttl_secs`, for use
+/// while DNS is unreachable. Sorted, so rotation walks them in a stable order.
+fn cached_seed_addrs(known: &HashMap<SocketAddr, KnownPeer>, now: u64, ttl_secs: u64) -> Vec<SocketAddr> {
+    let mut out: Vec<SocketAddr> = known
+        .iter()
+        .filter(|(_, p)| p.dns_resolved != 0 && p.dns_resolved.saturating_add(ttl_secs) >= now)
+        .map(|(a, _)| *a)
+        .collect();
+    out.sort();
+    out
+}
+
+/// Up to `max` of `addrs`, starting at `offset` and wrapping around, so
+/// successive bootstraps spread over every record of a seed.
+fn rotate_addrs(addrs: &[SocketAddr], offset: usize, max: usize) -> Vec<SocketAddr> {
+    if addrs.is_empty() {
+        return Vec::new();
+    }
+    (0..addrs.len().min(max)).map(|i| addrs[(offset + i) % addrs.len()]).collect()
+}
+
+/// How often the stale-tip watchdog looks at the tip.
+const STALL_CHECK_INTERVAL_SECS: u64 = 15;
+
+/// What the stale-tip watchdog should do on one check.
+#[derive(Debug, PartialEq)]
+enum StallAction {
+    Wait,
+    /// Send every peer a fresh locator.
+    Resync,
+    /// A resync didn't help either; drop the best-advertised peer.
+    Disconnect(SocketAddr),
+}
+
+/// Decide whether sync is stalled: some peer claims more than `our_height`
+/// and the tip hasn't moved for `timeout` secs. The first response is a
+/// resync; if the tip is still stuck `timeout` secs after that (`resynced_at`),
+/// the peer advertising the most blocks is the one that isn't delivering.
+fn stall_action(
+    now: u64,
+    last_tip_update: u64,
+    timeout: u64,
+    our_height: u32,
+    peers: &[(SocketAddr, u32)],
+    resynced_at: Option<u64>,
+) -> StallAction {
+    let Some(&(best, best_height)) = peers.iter().max_by_key(|(a, h)| (*h, std::cmp::Reverse(*a))) else {
+        return StallAction::Wait;
+    };
+    if best_height <= our_height || now < last_tip_update.saturating_add(timeout) {
+        return StallAction::Wait;
+    }
+    match resynced_at {
+        None => StallAction::Resync,
+        Some(t) if now >= t.saturating_add(timeout) => StallAction::Disconnect(best),
+        Some(_) => StallAction::Wait,
+    }
+}
+
+/// Default window for `prunepeers`: one week without a successful connection.
+pub const DEFAULT_PEER_PRUNE_SECS: u64 = 7 * 24 * 3600;
+
+/// Drop stale entries from the known-address set. Returns the removed addresses.
+pub fn prune_known_peers(known: &mut HashMap<SocketAddr, KnownPeer>, now: u64, max_age_secs: u64) -> Vec<SocketAddr> {
+    let mut removed: Vec<SocketAddr> = known
+        .iter()
+        .filter(|(_, p)| p.is_stale(now, max_age_secs))
+        .map(|(a, _)| *a)
+        .collect();
+    removed.sort();
+    for a in &removed {
+        known.remove(a);
+    }
+    removed
+}
+
+/// Exponentially distributed delay (inter-arrival time of a Poisson process)
+/// for a uniform sample `u` in (0, 1]. Capped at 10x the mean so a tail sample
+/// can't hold a transaction back indefinitely.
+fn poisson_delay_ms(mean_ms: u64, u: f64) -> u64 {
+    let d = -(u.max(f64::MIN_POSITIVE)).ln() * mean_ms as f64;
+    (d as u64).min(mean_ms.saturating_mul(10))
+}
+
+fn sample_relay_delay_ms(mean_ms: u64) -> u64 {
+    // random::<f64>() is in [0, 1); flip to (0, 1] so ln() is finite.
+    let u = 1.0 - rand::random::<f64>();
+    poisson_delay_ms(mean_ms, u)
+}
+
+/// Send a message to all peers. Transactions are trickled after a random
+/// delay when `tx_delay_mean_ms > 0`, so the first peers to hear of a
+/// transaction can't reliably pin it on its origin. Everything else,
+/// blocks in particular, goes out immediately.
+pub fn relay_message(
+    broadcast_tx: &tokio::sync::broadcast::Sender<NetworkMessage>,
+    msg: NetworkMessage,
+    tx_delay_mean_ms: u64,
+) {
+    if tx_delay_mean_ms == 0 || !matches!(msg, NetworkMessage::Tx(_)) {
+        let _ = broadcast_tx.send(msg);
+        return;
+    }
+    let delay = sample_relay_delay_ms(tx_delay_mean_ms);
+    let sender = broadcast_tx.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(tokio::time::Duration::from_millis(delay)).await;
+        let _ = sender.send(msg);
+    });
+}
+
+/// Shape a broadcast for one peer: peers that advertise `SERVICE_INV_RELAY`
+/// get a txid announcement and fetch the ~5 KB body only if they lack it,
+/// and peers that advertise `SERVICE_COMPACT_BLOCKS` get a single new block
+/// as a `CompactBlock`. Older peers keep receiving the full `Tx` / `Blocks`.
+fn announce_for_peer(msg: NetworkMessage, peer_services: u64) -> NetworkMessage {
+    match msg {
+        NetworkMessage::Blocks(raws) if raws.len() == 1 && peer_services & SERVICE_COMPACT_BLOCKS != 0 => {
+            match StoredBlock::from_bytes(&raws[0]) {
+                Ok(block) => compact_block(&block),
+                Err(_) => NetworkMessage::Blocks(raws),
+            }
+        }
+        NetworkMessage::Tx(raw) if peer_services & SERVICE_INV_RELAY != 0 => {
+            match crate::node::db_common::StoredTransaction::from_bytes(&raw) {
+                Ok((stx, _)) => NetworkMessage::Inv(vec![Mempool::compute_txid_from_stored(&stx)]),
+                Err(_) => NetworkMessage::Tx(raw),
+            }
+        }
+        other => other,
+    }
+}
+
+/// Txids of a `Tx` and hashes of `Blocks`, for `SeenInv`; nothing for other messages.
+fn inv_hashes(msg: &NetworkMessage) -> Vec<[u8; 32]> {
+    match msg {
+        NetworkMessage::Tx(raw) => StoredTransaction::from_bytes(raw)
+            .map(|(stx, _)| vec![Mempool::compute_txid_from_stored(&stx)])
+            .unwrap_or_default(),
+        NetworkMessage::Blocks(raws) => raws
+            .iter()
+            .filter_map(|raw| StoredBlock::from_bytes(raw).ok())
+            .map(|b| block_hash(&b))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// This node's own reachable addresses, from `Config::advertise_addrs`.
+#[derive(Debug, Default, PartialEq)]
+pub struct AdvertisedAddrs {
+    pub ip: Vec<SocketAddr>,
+    pub onion: Vec<OnionAddr>,
+}
+
+/// Split `ip:port`, `[ipv6]:port` and `<v3>.onion:port` entries into their
+/// lists. Entries that parse as neither are returned separately.
+pub fn parse_advertised_addrs(entries: &[String]) -> (AdvertisedAddrs, Vec<String>) {
+    let mut out = AdvertisedAddrs::default();
+    let mut rejected = Vec::new();
+    for entry in entries {
+        if let Ok(a) = entry.parse::<SocketAddr>() {
+            out.ip.push(a);
+        } else if let Ok(o) = entry.parse::<OnionAddr>() {
+            out.onion.push(o);
+        } else {
+            rejected.push(entry.clone());
+        }
+    }
+    (out, rejected)
+}
+
+/// Address list for a peer speaking `peer_version`: `AddrV2` when it can
+/// decode one, otherwise a legacy `Addr` without the onion entries.
+fn addr_message(ips: Vec<SocketAddr>, onion: Vec<OnionAddr>, peer_version: u32) -> NetworkMessage {
+    if peer_version >= ADDR_V2_VERSION {
+        NetworkMessage::AddrV2 { ips, onion }
+    } else {
+        NetworkMessage::Addr(ips)
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
 pub enum P2pCommand {
     Connect(SocketAddr),
+    /// Drop the live connection to this exact address, if any.
+    Disconnect(SocketAddr),
     Broadcast(NetworkMessage),
 }
 
 #[derive(Clone)]
 pub struct P2PNode {
     pub peers: Arc<Mutex<HashMap<SocketAddr, PeerInfo>>>,
-    pub known_addrs: Arc<Mutex<HashSet<SocketAddr>>>,
+    pub known_addrs: KnownPeers,
     pub db: ChainDB,
     pub mempool: Arc<Mutex<Mempool>>,
     pub broadcast_tx: tokio::sync::broadcast::Sender<NetworkMessage>,
     pub connected_peers: Arc<std::sync::atomic::AtomicUsize>,
+    pub config: Arc<Config>,
+    pub time_data: Arc<Mutex<TimeData>>,
+    pub banned: BannedPeers,
+    pub block_events: BlockEvents,
+    pub orphans: Arc<Mutex<OrphanPool>>,
+    /// Txids and block hashes already handled, so gossip echoes are dropped.
+    pub seen_inv: Arc<Mutex<SeenInv>>,
+    /// `RpcState::shutdown_tx`; `start_on_port` returns when it fires.
+    pub shutdown: tokio::sync::broadcast::Sender<()>,
+    /// Random identity sent in our `Version`, fresh each run.
+    pub node_id: [u8; 32],
+    /// Bumped every bootstrap; where dialing starts in a seed's address list.
+    pub seed_rotation: Arc<std::sync::atomic::AtomicUsize>,
+    /// `RpcState::last_tip_update`, kept current by the stall watchdog.
+    pub last_tip_update: Arc<std::sync::atomic::AtomicU64>,
 }
 
 pub struct PeerInfo {
@@ -104,6 +430,25 @@ pub struct PeerInfo {
     pub challenge: [u8; 32],
     pub is_outbound: bool,
     pub handshake_stage: HandshakeStage,
+    /// `SERVICE_*` bits from the peer's `Version`.
+    pub services: u64,
+    /// Accumulated misbehavior points; see `misbehaving`.
+    pub ban_score: u32,
+    /// Compact block from this peer waiting on its `BlockTxn` reply.
+    pub compact_pending: Option<PartialBlock>,
+    /// Notified to make the connection task hang up.
+    pub disconnect: Arc<tokio::sync::Notify>,
+    /// Notified to send the peer a fresh `GetHeaders`/`GetBlocks` locator.
+    pub resync: Arc<tokio::sync::Notify>,
+    /// Round-trip time of the last answered `Ping`.
+    pub last_ping_ms: Option<u64>,
+    /// Nonce and send time of the `Ping` still waiting on its `Pong`.
+    pub ping_pending: Option<(u64, std::time::Instant)>,
+    /// `node_id` from the peer's `Version`; zeros until it arrives, and for
+    /// peers that don't send one.
+    pub node_id: [u8; 32],
+    /// `protocol_version` from the peer's `Version`; 1 until it arrives.
+    pub protocol_version: u32,
 }
 
 #[derive(PartialEq, Debug, Clone, Copy)]
@@ -114,30 +459,70 @@ pub enum HandshakeStage {
     Done,
 }
 
+/// Node-wide handles every connection task works with.
+#[derive(Clone)]
+struct PeerCtx {
+    db: ChainDB,
+    mempool: Arc<Mutex<Mempool>>,
+    peers: Peers,
+    known_addrs: KnownPeers,
+    broadcast_tx: tokio::sync::broadcast::Sender<NetworkMessage>,
+    config: Arc<Config>,
+    time_data: Arc<Mutex<TimeData>>,
+    banned: BannedPeers,
+    block_events: BlockEvents,
+    orphans: Arc<Mutex<OrphanPool>>,
+    seen_inv: Arc<Mutex<SeenInv>>,
+    node_id: [u8; 32],
+}
+
 impl P2PNode {
     pub fn new_from_rpc_state(s: Arc<RpcState>) -> Self {
         let (broadcast_tx, _) = tokio::sync::broadcast::channel(256);
-        let known = load_known_peers();
         P2PNode {
-            peers: Arc::new(Mutex::new(HashMap::new())),
-            known_addrs: Arc::new(Mutex::new(known)),
+            peers: s.peers.clone(),
+            known_addrs: s.known_peers.clone(),
             db: s.db.clone(),
             mempool: s.mempool.clone(),
             broadcast_tx,
             connected_peers: s.connected_peers.clone(),
+            config: s.config.clone(),
+            time_data: s.time_data.clone(),
+            banned: Arc::new(Mutex::new(BanList::load(Path::new(&s.config.data_dir), unix_now()))),
+            block_events: s.block_events.clone(),
+            orphans: Arc::new(Mutex::new(OrphanPool::new())),
+            seen_inv: Arc::new(Mutex::new(SeenInv::new())),
+            shutdown: s.shutdown_tx.clone(),
+            node_id: {
+                let mut id = [0u8; 32];
+                getrandom::getrandom(&mut id).unwrap();
+                id
+            },
+            seed_rotation: Arc::new(std::sync::atomic::AtomicUsize::new(0)),
+            last_tip_update: s.last_tip_update.clone(),
         }
     }
 
     /// Shared helper: spawn a connection handler task for an already-opened TcpStream.
     fn spawn_connection(&self, stream: TcpStream, addr: SocketAddr, is_outbound: bool) {
-        let db = self.db.clone();
-        let mempool = self.mempool.clone();
-        let peers = self.peers.clone();
-        let known_addrs = self.known_addrs.clone();
-        let broadcast_tx = self.broadcast_tx.clone();
+        let ctx = PeerCtx {
+            db: self.db.clone(),
+            mempool: self.mempool.clone(),
+            peers: self.peers.clone(),
+            known_addrs: self.known_addrs.clone(),
+            broadcast_tx: self.broadcast_tx.clone(),
+            config: self.config.clone(),
+            time_data: self.time_data.clone(),
+            banned: self.banned.clone(),
+            block_events: self.block_events.clone(),
+            orphans: self.orphans.clone(),
+            seen_inv: self.seen_inv.clone(),
+            node_id: self.node_id,
+        };
+        let s = FramedStream::new(stream).with_magic(ctx.config.network.magic());
         tokio::spawn(async move {
-            if let Err(e) = handle_connection(stream, addr, db, mempool, peers, known_addrs, broadcast_tx, is_outbound).await {
-                println!("[p2p] {addr} disconnected: {e}");
+            if let Err(e) = run_connection(s, addr, is_outbound, &ctx).await {
+                info!("[p2p] {addr} disconnected: {e}");
             }
         });
     }
@@ -164,7 +549,16 @@ impl P2PNode {
         socket.listen(1024)?;
         
         let listener = TcpListener::from_std(socket.into())?;
-        println!("[p2p] listening on {addr}");
+        info!("[p2p] listening on {addr}");
+        let (own, rejected) = parse_advertised_addrs(&self.config.advertise_addrs);
+        for entry in rejected {
+            warn!("[p2p] ignoring advertised address {entry:?}: expected ip:port or <v3>.onion:port");
+        }
+        if !own.ip.is_empty() || !own.onion.is_empty() {
+            let all: Vec<String> = own.ip.iter().map(|a| a.to_string()).chain(own.onion.iter().map(|o| o.to_string())).collect();
+            info!("[p2p] advertising {}", all.join(", "));
+        }
+        let mut shutdown_rx = self.shutdown.subscribe();
         
         // Spawn the lightweight peer count sync loop
         let cp = self.connected_peers.clone();
@@ -176,30 +570,128 @@ impl P2PNode {
             }
         });
 
+        // Periodically drop mempool transactions past their TTL.
+        let pool = self.mempool.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(tokio::time::Duration::from_secs(MEMPOOL_EXPIRY_INTERVAL_SECS)).await;
+                let removed = pool.lock().await.prune_expired(unix_now());
+                if removed > 0 {
+                    info!("[p2p] expired {removed} mempool transaction(s)");
+                }
+            }
+        });
+
+        // Stale-tip watchdog: if peers are ahead but our tip hasn't moved for
+        // `stall_timeout_secs`, ask every peer again with a fresh locator;
+        // if that doesn't help either, drop the peer we'd be syncing from.
+        if self.config.stall_timeout_secs > 0 {
+            let watchdog = self.clone();
+            tokio::spawn(async move {
+                let timeout = watchdog.config.stall_timeout_secs;
+                let mut events = watchdog.block_events.subscribe();
+                let mut check = tokio::time::interval(tokio::time::Duration::from_secs(STALL_CHECK_INTERVAL_SECS));
+                let mut resynced_at = None;
+                loop {
+                    tokio::select! {
+                        ev = events.recv() => {
+                            if let Err(tokio::sync::broadcast::error::RecvError::Closed) = ev {
+                                break;
+                            }
+                            watchdog.last_tip_update.store(unix_now(), std::sync::atomic::Ordering::Relaxed);
+                            resynced_at = None;
+                        }
+                        _ = check.tick() => {
+                            let our_height = watchdog.db.get_chain_height().unwrap_or(0);
+                            let last = watchdog.last_tip_update.load(std::sync::atomic::Ordering::Relaxed);
+                            let now = unix_now();
+                            let peers = watchdog.peers.lock().await;
+                            let heights: Vec<(SocketAddr, u32)> = peers
+                                .iter()
+                                .filter(|(_, i)| i.handshake_stage == HandshakeStage::Done)
+                                .map(|(a, i)| (*a, i.height))
+                                .collect();
+                            match stall_action(now, last, timeout, our_height, &heights, resynced_at) {
+                                StallAction::Wait => {}
+                                StallAction::Resync => {
+                                    warn!("[p2p] tip stuck at {our_height} for {}s while peers are ahead; re-requesting headers", now.saturating_sub(last));
+                                    for info in peers.values().filter(|i| i.handshake_stage == HandshakeStage::Done) {
+                                        info.resync.notify_one();
+                                    }
+                                    resynced_at = Some(now);
+                                }
+                                StallAction::Disconnect(addr) => {
+                                    warn!("[p2p] still stuck at {our_height}; disconnecting stalling peer {addr}");
+                                    if let Some(info) = peers.get(&addr) {
+                                        info.disconnect.notify_one();
+                                    }
+                                    resynced_at = None;
+                                }
+                            }
+                        }
+                    }
+                }
+            });
+        }
+
+        // Drop block bodies deeper than `prune_keep` below the tip.
+        if self.config.prune_keep > 0 {
+            let db = self.db.clone();
+            let keep = self.config.prune_keep.max(crate::config::MIN_PRUNE_KEEP);
+            tokio::spawn(async move {
+                loop {
+                    let db = db.clone();
+                    let res = tokio::task::spawn_blocking(move || {
+                        let tip = db.get_chain_height()?;
+                        db.prune_below(tip.saturating_sub(keep))
+                    })
+                    .await;
+                    match res {
+                        Ok(Ok(0)) => {}
+                        Ok(Ok(n)) => info!("[chain] pruned {n} block(s), keeping the last {keep}"),
+                        Ok(Err(e)) => error!("[chain] prune failed: {e}"),
+                        Err(e) => error!("[chain] prune task failed: {e}"),
+                    }
+                    tokio::time::sleep(tokio::time::Duration::from_secs(PRUNE_INTERVAL_SECS)).await;
+                }
+            });
+        }
+
         // Spawn the known-peer dialer loop (gradually forms a mesh beyond the seed).
-        // Tries a few known peers periodically when outbound slots are available.
+        // Below MIN_OUTBOUND it refills quickly, across distinct network groups;
+        // above it, it tries a couple of known peers now and then. Inbound peers
+        // have their own MAX_INBOUND cap, so they never take these slots.
         let dialer = self.clone();
         tokio::spawn(async move {
+            let mut interval = DIAL_INTERVAL_SECS;
             loop {
-                tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
+                tokio::time::sleep(tokio::time::Duration::from_secs(interval)).await;
 
-                let outbound_count = dialer.peers.lock().await.values().filter(|i| i.is_outbound).count();
-                if outbound_count >= MAX_OUTBOUND {
+                let (connected, outbound): (HashSet<SocketAddr>, Vec<SocketAddr>) = {
+                    let peers = dialer.peers.lock().await;
+                    (
+                        peers.keys().cloned().collect(),
+                        peers.iter().filter(|(_, i)| i.is_outbound).map(|(a, _)| *a).collect(),
+                    )
+                };
+                let max_outbound = dialer.config.max_outbound;
+                if outbound.len() >= max_outbound {
+                    interval = DIAL_INTERVAL_SECS;
                     continue;
                 }
+                let min_outbound = MIN_OUTBOUND.min(max_outbound);
+                let want = if outbound.len() < min_outbound {
+                    interval = DIAL_REFILL_INTERVAL_SECS;
+                    min_outbound - outbound.len()
+                } else {
+                    interval = DIAL_INTERVAL_SECS;
+                    2.min(max_outbound - outbound.len())
+                };
 
-                // Pick up to 2 candidates we are not already connected to.
-                let connected: HashSet<SocketAddr> = dialer.peers.lock().await.keys().cloned().collect();
-                let candidates: Vec<SocketAddr> = {
+                let candidates = {
                     let known = dialer.known_addrs.lock().await;
-                    known
-                        .iter()
-                        .cloned()
-                        .filter(|a| !connected.contains(a))
-                        .take(2)
-                        .collect()
+                    pick_outbound_candidates(&known, &connected, &outbound, want)
                 };
-
                 for addr in candidates {
                     let _ = dialer.connect(addr).await;
                     tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
@@ -213,8 +705,11 @@ impl P2PNode {
                     let (stream, peer_addr) = accept_res?;
                     let inbound_count = self.peers.lock().await.values().filter(|i| !i.is_outbound).count();
 
-                    if inbound_count >= MAX_INBOUND || (!dev_allow_local() && is_private_ip(peer_addr)) {
-                        println!("[p2p] rejecting inbound {peer_addr}");
+                    if inbound_count >= self.config.max_inbound
+                        || (!self.config.dev_allow_local && is_private_ip(peer_addr))
+                        || self.banned.lock().await.is_banned(peer_addr.ip(), unix_now())
+                    {
+                        debug!("[p2p] rejecting inbound {peer_addr}");
                         continue;
                     }
 
@@ -228,38 +723,59 @@ impl P2PNode {
                                 let node = self.clone();
                                 tokio::spawn(async move {
                                     if let Err(e) = node.connect(addr).await {
-                                        println!("[p2p] ✗ dial {addr} failed: {e}");
+                                        debug!("[p2p] ✗ dial {addr} failed: {e}");
                                     }
                                 });
                             }
+                            P2pCommand::Disconnect(addr) => {
+                                if let Some(info) = self.peers.lock().await.get(&addr) {
+                                    info!("[p2p] disconnecting {addr}");
+                                    info.disconnect.notify_one();
+                                }
+                            }
                             P2pCommand::Broadcast(msg) => {
-                                let _ = self.broadcast_tx.send(msg);
+                                // Our own tx or block: drop it if a peer echoes it back.
+                                let mut seen = self.seen_inv.lock().await;
+                                for h in inv_hashes(&msg) {
+                                    seen.insert(h, unix_now());
+                                }
+                                drop(seen);
+                                relay_message(&self.broadcast_tx, msg, self.config.tx_relay_delay_ms);
                             }
                         }
                     }
                 }
+                _ = shutdown_rx.recv() => {
+                    save_known_peers(&self.known_addrs, Path::new(&self.config.data_dir)).await;
+                    info!("[p2p] stopped; known peers saved");
+                    return Ok(());
+                }
             }
         }
     }
 
     /// Connect to a plain TCP peer directly.
     pub async fn connect(&self, addr: SocketAddr) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        if !dev_allow_local() && is_private_ip(addr) {
+        if !self.config.dev_allow_local && is_private_ip(addr) {
             return Err("refusing private/loopback peer (set KNOTCOIN_DEV_ALLOW_LOCAL=1 for local testing)".into());
         }
+        if self.banned.lock().await.is_banned(addr.ip(), unix_now()) {
+            return Err("peer is banned".into());
+        }
         let outbound_count = self.peers.lock().await.values().filter(|i| i.is_outbound).count();
-        if outbound_count >= MAX_OUTBOUND {
+        if outbound_count >= self.config.max_outbound {
             return Err("max outbound reached".into());
         }
 
         // Remember the peer for future runs and make the behavior visible in logs.
         {
+            let now = unix_now();
             let mut known = self.known_addrs.lock().await;
-            known.insert(addr);
+            known.entry(addr).or_insert_with(|| KnownPeer::new(now)).last_attempt = now;
         }
-        save_known_peers(&self.known_addrs).await;
+        save_known_peers(&self.known_addrs, Path::new(&self.config.data_dir)).await;
 
-        println!("[p2p] → dialing {addr}");
+        debug!("[p2p] → dialing {addr}");
         let stream = timeout(
             tokio::time::Duration::from_secs(OUTBOUND_CONNECT_TIMEOUT_SECS),
             TcpStream::connect(addr)
@@ -270,8 +786,43 @@ impl P2PNode {
         Ok(())
     }
 
+    /// Addresses for a `host:port` seed. Each is remembered in `known_addrs`
+    /// with the lookup time; if the lookup fails (no DNS, captive portal) the
+    /// addresses resolved within `KNOTCOIN_DNS_CACHE_SECS` are used instead.
+    async fn resolve_seed(&self, seed: &str) -> Vec<SocketAddr> {
+        let lookup = tokio::time::timeout(
+            tokio::time::Duration::from_secs(DNS_LOOKUP_TIMEOUT_SECS),
+            tokio::net::lookup_host(seed),
+        )
+        .await;
+        let now = unix_now();
+        let mut known = self.known_addrs.lock().await;
+        match lookup {
+            Ok(Ok(found)) => {
+                let mut addrs: Vec<SocketAddr> = found.collect();
+                addrs.sort();
+                addrs.dedup();
+                if !addrs.is_empty() {
+                    for a in &addrs {
+                        known.entry(*a).or_insert_with(|| KnownPeer::new(now)).dns_resolved = now;
+                    }
+                    debug!("[p2p] seed {seed} resolved to {} address(es)", addrs.len());
+                    return addrs;
+                }
+            }
+            Ok(Err(e)) => warn!("[p2p] seed {seed}: DNS lookup failed: {e}"),
+            Err(_) => warn!("[p2p] seed {seed}: DNS lookup timed out"),
+        }
+        let cached = cached_seed_addrs(&known, now, self.config.dns_cache_secs);
+        if !cached.is_empty() {
+            info!("[p2p] seed {seed}: using {} cached address(es)", cached.len());
+        }
+        cached
+    }
+
     /// Bootstrap the node by attempting connections to configured seed peers.
-    /// Connects directly to known IP seeds.
+    /// IP seeds are dialed directly; a hostname seed is resolved and up to
+    /// `MAX_ADDRS_PER_SEED` of its addresses are dialed, round-robin across runs.
     pub async fn connect_bootstrap(&self) {
         // Get current blockchain height for smart seed selection
         let current_height = match self.db.get_chain_height() {
@@ -280,30 +831,37 @@ impl P2PNode {
         };
         
         // Get appropriate seeds based on network maturity (silent phase transition)
-        let bootstrap_peers = get_bootstrap_peers(current_height);
+        let bootstrap_peers = get_bootstrap_peers(self.config.network, current_height, &self.config.bootstrap_peers);
         
         if bootstrap_peers.is_empty() {
             return;
         }
 
         let mut connected_count = 0u32;
+        let rotation = self.seed_rotation.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
 
         for (idx, seed) in bootstrap_peers.iter().enumerate() {
-            // ── Plain-IP path ─────────────────────────────────────────────
-            if let Ok(addr) = seed.parse::<SocketAddr>() {
-                // Remember the seed for future runs.
-                {
+            let addrs = match seed.parse::<SocketAddr>() {
+                // ── Plain-IP path ─────────────────────────────────────────
+                Ok(addr) => {
+                    // Remember the seed for future runs.
                     let mut known = self.known_addrs.lock().await;
-                    known.insert(addr);
+                    known.entry(addr).or_insert_with(|| KnownPeer::new(unix_now()));
+                    vec![addr]
                 }
+                // ── Hostname path ─────────────────────────────────────────
+                Err(_) => rotate_addrs(&self.resolve_seed(seed).await, rotation, MAX_ADDRS_PER_SEED),
+            };
+
+            for addr in addrs {
                 match self.connect(addr).await {
                     Ok(_) => {
-                        println!("[p2p] ✓ Seed #{}: connected to {}", idx + 1, addr);
+                        info!("[p2p] ✓ Seed #{}: connected to {}", idx + 1, addr);
                         connected_count += 1;
                     },
                     Err(e) => {
                         if !e.to_string().contains("refused") && !e.to_string().contains("10061") {
-                            println!("[p2p] Seed #{}: {e}", idx + 1);
+                            warn!("[p2p] Seed #{}: {e}", idx + 1);
                         }
                     }
                 }
@@ -313,34 +871,48 @@ impl P2PNode {
         }
 
         if connected_count > 0 {
-            println!("[p2p] bootstrap complete: {} seed(s) connected", connected_count);
+            info!("[p2p] bootstrap complete: {} seed(s) connected", connected_count);
         } else {
-            println!("[p2p] ⚠ bootstrap: no seeds reachable (check Tor/network)");
+            warn!("[p2p] ⚠ bootstrap: no seeds reachable (check Tor/network)");
         }
     }
 }
 
+/// Run a connection to the end, then release what it held however it ended:
+/// its `peers` entry, which counts against `max_inbound`/`max_outbound`,
+/// and its clock sample.
+async fn run_connection(
+    s: FramedStream,
+    addr: SocketAddr,
+    is_outbound: bool,
+    ctx: &PeerCtx,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let result = handle_connection(s, addr, is_outbound, ctx).await;
+    ctx.peers.lock().await.remove(&addr);
+    forget_time_sample(&ctx.time_data, addr).await;
+    result
+}
+
 async fn handle_connection(
-    stream: TcpStream,
+    mut s: FramedStream,
     addr: SocketAddr,
-    db: ChainDB,
-    mempool: Arc<Mutex<Mempool>>,
-    peers: Arc<Mutex<HashMap<SocketAddr, PeerInfo>>>,
-    known_addrs: Arc<Mutex<HashSet<SocketAddr>>>,
-    broadcast_tx: tokio::sync::broadcast::Sender<NetworkMessage>,
     is_outbound: bool,
+    ctx: &PeerCtx,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let mut s = FramedStream::new(stream);
+    let PeerCtx { db, peers, known_addrs, broadcast_tx, config, time_data, node_id, .. } = ctx;
+    let node_id = *node_id;
     let mut broadcast_rx = broadcast_tx.subscribe();
     let our_height = db.get_chain_height().unwrap_or(0);
 
     if is_outbound {
-        println!("[p2p] handshake start (outbound) {addr}");
+        debug!("[p2p] handshake start (outbound) {addr}");
     } else {
-        println!("[p2p] handshake start (inbound) {addr}");
+        debug!("[p2p] handshake start (inbound) {addr}");
     }
 
     // 1. Initial Handshake
+    let disconnect = Arc::new(tokio::sync::Notify::new());
+    let resync = Arc::new(tokio::sync::Notify::new());
     {
         let mut p = peers.lock().await;
         p.insert(addr, PeerInfo {
@@ -348,12 +920,28 @@ async fn handle_connection(
             challenge: [0u8; 32],
             is_outbound,
             handshake_stage: HandshakeStage::Version,
+            services: 0,
+            ban_score: 0,
+            compact_pending: None,
+            disconnect: disconnect.clone(),
+            resync: resync.clone(),
+            last_ping_ms: None,
+            ping_pending: None,
+            node_id: [0u8; 32],
+            protocol_version: 1,
         });
     }
+    let ping_every = tokio::time::Duration::from_secs(PING_INTERVAL_SECS);
+    let mut ping_timer = tokio::time::interval_at(tokio::time::Instant::now() + ping_every, ping_every);
 
-    s.send(&NetworkMessage::Version { height: our_height }).await?;
+    s.send(&NetworkMessage::Version { height: our_height, timestamp: unix_now(), services: LOCAL_SERVICES, node_id, protocol_version: PROTOCOL_VERSION }).await?;
+    let mut peer_services = 0u64;
+    let mut peer_version = 1u32;
+    let mut peer_offset: Option<i64> = None;
 
     let deadline = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + HANDSHAKE_TIMEOUT_SECS;
+    let idle_limit = tokio::time::Duration::from_secs(IDLE_TIMEOUT_SECS);
+    let mut last_recv = tokio::time::Instant::now();
 
     loop {
         tokio::select! {
@@ -362,6 +950,7 @@ async fn handle_connection(
                     Some(m) => m,
                     None => break,
                 };
+                last_recv = tokio::time::Instant::now();
 
                 let is_done = peers.lock().await.get(&addr).map(|i| i.handshake_stage == HandshakeStage::Done).unwrap_or(false);
                 
@@ -370,10 +959,26 @@ async fn handle_connection(
                 }
 
                 match (msg, is_done) {
-                    (NetworkMessage::Version { height: peer_height }, false) => {
+                    (NetworkMessage::Version { height: peer_height, timestamp: peer_time, services, node_id: peer_id, protocol_version }, false) => {
+                        {
+                            let mut p = peers.lock().await;
+                            if let Some(reason) = duplicate_connection(&p, addr, peer_id, node_id) {
+                                return Err(reason.into());
+                            }
+                            if let Some(info) = p.get_mut(&addr) {
+                                info.node_id = peer_id;
+                            }
+                        }
+                        peer_services = services;
+                        peer_version = protocol_version;
+                        if peer_time != 0 {
+                            peer_offset = Some(peer_time as i64 - unix_now() as i64);
+                        }
                         let mut p = peers.lock().await;
                         if let Some(info) = p.get_mut(&addr) {
                             info.height = peer_height;
+                            info.services = services;
+                            info.protocol_version = protocol_version;
                             info.handshake_stage = HandshakeStage::Challenge;
                             let mut challenge = [0u8; 32];
                             getrandom::getrandom(&mut challenge).unwrap();
@@ -404,296 +1009,328 @@ async fn handle_connection(
                                 info.handshake_stage = HandshakeStage::Done;
                             }
                         }
+                        // Only outbound addresses are dialable; inbound ones use ephemeral ports.
+                        if is_outbound {
+                            let mut known = known_addrs.lock().await;
+                            if let Some(k) = known.get_mut(&addr) {
+                                k.last_seen = unix_now();
+                            }
+                        }
+                        // Only peers we chose, once they've proven the handshake,
+                        // get a say in our clock.
+                        if is_outbound && let Some(offset) = peer_offset {
+                            let mut td = time_data.lock().await;
+                            let was_skewed = td.is_skewed();
+                            td.add_sample(addr, offset);
+                            crate::consensus::state::set_time_offset(td.median_offset().unwrap_or(0));
+                            if !was_skewed && let Some(w) = td.skew_warning() {
+                                warn!("[p2p] ⚠ {w}");
+                            }
+                        }
                         
                         let our_height = db.get_chain_height().unwrap_or(0);
                         let peer_height = peers.lock().await.get(&addr).map(|i| i.height).unwrap_or(0);
                         
                         if peer_height > our_height {
-                            println!("[p2p] ✓ {addr} connected (peer: {peer_height}, us: {our_height}) - syncing...");
+                            info!("[p2p] ✓ {addr} connected (peer: {peer_height}, us: {our_height}) - syncing...");
                         } else {
-                            println!("[p2p] ✓ {addr} connected (peer: {peer_height}, us: {our_height})");
+                            info!("[p2p] ✓ {addr} connected (peer: {peer_height}, us: {our_height})");
                         }
                         
                         // Start sync from our current tip
-                        let tip = db.get_tip().ok().flatten().unwrap_or([0u8; 32]);
-                        s.send(&NetworkMessage::GetHeaders { from_hash: tip }).await?;
+                        s.send(&sync_request(peer_services, peer_version, block_locator(db))).await?;
 
                         // Peer discovery: send a small list of known peers after handshake.
                         // This helps form a mesh and reduces dependency on bootstrap seeds.
                         let mut list: Vec<SocketAddr> = {
                             let known = known_addrs.lock().await;
-                            known.iter().cloned().filter(|a| *a != addr).take(32).collect()
+                            known.keys().cloned().filter(|a| *a != addr).take(32).collect()
                         };
                         // Also include any currently connected peers (excluding the recipient).
                         let connected_peers: Vec<SocketAddr> = peers.lock().await.keys().cloned().filter(|a| *a != addr).take(32).collect();
                         list.extend(connected_peers);
+                        // And our own advertised addresses, so inbound peers learn where to dial us.
+                        let (own, _) = parse_advertised_addrs(&config.advertise_addrs);
+                        list.extend(own.ip);
                         list.sort();
                         list.dedup();
-                        if !list.is_empty() {
-                            let _ = s.send(&NetworkMessage::Addr(list)).await;
+                        if !list.is_empty() || !own.onion.is_empty() {
+                            let _ = s.send(&addr_message(list, own.onion, peer_version)).await;
                         }
                         
                         // Request peers from the connected node (Bitcoin-style peer discovery)
                         let _ = s.send(&NetworkMessage::GetAddr).await;
                     }
                     (m, true) => {
-                        handle_msg(m, &mut s, addr, &db, &mempool, &peers, &known_addrs, &broadcast_tx).await?;
+                        handle_msg(m, &mut s, addr, ctx).await?;
                     }
                     _ => {}
                 }
             }
             local_msg = broadcast_rx.recv() => {
                 if let Ok(m) = local_msg {
-                    s.send(&m).await?;
+                    s.send(&announce_for_peer(m, peer_services)).await?;
+                }
+            }
+            _ = ping_timer.tick() => {
+                let nonce = {
+                    let mut p = peers.lock().await;
+                    match p.get_mut(&addr) {
+                        Some(info) if info.handshake_stage == HandshakeStage::Done => {
+                            let mut buf = [0u8; 8];
+                            getrandom::getrandom(&mut buf).unwrap();
+                            let nonce = u64::from_le_bytes(buf);
+                            info.ping_pending = Some((nonce, std::time::Instant::now()));
+                            Some(nonce)
+                        }
+                        _ => None,
+                    }
+                };
+                if let Some(nonce) = nonce {
+                    s.send(&NetworkMessage::Ping(nonce)).await?;
                 }
             }
+            _ = tokio::time::sleep_until(last_recv + idle_limit) => {
+                return Err(format!("idle timeout: nothing received for {IDLE_TIMEOUT_SECS}s").into());
+            }
+            _ = resync.notified() => {
+                s.send(&peer_sync_request(peers, addr, db).await).await?;
+            }
+            _ = disconnect.notified() => break,
         }
     }
+    Ok(())
+}
+
+/// Drop `addr`'s clock sample once its connection is gone and republish the
+/// median.
+async fn forget_time_sample(time_data: &Mutex<TimeData>, addr: SocketAddr) {
+    let mut td = time_data.lock().await;
+    td.remove_sample(addr);
+    crate::consensus::state::set_time_offset(td.median_offset().unwrap_or(0));
+}
 
+/// Why a handshake from `addr` advertising `peer_id` should be dropped: the
+/// peer is this node, or another connection already reached the same node
+/// (e.g. dialed once by IP and once by hostname). Zero ids are never matched.
+fn duplicate_connection(peers: &HashMap<SocketAddr, PeerInfo>, addr: SocketAddr, peer_id: [u8; 32], our_id: [u8; 32]) -> Option<String> {
+    if peer_id == [0u8; 32] {
+        return None;
+    }
+    if peer_id == our_id {
+        return Some("connected to self".to_string());
+    }
+    peers
+        .iter()
+        .find(|(a, info)| **a != addr && info.node_id == peer_id)
+        .map(|(a, _)| format!("duplicate connection (already connected as {a})"))
+}
+
+/// Record `addr`'s round-trip time if `nonce` answers its outstanding ping.
+async fn record_pong(peers: &Peers, addr: SocketAddr, nonce: u64) {
+    if let Some(info) = peers.lock().await.get_mut(&addr)
+        && let Some((pending, sent)) = info.ping_pending
+        && pending == nonce
     {
-        let mut p = peers.lock().await;
-        p.remove(&addr);
+        info.last_ping_ms = Some(sent.elapsed().as_millis() as u64);
+        info.ping_pending = None;
     }
-    Ok(())
 }
 
 async fn handle_msg(
     msg: NetworkMessage,
     s: &mut FramedStream,
     addr: SocketAddr,
-    db: &ChainDB,
-    mempool: &Arc<Mutex<Mempool>>,
-    _peers: &Arc<Mutex<HashMap<SocketAddr, PeerInfo>>>,
-    known_addrs: &Arc<Mutex<HashSet<SocketAddr>>>,
-    broadcast_tx: &tokio::sync::broadcast::Sender<NetworkMessage>,
+    ctx: &PeerCtx,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let PeerCtx { db, mempool, peers, known_addrs, broadcast_tx, config, banned, seen_inv, .. } = ctx;
     match msg {
         NetworkMessage::Ping(n) => {
             let _ = s.send(&NetworkMessage::Pong(n)).await;
         }
-        NetworkMessage::GetHeaders { from_hash } => {
-            let tip_height = db.get_chain_height().unwrap_or(0);
-            let start = find_height_of_hash(db, &from_hash).unwrap_or(0).saturating_add(1);
-            let end = (start + MAX_HEADERS_PER_MSG as u32 - 1).min(tip_height);
-            let mut hashes = Vec::new();
-            for h in start..=end {
-                if let Ok(Some(hash)) = db.get_block_hash_by_height(h) {
-                    hashes.push(hash);
-                }
-            }
+        NetworkMessage::Pong(n) => record_pong(peers, addr, n).await,
+        NetworkMessage::GetHeaders { locator } => {
+            let hashes = hashes_after(db, &locator);
             if !hashes.is_empty() {
                 s.send(&NetworkMessage::Headers(hashes)).await?;
             }
         }
+        NetworkMessage::GetBlockHeaders { locator } => {
+            let headers: Vec<[u8; 148]> = hashes_after(db, &locator)
+                .iter()
+                .map_while(|h| db.get_block_header(h).ok().flatten())
+                .map(|b| b.header_bytes())
+                .collect();
+            s.send(&NetworkMessage::BlockHeaders(headers)).await?;
+        }
         NetworkMessage::Headers(hashes) => {
             if hashes.is_empty() {
                 // No more headers - we're synced!
                 let our_height = db.get_chain_height().unwrap_or(0);
-                println!("[p2p] ✓ {addr} sync complete at height {our_height}");
+                info!("[p2p] ✓ {addr} sync complete at height {our_height}");
                 return Ok(());
             }
-            
-            // Filter blocks we don't have yet
-            let needed: Vec<[u8; 32]> = hashes.into_iter()
-                .filter(|h| db.get_block(h).ok().flatten().is_none())
-                .collect();
-            
-            if needed.is_empty() {
-                // We have all these blocks, continue syncing
-                let tip = db.get_tip().ok().flatten().unwrap_or([0u8; 32]);
-                s.send(&NetworkMessage::GetHeaders { from_hash: tip }).await?;
+            request_missing_blocks(hashes, s, addr, db, peers).await?;
+        }
+        NetworkMessage::BlockHeaders(headers) => {
+            if headers.is_empty() {
+                let our_height = db.get_chain_height().unwrap_or(0);
+                info!("[p2p] ✓ {addr} sync complete at height {our_height}");
                 return Ok(());
             }
-            
-            println!("[p2p] ← {addr} requesting {} block(s)...", needed.len());
-            
-            // Request blocks in chunks for smooth download
-            for chunk in needed.chunks(MAX_BLOCKS_PER_MSG) {
-                s.send(&NetworkMessage::GetBlocks { hashes: chunk.to_vec() }).await?;
+            match verify_pool(config).install(|| validate_header_chain(db, &headers)) {
+                Ok(hashes) => request_missing_blocks(hashes, s, addr, db, peers).await?,
+                Err(HeaderChainError::UnknownParent) => {
+                    // Forked below the tip we asked from; nothing to check against.
+                    warn!("[p2p] {addr} sent headers that don't connect to our chain");
+                }
+                Err(HeaderChainError::Disconnected) => {
+                    warn!("[p2p] {addr} sent headers that aren't a chain");
+                    if misbehaving(peers, banned, Path::new(&config.data_dir), addr, MISBEHAVIOR_MALFORMED_BLOCK, "disconnected headers").await {
+                        return Err("banned for misbehavior".into());
+                    }
+                }
+                Err(HeaderChainError::InvalidPow(height)) => {
+                    warn!("[p2p] {addr} header {height} failed PoW");
+                    if misbehaving(peers, banned, Path::new(&config.data_dir), addr, MISBEHAVIOR_INVALID_POW, "invalid header PoW").await {
+                        return Err("banned for misbehavior".into());
+                    }
+                }
             }
         }
         NetworkMessage::GetBlocks { hashes } => {
+            // Pruned blocks have no body to serve; leave them out rather than
+            // send a header that would parse as an empty block.
             let blocks: Vec<Vec<u8>> = hashes.iter()
+                .filter(|h| db.is_pruned(h).ok() == Some(false))
                 .filter_map(|h| db.get_block(h).ok().flatten())
                 .map(|b| b.to_bytes())
                 .collect();
-            if !blocks.is_empty() {
-                s.send(&NetworkMessage::Blocks(blocks)).await?;
-            }
+            s.send(&NetworkMessage::Blocks(blocks)).await?;
         }
         NetworkMessage::Blocks(raws) => {
-            // OPTIMIZATION: Fast, smooth, error-free block sync
-            // Design: Parallel PoW verification + Sequential consensus application
-            use rayon::prelude::*;
-            
-            if raws.is_empty() {
+            handle_blocks(raws, s, addr, ctx).await?;
+        }
+        NetworkMessage::CompactBlock { header, short_ids } => {
+            let Ok(block) = StoredBlock::header_only_from_bytes(&header) else {
                 return Ok(());
-            }
-            
-            // Step 1: Parse all blocks (fast, sequential)
-            let mut parsed: Vec<(StoredBlock, [u8; 32])> = Vec::new();
-            for raw in &raws {
-                match StoredBlock::from_bytes(raw) {
-                    Ok(block) => {
-                        let h = block_hash(&block);
-                        parsed.push((block, h));
-                    }
-                    Err(e) => {
-                        eprintln!("[p2p] {addr} sent malformed block: {e}");
-                        continue; // Skip bad blocks, don't disconnect
-                    }
-                }
-            }
-            
-            if parsed.is_empty() {
+            };
+            let hash = block_hash(&block);
+            if seen_inv.lock().await.contains(&hash, unix_now()) || db.get_block(&hash).ok().flatten().is_some() {
                 return Ok(());
             }
-            
-            // Step 2: Filter out blocks we already have
-            let mut new_blocks: Vec<(StoredBlock, [u8; 32])> = Vec::new();
-            for (block, h) in parsed {
-                match db.get_block(&h) {
-                    Ok(Some(_)) => continue, // Already have it
-                    Ok(None) => new_blocks.push((block, h)),
-                    Err(e) => {
-                        println!("[p2p] database error checking block: {e}");
-                        continue;
-                    }
+            let partial = {
+                let pool = mempool.lock().await;
+                let candidates = pool.get_all_txids().into_iter().filter_map(|id| pool.get(&id).map(|tx| (id, tx)));
+                PartialBlock::new(block, hash, &short_ids, candidates)
+            };
+            let missing = partial.missing();
+            if missing.is_empty() {
+                finish_compact_block(partial, s, addr, ctx).await?;
+            } else {
+                if let Some(info) = peers.lock().await.get_mut(&addr) {
+                    info.compact_pending = Some(partial);
                 }
+                s.send(&NetworkMessage::GetBlockTxn { block_hash: hash, indexes: missing }).await?;
             }
-            
-            if new_blocks.is_empty() {
+        }
+        NetworkMessage::GetBlockTxn { block_hash, indexes } => {
+            if db.is_pruned(&block_hash).ok() != Some(false) {
                 return Ok(());
             }
-            
-            // Step 3: Sort by height (CRITICAL for consensus)
-            new_blocks.sort_by_key(|(block, _)| u32::from_le_bytes(block.block_height));
-            
-            // Step 4: Verify parent chain exists
-            let mut valid_chain: Vec<(StoredBlock, [u8; 32])> = Vec::new();
-            for (block, h) in new_blocks {
-                let height = u32::from_le_bytes(block.block_height);
-                
-                // Genesis block has no parent
-                if height == 0 {
-                    valid_chain.push((block, h));
-                    continue;
-                }
-                
-                // Check parent exists
-                match db.get_block(&block.previous_hash) {
-                    Ok(Some(_)) => {
-                        valid_chain.push((block, h));
-                    }
-                    Ok(None) => {
-                        // Parent missing - request it
-                        eprintln!("[p2p] {addr} block {} missing parent, requesting...", height);
-                        let _ = s.send(&NetworkMessage::GetBlocks { 
-                            hashes: vec![block.previous_hash] 
-                        }).await;
-                        // Don't process this block yet
-                        continue;
-                    }
-                    Err(e) => {
-                        eprintln!("[p2p] database error checking parent: {e}");
-                        continue;
-                    }
-                }
-            }
-            
-            if valid_chain.is_empty() {
-                return Ok(());
+            if let Ok(Some(block)) = db.get_block(&block_hash) {
+                let txs: Vec<Vec<u8>> = indexes.iter()
+                    .filter_map(|&i| block.tx_data.get(i as usize))
+                    .map(|tx| tx.to_bytes())
+                    .collect();
+                s.send(&NetworkMessage::BlockTxn { block_hash, txs }).await?;
             }
-            
-            // Step 5: Parallel PoW verification (FAST)
-            // This is the bottleneck - use all CPU cores
-            let db_clone = db.clone();
-            let verified: Vec<(StoredBlock, [u8; 32])> = valid_chain.into_par_iter()
-                .filter_map(|(block, h)| {
-                    match crate::consensus::state::verify_block_pow(&block, &db_clone) {
-                        Ok(_) => Some((block, h)),
-                        Err(e) => {
-                            let height = u32::from_le_bytes(block.block_height);
-                            eprintln!("[p2p] {addr} block {} failed PoW: {e}", height);
-                            None
-                        }
-                    }
+        }
+        NetworkMessage::BlockTxn { block_hash, txs } => {
+            let pending = {
+                let mut p = peers.lock().await;
+                p.get_mut(&addr).and_then(|info| {
+                    info.compact_pending.take_if(|pb| pb.hash == block_hash)
                 })
-                .collect();
-            
-            if verified.is_empty() {
-                eprintln!("[p2p] {addr} sent blocks with invalid PoW");
+            };
+            let Some(mut partial) = pending else {
                 return Ok(());
+            };
+            let parsed: Option<Vec<StoredTransaction>> = txs.iter()
+                .map(|raw| StoredTransaction::from_bytes(raw).ok().map(|(tx, _)| tx))
+                .collect();
+            if parsed.is_some_and(|txs| partial.fill(txs)) {
+                finish_compact_block(partial, s, addr, ctx).await?;
+            } else {
+                s.send(&NetworkMessage::GetBlocks { hashes: vec![block_hash] }).await?;
             }
-            
-            // Step 6: Re-sort after parallel processing
-            let mut verified_sorted = verified;
-            verified_sorted.sort_by_key(|(block, _)| u32::from_le_bytes(block.block_height));
-            
-            // Step 7: Apply blocks sequentially (CONSENSUS-CRITICAL)
-            let mut applied = 0;
-            let mut failed = 0;
-            for (block, _hash) in verified_sorted {
-                let height = u32::from_le_bytes(block.block_height);
-                
-                match apply_block(db, &block) {
-                    Ok(_) => {
-                        applied += 1;
-                    }
-                    Err(e) => {
-                        println!("[p2p] {addr} block {} apply failed: {e}", height);
-                        failed += 1;
-                        // Stop processing on first failure (chain broken)
-                        break;
-                    }
-                }
+        }
+        NetworkMessage::Tx(raw) => {
+            let Ok((stx, _)) = StoredTransaction::from_bytes(&raw) else {
+                return Ok(());
+            };
+            let txid = Mempool::compute_txid_from_stored(&stx);
+            if seen_inv.lock().await.contains(&txid, unix_now()) {
+                return Ok(());
             }
-            
-            if applied > 0 {
-                let new_height = db.get_chain_height().unwrap_or(0);
-                println!("[p2p] ✓ {addr} synced +{applied} blocks → height {new_height}");
-                
-                // Continue syncing if we got a full batch
-                if applied >= MAX_BLOCKS_PER_MSG {
-                    let tip = db.get_tip().ok().flatten().unwrap_or([0u8; 32]);
-                    let _ = s.send(&NetworkMessage::GetHeaders { from_hash: tip }).await;
-                }
+            if mempool.lock().await.add_transaction(stx).is_ok() {
+                seen_inv.lock().await.insert(txid, unix_now());
+                relay_message(broadcast_tx, NetworkMessage::Tx(raw), config.tx_relay_delay_ms);
             }
-            
-            if failed > 0 {
-                println!("[p2p] ✗ {addr} sync stopped: {failed} block(s) failed validation");
+        }
+        NetworkMessage::Inv(txids) => {
+            let needed: Vec<[u8; 32]> = {
+                let seen = seen_inv.lock().await;
+                let pool = mempool.lock().await;
+                let now = unix_now();
+                txids.into_iter().filter(|id| !seen.contains(id, now) && !pool.contains(id)).collect()
+            };
+            if !needed.is_empty() {
+                s.send(&NetworkMessage::GetData(needed)).await?;
             }
         }
-        NetworkMessage::Tx(raw) => {
-            let mut pool = mempool.lock().await;
-            if let Ok(stx) = crate::node::db_common::StoredTransaction::from_bytes(&raw)
-                && pool.add_transaction(stx.0).is_ok() {
-                let _ = broadcast_tx.send(NetworkMessage::Tx(raw));
+        NetworkMessage::GetData(txids) => {
+            let raws: Vec<Vec<u8>> = {
+                let pool = mempool.lock().await;
+                txids.iter().filter_map(|id| pool.get(id)).map(|tx| tx.to_bytes()).collect()
+            };
+            for raw in raws {
+                s.send(&NetworkMessage::Tx(raw)).await?;
             }
         }
-        NetworkMessage::Addr(addrs) => {
+        // We can't dial through Tor, so onion entries are neither kept nor relayed.
+        NetworkMessage::Addr(addrs) | NetworkMessage::AddrV2 { ips: addrs, .. } => {
+            if addrs.len() > MAX_ADDR_PER_MSG {
+                warn!("[p2p] {addr} sent oversized addr ({} entries)", addrs.len());
+                if misbehaving(peers, banned, Path::new(&config.data_dir), addr, MISBEHAVIOR_OVERSIZED_ADDR, "oversized addr").await {
+                    return Err("banned for misbehavior".into());
+                }
+                return Ok(());
+            }
             let mut newly_learned: Vec<SocketAddr> = Vec::new();
             {
+                let now = unix_now();
                 let mut known = known_addrs.lock().await;
                 for a in addrs {
                     if a == addr {
                         continue;
                     }
-                    if !dev_allow_local() && is_private_ip(a) {
+                    if !config.dev_allow_local && is_private_ip(a) {
                         continue;
                     }
                     // Hard cap known peers to avoid unbounded growth.
                     if known.len() >= 2048 {
                         break;
                     }
-                    if known.insert(a) {
+                    if let std::collections::hash_map::Entry::Vacant(e) = known.entry(a) {
+                        e.insert(KnownPeer::new(now));
                         newly_learned.push(a);
                     }
                 }
             }
 
             if !newly_learned.is_empty() {
-                save_known_peers(known_addrs).await;
+                save_known_peers(known_addrs, Path::new(&config.data_dir)).await;
 
                 // Gossip the newly learned addresses (bounded) to other peers.
                 newly_learned.sort();
@@ -702,13 +1339,18 @@ async fn handle_msg(
             }
         }
         NetworkMessage::GetAddr => {
-            // Respond with our known peers (up to 64)
-            let list: Vec<SocketAddr> = {
+            // Respond with our known peers (up to 64) and our own addresses
+            let (own, _) = parse_advertised_addrs(&config.advertise_addrs);
+            let mut list: Vec<SocketAddr> = {
                 let known = known_addrs.lock().await;
-                known.iter().cloned().filter(|a| *a != addr).take(64).collect()
+                known.keys().cloned().filter(|a| *a != addr).take(64).collect()
             };
-            if !list.is_empty() {
-                let _ = s.send(&NetworkMessage::Addr(list)).await;
+            list.extend(own.ip);
+            list.sort();
+            list.dedup();
+            if !list.is_empty() || !own.onion.is_empty() {
+                let version = peers.lock().await.get(&addr).map_or(1, |info| info.protocol_version);
+                let _ = s.send(&addr_message(list, own.onion, version)).await;
             }
         }
         _ => {}
@@ -716,40 +1358,352 @@ async fn handle_msg(
     Ok(())
 }
 
-fn data_dir_path() -> PathBuf {
-    if let Ok(d) = std::env::var("KNOTCOIN_DATA_DIR") {
-        return PathBuf::from(d);
+/// Connect blocks received in a `Blocks` message (or rebuilt from a compact block).
+async fn handle_blocks(
+    raws: Vec<Vec<u8>>,
+    s: &mut FramedStream,
+    addr: SocketAddr,
+    ctx: &PeerCtx,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let PeerCtx { db, peers, config, banned, block_events, orphans, seen_inv, .. } = ctx;
+    // OPTIMIZATION: Fast, smooth, error-free block sync
+    // Design: Parallel PoW verification + Sequential consensus application
+    use rayon::prelude::*;
+    
+    if raws.is_empty() {
+        return Ok(());
+    }
+    
+    // Step 1: Parse all blocks (fast, sequential)
+    let mut parsed: Vec<(StoredBlock, [u8; 32])> = Vec::new();
+    let mut malformed = 0u32;
+    for raw in &raws {
+        match StoredBlock::from_bytes(raw) {
+            Ok(block) => {
+                let h = block_hash(&block);
+                parsed.push((block, h));
+            }
+            Err(e) => {
+                warn!("[p2p] {addr} sent malformed block: {e}");
+                malformed += 1;
+                continue; // Skip bad blocks; the ban score decides on disconnect
+            }
+        }
+    }
+    if malformed > 0
+        && misbehaving(peers, banned, Path::new(&config.data_dir), addr, malformed * MISBEHAVIOR_MALFORMED_BLOCK, "malformed block").await
+    {
+        return Err("banned for misbehavior".into());
+    }
+    
+    if parsed.is_empty() {
+        return Ok(());
+    }
+    
+    // Step 2: Filter out blocks we already have
+    let mut new_blocks: Vec<(StoredBlock, [u8; 32])> = Vec::new();
+    let seen: HashSet<[u8; 32]> = {
+        let seen = seen_inv.lock().await;
+        parsed.iter().map(|(_, h)| *h).filter(|h| seen.contains(h, unix_now())).collect()
+    };
+    for (block, h) in parsed {
+        if seen.contains(&h) {
+            continue;
+        }
+        match db.get_block(&h) {
+            Ok(Some(_)) => continue, // Already have it
+            Ok(None) => new_blocks.push((block, h)),
+            Err(e) => {
+                error!("[p2p] database error checking block: {e}");
+                continue;
+            }
+        }
+    }
+    
+    if new_blocks.is_empty() {
+        return Ok(());
+    }
+    
+    // Step 3: Sort by height (CRITICAL for consensus)
+    new_blocks.sort_by_key(|(block, _)| u32::from_le_bytes(block.block_height));
+    
+    // Step 4: Verify parent chain exists. A parent earlier in this
+    // batch counts; anything else waits in the orphan pool.
+    let batch: HashSet<[u8; 32]> = new_blocks.iter().map(|(_, h)| *h).collect();
+    let mut valid_chain: Vec<(StoredBlock, [u8; 32], bool)> = Vec::new();
+    let mut missing_parents: Vec<[u8; 32]> = Vec::new();
+    for (block, h) in new_blocks {
+        let height = u32::from_le_bytes(block.block_height);
+        
+        // Genesis block has no parent
+        if height == 0 || batch.contains(&block.previous_hash) {
+            valid_chain.push((block, h, true));
+            continue;
+        }
+        
+        // Check parent exists
+        match db.get_block(&block.previous_hash) {
+            Ok(Some(_)) => {
+                valid_chain.push((block, h, true));
+            }
+            Ok(None) => {
+                // Parent missing - request it, keep the child for later
+                debug!("[p2p] {addr} block {} missing parent, requesting...", height);
+                if !missing_parents.contains(&block.previous_hash) {
+                    missing_parents.push(block.previous_hash);
+                }
+                valid_chain.push((block, h, false));
+            }
+            Err(e) => {
+                error!("[p2p] database error checking parent: {e}");
+                continue;
+            }
+        }
+    }
+    if !missing_parents.is_empty() {
+        let _ = s.send(&NetworkMessage::GetBlocks { hashes: missing_parents }).await;
+    }
+    
+    if valid_chain.is_empty() {
+        return Ok(());
+    }
+    
+    // Step 5: Parallel PoW verification (FAST)
+    // This is the bottleneck - spread it over the verification pool.
+    // Each block is checked with the PONC rounds in effect at its parent. A
+    // block behind an unconnected governance block can't be checked yet;
+    // applying it does, exactly when it extends the tip and against every
+    // count the branch's governance could enact when it lands on a side
+    // branch, before any work is recorded. Orphans are checked with the
+    // tip's rounds and dropped quietly if they fail.
+    let batch_blocks: Vec<StoredBlock> = valid_chain.iter().map(|(b, _, _)| b.clone()).collect();
+    let rounds = crate::consensus::state::pow_rounds_for_batch(db, &batch_blocks)?;
+    let tip_rounds = db.get_governance_params()?.ponc_rounds;
+    let db_clone = db.clone();
+    let checked: Vec<_> = verify_pool(config).install(|| {
+        valid_chain.into_par_iter()
+            .zip(rounds)
+            .map(|((block, h, connectable), rounds)| {
+                let res = match rounds {
+                    None if connectable => Ok(()),
+                    r => crate::consensus::state::verify_block_pow_with_rounds(&block, &db_clone, r.unwrap_or(tip_rounds)),
+                };
+                (block, h, connectable, rounds, res)
+            })
+            .collect()
+    });
+    let mut invalid_pow = 0u32;
+    let mut verified: Vec<(StoredBlock, [u8; 32], bool)> = Vec::with_capacity(checked.len());
+    for (block, h, connectable, rounds, res) in checked {
+        match res {
+            Ok(()) => verified.push((block, h, connectable)),
+            Err(_) if rounds.is_none() => {
+                debug!("[p2p] {addr} dropped orphan {}: PoW can't be checked yet", u32::from_le_bytes(block.block_height));
+            }
+            Err(e) => {
+                warn!("[p2p] {addr} block {} failed PoW: {e}", u32::from_le_bytes(block.block_height));
+                invalid_pow += 1;
+            }
+        }
+    }
+
+    if invalid_pow > 0
+        && misbehaving(peers, banned, Path::new(&config.data_dir), addr, invalid_pow * MISBEHAVIOR_INVALID_POW, "invalid PoW").await
+    {
+        return Err("banned for misbehavior".into());
+    }
+    
+    if verified.is_empty() {
+        warn!("[p2p] {addr} sent blocks with invalid PoW");
+        return Ok(());
+    }
+    
+    // Step 6: Re-sort after parallel processing; park orphans
+    let mut verified_sorted: Vec<StoredBlock> = Vec::new();
+    {
+        let mut pool = orphans.lock().await;
+        for (block, h, connectable) in verified {
+            if connectable {
+                verified_sorted.push(block);
+            } else {
+                pool.insert(block, h);
+            }
+        }
+    }
+    verified_sorted.sort_by_key(|block| u32::from_le_bytes(block.block_height));
+    
+    // Step 7: Apply blocks sequentially (CONSENSUS-CRITICAL)
+    // Each connected block may release orphans waiting on it.
+    // While catching up, tolerate a lagging local clock on recent blocks.
+    let future_bound = if is_initial_block_download(db) {
+        IBD_MAX_FUTURE_BLOCK_SECS
+    } else {
+        MAX_FUTURE_BLOCK_SECS
+    };
+    let mut applied = 0;
+    let mut failed = 0;
+    let mut queue: std::collections::VecDeque<StoredBlock> = verified_sorted.into();
+    while let Some(block) = queue.pop_front() {
+        let height = u32::from_le_bytes(block.block_height);
+        
+        // Timestamp rules are measured on the active chain, so only
+        // blocks extending the tip can be checked up front.
+        if db.get_tip().ok().flatten() == Some(block.previous_hash)
+            && let Err(e) = validate_block_timestamp_within(db, &block, future_bound)
+        {
+            warn!("[p2p] {addr} block {} bad timestamp: {e}", height);
+            failed += 1;
+            break;
+        }
+        
+        match apply_block_with_time_bound(db, &block, future_bound) {
+            Ok(_) => {
+                applied += 1;
+                let hash = block_hash(&block);
+                seen_inv.lock().await.insert(hash, unix_now());
+                notify_block(block_events, db, &block);
+                queue.extend(orphans.lock().await.take_children(&hash));
+            }
+            Err(StateError::ReorgTooDeep { depth }) => {
+                warn!("[p2p] {addr} offered a branch forking {depth} blocks deep");
+                if misbehaving(peers, banned, Path::new(&config.data_dir), addr, MISBEHAVIOR_DEEP_REORG, "deep reorg").await {
+                    return Err("banned for misbehavior".into());
+                }
+                failed += 1;
+                break;
+            }
+            Err(e) => {
+                warn!("[p2p] {addr} block {} apply failed: {e}", height);
+                failed += 1;
+                // Stop processing on first failure (chain broken)
+                break;
+            }
+        }
+    }
+    
+    if applied > 0 {
+        let new_height = db.get_chain_height().unwrap_or(0);
+        info!("[p2p] ✓ {addr} synced +{applied} blocks → height {new_height}");
+        
+        // Continue syncing if we got a full batch
+        if applied >= MAX_BLOCKS_PER_MSG {
+            let _ = s.send(&peer_sync_request(peers, addr, db).await).await;
+        }
+    }
+    
+    if failed > 0 {
+        warn!("[p2p] ✗ {addr} sync stopped: {failed} block(s) failed validation");
+    }
+    Ok(())
+}
+
+/// Apply a fully rebuilt compact block, or fetch it whole if the rebuilt
+/// transactions don't match its merkle root.
+async fn finish_compact_block(
+    partial: PartialBlock,
+    s: &mut FramedStream,
+    addr: SocketAddr,
+    ctx: &PeerCtx,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let hash = partial.hash;
+    match partial.finish() {
+        Some(block) => handle_blocks(vec![block.to_bytes()], s, addr, ctx).await,
+        None => {
+            debug!("[p2p] {addr} compact block {} did not rebuild, fetching in full", hex::encode(hash));
+            s.send(&NetworkMessage::GetBlocks { hashes: vec![hash] }).await?;
+            Ok(())
+        }
     }
-    let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
-    PathBuf::from(home).join(crate::config::DATA_DIR)
 }
 
-fn known_peers_file() -> PathBuf {
-    data_dir_path().join("peers.json")
+/// Add `points` to `addr`'s ban score. Once the score reaches
+/// `BAN_THRESHOLD` the IP is banned, `banned.json` is rewritten, and `true`
+/// is returned so the caller drops the connection.
+pub async fn misbehaving(
+    peers: &Arc<Mutex<HashMap<SocketAddr, PeerInfo>>>,
+    banned: &BannedPeers,
+    data_dir: &Path,
+    addr: SocketAddr,
+    points: u32,
+    reason: &str,
+) -> bool {
+    let score = {
+        let mut p = peers.lock().await;
+        let Some(info) = p.get_mut(&addr) else {
+            return false;
+        };
+        info.ban_score = info.ban_score.saturating_add(points);
+        info.ban_score
+    };
+    if score < BAN_THRESHOLD {
+        warn!("[p2p] {addr} misbehaving ({reason}): score {score}");
+        return false;
+    }
+    let ip: IpAddr = addr.ip();
+    let mut list = banned.lock().await;
+    list.ban(ip, unix_now());
+    list.save(data_dir);
+    warn!("[p2p] ✗ banning {ip} for 24h ({reason}, score {score})");
+    true
 }
 
-fn load_known_peers() -> HashSet<SocketAddr> {
-    let path = known_peers_file();
-    let mut out = HashSet::new();
-    if let Ok(s) = fs::read_to_string(&path) {
-        if let Ok(list) = serde_json::from_str::<Vec<String>>(&s) {
-            for item in list {
-                if let Ok(a) = item.parse::<SocketAddr>() {
-                    if dev_allow_local() || !is_private_ip(a) {
-                        out.insert(a);
-                    }
-                }
+fn known_peers_file(data_dir: &Path) -> PathBuf {
+    data_dir.join("peers.json")
+}
+
+/// On-disk form of a `peers.json` entry.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct KnownPeerRecord {
+    addr: String,
+    #[serde(flatten)]
+    meta: KnownPeer,
+}
+
+/// Older releases stored `peers.json` as a bare list of address strings.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum KnownPeerEntry {
+    Plain(String),
+    Record(KnownPeerRecord),
+}
+
+fn parse_known_peers(s: &str, now: u64, allow_local: bool) -> HashMap<SocketAddr, KnownPeer> {
+    let mut out = HashMap::new();
+    if let Ok(list) = serde_json::from_str::<Vec<KnownPeerEntry>>(s) {
+        for item in list {
+            let (addr, meta) = match item {
+                KnownPeerEntry::Plain(a) => (a, KnownPeer::new(now)),
+                KnownPeerEntry::Record(r) => (r.addr, r.meta),
+            };
+            if let Ok(a) = addr.parse::<SocketAddr>()
+                && (allow_local || !is_private_ip(a))
+            {
+                out.insert(a, meta);
             }
         }
     }
     out
 }
 
-async fn save_known_peers(known_addrs: &Arc<Mutex<HashSet<SocketAddr>>>) {
-    let path = known_peers_file();
-    let list: Vec<String> = {
+pub fn load_known_peers(data_dir: &Path, allow_local: bool) -> HashMap<SocketAddr, KnownPeer> {
+    match fs::read_to_string(known_peers_file(data_dir)) {
+        Ok(s) => parse_known_peers(&s, unix_now(), allow_local),
+        Err(_) => HashMap::new(),
+    }
+}
+
+pub async fn save_known_peers(known_addrs: &KnownPeers, data_dir: &Path) {
+    let path = known_peers_file(data_dir);
+    let list: Vec<KnownPeerRecord> = {
         let known = known_addrs.lock().await;
-        known.iter().take(2048).map(|a| a.to_string()).collect()
+        // Manual (`addnode add`) peers go first so the cap never drops them.
+        known
+            .iter()
+            .filter(|(_, meta)| meta.manual)
+            .chain(known.iter().filter(|(_, meta)| !meta.manual))
+            .take(2048)
+            .map(|(a, meta)| KnownPeerRecord { addr: a.to_string(), meta: *meta })
+            .collect()
     };
     if let Some(parent) = path.parent() {
         let _ = fs::create_dir_all(parent);
@@ -759,8 +1713,639 @@ async fn save_known_peers(known_addrs: &Arc<Mutex<HashSet<SocketAddr>>>) {
     }
 }
 
-fn find_height_of_hash(db: &ChainDB, hash: &[u8; 32]) -> Option<u32> {
-    db.get_block(hash)
-        .ok()?
+/// Ask a peer with `services` for what follows `locator`: full headers when
+/// it serves them, so PoW is checked before any body is downloaded. Peers
+/// before `LOCATOR_VERSION` only get the locator's first hash (our tip).
+fn sync_request(services: u64, version: u32, mut locator: Vec<[u8; 32]>) -> NetworkMessage {
+    if version < LOCATOR_VERSION {
+        locator.truncate(1);
+    }
+    if services & SERVICE_BLOCK_HEADERS != 0 {
+        NetworkMessage::GetBlockHeaders { locator }
+    } else {
+        NetworkMessage::GetHeaders { locator }
+    }
+}
+
+/// `sync_request` from our current tip for the connected peer at `addr`.
+async fn peer_sync_request(peers: &Peers, addr: SocketAddr, db: &ChainDB) -> NetworkMessage {
+    let (services, version) = peers.lock().await.get(&addr).map_or((0, 1), |info| (info.services, info.protocol_version));
+    sync_request(services, version, block_locator(db))
+}
+
+/// Active-chain hashes at the tip and 1, 2, 4, 8, ... blocks below it,
+/// ending with genesis, so a peer on another branch can find where we forked.
+fn block_locator(db: &ChainDB) -> Vec<[u8; 32]> {
+    let tip_height = db.get_chain_height().unwrap_or(0);
+    let mut heights = vec![tip_height];
+    let mut step = 1u32;
+    while step < tip_height {
+        heights.push(tip_height - step);
+        step = step.saturating_mul(2);
+    }
+    if tip_height > 0 {
+        heights.push(0);
+    }
+    let locator: Vec<[u8; 32]> = heights.into_iter()
+        .filter_map(|h| db.get_block_hash_by_height(h).ok().flatten())
+        .collect();
+    if locator.is_empty() { vec![[0u8; 32]] } else { locator }
+}
+
+/// Hashes of up to `MAX_HEADERS_PER_MSG` active-chain blocks above the
+/// first `locator` hash we know (above genesis when none is).
+fn hashes_after(db: &ChainDB, locator: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    let tip_height = db.get_chain_height().unwrap_or(0);
+    let fork = locator.iter().find_map(|h| find_height_of_hash(db, h)).unwrap_or(0);
+    let start = fork.saturating_add(1);
+    let end = (start + MAX_HEADERS_PER_MSG as u32 - 1).min(tip_height);
+    (start..=end)
+        .filter_map(|h| db.get_block_hash_by_height(h).ok().flatten())
+        .collect()
+}
+
+/// Request, in order, the bodies of `hashes` we don't have yet; with nothing
+/// missing, ask for the next batch of headers instead.
+async fn request_missing_blocks(
+    hashes: Vec<[u8; 32]>,
+    s: &mut FramedStream,
+    addr: SocketAddr,
+    db: &ChainDB,
+    peers: &Peers,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let needed: Vec<[u8; 32]> = hashes.into_iter()
+        .filter(|h| db.get_block(h).ok().flatten().is_none())
+        .collect();
+
+    if needed.is_empty() {
+        // We have all these blocks, continue syncing
+        s.send(&peer_sync_request(peers, addr, db).await).await?;
+        return Ok(());
+    }
+
+    debug!("[p2p] ← {addr} requesting {} block(s)...", needed.len());
+
+    // Request blocks in chunks for smooth download
+    for chunk in needed.chunks(MAX_BLOCKS_PER_MSG) {
+        s.send(&NetworkMessage::GetBlocks { hashes: chunk.to_vec() }).await?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+enum HeaderChainError {
+    /// The first header's parent isn't a block we know.
+    UnknownParent,
+    /// A header doesn't extend the one before it.
+    Disconnected,
+    /// The header at this height fails its own difficulty target.
+    InvalidPow(u32),
+}
+
+/// Dedicated pool for sync-path PoW checks, sized by `KNOTCOIN_VERIFY_THREADS`,
+/// so a heavy sync doesn't occupy the global rayon pool. Built on first use.
+fn verify_pool(config: &Config) -> &'static rayon::ThreadPool {
+    static POOL: std::sync::OnceLock<rayon::ThreadPool> = std::sync::OnceLock::new();
+    POOL.get_or_init(|| {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(config.verify_threads)
+            .thread_name(|i| format!("knotcoin-verify-{i}"))
+            .build()
+            .expect("failed to start PoW verification threads")
+    })
+}
+
+/// Check that `headers` form a chain hanging off a known block and that every
+/// header meets its PoW target. Returns their hashes, lowest first. Full
+/// consensus checks (retarget, timestamps) still run when the bodies connect.
+fn validate_header_chain(db: &ChainDB, headers: &[[u8; 148]]) -> Result<Vec<[u8; 32]>, HeaderChainError> {
+    use rayon::prelude::*;
+
+    let blocks: Vec<StoredBlock> = headers.iter()
+        .map(|h| StoredBlock::header_only_from_bytes(h).map_err(|_| HeaderChainError::Disconnected))
+        .collect::<Result<_, _>>()?;
+    let Some(first) = blocks.first() else {
+        return Ok(Vec::new());
+    };
+    let parent = db.get_block_header(&first.previous_hash)
+        .ok()
+        .flatten()
+        .ok_or(HeaderChainError::UnknownParent)?;
+    let rounds = crate::consensus::state::pow_rounds_after(db, &first.previous_hash).ok().flatten();
+
+    let mut prev_hash = first.previous_hash;
+    let mut prev_height = u32::from_le_bytes(parent.block_height);
+    let mut hashes = Vec::with_capacity(blocks.len());
+    for b in &blocks {
+        let height = u32::from_le_bytes(b.block_height);
+        if b.previous_hash != prev_hash || Some(height) != prev_height.checked_add(1) {
+            return Err(HeaderChainError::Disconnected);
+        }
+        prev_hash = block_hash(b);
+        prev_height = height;
+        hashes.push(prev_hash);
+    }
+
+    // Headers are checked with the PONC rounds in effect at the first one's
+    // parent. A later header may follow a block whose body passes a
+    // `ponc_rounds` proposal, which headers can't show; so only a failing
+    // first header is invalid, and otherwise the chain is cut before the
+    // failure and the rest re-requested once those bodies connect.
+    // Passing headers land in the PoW cache, so their bodies aren't checked twice.
+    let Some(rounds) = rounds else {
+        return Ok(hashes);
+    };
+    let first_height = u32::from_le_bytes(parent.block_height) + 1;
+    match blocks.par_iter()
+        .filter(|b| crate::consensus::state::verify_block_pow_with_rounds(b, db, rounds).is_err())
         .map(|b| u32::from_le_bytes(b.block_height))
+        .min()
+    {
+        Some(bad) if bad == first_height => Err(HeaderChainError::InvalidPow(bad)),
+        Some(bad) => {
+            hashes.truncate((bad - first_height) as usize);
+            Ok(hashes)
+        }
+        None => Ok(hashes),
+    }
+}
+
+/// Height of `hash` on our active chain. A peer on a side branch gets the
+/// height of the fork point, so it is sent our branch from there.
+fn find_height_of_hash(db: &ChainDB, hash: &[u8; 32]) -> Option<u32> {
+    let mut cur = *hash;
+    loop {
+        let b = db.get_block(&cur).ok()??;
+        let h = u32::from_le_bytes(b.block_height);
+        if db.get_block_hash_by_height(h).ok()? == Some(cur) || h == 0 {
+            return Some(h);
+        }
+        cur = b.previous_hash;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_outbound_candidates_spread_across_netgroups() {
+        let mut known = HashMap::new();
+        for a in ["1.2.3.4:9000", "1.2.9.9:9000", "1.3.0.1:9000", "5.6.7.8:9000", "[2001:db8::1]:9000", "[2001:db8:1::1]:9000"] {
+            known.insert(addr(a), KnownPeer::new(100));
+        }
+        let mut manual = KnownPeer::new(100);
+        manual.manual = true;
+        known.insert(addr("5.6.1.1:9000"), manual);
+
+        let connected: HashSet<SocketAddr> = [addr("5.6.7.8:9000")].into();
+        let outbound = [addr("5.6.7.8:9000")];
+        let picked = pick_outbound_candidates(&known, &connected, &outbound, 3);
+        // The manual peer comes first even though its /16 is taken; then one
+        // address per unused group.
+        assert_eq!(picked[0], addr("5.6.1.1:9000"));
+        let groups: HashSet<[u8; 5]> = picked[1..].iter().map(|a| netgroup(a.ip())).collect();
+        assert_eq!(groups.len(), 2);
+        assert!(!picked.contains(&addr("5.6.7.8:9000")));
+
+        // Both IPv6 addresses share a /32; same-group addresses only fill the remainder.
+        let all = pick_outbound_candidates(&known, &connected, &outbound, 10);
+        assert_eq!(all.len(), 6);
+        let first_groups: HashSet<[u8; 5]> = all[1..4].iter().map(|a| netgroup(a.ip())).collect();
+        assert_eq!(first_groups.len(), 3);
+
+        assert_eq!(netgroup("::ffff:1.2.3.4".parse().unwrap()), netgroup("1.2.200.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_prune_known_peers_removes_only_stale() {
+        let now = 1_000_000;
+        let mut known = HashMap::new();
+        // Connected an hour ago.
+        known.insert(addr("8.8.8.8:9000"), KnownPeer { added: 10, last_seen: now - 3600, last_attempt: now - 3600, ..Default::default() });
+        // Learned long ago, dialed recently, never answered.
+        known.insert(addr("9.9.9.9:9000"), KnownPeer { added: 10, last_seen: 0, last_attempt: now - 60, ..Default::default() });
+        // Freshly gossiped, not tried yet.
+        known.insert(addr("1.1.1.1:9000"), KnownPeer::new(now - 60));
+
+        let removed = prune_known_peers(&mut known, now, 86_400);
+        assert_eq!(removed, vec![addr("9.9.9.9:9000")]);
+        assert_eq!(known.len(), 2);
+        assert!(known.contains_key(&addr("8.8.8.8:9000")));
+        assert!(known.contains_key(&addr("1.1.1.1:9000")));
+    }
+
+    #[test]
+    fn test_parse_known_peers_accepts_legacy_and_records() {
+        let legacy = r#"["8.8.8.8:9000", "not-an-addr"]"#;
+        let known = parse_known_peers(legacy, 42, false);
+        assert_eq!(known.get(&addr("8.8.8.8:9000")), Some(&KnownPeer::new(42)));
+        assert_eq!(known.len(), 1);
+
+        let records = r#"[{"addr":"8.8.4.4:9000","added":1,"last_seen":2,"last_attempt":3}]"#;
+        let known = parse_known_peers(records, 42, false);
+        assert_eq!(
+            known.get(&addr("8.8.4.4:9000")),
+            Some(&KnownPeer { added: 1, last_seen: 2, last_attempt: 3, ..Default::default() })
+        );
+    }
+
+    #[test]
+    fn test_cached_seed_addrs_respect_ttl_and_rotate() {
+        let now = 1_000_000;
+        let mut known = HashMap::new();
+        known.insert(addr("8.8.8.8:9000"), KnownPeer { dns_resolved: now - 100, ..KnownPeer::new(1) });
+        known.insert(addr("8.8.4.4:9000"), KnownPeer { dns_resolved: now - 10, ..KnownPeer::new(1) });
+        // Resolved too long ago.
+        known.insert(addr("9.9.9.9:9000"), KnownPeer { dns_resolved: now - 5000, ..KnownPeer::new(1) });
+        // Gossiped, never came from DNS.
+        known.insert(addr("1.1.1.1:9000"), KnownPeer::new(now));
+
+        let cached = cached_seed_addrs(&known, now, 1000);
+        assert_eq!(cached, vec![addr("8.8.4.4:9000"), addr("8.8.8.8:9000")]);
+        assert!(cached_seed_addrs(&known, now, 0).is_empty());
+
+        let all = [addr("1.1.1.1:1"), addr("1.1.1.1:2"), addr("1.1.1.1:3")];
+        assert_eq!(rotate_addrs(&all, 0, 8), all.to_vec());
+        assert_eq!(rotate_addrs(&all, 4, 2), vec![addr("1.1.1.1:2"), addr("1.1.1.1:3")]);
+        assert!(rotate_addrs(&[], 3, 8).is_empty());
+
+        let json = serde_json::to_string(&KnownPeerRecord { addr: "8.8.8.8:9000".to_string(), meta: known[&addr("8.8.8.8:9000")] }).unwrap();
+        let back = parse_known_peers(&format!("[{json}]"), 42, false);
+        assert_eq!(back[&addr("8.8.8.8:9000")].dns_resolved, now - 100);
+    }
+
+    #[test]
+    fn test_stall_action_resyncs_then_drops_best_peer() {
+        let peers = [(addr("8.8.8.8:9000"), 120), (addr("9.9.9.9:9000"), 150)];
+        // Tip moved recently, or nobody is ahead: nothing to do.
+        assert_eq!(stall_action(1000, 950, 120, 100, &peers, None), StallAction::Wait);
+        assert_eq!(stall_action(1000, 0, 120, 150, &peers, None), StallAction::Wait);
+        assert_eq!(stall_action(1000, 0, 120, 100, &[], None), StallAction::Wait);
+
+        assert_eq!(stall_action(1000, 800, 120, 100, &peers, None), StallAction::Resync);
+        assert_eq!(stall_action(1060, 800, 120, 100, &peers, Some(1000)), StallAction::Wait);
+        assert_eq!(stall_action(1120, 800, 120, 100, &peers, Some(1000)), StallAction::Disconnect(addr("9.9.9.9:9000")));
+    }
+
+    #[test]
+    fn test_poisson_delay_shape() {
+        assert_eq!(poisson_delay_ms(1000, 1.0), 0);
+        // u = e^-1 gives exactly one mean.
+        assert_eq!(poisson_delay_ms(1000, (-1.0f64).exp()), 1000);
+        // Tail is capped.
+        assert_eq!(poisson_delay_ms(1000, 1e-300), 10_000);
+        assert_eq!(poisson_delay_ms(0, 0.5), 0);
+    }
+
+    #[test]
+    fn test_sampled_relay_delay_mean() {
+        let n = 4000;
+        let total: u64 = (0..n).map(|_| sample_relay_delay_ms(1000)).sum();
+        let mean = total / n;
+        assert!((850..=1150).contains(&mean), "mean delay {mean}ms");
+    }
+
+    #[tokio::test]
+    async fn test_tx_relay_deferred_block_relay_immediate() {
+        let (tx, mut rx) = tokio::sync::broadcast::channel(16);
+
+        relay_message(&tx, NetworkMessage::Tx(vec![1, 2, 3]), 50);
+        relay_message(&tx, NetworkMessage::Blocks(vec![vec![9]]), 50);
+
+        // The block is queued synchronously; the tx is still waiting on its timer.
+        assert!(matches!(rx.try_recv(), Ok(NetworkMessage::Blocks(_))));
+        assert!(rx.try_recv().is_err());
+
+        let got = tokio::time::timeout(tokio::time::Duration::from_secs(5), rx.recv())
+            .await
+            .expect("tx relayed within cap")
+            .unwrap();
+        assert!(matches!(got, NetworkMessage::Tx(ref b) if b == &vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn test_tx_announced_as_inv_only_to_inv_peers() {
+        let stx = crate::node::db_common::StoredTransaction {
+            version: 1,
+            sender_address: [1u8; 32],
+            sender_pubkey: vec![0u8; 1952],
+            recipient_address: [2u8; 32],
+            amount: 5,
+            fee: 1,
+            nonce: 1,
+            timestamp: 0,
+            referrer_address: None,
+            governance_data: None,
+            proposal: None,
+            memo: None,
+            signature: vec![0u8; 3309],
+        };
+        let raw = stx.to_bytes();
+        let txid = Mempool::compute_txid_from_stored(&stx);
+
+        assert_eq!(inv_hashes(&NetworkMessage::Tx(raw.clone())), vec![txid]);
+        match announce_for_peer(NetworkMessage::Tx(raw.clone()), SERVICE_INV_RELAY) {
+            NetworkMessage::Inv(ids) => assert_eq!(ids, vec![txid]),
+            other => panic!("unexpected: {other:?}"),
+        }
+        match announce_for_peer(NetworkMessage::Tx(raw.clone()), 0) {
+            NetworkMessage::Tx(body) => assert_eq!(body, raw),
+            other => panic!("unexpected: {other:?}"),
+        }
+        assert!(matches!(
+            announce_for_peer(NetworkMessage::Blocks(vec![vec![1]]), SERVICE_INV_RELAY),
+            NetworkMessage::Blocks(_)
+        ));
+    }
+
+    #[test]
+    fn test_block_announced_compact_only_to_compact_peers() {
+        let block = StoredBlock {
+            version: [0, 0, 0, 1],
+            previous_hash: [0u8; 32],
+            merkle_root: [0u8; 32],
+            timestamp: 0u32.to_le_bytes(),
+            difficulty_target: [0xFF; 32],
+            nonce: [0u8; 8],
+            block_height: 1u32.to_le_bytes(),
+            miner_address: [1u8; 32],
+            tx_data: vec![],
+        };
+        let raw = block.to_bytes();
+        assert_eq!(inv_hashes(&NetworkMessage::Blocks(vec![raw.clone(), vec![1]])), vec![block_hash(&block)]);
+        match announce_for_peer(NetworkMessage::Blocks(vec![raw.clone()]), SERVICE_COMPACT_BLOCKS) {
+            NetworkMessage::CompactBlock { header, short_ids } => {
+                assert_eq!(header, block.header_bytes());
+                assert!(short_ids.is_empty());
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+        // Batches (sync replies) and legacy peers get full blocks.
+        assert!(matches!(
+            announce_for_peer(NetworkMessage::Blocks(vec![raw.clone(), raw.clone()]), SERVICE_COMPACT_BLOCKS),
+            NetworkMessage::Blocks(_)
+        ));
+        assert!(matches!(
+            announce_for_peer(NetworkMessage::Blocks(vec![raw]), SERVICE_INV_RELAY),
+            NetworkMessage::Blocks(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_pong_records_latency_only_for_pending_nonce() {
+        let peers: Peers = Arc::new(Mutex::new(HashMap::new()));
+        let a = addr("8.8.8.8:9000");
+        let sent = std::time::Instant::now() - std::time::Duration::from_millis(40);
+        peers.lock().await.insert(a, PeerInfo {
+            height: 0,
+            challenge: [0u8; 32],
+            is_outbound: true,
+            handshake_stage: HandshakeStage::Done,
+            services: 0,
+            ban_score: 0,
+            compact_pending: None,
+            disconnect: Arc::default(),
+            resync: Arc::default(),
+            last_ping_ms: None,
+            ping_pending: Some((7, sent)),
+            node_id: [0u8; 32],
+            protocol_version: 1,
+        });
+
+        record_pong(&peers, a, 8).await;
+        assert_eq!(peers.lock().await[&a].last_ping_ms, None);
+        record_pong(&peers, a, 7).await;
+        let info = &peers.lock().await[&a];
+        assert!(info.last_ping_ms.unwrap() >= 40);
+        assert!(info.ping_pending.is_none());
+    }
+
+    #[test]
+    fn test_duplicate_connection_by_node_id() {
+        let peer = |node_id| PeerInfo {
+            height: 0,
+            challenge: [0u8; 32],
+            is_outbound: true,
+            handshake_stage: HandshakeStage::Challenge,
+            services: 0,
+            ban_score: 0,
+            compact_pending: None,
+            disconnect: Arc::default(),
+            resync: Arc::default(),
+            last_ping_ms: None,
+            ping_pending: None,
+            node_id,
+            protocol_version: 1,
+        };
+        let ours = [1u8; 32];
+        let (a, b) = (addr("8.8.8.8:9000"), addr("8.8.4.4:9000"));
+        let mut peers = HashMap::new();
+        peers.insert(a, peer([7u8; 32]));
+        peers.insert(b, peer([0u8; 32]));
+
+        assert!(duplicate_connection(&peers, b, [7u8; 32], ours).unwrap().contains("8.8.8.8:9000"));
+        assert!(duplicate_connection(&peers, b, ours, ours).unwrap().contains("self"));
+        assert_eq!(duplicate_connection(&peers, a, [7u8; 32], ours), None);
+        assert_eq!(duplicate_connection(&peers, b, [8u8; 32], ours), None);
+        // Peers without an id can't be told apart.
+        assert_eq!(duplicate_connection(&peers, a, [0u8; 32], ours), None);
+    }
+
+    #[tokio::test]
+    async fn test_stalled_connection_frees_its_slot() {
+        use tokio::io::AsyncWriteExt;
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::default();
+        let magic = config.network.magic();
+        let ctx = PeerCtx {
+            db: ChainDB::open(dir.path()).unwrap().with_network(crate::config::Network::Testnet),
+            mempool: Arc::new(Mutex::new(Mempool::new())),
+            peers: Arc::new(Mutex::new(HashMap::new())),
+            known_addrs: Arc::new(Mutex::new(HashMap::new())),
+            broadcast_tx: tokio::sync::broadcast::channel(16).0,
+            config: Arc::new(config),
+            time_data: Arc::new(Mutex::new(TimeData::new(false))),
+            banned: Arc::new(Mutex::new(BanList::new())),
+            block_events: tokio::sync::broadcast::channel(16).0,
+            orphans: Arc::new(Mutex::new(OrphanPool::new())),
+            seen_inv: Arc::new(Mutex::new(SeenInv::new())),
+            node_id: [1u8; 32],
+        };
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (server, peer_addr) = listener.accept().await.unwrap();
+        // The first byte of a frame, then nothing.
+        client.write_all(&magic[..1]).await.unwrap();
+
+        let s = FramedStream::new(server)
+            .with_magic(magic)
+            .with_timeouts(std::time::Duration::from_secs(10), std::time::Duration::from_millis(200));
+        let err = run_connection(s, peer_addr, false, &ctx).await.unwrap_err();
+        assert!(err.to_string().contains("frame not completed"), "{err}");
+        assert!(ctx.peers.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_misbehaving_bans_at_threshold() {
+        let dir = tempfile::tempdir().unwrap();
+        let peers = Arc::new(Mutex::new(HashMap::new()));
+        let banned: BannedPeers = Arc::new(Mutex::new(BanList::new()));
+        let a = addr("8.8.8.8:9000");
+        peers.lock().await.insert(a, PeerInfo {
+            height: 0,
+            challenge: [0u8; 32],
+            is_outbound: false,
+            handshake_stage: HandshakeStage::Done,
+            services: 0,
+            ban_score: 0,
+            compact_pending: None,
+            disconnect: Arc::default(),
+            resync: Arc::default(),
+            last_ping_ms: None,
+            ping_pending: None,
+            node_id: [0u8; 32],
+            protocol_version: 1,
+        });
+
+        assert!(!misbehaving(&peers, &banned, dir.path(), a, MISBEHAVIOR_INVALID_POW, "pow").await);
+        assert!(!misbehaving(&peers, &banned, dir.path(), a, MISBEHAVIOR_MALFORMED_BLOCK * 2, "block").await);
+        assert_eq!(peers.lock().await[&a].ban_score, 90);
+        assert!(!banned.lock().await.is_banned(a.ip(), unix_now()));
+
+        assert!(misbehaving(&peers, &banned, dir.path(), a, MISBEHAVIOR_OVERSIZED_ADDR, "addr").await);
+        assert!(banned.lock().await.is_banned(a.ip(), unix_now()));
+        // Persisted, and a different port on the same IP is still banned.
+        let reloaded = BanList::load(dir.path(), unix_now());
+        assert!(reloaded.is_banned(addr("8.8.8.8:9001").ip(), unix_now()));
+    }
+
+    #[tokio::test]
+    async fn test_tx_relay_immediate_when_disabled() {
+        let (tx, mut rx) = tokio::sync::broadcast::channel(16);
+        relay_message(&tx, NetworkMessage::Tx(vec![7]), 0);
+        assert!(matches!(rx.try_recv(), Ok(NetworkMessage::Tx(_))));
+    }
+
+    #[test]
+    fn test_advertised_addrs_and_addr_version() {
+        let onion = "duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczad.onion:9000";
+        let entries: Vec<String> = ["1.2.3.4:9000", onion, "[2001:db8::1]:9000", "example.com:9000"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let (own, rejected) = parse_advertised_addrs(&entries);
+        assert_eq!(own.ip, vec![addr("1.2.3.4:9000"), addr("[2001:db8::1]:9000")]);
+        assert_eq!(own.onion, vec![onion.parse().unwrap()]);
+        assert_eq!(rejected, vec!["example.com:9000".to_string()]);
+
+        match addr_message(own.ip.clone(), own.onion.clone(), PROTOCOL_VERSION) {
+            NetworkMessage::AddrV2 { ips, onion } => assert_eq!((ips.len(), onion.len()), (2, 1)),
+            other => panic!("unexpected: {other:?}"),
+        }
+        match addr_message(own.ip, own.onion, 1) {
+            NetworkMessage::Addr(ips) => assert_eq!(ips.len(), 2),
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_validate_header_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = ChainDB::open(dir.path()).unwrap().with_network(crate::config::Network::Testnet);
+        let header = |prev: [u8; 32], height: u32| StoredBlock {
+            version: [0, 0, 0, 1],
+            previous_hash: prev,
+            merkle_root: [0u8; 32],
+            timestamp: (height * 60).to_le_bytes(),
+            difficulty_target: [0xFF; 32],
+            nonce: [0u8; 8],
+            block_height: height.to_le_bytes(),
+            miner_address: [1u8; 32],
+            tx_data: vec![],
+        };
+        let genesis = header([0u8; 32], 0);
+        crate::consensus::state::apply_block(&db, &genesis).unwrap();
+
+        let mut chain = vec![header(block_hash(&genesis), 1)];
+        for h in 2..=3 {
+            let next = header(block_hash(chain.last().unwrap()), h);
+            chain.push(next);
+        }
+        let raw: Vec<[u8; 148]> = chain.iter().map(|b| b.header_bytes()).collect();
+        let want: Vec<[u8; 32]> = chain.iter().map(block_hash).collect();
+        assert_eq!(validate_header_chain(&db, &raw), Ok(want.clone()));
+
+        // Skipping a header breaks the chain.
+        assert_eq!(validate_header_chain(&db, &[raw[0], raw[2]]), Err(HeaderChainError::Disconnected));
+        assert_eq!(validate_header_chain(&db, &raw[1..]), Err(HeaderChainError::UnknownParent));
+
+        // An impossible target fails PoW from the header alone. Past the
+        // first header the rounds may have changed, so the chain is cut there.
+        let mut bad = chain[1].clone();
+        bad.difficulty_target = [0u8; 32];
+        let mut tampered = raw.clone();
+        tampered[1] = bad.header_bytes();
+        tampered[2] = header(block_hash(&bad), 3).header_bytes();
+        assert_eq!(validate_header_chain(&db, &tampered), Ok(want[..1].to_vec()));
+
+        let mut bad_first = chain[0].clone();
+        bad_first.difficulty_target = [0u8; 32];
+        assert_eq!(validate_header_chain(&db, &[bad_first.header_bytes()]), Err(HeaderChainError::InvalidPow(1)));
+
+        let locator = vec![[2u8; 32], [1u8; 32], [0u8; 32]];
+        assert!(matches!(
+            sync_request(SERVICE_BLOCK_HEADERS, PROTOCOL_VERSION, locator.clone()),
+            NetworkMessage::GetBlockHeaders { locator: l } if l.len() == 3
+        ));
+        assert!(matches!(
+            sync_request(SERVICE_INV_RELAY, LOCATOR_VERSION - 1, locator),
+            NetworkMessage::GetHeaders { locator: l } if l == vec![[2u8; 32]]
+        ));
+    }
+
+    #[test]
+    fn test_bootstrap_seeds_are_mainnet_only() {
+        assert!(!get_bootstrap_peers(Network::Mainnet, 0, &[]).is_empty());
+        assert!(get_bootstrap_peers(Network::Testnet, 0, &[]).is_empty());
+        assert!(get_bootstrap_peers(Network::Regtest, 10_000, &[]).is_empty());
+        let configured = vec!["127.0.0.1:29000".to_string()];
+        assert_eq!(get_bootstrap_peers(Network::Regtest, 0, &configured), configured);
+    }
+
+    #[test]
+    fn test_block_locator_and_fork_point() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = ChainDB::open(dir.path()).unwrap().with_network(crate::config::Network::Testnet);
+        let mut prev = [0u8; 32];
+        for height in 0..=20u32 {
+            let b = StoredBlock {
+                version: [0, 0, 0, 1],
+                previous_hash: prev,
+                merkle_root: [0u8; 32],
+                timestamp: (height * 60).to_le_bytes(),
+                difficulty_target: [0xFF; 32],
+                nonce: [0u8; 8],
+                block_height: height.to_le_bytes(),
+                miner_address: [1u8; 32],
+                tx_data: vec![],
+            };
+            crate::consensus::state::apply_block(&db, &b).unwrap();
+            prev = block_hash(&b);
+        }
+        let at = |h: u32| db.get_block_hash_by_height(h).unwrap().unwrap();
+
+        let want: Vec<[u8; 32]> = [20, 19, 18, 16, 12, 4, 0].into_iter().map(at).collect();
+        assert_eq!(block_locator(&db), want);
+
+        // The first hash we know decides where the reply starts.
+        assert_eq!(hashes_after(&db, &[[9u8; 32], at(12), at(4)])[0], at(13));
+        assert_eq!(hashes_after(&db, &[[9u8; 32]])[0], at(1));
+        assert!(hashes_after(&db, &[at(20)]).is_empty());
+    }
 }
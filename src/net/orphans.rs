@@ -0,0 +1,133 @@
+// Orphan Blocks
+//
+// Blocks whose parent we don't have yet wait here, keyed by the missing
+// parent hash, instead of being dropped. When a block connects, the children
+// waiting on it are handed back to be applied in turn, so a parent arriving
+// in a later batch (or from another peer) still lets sync make progress.
+// The pool is capped; the oldest orphan is forgotten first.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::node::db_common::StoredBlock;
+
+pub const MAX_ORPHAN_BLOCKS: usize = 256;
+
+#[derive(Default)]
+pub struct OrphanPool {
+    /// missing parent hash -> blocks waiting on it
+    orphan_blocks: HashMap<[u8; 32], Vec<StoredBlock>>,
+    /// (parent, hash) in arrival order, oldest first
+    order: VecDeque<([u8; 32], [u8; 32])>,
+}
+
+impl OrphanPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    pub fn contains(&self, hash: &[u8; 32]) -> bool {
+        self.order.iter().any(|(_, h)| h == hash)
+    }
+
+    /// Park `block` (whose hash is `hash`) until its parent connects.
+    /// Returns false if it was already waiting.
+    pub fn insert(&mut self, block: StoredBlock, hash: [u8; 32]) -> bool {
+        if self.contains(&hash) {
+            return false;
+        }
+        while self.order.len() >= MAX_ORPHAN_BLOCKS {
+            self.evict_oldest();
+        }
+        self.order.push_back((block.previous_hash, hash));
+        self.orphan_blocks.entry(block.previous_hash).or_default().push(block);
+        true
+    }
+
+    /// Remove and return every block waiting on `parent`.
+    pub fn take_children(&mut self, parent: &[u8; 32]) -> Vec<StoredBlock> {
+        let Some(children) = self.orphan_blocks.remove(parent) else {
+            return Vec::new();
+        };
+        self.order.retain(|(p, _)| p != parent);
+        children
+    }
+
+    fn evict_oldest(&mut self) {
+        let Some((parent, _)) = self.order.pop_front() else {
+            return;
+        };
+        // Children of one parent are pushed in arrival order, so the oldest
+        // entry for `parent` is the first in its list.
+        if let Some(list) = self.orphan_blocks.get_mut(&parent) {
+            list.remove(0);
+            if list.is_empty() {
+                self.orphan_blocks.remove(&parent);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(parent: u8, height: u32) -> StoredBlock {
+        StoredBlock {
+            version: [0, 0, 0, 1],
+            previous_hash: [parent; 32],
+            merkle_root: [0u8; 32],
+            timestamp: 0u32.to_le_bytes(),
+            difficulty_target: [0xFF; 32],
+            nonce: height.to_le_bytes().repeat(2).try_into().unwrap(),
+            block_height: height.to_le_bytes(),
+            miner_address: [1u8; 32],
+            tx_data: vec![],
+        }
+    }
+
+    fn hash(i: u32) -> [u8; 32] {
+        let mut h = [0u8; 32];
+        h[..4].copy_from_slice(&i.to_le_bytes());
+        h
+    }
+
+    #[test]
+    fn test_children_released_by_parent() {
+        let mut pool = OrphanPool::new();
+        assert!(pool.insert(block(7, 5), hash(1)));
+        assert!(pool.insert(block(7, 5), hash(2)));
+        assert!(pool.insert(block(8, 9), hash(3)));
+        assert!(!pool.insert(block(7, 5), hash(1)));
+        assert_eq!(pool.len(), 3);
+
+        let kids = pool.take_children(&[7u8; 32]);
+        assert_eq!(kids.len(), 2);
+        assert_eq!(pool.len(), 1);
+        assert!(pool.contains(&hash(3)));
+        assert!(pool.take_children(&[7u8; 32]).is_empty());
+    }
+
+    #[test]
+    fn test_cap_evicts_oldest() {
+        let mut pool = OrphanPool::new();
+        for i in 0..MAX_ORPHAN_BLOCKS as u32 + 10 {
+            pool.insert(block((i % 3) as u8, i), hash(i));
+        }
+        assert_eq!(pool.len(), MAX_ORPHAN_BLOCKS);
+        for i in 0..10 {
+            assert!(!pool.contains(&hash(i)));
+        }
+        assert!(pool.contains(&hash(10)));
+        let total: usize = (0..3u8).map(|p| pool.take_children(&[p; 32]).len()).sum();
+        assert_eq!(total, MAX_ORPHAN_BLOCKS);
+        assert!(pool.is_empty());
+    }
+}
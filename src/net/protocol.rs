@@ -14,17 +14,110 @@ use tokio::net::TcpStream;
 
 use crate::node::db_common::StoredBlock;
 
-const MAGIC: [u8; 4] = [0x4B, 0x4E, 0x4F, 0x54]; // "KNOT"
+pub const MAGIC: [u8; 4] = [0x4B, 0x4E, 0x4F, 0x54]; // "KNOT"
 // SECURITY FIX: Reduced from 8MB to 1MB to prevent memory exhaustion DoS
-// Max block size is 500KB, so 1MB provides sufficient overhead while preventing
+// Max block size is 500KB (MAX_BLOCK_BYTES), so 1MB provides sufficient overhead while preventing
 // malicious peers from forcing nodes to allocate excessive memory buffers
 const MAX_FRAME: usize = 1 * 1024 * 1024; // 1 MB safety limit
 
+/// A connection that sends nothing at all for this long is dropped by `recv`.
+/// Pings go out every 30s and the node's own idle check fires at 90s, so this
+/// only catches what that misses.
+pub const READ_TIMEOUT_SECS: u64 = 120;
+
+/// Once a frame has started arriving, all of it must be in within this
+/// window (about 35 KB/s for a full 1 MB frame), so a peer can't hold the
+/// connection by trickling a frame or stopping halfway through.
+pub const FRAME_TIMEOUT_SECS: u64 = 30;
+
+/// Service bit: peer understands `Inv`/`GetData` and prefers txids over full `Tx` bodies.
+pub const SERVICE_INV_RELAY: u64 = 1 << 0;
+
+/// Service bit: peer takes new blocks as `CompactBlock` and answers `GetBlockTxn`.
+pub const SERVICE_COMPACT_BLOCKS: u64 = 1 << 1;
+
+/// Service bit: peer answers `GetBlockHeaders` with full headers, so sync can
+/// check PoW before downloading bodies.
+pub const SERVICE_BLOCK_HEADERS: u64 = 1 << 2;
+
+/// Services this node advertises in its `Version`.
+pub const LOCAL_SERVICES: u64 = SERVICE_INV_RELAY | SERVICE_COMPACT_BLOCKS | SERVICE_BLOCK_HEADERS;
+
+/// Wire protocol version sent in our `Version`. Peers that don't send one
+/// are version 1.
+pub const PROTOCOL_VERSION: u32 = 3;
+
+/// First protocol version that understands `AddrV2`.
+pub const ADDR_V2_VERSION: u32 = 2;
+
+/// First protocol version that accepts a multi-hash locator in `GetHeaders`
+/// and `GetBlockHeaders`. Older peers are sent just the first hash.
+pub const LOCATOR_VERSION: u32 = 3;
+
+/// Most hashes accepted in a block locator (a u32 height needs ~34).
+pub const MAX_LOCATOR_HASHES: usize = 64;
+
+/// Tor v3 hidden service address: the 35 bytes behind the 56-character
+/// base32 label (ed25519 key, 2-byte checksum, version 3) and a port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OnionAddr {
+    pub id: [u8; 35],
+    pub port: u16,
+}
+
+impl OnionAddr {
+    /// Whether `id` carries version 3 and a checksum matching its key.
+    pub fn is_valid(&self) -> bool {
+        let mut pre = b".onion checksum".to_vec();
+        pre.extend_from_slice(&self.id[..32]);
+        pre.push(3);
+        let check = crate::crypto::hash::hash_sha3_256(&pre);
+        self.id[34] == 3 && self.id[32..34] == check[..2]
+    }
+}
+
+impl std::str::FromStr for OnionAddr {
+    type Err = &'static str;
+
+    /// Parse `<56 base32 chars>.onion:port`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (host, port) = s.rsplit_once(':').ok_or("missing port")?;
+        let port = port.parse().map_err(|_| "invalid port")?;
+        let label = host.strip_suffix(".onion").ok_or("not an .onion host")?;
+        if label.len() != 56 {
+            return Err("not a v3 onion address");
+        }
+        let raw = data_encoding::BASE32_NOPAD
+            .decode(label.to_ascii_uppercase().as_bytes())
+            .map_err(|_| "invalid base32")?;
+        let addr = OnionAddr { id: raw.try_into().map_err(|_| "not a v3 onion address")?, port };
+        if !addr.is_valid() {
+            return Err("bad onion checksum or version");
+        }
+        Ok(addr)
+    }
+}
+
+impl std::fmt::Display for OnionAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = data_encoding::BASE32_NOPAD.encode(&self.id).to_ascii_lowercase();
+        write!(f, "{label}.onion:{}", self.port)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum NetworkMessage {
-    Version { height: u32 },
+    /// `timestamp` is the sender's unix time; 0 when sent by a node that predates it.
+    /// `services` is a `SERVICE_*` bitmask; 0 for nodes that predate it.
+    /// `node_id` is random per node run, used to spot duplicate and self
+    /// connections; all zeros for nodes that predate it.
+    /// `protocol_version` is `PROTOCOL_VERSION`; 1 for nodes that predate it.
+    Version { height: u32, timestamp: u64, services: u64, node_id: [u8; 32], protocol_version: u32 },
     Verack,
-    GetHeaders { from_hash: [u8; 32] },
+    /// `locator` is our tip then hashes further and further back, ending at
+    /// genesis; the peer answers from the first one on its active chain.
+    /// A one-hash locator uses the pre-`LOCATOR_VERSION` encoding.
+    GetHeaders { locator: Vec<[u8; 32]> },
     Headers(Vec<[u8; 32]>),
     GetBlocks { hashes: Vec<[u8; 32]> },
     Blocks(Vec<Vec<u8>>), // each inner Vec is raw StoredBlock bytes
@@ -35,6 +128,22 @@ pub enum NetworkMessage {
     Addr(Vec<SocketAddr>),
     GetAddr, // Request peers from connected node
     Tx(Vec<u8>), // raw transaction bytes
+    Inv(Vec<[u8; 32]>),     // announce txids we have
+    GetData(Vec<[u8; 32]>), // request announced txs by id
+    /// New block as its header plus the first 8 bytes of each txid.
+    CompactBlock { header: [u8; 148], short_ids: Vec<[u8; 8]> },
+    /// Ask for the transactions at `indexes` of a compact block.
+    GetBlockTxn { block_hash: [u8; 32], indexes: Vec<u32> },
+    /// Reply to `GetBlockTxn`: raw transactions in the requested order.
+    BlockTxn { block_hash: [u8; 32], txs: Vec<Vec<u8>> },
+    /// Like `GetHeaders`, answered with `BlockHeaders` instead of bare hashes.
+    GetBlockHeaders { locator: Vec<[u8; 32]> },
+    /// Consecutive 148-byte block headers, lowest height first.
+    BlockHeaders(Vec<[u8; 148]>),
+    /// `Addr` with Tor v3 entries, for peers at `ADDR_V2_VERSION` or later.
+    /// Every entry carries its length, so types added later are skipped by
+    /// decoders that don't know them instead of failing the message.
+    AddrV2 { ips: Vec<SocketAddr>, onion: Vec<OnionAddr> },
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -46,13 +155,23 @@ enum MsgType {
     Headers = 0x11,
     GetBlocks = 0x12,
     Blocks = 0x13,
+    CompactBlock = 0x14,
+    GetBlockTxn = 0x15,
+    BlockTxn = 0x16,
+    GetBlockHeaders = 0x17,
+    BlockHeaders = 0x18,
+    GetHeadersLocator = 0x19,
+    GetBlockHeadersLocator = 0x1A,
     Ping = 0x20,
     Pong = 0x21,
     Challenge = 0x30,
     Response = 0x31,
     Addr = 0x40,
     GetAddr = 0x41,
+    AddrV2 = 0x42,
     Tx = 0x50,
+    Inv = 0x51,
+    GetData = 0x52,
 }
 
 impl MsgType {
@@ -64,13 +183,23 @@ impl MsgType {
             0x11 => Some(Self::Headers),
             0x12 => Some(Self::GetBlocks),
             0x13 => Some(Self::Blocks),
+            0x14 => Some(Self::CompactBlock),
+            0x15 => Some(Self::GetBlockTxn),
+            0x16 => Some(Self::BlockTxn),
+            0x17 => Some(Self::GetBlockHeaders),
+            0x18 => Some(Self::BlockHeaders),
+            0x19 => Some(Self::GetHeadersLocator),
+            0x1A => Some(Self::GetBlockHeadersLocator),
             0x20 => Some(Self::Ping),
             0x21 => Some(Self::Pong),
             0x30 => Some(Self::Challenge),
             0x31 => Some(Self::Response),
             0x40 => Some(Self::Addr),
             0x41 => Some(Self::GetAddr),
+            0x42 => Some(Self::AddrV2),
             0x50 => Some(Self::Tx),
+            0x51 => Some(Self::Inv),
+            0x52 => Some(Self::GetData),
             _ => None,
         }
     }
@@ -91,6 +220,9 @@ fn write_hashes(buf: &mut Vec<u8>, hashes: &[[u8; 32]]) {
     }
 }
 
+/// Upper bound on the item count of any list in a message.
+const MAX_LIST_ITEMS: usize = 2000;
+
 fn read_u32(d: &[u8], off: &mut usize) -> Option<u32> {
     if d.len() < *off + 4 {
         return None;
@@ -119,9 +251,25 @@ fn read_hash(d: &[u8], off: &mut usize) -> Option<[u8; 32]> {
     Some(h)
 }
 
+/// One hash in the original single-hash layout, so peers before
+/// `LOCATOR_VERSION` can read it; a counted list under `locator_type` otherwise.
+fn write_locator(buf: &mut Vec<u8>, single_type: MsgType, locator_type: MsgType, locator: &[[u8; 32]]) {
+    if let [hash] = locator {
+        buf.push(single_type as u8);
+        buf.extend_from_slice(hash);
+    } else {
+        buf.push(locator_type as u8);
+        write_hashes(buf, locator);
+    }
+}
+
+fn read_locator(d: &[u8], off: &mut usize) -> Option<Vec<[u8; 32]>> {
+    read_hashes(d, off).filter(|l| l.len() <= MAX_LOCATOR_HASHES)
+}
+
 fn read_hashes(d: &[u8], off: &mut usize) -> Option<Vec<[u8; 32]>> {
     let count = read_u32(d, off)? as usize;
-    if count > 2000 {
+    if count > MAX_LIST_ITEMS {
         return None;
     }
     let mut out = Vec::with_capacity(count);
@@ -131,20 +279,70 @@ fn read_hashes(d: &[u8], off: &mut usize) -> Option<Vec<[u8; 32]>> {
     Some(out)
 }
 
+fn write_raws(buf: &mut Vec<u8>, raws: &[Vec<u8>]) {
+    write_u32(buf, raws.len() as u32);
+    for raw in raws {
+        write_u32(buf, raw.len() as u32);
+        buf.extend_from_slice(raw);
+    }
+}
+
+fn read_raws(d: &[u8], off: &mut usize, max: usize) -> Option<Vec<Vec<u8>>> {
+    let count = read_u32(d, off)? as usize;
+    if count > max {
+        return None;
+    }
+    let mut out = Vec::with_capacity(count);
+    for _ in 0..count {
+        let len = read_u32(d, off)? as usize;
+        if d.len() < *off + len {
+            return None;
+        }
+        out.push(d[*off..*off + len].to_vec());
+        *off += len;
+    }
+    Some(out)
+}
+
+// Address type bytes in `Addr` / `AddrV2` entries.
+const ADDR_TYPE_ONION: u8 = 0x03;
+const ADDR_TYPE_IPV4: u8 = 0x04;
+const ADDR_TYPE_IPV6: u8 = 0x06;
+
+/// An IPv4 (6-byte) or IPv6 (18-byte) entry: address, then big-endian port.
+fn decode_ip_entry(ty: u8, entry: &[u8]) -> Option<SocketAddr> {
+    let (ip, port) = entry.split_at_checked(entry.len().checked_sub(2)?)?;
+    let port = u16::from_be_bytes(port.try_into().ok()?);
+    let ip = match ty {
+        ADDR_TYPE_IPV4 => std::net::IpAddr::V4(<[u8; 4]>::try_from(ip).ok()?.into()),
+        ADDR_TYPE_IPV6 => std::net::IpAddr::V6(<[u8; 16]>::try_from(ip).ok()?.into()),
+        _ => return None,
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
 impl NetworkMessage {
+    /// Mainnet frame; see `encode_with_magic` for other networks.
     pub fn encode(&self) -> Vec<u8> {
+        self.encode_with_magic(MAGIC)
+    }
+
+    pub fn encode_with_magic(&self, magic: [u8; 4]) -> Vec<u8> {
         let mut payload = Vec::new();
         match self {
-            NetworkMessage::Version { height } => {
+            NetworkMessage::Version { height, timestamp, services, node_id, protocol_version } => {
                 payload.push(MsgType::Version as u8);
                 write_u32(&mut payload, *height);
+                write_u64(&mut payload, *timestamp);
+                write_u64(&mut payload, *services);
+                payload.extend_from_slice(node_id);
+                write_u32(&mut payload, *protocol_version);
             }
             NetworkMessage::Verack => {
                 payload.push(MsgType::Verack as u8);
             }
-            NetworkMessage::GetHeaders { from_hash } => {
-                payload.push(MsgType::GetHeaders as u8);
-                payload.extend_from_slice(from_hash);
+            NetworkMessage::GetHeaders { locator } => {
+                write_locator(&mut payload, MsgType::GetHeaders, MsgType::GetHeadersLocator, locator);
             }
             NetworkMessage::Headers(hashes) => {
                 payload.push(MsgType::Headers as u8);
@@ -158,10 +356,37 @@ impl NetworkMessage {
                 // Each block is stored as [4-byte LE length][raw bytes].
                 // This is byte-for-byte identical to ChainDB storage.
                 payload.push(MsgType::Blocks as u8);
-                write_u32(&mut payload, blocks.len() as u32);
-                for raw in blocks {
-                    write_u32(&mut payload, raw.len() as u32);
-                    payload.extend_from_slice(raw);
+                write_raws(&mut payload, blocks);
+            }
+            NetworkMessage::CompactBlock { header, short_ids } => {
+                payload.push(MsgType::CompactBlock as u8);
+                payload.extend_from_slice(header);
+                write_u32(&mut payload, short_ids.len() as u32);
+                for id in short_ids {
+                    payload.extend_from_slice(id);
+                }
+            }
+            NetworkMessage::GetBlockTxn { block_hash, indexes } => {
+                payload.push(MsgType::GetBlockTxn as u8);
+                payload.extend_from_slice(block_hash);
+                write_u32(&mut payload, indexes.len() as u32);
+                for i in indexes {
+                    write_u32(&mut payload, *i);
+                }
+            }
+            NetworkMessage::BlockTxn { block_hash, txs } => {
+                payload.push(MsgType::BlockTxn as u8);
+                payload.extend_from_slice(block_hash);
+                write_raws(&mut payload, txs);
+            }
+            NetworkMessage::GetBlockHeaders { locator } => {
+                write_locator(&mut payload, MsgType::GetBlockHeaders, MsgType::GetBlockHeadersLocator, locator);
+            }
+            NetworkMessage::BlockHeaders(headers) => {
+                payload.push(MsgType::BlockHeaders as u8);
+                write_u32(&mut payload, headers.len() as u32);
+                for h in headers {
+                    payload.extend_from_slice(h);
                 }
             }
             NetworkMessage::Ping(n) => {
@@ -186,18 +411,39 @@ impl NetworkMessage {
                 for addr in addrs {
                     match addr {
                         std::net::SocketAddr::V4(v4) => {
-                            payload.push(0x04);
+                            payload.push(ADDR_TYPE_IPV4);
                             payload.extend_from_slice(&v4.ip().octets());
                             payload.extend_from_slice(&v4.port().to_be_bytes());
                         }
                         std::net::SocketAddr::V6(v6) => {
-                            payload.push(0x06);
+                            payload.push(ADDR_TYPE_IPV6);
                             payload.extend_from_slice(&v6.ip().octets());
                             payload.extend_from_slice(&v6.port().to_be_bytes());
                         }
                     }
                 }
             }
+            NetworkMessage::AddrV2 { ips, onion } => {
+                payload.push(MsgType::AddrV2 as u8);
+                write_u32(&mut payload, (ips.len() + onion.len()) as u32);
+                // Each entry: type, length, then address bytes and big-endian port.
+                for addr in ips {
+                    let (ty, ip) = match addr {
+                        std::net::SocketAddr::V4(v4) => (ADDR_TYPE_IPV4, v4.ip().octets().to_vec()),
+                        std::net::SocketAddr::V6(v6) => (ADDR_TYPE_IPV6, v6.ip().octets().to_vec()),
+                    };
+                    payload.push(ty);
+                    payload.push(ip.len() as u8 + 2);
+                    payload.extend_from_slice(&ip);
+                    payload.extend_from_slice(&addr.port().to_be_bytes());
+                }
+                for o in onion {
+                    payload.push(ADDR_TYPE_ONION);
+                    payload.push(o.id.len() as u8 + 2);
+                    payload.extend_from_slice(&o.id);
+                    payload.extend_from_slice(&o.port.to_be_bytes());
+                }
+            }
             NetworkMessage::GetAddr => {
                 payload.push(MsgType::GetAddr as u8);
             }
@@ -205,21 +451,33 @@ impl NetworkMessage {
                 payload.push(MsgType::Tx as u8);
                 payload.extend_from_slice(raw);
             }
+            NetworkMessage::Inv(txids) => {
+                payload.push(MsgType::Inv as u8);
+                write_hashes(&mut payload, txids);
+            }
+            NetworkMessage::GetData(txids) => {
+                payload.push(MsgType::GetData as u8);
+                write_hashes(&mut payload, txids);
+            }
         }
 
         // Frame: MAGIC[4] + length[4] + payload
         let mut frame = Vec::with_capacity(8 + payload.len());
-        frame.extend_from_slice(&MAGIC);
+        frame.extend_from_slice(&magic);
         frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
         frame.extend_from_slice(&payload);
         frame
     }
 
     pub fn decode(data: &[u8]) -> Option<Self> {
+        Self::decode_with_magic(data, MAGIC)
+    }
+
+    pub fn decode_with_magic(data: &[u8], magic: [u8; 4]) -> Option<Self> {
         if data.len() < 9 {
             return None;
         }
-        if data[..4] != MAGIC {
+        if data[..4] != magic {
             return None;
         }
         let payload_len = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
@@ -235,14 +493,16 @@ impl NetworkMessage {
         match MsgType::from_u8(type_byte)? {
             MsgType::Version => {
                 let height = read_u32(body, &mut off)?;
-                Some(NetworkMessage::Version { height })
+                // Trailing fields: older peers stop after height (or timestamp).
+                let timestamp = read_u64(body, &mut off).unwrap_or(0);
+                let services = read_u64(body, &mut off).unwrap_or(0);
+                let node_id = read_hash(body, &mut off).unwrap_or([0u8; 32]);
+                let protocol_version = read_u32(body, &mut off).unwrap_or(1);
+                Some(NetworkMessage::Version { height, timestamp, services, node_id, protocol_version })
             }
             MsgType::Verack => Some(NetworkMessage::Verack),
-            MsgType::GetHeaders => {
-                let mut off2 = 0;
-                let from_hash = read_hash(body, &mut off2)?;
-                Some(NetworkMessage::GetHeaders { from_hash })
-            }
+            MsgType::GetHeaders => Some(NetworkMessage::GetHeaders { locator: vec![read_hash(body, &mut off)?] }),
+            MsgType::GetHeadersLocator => Some(NetworkMessage::GetHeaders { locator: read_locator(body, &mut off)? }),
             MsgType::Headers => {
                 let hashes = read_hashes(body, &mut off)?;
                 Some(NetworkMessage::Headers(hashes))
@@ -251,21 +511,51 @@ impl NetworkMessage {
                 let hashes = read_hashes(body, &mut off)?;
                 Some(NetworkMessage::GetBlocks { hashes })
             }
-            MsgType::Blocks => {
+            MsgType::Blocks => Some(NetworkMessage::Blocks(read_raws(body, &mut off, 500)?)),
+            MsgType::CompactBlock => {
+                if body.len() < 148 {
+                    return None;
+                }
+                let header: [u8; 148] = body[..148].try_into().unwrap();
+                off = 148;
                 let count = read_u32(body, &mut off)? as usize;
-                if count > 500 {
+                if count > MAX_LIST_ITEMS || body.len() < off + count * 8 {
                     return None;
                 }
-                let mut blocks = Vec::with_capacity(count);
-                for _ in 0..count {
-                    let len = read_u32(body, &mut off)? as usize;
-                    if body.len() < off + len {
-                        return None;
-                    }
-                    blocks.push(body[off..off + len].to_vec());
-                    off += len;
+                let short_ids = body[off..off + count * 8]
+                    .chunks_exact(8)
+                    .map(|c| c.try_into().unwrap())
+                    .collect();
+                Some(NetworkMessage::CompactBlock { header, short_ids })
+            }
+            MsgType::GetBlockTxn => {
+                let block_hash = read_hash(body, &mut off)?;
+                let count = read_u32(body, &mut off)? as usize;
+                if count > MAX_LIST_ITEMS {
+                    return None;
                 }
-                Some(NetworkMessage::Blocks(blocks))
+                let indexes = (0..count).map(|_| read_u32(body, &mut off)).collect::<Option<_>>()?;
+                Some(NetworkMessage::GetBlockTxn { block_hash, indexes })
+            }
+            MsgType::BlockTxn => {
+                let block_hash = read_hash(body, &mut off)?;
+                let txs = read_raws(body, &mut off, MAX_LIST_ITEMS)?;
+                Some(NetworkMessage::BlockTxn { block_hash, txs })
+            }
+            MsgType::GetBlockHeaders => Some(NetworkMessage::GetBlockHeaders { locator: vec![read_hash(body, &mut off)?] }),
+            MsgType::GetBlockHeadersLocator => {
+                Some(NetworkMessage::GetBlockHeaders { locator: read_locator(body, &mut off)? })
+            }
+            MsgType::BlockHeaders => {
+                let count = read_u32(body, &mut off)? as usize;
+                if count > MAX_LIST_ITEMS || body.len() < off + count * 148 {
+                    return None;
+                }
+                let headers = body[off..off + count * 148]
+                    .chunks_exact(148)
+                    .map(|c| c.try_into().unwrap())
+                    .collect();
+                Some(NetworkMessage::BlockHeaders(headers))
             }
             MsgType::Ping => Some(NetworkMessage::Ping(read_u64(body, &mut off)?)),
             MsgType::Pong => Some(NetworkMessage::Pong(read_u64(body, &mut off)?)),
@@ -284,32 +574,54 @@ impl NetworkMessage {
                 if count > 1000 { return None; }
                 let mut addrs = Vec::with_capacity(count);
                 for _ in 0..count {
-                    let ty = body[off];
+                    let ty = *body.get(off)?;
                     off += 1;
-                    if ty == 0x04 {
-                        let mut ip = [0u8; 4];
-                        ip.copy_from_slice(&body[off..off+4]);
-                        off += 4;
-                        let port = u16::from_be_bytes(body[off..off+2].try_into().unwrap());
-                        off += 2;
-                        addrs.push(std::net::SocketAddr::new(std::net::IpAddr::V4(ip.into()), port));
-                    } else if ty == 0x06 {
-                        let mut ip = [0u8; 16];
-                        ip.copy_from_slice(&body[off..off+16]);
-                        off += 16;
-                        let port = u16::from_be_bytes(body[off..off+2].try_into().unwrap());
-                        off += 2;
-                        addrs.push(std::net::SocketAddr::new(std::net::IpAddr::V6(ip.into()), port));
-                    } else { return None; }
+                    let len = match ty {
+                        ADDR_TYPE_IPV4 => 4 + 2,
+                        ADDR_TYPE_IPV6 => 16 + 2,
+                        _ => return None,
+                    };
+                    let entry = body.get(off..off + len)?;
+                    off += len;
+                    addrs.push(decode_ip_entry(ty, entry)?);
                 }
                 Some(NetworkMessage::Addr(addrs))
             }
+            MsgType::AddrV2 => {
+                let count = read_u32(body, &mut off)? as usize;
+                if count > 1000 { return None; }
+                let mut ips = Vec::with_capacity(count);
+                let mut onion = Vec::new();
+                for _ in 0..count {
+                    let ty = *body.get(off)?;
+                    let len = *body.get(off + 1)? as usize;
+                    let entry = body.get(off + 2..off + 2 + len)?;
+                    off += 2 + len;
+                    // Unknown types and malformed entries are skipped, not fatal.
+                    match ty {
+                        ADDR_TYPE_IPV4 | ADDR_TYPE_IPV6 => ips.extend(decode_ip_entry(ty, entry)),
+                        ADDR_TYPE_ONION if len == 37 => {
+                            let o = OnionAddr {
+                                id: entry[..35].try_into().unwrap(),
+                                port: u16::from_be_bytes([entry[35], entry[36]]),
+                            };
+                            if o.is_valid() {
+                                onion.push(o);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Some(NetworkMessage::AddrV2 { ips, onion })
+            }
             MsgType::GetAddr => {
                 Some(NetworkMessage::GetAddr)
             }
             MsgType::Tx => {
                 Some(NetworkMessage::Tx(body.to_vec()))
             }
+            MsgType::Inv => Some(NetworkMessage::Inv(read_hashes(body, &mut off)?)),
+            MsgType::GetData => Some(NetworkMessage::GetData(read_hashes(body, &mut off)?)),
         }
     }
 
@@ -328,6 +640,13 @@ impl NetworkMessage {
 pub struct FramedStream {
     stream: TcpStream,
     buf: Vec<u8>,
+    magic: [u8; 4],
+    read_timeout: std::time::Duration,
+    frame_timeout: std::time::Duration,
+    /// When bytes last arrived.
+    last_read: tokio::time::Instant,
+    /// When the first byte of the frame still incomplete in `buf` arrived.
+    frame_start: Option<tokio::time::Instant>,
 }
 
 impl FramedStream {
@@ -335,17 +654,38 @@ impl FramedStream {
         FramedStream {
             stream,
             buf: Vec::new(),
+            magic: MAGIC,
+            read_timeout: std::time::Duration::from_secs(READ_TIMEOUT_SECS),
+            frame_timeout: std::time::Duration::from_secs(FRAME_TIMEOUT_SECS),
+            last_read: tokio::time::Instant::now(),
+            frame_start: None,
         }
     }
 
+    /// Replace `READ_TIMEOUT_SECS` and `FRAME_TIMEOUT_SECS`.
+    pub fn with_timeouts(mut self, read: std::time::Duration, frame: std::time::Duration) -> Self {
+        self.read_timeout = read;
+        self.frame_timeout = frame;
+        self
+    }
+
+    /// Frame with another network's magic (testnet, regtest).
+    pub fn with_magic(mut self, magic: [u8; 4]) -> Self {
+        self.magic = magic;
+        self
+    }
+
     pub async fn send(&mut self, msg: &NetworkMessage) -> io::Result<()> {
-        self.stream.write_all(&msg.encode()).await
+        self.stream.write_all(&msg.encode_with_magic(self.magic)).await
     }
 
     pub async fn recv(&mut self) -> io::Result<Option<NetworkMessage>> {
         loop {
             // Do we have a full frame already buffered?
             if self.buf.len() >= 8 {
+                if self.buf[..4] != self.magic {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "wrong network magic"));
+                }
                 let payload_len = u32::from_le_bytes(self.buf[4..8].try_into().unwrap()) as usize;
                 let frame_len = 8 + payload_len;
 
@@ -359,16 +699,28 @@ impl FramedStream {
                 if self.buf.len() >= frame_len {
                     let frame = self.buf[..frame_len].to_vec();
                     self.buf.drain(..frame_len);
-                    return Ok(NetworkMessage::decode(&frame));
+                    self.frame_start = None;
+                    return Ok(NetworkMessage::decode_with_magic(&frame, self.magic));
                 }
             }
 
-            // Need more data
+            // Need more data. The deadlines live in `self`, so being cancelled
+            // by a `select!` and called again doesn't restart them.
+            if !self.buf.is_empty() && self.frame_start.is_none() {
+                self.frame_start = Some(tokio::time::Instant::now());
+            }
+            let (deadline, what) = match self.frame_start {
+                Some(start) => (start + self.frame_timeout, "frame not completed in time"),
+                None => (self.last_read + self.read_timeout, "read timed out"),
+            };
             let mut tmp = vec![0u8; 4096];
-            let n = self.stream.read(&mut tmp).await?;
+            let n = tokio::time::timeout_at(deadline, self.stream.read(&mut tmp))
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, what))??;
             if n == 0 {
                 return Ok(None);
             }
+            self.last_read = tokio::time::Instant::now();
             self.buf.extend_from_slice(&tmp[..n]);
         }
     }
@@ -378,6 +730,47 @@ impl FramedStream {
 mod tests {
     use super::*;
 
+    /// A connected (client, server) pair on loopback.
+    async fn tcp_pair() -> (TcpStream, TcpStream) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        (client, server)
+    }
+
+    #[tokio::test]
+    async fn test_recv_times_out_on_stalled_frame() {
+        use std::time::Duration;
+        let (mut client, server) = tcp_pair().await;
+        let mut s = FramedStream::new(server).with_timeouts(Duration::from_secs(60), Duration::from_millis(200));
+
+        // Header promising 100 bytes, then only 10 of them.
+        let mut partial = MAGIC.to_vec();
+        partial.extend_from_slice(&100u32.to_le_bytes());
+        partial.extend_from_slice(&[0u8; 10]);
+        client.write_all(&partial).await.unwrap();
+
+        let started = std::time::Instant::now();
+        let err = s.recv().await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_recv_times_out_on_silent_peer_but_not_busy_one() {
+        use std::time::Duration;
+        let (mut client, server) = tcp_pair().await;
+        let mut s = FramedStream::new(server).with_timeouts(Duration::from_millis(300), Duration::from_secs(60));
+
+        // Frames arriving more often than the read timeout keep it alive.
+        for n in 0..4 {
+            client.write_all(&NetworkMessage::Ping(n).encode()).await.unwrap();
+            assert!(matches!(s.recv().await.unwrap(), Some(NetworkMessage::Ping(got)) if got == n));
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(s.recv().await.unwrap_err().kind(), io::ErrorKind::TimedOut);
+    }
+
     fn roundtrip(msg: NetworkMessage) -> NetworkMessage {
         let enc = msg.encode();
         NetworkMessage::decode(&enc).expect("decode failed")
@@ -385,14 +778,52 @@ mod tests {
 
     #[test]
     fn test_version() {
-        let m = roundtrip(NetworkMessage::Version { height: 12345 });
-        if let NetworkMessage::Version { height } = m {
+        let m = roundtrip(NetworkMessage::Version {
+            height: 12345,
+            timestamp: 1_772_004_727,
+            services: LOCAL_SERVICES,
+            node_id: [0x5A; 32],
+            protocol_version: PROTOCOL_VERSION,
+        });
+        if let NetworkMessage::Version { height, timestamp, services, node_id, protocol_version } = m {
             assert_eq!(height, 12345);
+            assert_eq!(timestamp, 1_772_004_727);
+            assert_eq!(services, LOCAL_SERVICES);
+            assert_eq!(node_id, [0x5A; 32]);
+            assert_eq!(protocol_version, PROTOCOL_VERSION);
         } else {
             panic!("wrong type");
         }
     }
 
+    #[test]
+    fn test_version_without_timestamp_decodes() {
+        // Frame as sent by a peer that only knows the height field.
+        let mut payload = vec![MsgType::Version as u8];
+        payload.extend_from_slice(&777u32.to_le_bytes());
+        let mut frame = MAGIC.to_vec();
+        frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&payload);
+        match NetworkMessage::decode(&frame) {
+            Some(NetworkMessage::Version { height: 777, timestamp: 0, services: 0, node_id, protocol_version: 1 })
+                if node_id == [0u8; 32] => {}
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_inv_getdata_roundtrip() {
+        let ids = vec![[0x11u8; 32], [0x22u8; 32]];
+        match roundtrip(NetworkMessage::Inv(ids.clone())) {
+            NetworkMessage::Inv(got) => assert_eq!(got, ids),
+            other => panic!("unexpected: {other:?}"),
+        }
+        match roundtrip(NetworkMessage::GetData(ids.clone())) {
+            NetworkMessage::GetData(got) => assert_eq!(got, ids),
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+
     #[test]
     fn test_blocks_roundtrip() {
         let raw1 = vec![0xABu8; 148];
@@ -406,17 +837,167 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_compact_block_messages_roundtrip() {
+        let header = [0x5Au8; 148];
+        let ids = vec![[1u8; 8], [2u8; 8]];
+        match roundtrip(NetworkMessage::CompactBlock { header, short_ids: ids.clone() }) {
+            NetworkMessage::CompactBlock { header: h, short_ids } => {
+                assert_eq!(h, header);
+                assert_eq!(short_ids, ids);
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+        match roundtrip(NetworkMessage::GetBlockTxn { block_hash: [3u8; 32], indexes: vec![0, 4] }) {
+            NetworkMessage::GetBlockTxn { block_hash, indexes } => {
+                assert_eq!(block_hash, [3u8; 32]);
+                assert_eq!(indexes, vec![0, 4]);
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+        let txs = vec![vec![7u8; 40], vec![8u8; 3]];
+        match roundtrip(NetworkMessage::BlockTxn { block_hash: [3u8; 32], txs: txs.clone() }) {
+            NetworkMessage::BlockTxn { txs: got, .. } => assert_eq!(got, txs),
+            other => panic!("unexpected: {other:?}"),
+        }
+
+        // A short-id count that overruns the frame is rejected.
+        let mut enc = NetworkMessage::CompactBlock { header, short_ids: ids }.encode();
+        let count_at = 8 + 1 + 148;
+        enc[count_at..count_at + 4].copy_from_slice(&3u32.to_le_bytes());
+        assert!(NetworkMessage::decode(&enc).is_none());
+    }
+
+    const DDG_ONION: &str = "duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczad.onion:443";
+
+    #[test]
+    fn test_onion_addr_parse_and_display() {
+        let o: OnionAddr = DDG_ONION.parse().unwrap();
+        assert_eq!(o.port, 443);
+        assert_eq!(o.to_string(), DDG_ONION);
+        assert_eq!(DDG_ONION.to_uppercase().replace(".ONION", ".onion").parse::<OnionAddr>().unwrap(), o);
+
+        assert!("duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczad.onion".parse::<OnionAddr>().is_err());
+        assert!("expyuzz4wqqyqhjn.onion:80".parse::<OnionAddr>().is_err()); // v2
+        // One changed character breaks the checksum.
+        assert!(DDG_ONION.replacen('d', "e", 1).parse::<OnionAddr>().is_err());
+    }
+
+    #[test]
+    fn test_addr_v2_roundtrip_with_onion() {
+        let ips: Vec<SocketAddr> = vec!["1.2.3.4:9000".parse().unwrap(), "[2001:db8::1]:9000".parse().unwrap()];
+        let onion: OnionAddr = DDG_ONION.parse().unwrap();
+        match roundtrip(NetworkMessage::AddrV2 { ips: ips.clone(), onion: vec![onion] }) {
+            NetworkMessage::AddrV2 { ips: got, onion: got_onion } => {
+                assert_eq!(got, ips);
+                assert_eq!(got_onion, vec![onion]);
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+
+        // An onion entry with a bad checksum is dropped, not the whole message.
+        let mut bad = onion;
+        bad.id[0] ^= 1;
+        match roundtrip(NetworkMessage::AddrV2 { ips: ips.clone(), onion: vec![bad] }) {
+            NetworkMessage::AddrV2 { ips: got, onion: got_onion } => {
+                assert_eq!(got, ips);
+                assert!(got_onion.is_empty());
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+
+        // Truncated entries fail cleanly instead of panicking.
+        let enc = NetworkMessage::AddrV2 { ips: vec![], onion: vec![onion] }.encode();
+        let mut cut = enc[..enc.len() - 3].to_vec();
+        let len = (cut.len() - 8) as u32;
+        cut[4..8].copy_from_slice(&len.to_le_bytes());
+        assert!(NetworkMessage::decode(&cut).is_none());
+    }
+
+    #[test]
+    fn test_addr_v2_skips_unknown_address_type() {
+        // [v4][future type 0x09 with a 7-byte body][v6]
+        let mut payload = vec![MsgType::AddrV2 as u8];
+        payload.extend_from_slice(&3u32.to_le_bytes());
+        payload.extend_from_slice(&[0x04, 6, 1, 2, 3, 4, 0x23, 0x28]);
+        payload.extend_from_slice(&[0x09, 7, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF, 0x11]);
+        payload.extend_from_slice(&[0x06, 18]);
+        payload.extend_from_slice(&std::net::Ipv6Addr::LOCALHOST.octets());
+        payload.extend_from_slice(&9000u16.to_be_bytes());
+        let mut frame = MAGIC.to_vec();
+        frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&payload);
+
+        match NetworkMessage::decode(&frame) {
+            Some(NetworkMessage::AddrV2 { ips, onion }) => {
+                assert_eq!(ips, vec!["1.2.3.4:9000".parse::<SocketAddr>().unwrap(), "[::1]:9000".parse().unwrap()]);
+                assert!(onion.is_empty());
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_legacy_addr_roundtrip() {
+        let ips: Vec<SocketAddr> = vec!["1.2.3.4:9000".parse().unwrap(), "[2001:db8::1]:9000".parse().unwrap()];
+        match roundtrip(NetworkMessage::Addr(ips.clone())) {
+            NetworkMessage::Addr(got) => assert_eq!(got, ips),
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_block_headers_roundtrip() {
+        match roundtrip(NetworkMessage::GetBlockHeaders { locator: vec![[4u8; 32]] }) {
+            NetworkMessage::GetBlockHeaders { locator } => assert_eq!(locator, vec![[4u8; 32]]),
+            other => panic!("unexpected: {other:?}"),
+        }
+        let headers = vec![[1u8; 148], [2u8; 148]];
+        match roundtrip(NetworkMessage::BlockHeaders(headers.clone())) {
+            NetworkMessage::BlockHeaders(got) => assert_eq!(got, headers),
+            other => panic!("unexpected: {other:?}"),
+        }
+
+        // A count larger than the headers present is rejected.
+        let mut enc = NetworkMessage::BlockHeaders(headers).encode();
+        enc[9..13].copy_from_slice(&3u32.to_le_bytes());
+        assert!(NetworkMessage::decode(&enc).is_none());
+    }
+
     #[test]
     fn test_get_headers() {
         let h = [0x42u8; 32];
-        let m = roundtrip(NetworkMessage::GetHeaders { from_hash: h });
-        if let NetworkMessage::GetHeaders { from_hash } = m {
-            assert_eq!(from_hash, h);
+        let m = roundtrip(NetworkMessage::GetHeaders { locator: vec![h] });
+        if let NetworkMessage::GetHeaders { locator } = m {
+            assert_eq!(locator, vec![h]);
         } else {
             panic!("wrong type");
         }
     }
 
+    #[test]
+    fn test_locator_encoding() {
+        // A single hash keeps the layout older peers decode.
+        let enc = NetworkMessage::GetHeaders { locator: vec![[7u8; 32]] }.encode();
+        assert_eq!(enc[8], MsgType::GetHeaders as u8);
+        assert_eq!(enc.len(), 8 + 1 + 32);
+
+        let locator = vec![[3u8; 32], [2u8; 32], [0u8; 32]];
+        let enc = NetworkMessage::GetBlockHeaders { locator: locator.clone() }.encode();
+        assert_eq!(enc[8], MsgType::GetBlockHeadersLocator as u8);
+        match NetworkMessage::decode(&enc) {
+            Some(NetworkMessage::GetBlockHeaders { locator: got }) => assert_eq!(got, locator),
+            other => panic!("unexpected: {other:?}"),
+        }
+        match roundtrip(NetworkMessage::GetHeaders { locator: locator.clone() }) {
+            NetworkMessage::GetHeaders { locator: got } => assert_eq!(got, locator),
+            other => panic!("unexpected: {other:?}"),
+        }
+
+        let too_long = vec![[1u8; 32]; MAX_LOCATOR_HASHES + 1];
+        assert!(NetworkMessage::decode(&NetworkMessage::GetHeaders { locator: too_long }.encode()).is_none());
+    }
+
     #[test]
     fn test_ping_pong() {
         let m = roundtrip(NetworkMessage::Ping(9999999));
@@ -433,4 +1014,13 @@ mod tests {
         enc[0] = 0xFF;
         assert!(NetworkMessage::decode(&enc).is_none());
     }
+
+    #[test]
+    fn test_other_network_magic() {
+        let testnet = *b"KNTT";
+        let enc = NetworkMessage::Ping(7).encode_with_magic(testnet);
+        assert_eq!(enc[..4], testnet);
+        assert!(NetworkMessage::decode(&enc).is_none());
+        assert!(matches!(NetworkMessage::decode_with_magic(&enc, testnet), Some(NetworkMessage::Ping(7))));
+    }
 }
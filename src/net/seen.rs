@@ -0,0 +1,108 @@
+// Recently Seen Inventory
+//
+// Txids and block hashes this node has already accepted or announced, shared
+// by every connection. A tx or block echoed back by the gossip is dropped
+// instead of being processed, relayed or requested again. Entries expire
+// after a few minutes, so something announced again later (say after a
+// reorg put it back in play) is handled normally. The set is capped; the
+// oldest entry is forgotten first.
+
+use std::collections::{HashMap, VecDeque};
+
+pub const MAX_SEEN_INV: usize = 50_000;
+
+/// How long a hash stays seen.
+pub const SEEN_INV_TTL_SECS: u64 = 300;
+
+#[derive(Default)]
+pub struct SeenInv {
+    /// hash -> unix time it was (last) marked
+    seen: HashMap<[u8; 32], u64>,
+    /// (hash, time) in marking order, oldest first; stale when `seen` has a
+    /// newer time for the hash
+    order: VecDeque<([u8; 32], u64)>,
+}
+
+impl SeenInv {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+
+    pub fn contains(&self, hash: &[u8; 32], now: u64) -> bool {
+        self.seen.get(hash).is_some_and(|&t| t + SEEN_INV_TTL_SECS > now)
+    }
+
+    /// Mark `hash` seen at `now`. Returns false if it already was.
+    pub fn insert(&mut self, hash: [u8; 32], now: u64) -> bool {
+        self.expire(now);
+        let fresh = !self.contains(&hash, now);
+        self.seen.insert(hash, now);
+        self.order.push_back((hash, now));
+        while self.seen.len() > MAX_SEEN_INV {
+            self.pop_oldest();
+        }
+        fresh
+    }
+
+    fn expire(&mut self, now: u64) {
+        while let Some(&(_, t)) = self.order.front()
+            && t + SEEN_INV_TTL_SECS <= now
+        {
+            self.pop_oldest();
+        }
+    }
+
+    fn pop_oldest(&mut self) {
+        let Some((hash, t)) = self.order.pop_front() else {
+            return;
+        };
+        // A later insert of the same hash left a newer entry behind this one.
+        if self.seen.get(&hash) == Some(&t) {
+            self.seen.remove(&hash);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(i: u32) -> [u8; 32] {
+        let mut h = [0u8; 32];
+        h[..4].copy_from_slice(&i.to_le_bytes());
+        h
+    }
+
+    #[test]
+    fn test_seen_until_expiry() {
+        let mut seen = SeenInv::new();
+        assert!(seen.insert(hash(1), 1000));
+        assert!(!seen.insert(hash(1), 1010));
+        assert!(seen.contains(&hash(1), 1000 + SEEN_INV_TTL_SECS));
+        // The second insert refreshed it.
+        assert!(!seen.contains(&hash(1), 1010 + SEEN_INV_TTL_SECS));
+        assert!(seen.insert(hash(1), 1010 + SEEN_INV_TTL_SECS));
+        assert_eq!(seen.len(), 1);
+    }
+
+    #[test]
+    fn test_cap_forgets_oldest() {
+        let mut seen = SeenInv::new();
+        for i in 0..MAX_SEEN_INV as u32 + 10 {
+            seen.insert(hash(i), 1000);
+        }
+        assert_eq!(seen.len(), MAX_SEEN_INV);
+        for i in 0..10 {
+            assert!(!seen.contains(&hash(i), 1000));
+        }
+        assert!(seen.contains(&hash(10), 1000));
+    }
+}
@@ -0,0 +1,206 @@
+// Peer Clock Offsets
+//
+// Every peer reports its wall-clock time in the `Version` handshake. We keep
+// one offset sample per network group, taken only from outbound peers that
+// complete the handshake and dropped when they disconnect, and compare our
+// clock against the median. Inbound peers choose to connect to us, so many
+// connections from one host could otherwise own the median. There is no NTP dependency: if most of the peers we talk to agree
+// on a time that is far from ours, our clock is the one that is wrong, and
+// blocks we mine will be rejected as too far in the future or before MTP.
+// The median, clamped to ±70 minutes, also corrects the clock that incoming
+// block timestamps are checked against (`consensus::state::adjusted_now`).
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+
+use crate::net::node::netgroup;
+
+/// Local clock vs peer median beyond which we warn (10 minutes).
+pub const MAX_CLOCK_SKEW_SECS: i64 = 600;
+
+/// Below this many samples the median is too easy to steer, so we stay silent.
+pub const MIN_TIME_SAMPLES: usize = 5;
+
+/// Hard cap on tracked samples; once full, a new one replaces the oldest.
+const MAX_TIME_SAMPLES: usize = 200;
+
+#[derive(Debug)]
+struct Sample {
+    peer: SocketAddr,
+    group: [u8; 5],
+    offset_secs: i64,
+}
+
+#[derive(Debug, Default)]
+pub struct TimeData {
+    /// Oldest first; at most one per network group.
+    samples: VecDeque<Sample>,
+    /// When set, mining is refused while the clock is skewed.
+    pub refuse_mining_on_skew: bool,
+}
+
+impl TimeData {
+    pub fn new(refuse_mining_on_skew: bool) -> Self {
+        Self { samples: VecDeque::new(), refuse_mining_on_skew }
+    }
+
+    /// Record `peer_time - local_time` for a peer. It replaces any sample from
+    /// the same network group, so a host (or subnet) reconnecting from many
+    /// ports adds no weight.
+    pub fn add_sample(&mut self, peer: SocketAddr, offset_secs: i64) {
+        let group = netgroup(peer.ip());
+        self.samples.retain(|s| s.group != group);
+        if self.samples.len() >= MAX_TIME_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(Sample { peer, group, offset_secs });
+    }
+
+    /// Forget `peer`'s sample, if it still holds one, when it disconnects.
+    pub fn remove_sample(&mut self, peer: SocketAddr) {
+        self.samples.retain(|s| s.peer != peer);
+    }
+
+    pub fn sample_count(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Median peer offset in seconds, or `None` with too few samples.
+    pub fn median_offset(&self) -> Option<i64> {
+        if self.samples.len() < MIN_TIME_SAMPLES {
+            return None;
+        }
+        let mut v: Vec<i64> = self.samples.iter().map(|s| s.offset_secs).collect();
+        v.sort_unstable();
+        Some(v[v.len() / 2])
+    }
+
+    pub fn is_skewed(&self) -> bool {
+        self.median_offset()
+            .map(|o| o.abs() > MAX_CLOCK_SKEW_SECS)
+            .unwrap_or(false)
+    }
+
+    /// Human-readable warning when our clock disagrees with the peer median.
+    pub fn skew_warning(&self) -> Option<String> {
+        let offset = self.median_offset()?;
+        if offset.abs() <= MAX_CLOCK_SKEW_SECS {
+            return None;
+        }
+        let direction = if offset > 0 { "behind" } else { "ahead of" };
+        Some(format!(
+            "local clock is {}s {} the median of {} peers; check your system time, mined blocks will likely be rejected",
+            offset.abs(),
+            direction,
+            self.samples.len()
+        ))
+    }
+
+    pub fn mining_blocked(&self) -> bool {
+        self.refuse_mining_on_skew && self.is_skewed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A peer in its own /16
+    fn peer(i: u8) -> SocketAddr {
+        SocketAddr::from(([10, i, 0, 1], 9000))
+    }
+
+    #[test]
+    fn test_no_median_below_min_samples() {
+        let mut td = TimeData::new(true);
+        for i in 0..(MIN_TIME_SAMPLES - 1) as u8 {
+            td.add_sample(peer(i), 3600);
+        }
+        assert_eq!(td.median_offset(), None);
+        assert!(!td.is_skewed());
+        assert!(!td.mining_blocked());
+    }
+
+    #[test]
+    fn test_large_offset_triggers_warning_and_guard() {
+        let mut td = TimeData::new(true);
+        for i in 0..5 {
+            td.add_sample(peer(i), 1800);
+        }
+        assert_eq!(td.median_offset(), Some(1800));
+        assert!(td.skew_warning().unwrap().contains("behind"));
+        assert!(td.mining_blocked());
+
+        td.refuse_mining_on_skew = false;
+        assert!(td.skew_warning().is_some());
+        assert!(!td.mining_blocked());
+    }
+
+    #[test]
+    fn test_small_offset_no_warning() {
+        let mut td = TimeData::new(true);
+        for i in 0..5 {
+            td.add_sample(peer(i), MAX_CLOCK_SKEW_SECS);
+        }
+        assert!(td.skew_warning().is_none());
+        assert!(!td.mining_blocked());
+    }
+
+    #[test]
+    fn test_outlier_minority_cannot_move_median() {
+        let mut td = TimeData::new(true);
+        for i in 0..3 {
+            td.add_sample(peer(i), 5);
+        }
+        td.add_sample(peer(3), -100_000);
+        td.add_sample(peer(4), 100_000);
+        assert_eq!(td.median_offset(), Some(5));
+    }
+
+    #[test]
+    fn test_reconnecting_peer_replaces_sample() {
+        let mut td = TimeData::new(false);
+        td.add_sample(peer(1), 10);
+        td.add_sample(peer(1), 20);
+        assert_eq!(td.sample_count(), 1);
+    }
+
+    #[test]
+    fn test_one_host_on_many_ports_is_one_sample() {
+        let mut td = TimeData::new(true);
+        for port in 0..10 {
+            td.add_sample(SocketAddr::from(([10, 7, 0, 1], 9000 + port)), 3600);
+            td.add_sample(SocketAddr::from(([10, 7, port as u8, 2], 9000)), 3600);
+        }
+        assert_eq!(td.sample_count(), 1);
+        assert!(!td.mining_blocked());
+    }
+
+    #[test]
+    fn test_disconnected_peer_sample_dropped() {
+        let mut td = TimeData::new(false);
+        for i in 0..5 {
+            td.add_sample(peer(i), 1800);
+        }
+        assert_eq!(td.median_offset(), Some(1800));
+        td.remove_sample(peer(2));
+        assert_eq!(td.sample_count(), 4);
+        assert_eq!(td.median_offset(), None);
+        // Another peer's address in a group doesn't remove the sample.
+        td.remove_sample(SocketAddr::from(([10, 1, 0, 9], 9000)));
+        assert_eq!(td.sample_count(), 4);
+    }
+
+    #[test]
+    fn test_full_table_replaces_oldest() {
+        let mut td = TimeData::new(false);
+        for i in 0..MAX_TIME_SAMPLES as u16 {
+            let [a, b] = i.to_be_bytes();
+            td.add_sample(SocketAddr::from(([a, b, 0, 1], 9000)), 0);
+        }
+        td.add_sample(SocketAddr::from(([99, 99, 0, 1], 9000)), 0);
+        assert_eq!(td.sample_count(), MAX_TIME_SAMPLES);
+        assert!(td.samples.iter().all(|s| s.peer != SocketAddr::from(([0, 0, 0, 1], 9000))));
+        assert_eq!(td.samples.back().unwrap().peer, SocketAddr::from(([99, 99, 0, 1], 9000)));
+    }
+}
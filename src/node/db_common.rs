@@ -2,7 +2,12 @@
 // These types define the on-disk format and must remain stable
 
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use crate::consensus::chain::COINBASE_MATURITY;
+use crate::consensus::governance::TX_VERSION_PROPOSAL;
+use crate::consensus::state::GovernanceParams;
 use crate::crypto::keys::ADDRESS_BYTES;
+use crate::primitives::transaction::{MAX_MEMO_BYTES, TX_VERSION_MEMO};
 
 /// Account state stored in database
 /// 
@@ -16,6 +21,8 @@ use crate::crypto::keys::ADDRESS_BYTES;
 ///   [65..73] total_referral_bonus_earned (LE u64)
 ///   [73..81] governance_weight (LE u64)
 ///   [81..89] total_blocks_mined (LE u64)
+///   [89..93] immature credit count (LE u32), then per credit:
+///            height (LE u64), amount (LE u64)
 #[derive(Debug, Clone)]
 pub struct AccountState {
     pub balance: u64,
@@ -26,6 +33,9 @@ pub struct AccountState {
     pub total_referral_bonus_earned: u64,
     pub governance_weight: u64,
     pub total_blocks_mined: u64,
+    /// Rewards not yet spendable, as (height credited, amount), oldest
+    /// first; see `COINBASE_MATURITY`.
+    pub immature: Vec<(u64, u64)>,
 }
 
 impl AccountState {
@@ -39,9 +49,37 @@ impl AccountState {
             total_referral_bonus_earned: 0,
             governance_weight: 0,
             total_blocks_mined: 0,
+            immature: Vec::new(),
         }
     }
 
+    /// Sum of the credits still waiting for `COINBASE_MATURITY`.
+    pub fn immature_balance(&self) -> u64 {
+        self.immature.iter().fold(0u64, |sum, (_, amount)| sum.saturating_add(*amount))
+    }
+
+    /// Record a reward earned at `height`; None on overflow.
+    pub fn credit_immature(&mut self, height: u64, amount: u64) -> Option<()> {
+        if amount == 0 {
+            return Some(());
+        }
+        match self.immature.last_mut() {
+            Some((h, a)) if *h == height => *a = a.checked_add(amount)?,
+            _ => self.immature.push((height, amount)),
+        }
+        Some(())
+    }
+
+    /// Move every credit that is spendable in a block at `height` into
+    /// `balance`. None on overflow.
+    pub fn mature(&mut self, height: u64) -> Option<()> {
+        let ripe = self.immature.partition_point(|(h, _)| h.saturating_add(COINBASE_MATURITY) <= height);
+        for (_, amount) in self.immature.drain(..ripe) {
+            self.balance = self.balance.checked_add(amount)?;
+        }
+        Some(())
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut b = Vec::with_capacity(89);
         b.extend_from_slice(&self.balance.to_le_bytes());
@@ -62,6 +100,11 @@ impl AccountState {
         b.extend_from_slice(&self.total_referral_bonus_earned.to_le_bytes());
         b.extend_from_slice(&self.governance_weight.to_le_bytes());
         b.extend_from_slice(&self.total_blocks_mined.to_le_bytes());
+        b.extend_from_slice(&(self.immature.len() as u32).to_le_bytes());
+        for (height, amount) in &self.immature {
+            b.extend_from_slice(&height.to_le_bytes());
+            b.extend_from_slice(&amount.to_le_bytes());
+        }
         b
     }
 
@@ -103,6 +146,20 @@ impl AccountState {
         let governance_weight = read_u64(off, d);
         off += 8;
         let total_blocks_mined = read_u64(off, d);
+        off += 8;
+
+        let mut immature = Vec::new();
+        if d.len() >= off + 4 {
+            let count = u32::from_le_bytes(d[off..off + 4].try_into().unwrap()) as usize;
+            off += 4;
+            if count.checked_mul(16).is_none_or(|n| d.len() < off + n) {
+                return Err("truncated immature credits");
+            }
+            for _ in 0..count {
+                immature.push((read_u64(off, d), read_u64(off + 8, d)));
+                off += 16;
+            }
+        }
 
         Ok(AccountState {
             balance,
@@ -113,6 +170,7 @@ impl AccountState {
             total_referral_bonus_earned,
             governance_weight,
             total_blocks_mined,
+            immature,
         })
     }
 }
@@ -181,6 +239,25 @@ impl StoredBlock {
     }
 
     pub fn from_bytes(d: &[u8]) -> Result<Self, &'static str> {
+        let mut block = Self::header_only_from_bytes(d)?;
+        let mut off = 148usize;
+
+        if d.len() >= off + 4 {
+            let tx_count = u32::from_le_bytes(d[off..off + 4].try_into().unwrap()) as usize;
+            off += 4;
+            for _ in 0..tx_count {
+                let (tx, n) = StoredTransaction::from_bytes(&d[off..])?;
+                block.tx_data.push(tx);
+                off += n;
+            }
+        }
+
+        Ok(block)
+    }
+
+    /// Parse only the 148-byte header, leaving `tx_data` empty. The
+    /// transactions that follow are not read or validated.
+    pub fn header_only_from_bytes(d: &[u8]) -> Result<Self, &'static str> {
         if d.len() < 148 {
             return Err("block header too short");
         }
@@ -209,18 +286,7 @@ impl StoredBlock {
         let mut block_height = [0u8; 4];
         block_height.copy_from_slice(read!(4));
         let mut miner_address = [0u8; 32];
-        miner_address.copy_from_slice(read!(32));
-
-        let mut tx_data = Vec::new();
-        if d.len() >= off + 4 {
-            let tx_count = u32::from_le_bytes(d[off..off + 4].try_into().unwrap()) as usize;
-            off += 4;
-            for _ in 0..tx_count {
-                let (tx, n) = StoredTransaction::from_bytes(&d[off..])?;
-                tx_data.push(tx);
-                off += n;
-            }
-        }
+        miner_address.copy_from_slice(&d[off..off + 32]);
 
         Ok(StoredBlock {
             version,
@@ -231,7 +297,7 @@ impl StoredBlock {
             nonce,
             block_height: block_height[0..4].try_into().unwrap(),
             miner_address,
-            tx_data,
+            tx_data: Vec::new(),
         })
     }
 }
@@ -249,6 +315,10 @@ pub struct StoredTransaction {
     pub timestamp: u64,
     pub referrer_address: Option<[u8; 32]>,
     pub governance_data: Option<[u8; 32]>,
+    /// Encoded `Proposal`; present exactly when `version` is `TX_VERSION_PROPOSAL`.
+    pub proposal: Option<Vec<u8>>,
+    /// Present exactly when `version` is `TX_VERSION_MEMO`; at most `MAX_MEMO_BYTES`.
+    pub memo: Option<Vec<u8>>,
     pub signature: Vec<u8>,
 }
 
@@ -282,6 +352,19 @@ impl StoredTransaction {
                 b.push(0);
             }
         }
+        if self.version == TX_VERSION_MEMO {
+            // Over-long memos are rejected before serializing (`has_valid_structure`,
+            // the wallet RPCs), so the length always fits its byte.
+            let m = self.memo.as_deref().unwrap_or_default();
+            debug_assert!(m.len() <= MAX_MEMO_BYTES, "memo longer than MAX_MEMO_BYTES");
+            b.push(m.len() as u8);
+            b.extend_from_slice(m);
+        }
+        if self.version == TX_VERSION_PROPOSAL {
+            let p = self.proposal.as_deref().unwrap_or_default();
+            b.extend_from_slice(&(p.len() as u32).to_le_bytes());
+            b.extend_from_slice(p);
+        }
         b.extend_from_slice(&(self.signature.len() as u32).to_le_bytes());
         b.extend_from_slice(&self.signature);
         b
@@ -369,6 +452,41 @@ impl StoredTransaction {
             None
         };
 
+        let memo = if version == TX_VERSION_MEMO {
+            if d.len() < off + 1 {
+                return Err("tx: missing memo len");
+            }
+            let len = d[off] as usize;
+            off += 1;
+            if len > MAX_MEMO_BYTES {
+                return Err("tx: memo too long");
+            }
+            if d.len() < off + len {
+                return Err("tx: truncated memo");
+            }
+            let m = d[off..off + len].to_vec();
+            off += len;
+            Some(m)
+        } else {
+            None
+        };
+
+        let proposal = if version == TX_VERSION_PROPOSAL {
+            if d.len() < off + 4 {
+                return Err("tx: missing proposal len");
+            }
+            let len = u32::from_le_bytes(d[off..off + 4].try_into().unwrap()) as usize;
+            off += 4;
+            if d.len() < off + len {
+                return Err("tx: truncated proposal");
+            }
+            let p = d[off..off + len].to_vec();
+            off += len;
+            Some(p)
+        } else {
+            None
+        };
+
         let signature = if d.len() >= off + 4 {
             let sig_len = u32::from_le_bytes(d[off..off + 4].try_into().unwrap()) as usize;
             off += 4;
@@ -394,9 +512,296 @@ impl StoredTransaction {
                 timestamp,
                 referrer_address,
                 governance_data,
+                proposal,
+                memo,
                 signature,
             },
             off,
         ))
     }
 }
+
+/// State a block overwrote, kept so the block can be disconnected in a reorg
+///
+/// Serialization Format:
+///   [u32 LE] account count, then per account:
+///            addr[32], flag (0 = did not exist | 1), [u32 LE] len + AccountState bytes if flag == 1
+///   [u32 LE] tally count, then per tally: proposal[32], previous tally (LE u64)
+///   [u32 LE] vote count, then per vote: proposal[32]+voter[32]
+///   [u32 LE] proposal count, then per proposal: hash[32] (absent in older records)
+///   flag (1 = governance params changed), previous params[24] if flag == 1 (absent in older records)
+///   flag (1 = supply recorded), previous total supply (LE u64) if flag == 1 (absent in older records)
+#[derive(Debug, Clone, Default)]
+pub struct BlockUndo {
+    pub accounts: Vec<([u8; 32], Option<AccountState>)>,
+    pub tallies: Vec<([u8; 32], u64)>,
+    pub votes: Vec<[u8; 64]>,
+    /// Proposals first recorded by this block.
+    pub proposals: Vec<[u8; 32]>,
+    /// Governance params before this block enacted a proposal.
+    pub gov_params: Option<GovernanceParams>,
+    /// Total supply before this block's reward and referral bonus.
+    pub total_supply: Option<u64>,
+}
+
+impl BlockUndo {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut b = Vec::new();
+        b.extend_from_slice(&(self.accounts.len() as u32).to_le_bytes());
+        for (addr, prev) in &self.accounts {
+            b.extend_from_slice(addr);
+            match prev {
+                Some(state) => {
+                    let bytes = state.to_bytes();
+                    b.push(1);
+                    b.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+                    b.extend_from_slice(&bytes);
+                }
+                None => {
+                    b.push(0);
+                }
+            }
+        }
+        b.extend_from_slice(&(self.tallies.len() as u32).to_le_bytes());
+        for (prop, tally) in &self.tallies {
+            b.extend_from_slice(prop);
+            b.extend_from_slice(&tally.to_le_bytes());
+        }
+        b.extend_from_slice(&(self.votes.len() as u32).to_le_bytes());
+        for key in &self.votes {
+            b.extend_from_slice(key);
+        }
+        b.extend_from_slice(&(self.proposals.len() as u32).to_le_bytes());
+        for hash in &self.proposals {
+            b.extend_from_slice(hash);
+        }
+        match &self.gov_params {
+            Some(p) => {
+                b.push(1);
+                b.extend_from_slice(&p.to_bytes());
+            }
+            None => b.push(0),
+        }
+        match self.total_supply {
+            Some(supply) => {
+                b.push(1);
+                b.extend_from_slice(&supply.to_le_bytes());
+            }
+            None => b.push(0),
+        }
+        b
+    }
+
+    pub fn from_bytes(d: &[u8]) -> Result<Self, &'static str> {
+        let mut off = 0usize;
+        let take = |off: &mut usize, n: usize| -> Result<&[u8], &'static str> {
+            if d.len() < *off + n {
+                return Err("undo record truncated");
+            }
+            let s = &d[*off..*off + n];
+            *off += n;
+            Ok(s)
+        };
+        let read_u32 = |off: &mut usize| -> Result<usize, &'static str> {
+            Ok(u32::from_le_bytes(take(off, 4)?.try_into().unwrap()) as usize)
+        };
+
+        let n = read_u32(&mut off)?;
+        let mut accounts = Vec::with_capacity(n.min(1024));
+        for _ in 0..n {
+            let addr: [u8; 32] = take(&mut off, 32)?.try_into().unwrap();
+            let prev = match take(&mut off, 1)?[0] {
+                1 => {
+                    let len = read_u32(&mut off)?;
+                    Some(AccountState::from_bytes(take(&mut off, len)?)?)
+                }
+                _ => None,
+            };
+            accounts.push((addr, prev));
+        }
+
+        let n = read_u32(&mut off)?;
+        let mut tallies = Vec::with_capacity(n.min(1024));
+        for _ in 0..n {
+            let prop: [u8; 32] = take(&mut off, 32)?.try_into().unwrap();
+            let tally = u64::from_le_bytes(take(&mut off, 8)?.try_into().unwrap());
+            tallies.push((prop, tally));
+        }
+
+        let n = read_u32(&mut off)?;
+        let mut votes = Vec::with_capacity(n.min(1024));
+        for _ in 0..n {
+            votes.push(take(&mut off, 64)?.try_into().unwrap());
+        }
+
+        let mut proposals = Vec::new();
+        if off < d.len() {
+            let n = read_u32(&mut off)?;
+            for _ in 0..n {
+                proposals.push(take(&mut off, 32)?.try_into().unwrap());
+            }
+        }
+
+        let mut gov_params = None;
+        if off < d.len() && take(&mut off, 1)?[0] == 1 {
+            gov_params = Some(GovernanceParams::from_bytes(take(&mut off, 24)?));
+        }
+
+        let mut total_supply = None;
+        if off < d.len() && take(&mut off, 1)?[0] == 1 {
+            total_supply = Some(u64::from_le_bytes(take(&mut off, 8)?.try_into().unwrap()));
+        }
+
+        Ok(BlockUndo { accounts, tallies, votes, proposals, gov_params, total_supply })
+    }
+}
+
+/// Account and governance state as of one block, for fast bootstrap
+///
+/// Serialization Format (the snapshot hash is SHA3-256 of these bytes):
+///   magic "KSNP", version (u8 = 1)
+///   height (LE u32), tip[32], chain work[32] (BE U256), governance params[24]
+///   [u32 LE] account count, then per account: addr[32], [u32 LE] len + AccountState bytes
+///   [u32 LE] tally count, then per tally: proposal[32], tally (LE u64)
+///   [u32 LE] vote count, then per vote: proposal[32]+voter[32]
+///   [u32 LE] proposal count, then per proposal: hash[32], [u32 LE] len + StoredProposal bytes
+/// Each section is sorted by key, so the same state always gives the same bytes.
+#[derive(Debug, Clone, Default)]
+pub struct StateSnapshot {
+    pub height: u32,
+    pub tip: [u8; 32],
+    pub chain_work: [u8; 32],
+    pub gov_params: GovernanceParams,
+    pub accounts: BTreeMap<[u8; 32], AccountState>,
+    pub tallies: BTreeMap<[u8; 32], u64>,
+    pub votes: BTreeSet<[u8; 64]>,
+    /// proposal hash -> StoredProposal bytes
+    pub proposals: BTreeMap<[u8; 32], Vec<u8>>,
+}
+
+const SNAPSHOT_MAGIC: &[u8; 4] = b"KSNP";
+const SNAPSHOT_VERSION: u8 = 1;
+
+impl StateSnapshot {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut b = Vec::new();
+        b.extend_from_slice(SNAPSHOT_MAGIC);
+        b.push(SNAPSHOT_VERSION);
+        b.extend_from_slice(&self.height.to_le_bytes());
+        b.extend_from_slice(&self.tip);
+        b.extend_from_slice(&self.chain_work);
+        b.extend_from_slice(&self.gov_params.to_bytes());
+        b.extend_from_slice(&(self.accounts.len() as u32).to_le_bytes());
+        for (addr, state) in &self.accounts {
+            let bytes = state.to_bytes();
+            b.extend_from_slice(addr);
+            b.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+            b.extend_from_slice(&bytes);
+        }
+        b.extend_from_slice(&(self.tallies.len() as u32).to_le_bytes());
+        for (prop, tally) in &self.tallies {
+            b.extend_from_slice(prop);
+            b.extend_from_slice(&tally.to_le_bytes());
+        }
+        b.extend_from_slice(&(self.votes.len() as u32).to_le_bytes());
+        for key in &self.votes {
+            b.extend_from_slice(key);
+        }
+        b.extend_from_slice(&(self.proposals.len() as u32).to_le_bytes());
+        for (hash, raw) in &self.proposals {
+            b.extend_from_slice(hash);
+            b.extend_from_slice(&(raw.len() as u32).to_le_bytes());
+            b.extend_from_slice(raw);
+        }
+        b
+    }
+
+    pub fn from_bytes(d: &[u8]) -> Result<Self, &'static str> {
+        let mut off = 0usize;
+        let take = |off: &mut usize, n: usize| -> Result<&[u8], &'static str> {
+            if d.len() < *off + n {
+                return Err("snapshot truncated");
+            }
+            let s = &d[*off..*off + n];
+            *off += n;
+            Ok(s)
+        };
+        let read_u32 = |off: &mut usize| -> Result<usize, &'static str> {
+            Ok(u32::from_le_bytes(take(off, 4)?.try_into().unwrap()) as usize)
+        };
+
+        if take(&mut off, 4)? != SNAPSHOT_MAGIC {
+            return Err("not a state snapshot");
+        }
+        if take(&mut off, 1)?[0] != SNAPSHOT_VERSION {
+            return Err("unsupported snapshot version");
+        }
+        let height = read_u32(&mut off)? as u32;
+        let tip = take(&mut off, 32)?.try_into().unwrap();
+        let chain_work = take(&mut off, 32)?.try_into().unwrap();
+        let gov_params = GovernanceParams::from_bytes(take(&mut off, 24)?);
+
+        let mut accounts = BTreeMap::new();
+        for _ in 0..read_u32(&mut off)? {
+            let addr: [u8; 32] = take(&mut off, 32)?.try_into().unwrap();
+            let len = read_u32(&mut off)?;
+            accounts.insert(addr, AccountState::from_bytes(take(&mut off, len)?)?);
+        }
+
+        let mut tallies = BTreeMap::new();
+        for _ in 0..read_u32(&mut off)? {
+            let prop: [u8; 32] = take(&mut off, 32)?.try_into().unwrap();
+            tallies.insert(prop, u64::from_le_bytes(take(&mut off, 8)?.try_into().unwrap()));
+        }
+
+        let mut votes = BTreeSet::new();
+        for _ in 0..read_u32(&mut off)? {
+            votes.insert(take(&mut off, 64)?.try_into().unwrap());
+        }
+
+        let mut proposals = BTreeMap::new();
+        for _ in 0..read_u32(&mut off)? {
+            let hash: [u8; 32] = take(&mut off, 32)?.try_into().unwrap();
+            let len = read_u32(&mut off)?;
+            let raw = take(&mut off, len)?;
+            crate::consensus::governance::StoredProposal::from_bytes(raw)?;
+            proposals.insert(hash, raw.to_vec());
+        }
+
+        if off != d.len() {
+            return Err("trailing bytes after snapshot");
+        }
+        Ok(StateSnapshot { height, tip, chain_work, gov_params, accounts, tallies, votes, proposals })
+    }
+
+    /// SHA3-256 of `to_bytes`; what `getstatesnapshothash` reports.
+    pub fn hash(&self) -> [u8; 32] {
+        crate::crypto::hash::hash_sha3_256(&self.to_bytes())
+    }
+
+    /// Put back what the block with `undo` changed; the inverse of connecting it.
+    pub fn undo_block(&mut self, undo: BlockUndo) {
+        for (addr, prev) in undo.accounts {
+            match prev {
+                Some(state) => self.accounts.insert(addr, state),
+                None => self.accounts.remove(&addr),
+            };
+        }
+        for (prop, tally) in undo.tallies {
+            if tally == 0 {
+                self.tallies.remove(&prop);
+            } else {
+                self.tallies.insert(prop, tally);
+            }
+        }
+        for key in undo.votes {
+            self.votes.remove(&key);
+        }
+        for prop in undo.proposals {
+            self.proposals.remove(&prop);
+        }
+        if let Some(params) = undo.gov_params {
+            self.gov_params = params;
+        }
+    }
+}
@@ -15,6 +15,12 @@
 // - "referral_index"  : code[8] → addr[32]
 // - "gov_tallies"     : proposal[32] → tally[8]
 // - "gov_votes"       : proposal[32]+voter[32] → flag[1]
+// - "work"            : hash[32] → cumulative chain work[32] (BE U256)
+// - "undo"            : hash[32] → BlockUndo bytes (active chain only)
+// - "gov_proposals"   : proposal[32] → StoredProposal bytes
+// - "tx_index"        : txid[32] → height[4] LE + block hash[32] (only with txindex on)
+// - "pow_rounds"      : hash[32] → PONC rounds for its children[8] LE
+// - "accounts_tree"   : level[1] + index[2] BE → node hash[32] (zero nodes absent)
 
 use rocksdb::{DB, Options, WriteBatch, ColumnFamilyDescriptor, SliceTransform};
 use std::path::Path;
@@ -28,13 +34,52 @@ const CF_META: &str = "meta";
 const CF_REFERRAL_INDEX: &str = "referral_index";
 const CF_GOV_TALLIES: &str = "gov_tallies";
 const CF_GOV_VOTES: &str = "gov_votes";
+const CF_WORK: &str = "work";
+const CF_UNDO: &str = "undo";
+const CF_GOV_PROPOSALS: &str = "gov_proposals";
+const CF_TX_INDEX: &str = "tx_index";
+const CF_POW_ROUNDS: &str = "pow_rounds";
+const CF_ACCOUNTS_TREE: &str = "accounts_tree";
+
+const ALL_CFS: [&str; 13] = [
+    CF_BLOCKS,
+    CF_HEIGHTS,
+    CF_ACCOUNTS,
+    CF_META,
+    CF_REFERRAL_INDEX,
+    CF_GOV_TALLIES,
+    CF_GOV_VOTES,
+    CF_WORK,
+    CF_UNDO,
+    CF_GOV_PROPOSALS,
+    CF_TX_INDEX,
+    CF_POW_ROUNDS,
+    CF_ACCOUNTS_TREE,
+];
 
 // Metadata keys
 pub const KEY_TIP: &[u8] = b"tip";
 pub const KEY_GOV_PARAMS: &[u8] = b"gov_params";
+pub const KEY_PRUNE_HEIGHT: &[u8] = b"prune_height";
+pub const KEY_TOTAL_SUPPLY: &[u8] = b"total_supply";
+/// Accounts Merkle root (32 bytes) followed by the tip it was computed at.
+pub const KEY_ACCOUNTS_ROOT: &[u8] = b"accounts_root";
+/// Tip the `accounts_tree` CF was last brought up to date with. Cleared by
+/// any account write outside block connect/disconnect.
+pub const KEY_ACCOUNTS_TREE_TIP: &[u8] = b"accounts_tree_tip";
+
+/// `KEY_ACCOUNTS_ROOT` as stored by the last connected block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoredAccountsRoot {
+    pub root: [u8; 32],
+    pub tip: [u8; 32],
+}
+
+/// A pruned block is stored as its bare header, with no tx count after it.
+const PRUNED_BLOCK_LEN: usize = 148;
 
 // Re-export types from db_common
-pub use super::db_common::{AccountState, StoredBlock, StoredTransaction};
+pub use super::db_common::{AccountState, BlockUndo, StateSnapshot, StoredBlock, StoredTransaction};
 
 /// Custom error type for database operations
 #[derive(Debug)]
@@ -42,6 +87,8 @@ pub enum DbError {
     RocksDb(rocksdb::Error),
     Corruption(&'static str),
     NotFound,
+    Io(std::io::Error),
+    Snapshot(&'static str),
 }
 
 impl From<rocksdb::Error> for DbError {
@@ -50,22 +97,50 @@ impl From<rocksdb::Error> for DbError {
     }
 }
 
+impl From<std::io::Error> for DbError {
+    fn from(e: std::io::Error) -> Self {
+        DbError::Io(e)
+    }
+}
+
 impl std::fmt::Display for DbError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             DbError::RocksDb(e) => write!(f, "RocksDB error: {}", e),
             DbError::Corruption(msg) => write!(f, "Data corruption: {}", msg),
             DbError::NotFound => write!(f, "Key not found"),
+            DbError::Io(e) => write!(f, "I/O error: {}", e),
+            DbError::Snapshot(msg) => write!(f, "State snapshot: {}", msg),
         }
     }
 }
 
 impl std::error::Error for DbError {}
 
+/// Table options for one column family; every CF shares `cache`.
+fn table_options(cache: &rocksdb::Cache, block_size: usize, bloom: bool) -> rocksdb::BlockBasedOptions {
+    let mut t = rocksdb::BlockBasedOptions::default();
+    t.set_block_cache(cache);
+    t.set_block_size(block_size);
+    if bloom {
+        t.set_bloom_filter(10.0, false);
+    }
+    t
+}
+
 /// Main database handle with column families
 #[derive(Clone)]
 pub struct ChainDB {
     pub db: Arc<DB>,
+    /// Blocks that already passed PoW; see `consensus::pow_cache`.
+    pub pow_cache: Arc<std::sync::Mutex<crate::consensus::pow_cache::PowCache>>,
+    /// Held by `consensus::state` from reading the tip to writing the new one,
+    /// so concurrent block applies (miner, sync, RPC) can't both extend it.
+    pub chain_lock: Arc<std::sync::Mutex<()>>,
+    /// Maintain the `tx_index` CF as blocks connect (`KNOTCOIN_TXINDEX`).
+    tx_index: bool,
+    /// Chain these blocks belong to (`KNOTCOIN_NETWORK`); regtest skips retargeting.
+    network: crate::config::Network,
 }
 
 impl ChainDB {
@@ -73,7 +148,7 @@ impl ChainDB {
     /// 
     /// Performance Tuning Rationale:
     /// - write_buffer_size: 64MB - Balance between memory and flush frequency
-    ///   Larger = fewer flushes but more memory. 64MB good for 60-second blocks.
+    ///   Larger = fewer flushes but more memory. 64MB good for TARGET_BLOCK_SECS (60s) blocks.
     /// - max_write_buffer_number: 3 - Allow 3 memtables before blocking writes
     ///   Prevents write stalls during compaction.
     /// - target_file_size_base: 64MB - SST file size target
@@ -82,6 +157,11 @@ impl ChainDB {
     ///   Blockchain data compresses well (lots of zeros in hashes).
     /// - prefix_extractor: 8 bytes - Optimize for referral code lookups
     ///   Referral codes are 8-byte prefixes of SHA3 hashes.
+    /// - accounts: 4 KB blocks + 10-bit bloom filter - Point reads by address
+    ///   (often for accounts that don't exist) skip SST files via the bloom.
+    /// - blocks: 64 KB blocks, no bloom - Large values, read by known hash;
+    ///   a bloom would cost memory without saving reads.
+    /// - Everything else: 16 KB blocks. All CFs share one 256 MB block cache.
     pub fn open(path: &Path) -> Result<Self, DbError> {
         // Base options for all column families
         let mut opts = Options::default();
@@ -100,12 +180,9 @@ impl ChainDB {
         // Compression - LZ4 for speed
         opts.set_compression_type(rocksdb::DBCompressionType::Lz4);
         
-        // Block cache - 256 MB for hot data
+        // Block cache - 256 MB for hot data, shared by every CF
         let cache = rocksdb::Cache::new_lru_cache(256 * 1024 * 1024);
-        let mut block_opts = rocksdb::BlockBasedOptions::default();
-        block_opts.set_block_cache(&cache);
-        block_opts.set_block_size(16 * 1024); // 16 KB blocks
-        opts.set_block_based_table_factory(&block_opts);
+        opts.set_block_based_table_factory(&table_options(&cache, 16 * 1024, false));
         
         // WAL settings - critical for crash recovery
         opts.set_wal_bytes_per_sync(1024 * 1024); // Sync WAL every 1 MB
@@ -116,9 +193,13 @@ impl ChainDB {
         opts.set_max_background_jobs(4); // Parallel compaction
         
         // Column family descriptors
-        let cf_blocks = ColumnFamilyDescriptor::new(CF_BLOCKS, opts.clone());
+        let mut blocks_opts = opts.clone();
+        blocks_opts.set_block_based_table_factory(&table_options(&cache, 64 * 1024, false));
+        let cf_blocks = ColumnFamilyDescriptor::new(CF_BLOCKS, blocks_opts);
         let cf_heights = ColumnFamilyDescriptor::new(CF_HEIGHTS, opts.clone());
-        let cf_accounts = ColumnFamilyDescriptor::new(CF_ACCOUNTS, opts.clone());
+        let mut accounts_opts = opts.clone();
+        accounts_opts.set_block_based_table_factory(&table_options(&cache, 4 * 1024, true));
+        let cf_accounts = ColumnFamilyDescriptor::new(CF_ACCOUNTS, accounts_opts);
         let cf_meta = ColumnFamilyDescriptor::new(CF_META, opts.clone());
         
         // Referral index with prefix extractor for efficient lookups
@@ -128,6 +209,12 @@ impl ChainDB {
         
         let cf_gov_tallies = ColumnFamilyDescriptor::new(CF_GOV_TALLIES, opts.clone());
         let cf_gov_votes = ColumnFamilyDescriptor::new(CF_GOV_VOTES, opts.clone());
+        let cf_work = ColumnFamilyDescriptor::new(CF_WORK, opts.clone());
+        let cf_undo = ColumnFamilyDescriptor::new(CF_UNDO, opts.clone());
+        let cf_gov_proposals = ColumnFamilyDescriptor::new(CF_GOV_PROPOSALS, opts.clone());
+        let cf_tx_index = ColumnFamilyDescriptor::new(CF_TX_INDEX, opts.clone());
+        let cf_pow_rounds = ColumnFamilyDescriptor::new(CF_POW_ROUNDS, opts.clone());
+        let cf_accounts_tree = ColumnFamilyDescriptor::new(CF_ACCOUNTS_TREE, opts.clone());
         
         let cfs = vec![
             cf_blocks,
@@ -137,6 +224,12 @@ impl ChainDB {
             cf_referral,
             cf_gov_tallies,
             cf_gov_votes,
+            cf_work,
+            cf_undo,
+            cf_gov_proposals,
+            cf_tx_index,
+            cf_pow_rounds,
+            cf_accounts_tree,
         ];
         
         // Open database with all column families
@@ -144,9 +237,38 @@ impl ChainDB {
         
         Ok(ChainDB {
             db: Arc::new(db),
+            pow_cache: Arc::default(),
+            chain_lock: Arc::default(),
+            tx_index: false,
+            network: crate::config::Network::Mainnet,
         })
     }
+
+    /// Index each connected block's txids in `tx_index`. Blocks connected
+    /// while this is off are not indexed.
+    pub fn with_tx_index(mut self, on: bool) -> Self {
+        self.tx_index = on;
+        self
+    }
+
+    pub fn tx_index_enabled(&self) -> bool {
+        self.tx_index
+    }
+
+    pub fn with_network(mut self, network: crate::config::Network) -> Self {
+        self.network = network;
+        self
+    }
+
+    pub fn network(&self) -> crate::config::Network {
+        self.network
+    }
     
+    /// Forget every cached PoW result.
+    pub fn clear_pow_cache(&self) {
+        self.pow_cache.lock().unwrap().clear();
+    }
+
     /// Get column family handle (internal helper)
     fn cf(&self, name: &str) -> Result<&rocksdb::ColumnFamily, DbError> {
         self.db.cf_handle(name)
@@ -207,6 +329,21 @@ impl ChainDB {
         }
     }
     
+    /// Get a block's header without decoding its transactions.
+    /// The returned block has an empty `tx_data`.
+    pub fn get_block_header(&self, hash: &[u8; 32]) -> Result<Option<StoredBlock>, DbError> {
+        let cf = self.cf(CF_BLOCKS)?;
+
+        match self.db.get_pinned_cf(cf, hash)? {
+            Some(data) => {
+                let header = StoredBlock::header_only_from_bytes(&data)
+                    .map_err(DbError::Corruption)?;
+                Ok(Some(header))
+            }
+            None => Ok(None),
+        }
+    }
+
     /// Get block hash by height
     pub fn get_block_hash_by_height(&self, height: u32) -> Result<Option<[u8; 32]>, DbError> {
         let cf = self.cf(CF_HEIGHTS)?;
@@ -224,6 +361,165 @@ impl ChainDB {
         }
     }
     
+    /// Height below which block bodies have been pruned (0 = none).
+    pub fn get_prune_height(&self) -> Result<u32, DbError> {
+        let cf = self.cf(CF_META)?;
+        match self.db.get_cf(cf, KEY_PRUNE_HEIGHT)? {
+            Some(data) => data
+                .as_slice()
+                .try_into()
+                .map(u32::from_le_bytes)
+                .map_err(|_| DbError::Corruption("invalid prune height length")),
+            None => Ok(0),
+        }
+    }
+
+    /// Stored accounts Merkle root and the tip hash it belongs to.
+    pub fn get_accounts_root(&self) -> Result<Option<StoredAccountsRoot>, DbError> {
+        let cf = self.cf(CF_META)?;
+        match self.db.get_cf(cf, KEY_ACCOUNTS_ROOT)? {
+            Some(data) if data.len() == 64 => {
+                let mut entry = StoredAccountsRoot { root: [0u8; 32], tip: [0u8; 32] };
+                entry.root.copy_from_slice(&data[..32]);
+                entry.tip.copy_from_slice(&data[32..]);
+                Ok(Some(entry))
+            }
+            Some(_) => Err(DbError::Corruption("invalid accounts root length")),
+            None => Ok(None),
+        }
+    }
+
+    /// Tip the persisted accounts tree matches, if it is current for any.
+    pub fn get_accounts_tree_tip(&self) -> Result<Option<[u8; 32]>, DbError> {
+        let cf = self.cf(CF_META)?;
+        match self.db.get_cf(cf, KEY_ACCOUNTS_TREE_TIP)? {
+            Some(data) => Ok(Some(<[u8; 32]>::try_from(data.as_slice())
+                .map_err(|_| DbError::Corruption("invalid accounts tree tip length"))?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Node `index` at `level` of the accounts tree; level 0 holds the bucket
+    /// hashes. Absent nodes are zero.
+    pub fn get_accounts_tree_node(&self, level: u8, index: u16) -> Result<[u8; 32], DbError> {
+        let cf = self.cf(CF_ACCOUNTS_TREE)?;
+        let [hi, lo] = index.to_be_bytes();
+        match self.db.get_cf(cf, [level, hi, lo])? {
+            Some(data) => <[u8; 32]>::try_from(data.as_slice())
+                .map_err(|_| DbError::Corruption("invalid accounts tree node length")),
+            None => Ok([0u8; 32]),
+        }
+    }
+
+    /// Knots minted up to the tip, as kept by `connect_block`; `None` if the
+    /// running total hasn't been recorded yet.
+    pub fn get_total_supply(&self) -> Result<Option<u64>, DbError> {
+        let cf = self.cf(CF_META)?;
+        match self.db.get_cf(cf, KEY_TOTAL_SUPPLY)? {
+            Some(data) => data
+                .as_slice()
+                .try_into()
+                .map(|b| Some(u64::from_le_bytes(b)))
+                .map_err(|_| DbError::Corruption("invalid total supply length")),
+            None => Ok(None),
+        }
+    }
+
+    /// Whether the block's transactions have been dropped by `prune_below`.
+    pub fn is_pruned(&self, hash: &[u8; 32]) -> Result<bool, DbError> {
+        let cf = self.cf(CF_BLOCKS)?;
+        Ok(self.db.get_pinned_cf(cf, hash)?.is_some_and(|d| d.len() == PRUNED_BLOCK_LEN))
+    }
+
+    /// Replace the bodies of active-chain blocks below `height` with their
+    /// headers. Hashes, the `heights` index and PoW checks of descendants keep
+    /// working; `get_block` returns such blocks with an empty `tx_data`.
+    /// Genesis is never pruned. Returns how many blocks were pruned.
+    pub fn prune_below(&self, height: u32) -> Result<u32, DbError> {
+        let start = self.get_prune_height()?.max(1);
+        if height <= start {
+            return Ok(0);
+        }
+        let cf_blocks = self.cf(CF_BLOCKS)?;
+        let mut batch = WriteBatch::default();
+        let mut pruned = 0u32;
+        for h in start..height {
+            let Some(hash) = self.get_block_hash_by_height(h)? else {
+                continue;
+            };
+            if let Some(data) = self.db.get_pinned_cf(cf_blocks, hash)?
+                && data.len() > PRUNED_BLOCK_LEN
+            {
+                batch.put_cf(cf_blocks, hash, &data[..PRUNED_BLOCK_LEN]);
+                pruned += 1;
+            }
+        }
+        batch.put_cf(self.cf(CF_META)?, KEY_PRUNE_HEIGHT, height.to_le_bytes());
+
+        let mut write_opts = rocksdb::WriteOptions::default();
+        write_opts.set_sync(true);
+        self.db.write_opt(batch, &write_opts)?;
+        Ok(pruned)
+    }
+
+    /// Cumulative chain work up to and including `hash` (BE U256)
+    pub fn get_chain_work(&self, hash: &[u8; 32]) -> Result<Option<[u8; 32]>, DbError> {
+        let cf = self.cf(CF_WORK)?;
+        
+        match self.db.get_cf(cf, hash)? {
+            Some(data) => {
+                if data.len() != 32 {
+                    return Err(DbError::Corruption("invalid chain work length"));
+                }
+                let mut work = [0u8; 32];
+                work.copy_from_slice(&data);
+                Ok(Some(work))
+            }
+            None => Ok(None),
+        }
+    }
+    
+    /// PONC rounds in effect for children of `hash`: the governance params as
+    /// connecting `hash` left them. Recorded for every block ever connected,
+    /// and for side blocks without governance txs whose parent's are known.
+    pub fn get_pow_rounds(&self, hash: &[u8; 32]) -> Result<Option<u64>, DbError> {
+        let cf = self.cf(CF_POW_ROUNDS)?;
+        match self.db.get_cf(cf, hash)? {
+            Some(data) => {
+                let bytes: [u8; 8] = data.as_slice().try_into()
+                    .map_err(|_| DbError::Corruption("invalid pow rounds length"))?;
+                Ok(Some(u64::from_le_bytes(bytes)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Undo record for a block on the active chain
+    pub fn get_block_undo(&self, hash: &[u8; 32]) -> Result<Option<BlockUndo>, DbError> {
+        let cf = self.cf(CF_UNDO)?;
+        
+        match self.db.get_cf(cf, hash)? {
+            Some(data) => Ok(Some(BlockUndo::from_bytes(&data).map_err(DbError::Corruption)?)),
+            None => Ok(None),
+        }
+    }
+    
+    /// Height and hash of the block that confirmed `txid`, from `tx_index`
+    pub fn get_tx_location(&self, txid: &[u8; 32]) -> Result<Option<(u32, [u8; 32])>, DbError> {
+        let cf = self.cf(CF_TX_INDEX)?;
+
+        match self.db.get_cf(cf, txid)? {
+            Some(data) => {
+                if data.len() != 36 {
+                    return Err(DbError::Corruption("invalid tx index entry length"));
+                }
+                let height = u32::from_le_bytes(data[..4].try_into().unwrap());
+                Ok(Some((height, data[4..].try_into().unwrap())))
+            }
+            None => Ok(None),
+        }
+    }
+
     // ========== ACCOUNT OPERATIONS ==========
     
     /// Get account state (returns empty if not found)
@@ -242,6 +538,25 @@ impl ChainDB {
             None => Ok(AccountState::empty()),
         }
     }
+
+    /// `addr`'s account as the next block sees it: rewards that mature at
+    /// that height already count as balance.
+    pub fn get_spendable_account(&self, addr: &[u8; 32]) -> Result<AccountState, DbError> {
+        let mut acc = self.get_account(addr)?;
+        let next = self.get_chain_height()? as u64 + 1;
+        acc.mature(next).ok_or(DbError::Corruption("balance overflow"))?;
+        Ok(acc)
+    }
+    
+    /// Get account state, distinguishing "never touched" from an empty account
+    pub fn get_account_if_exists(&self, addr: &[u8; 32]) -> Result<Option<AccountState>, DbError> {
+        let cf = self.cf(CF_ACCOUNTS)?;
+        
+        match self.db.get_cf(cf, addr)? {
+            Some(data) => Ok(Some(AccountState::from_bytes(&data).map_err(DbError::Corruption)?)),
+            None => Ok(None),
+        }
+    }
     
     /// Store account state and update referral index
     pub fn put_account(&self, addr: &[u8; 32], state: &AccountState) -> Result<(), DbError> {
@@ -255,6 +570,7 @@ impl ChainDB {
         // Update referral index
         let hash = crate::crypto::hash::hash_sha3_256(addr);
         batch.put_cf(cf_referral, &hash[..8], addr);
+        batch.delete_cf(self.cf(CF_META)?, KEY_ACCOUNTS_TREE_TIP);
         
         self.db.write(batch)?;
         Ok(())
@@ -274,6 +590,7 @@ impl ChainDB {
             let hash = crate::crypto::hash::hash_sha3_256(&addr);
             batch.put_cf(cf_referral, &hash[..8], &addr);
         }
+        batch.delete_cf(self.cf(CF_META)?, KEY_ACCOUNTS_TREE_TIP);
         
         // Sync for durability
         let mut write_opts = rocksdb::WriteOptions::default();
@@ -423,32 +740,27 @@ impl ChainDB {
         Ok(self.db.get_cf(cf, &vote_key)?.is_some())
     }
     
+    /// A proposal recorded on chain by a proposal tx
+    pub fn get_governance_proposal(
+        &self,
+        proposal_hash: &[u8; 32],
+    ) -> Result<Option<crate::consensus::governance::StoredProposal>, DbError> {
+        let cf = self.cf(CF_GOV_PROPOSALS)?;
+
+        match self.db.get_cf(cf, proposal_hash)? {
+            Some(data) => crate::consensus::governance::StoredProposal::from_bytes(&data)
+                .map(Some)
+                .map_err(DbError::Corruption),
+            None => Ok(None),
+        }
+    }
+
     /// Get governance parameters
     pub fn get_governance_params(&self) -> Result<crate::consensus::state::GovernanceParams, DbError> {
         let cf = self.cf(CF_META)?;
         
         match self.db.get_cf(cf, KEY_GOV_PARAMS)? {
-            Some(data) => {
-                if data.len() >= 24 {
-                    // New format: cap_bps + ponc_rounds + mining_threads (24 bytes)
-                    let cap_bps = u64::from_le_bytes(data[0..8].try_into().unwrap());
-                    let ponc_rounds = u64::from_le_bytes(data[8..16].try_into().unwrap());
-                    let mining_threads = u64::from_le_bytes(data[16..24].try_into().unwrap());
-                    Ok(crate::consensus::state::GovernanceParams { cap_bps, ponc_rounds, mining_threads })
-                } else if data.len() >= 16 {
-                    // Legacy format: cap_bps + ponc_rounds (16 bytes)
-                    // Automatically upgrade to include default mining_threads
-                    let cap_bps = u64::from_le_bytes(data[0..8].try_into().unwrap());
-                    let ponc_rounds = u64::from_le_bytes(data[8..16].try_into().unwrap());
-                    Ok(crate::consensus::state::GovernanceParams { 
-                        cap_bps, 
-                        ponc_rounds, 
-                        mining_threads: crate::consensus::chain::MINING_THREADS_DEFAULT 
-                    })
-                } else {
-                    Ok(crate::consensus::state::GovernanceParams::default())
-                }
-            }
+            Some(data) => Ok(crate::consensus::state::GovernanceParams::from_bytes(&data)),
             None => Ok(crate::consensus::state::GovernanceParams::default()),
         }
     }
@@ -460,10 +772,7 @@ impl ChainDB {
     ) -> Result<(), DbError> {
         let cf = self.cf(CF_META)?;
         
-        let mut buf = Vec::with_capacity(24);
-        buf.extend_from_slice(&params.cap_bps.to_le_bytes());
-        buf.extend_from_slice(&params.ponc_rounds.to_le_bytes());
-        buf.extend_from_slice(&params.mining_threads.to_le_bytes());
+        let buf = params.to_bytes();
         
         let mut write_opts = rocksdb::WriteOptions::default();
         write_opts.set_sync(true); // Critical metadata
@@ -498,17 +807,7 @@ impl ChainDB {
     /// is only needed for performance tuning, not correctness.
     pub fn flush(&self) -> Result<(), DbError> {
         // Flush all column families
-        let cfs = vec![
-            CF_BLOCKS,
-            CF_HEIGHTS,
-            CF_ACCOUNTS,
-            CF_META,
-            CF_REFERRAL_INDEX,
-            CF_GOV_TALLIES,
-            CF_GOV_VOTES,
-        ];
-        
-        for cf_name in cfs {
+        for cf_name in ALL_CFS {
             if let Some(cf) = self.db.cf_handle(cf_name) {
                 self.db.flush_cf(cf)?;
             }
@@ -516,6 +815,27 @@ impl ChainDB {
         
         Ok(())
     }
+
+    /// Full manual compaction of every column family, e.g. to hand the space
+    /// freed by `prune_below` back to the filesystem. Blocks until done.
+    pub fn compact(&self) -> Result<(), DbError> {
+        for cf_name in ALL_CFS {
+            let cf = self.cf(cf_name)?;
+            self.db.compact_range_cf(cf, None::<&[u8]>, None::<&[u8]>);
+        }
+        Ok(())
+    }
+
+    /// Bytes of SST files across all column families. Data still in the
+    /// memtables or WAL isn't counted until it is flushed.
+    pub fn size_on_disk(&self) -> Result<u64, DbError> {
+        let mut total = 0u64;
+        for cf_name in ALL_CFS {
+            let cf = self.cf(cf_name)?;
+            total += self.db.property_int_value_cf(cf, "rocksdb.total-sst-files-size")?.unwrap_or(0);
+        }
+        Ok(total)
+    }
     
     /// Iterate over all accounts (for RPC queries)
     /// Returns iterator of (address, AccountState) pairs
@@ -545,6 +865,169 @@ impl ChainDB {
         
         Ok(results)
     }
+
+    /// Accounts whose address starts with `prefix`, in address order
+    pub fn iter_accounts_with_prefix(&self, prefix: &[u8]) -> Result<Vec<([u8; 32], AccountState)>, DbError> {
+        let cf = self.cf(CF_ACCOUNTS)?;
+        let mut results = Vec::new();
+        let mode = rocksdb::IteratorMode::From(prefix, rocksdb::Direction::Forward);
+        for item in self.db.iterator_cf(cf, mode) {
+            let (key, value) = item?;
+            if !key.starts_with(prefix) {
+                break;
+            }
+            let Ok(addr) = <[u8; 32]>::try_from(&key[..]) else {
+                continue;
+            };
+            if let Ok(state) = AccountState::from_bytes(&value) {
+                results.push((addr, state));
+            }
+        }
+        Ok(results)
+    }
+
+    // ========== STATE SNAPSHOTS ==========
+
+    /// Account and governance state as of `height` on the active chain
+    ///
+    /// Read from one RocksDB snapshot at the tip, then walked back through
+    /// the undo records, so any height that still has undo data works.
+    pub fn state_snapshot(&self, height: u32) -> Result<StateSnapshot, DbError> {
+        let snap = self.db.snapshot();
+        let tip = match snap.get_cf(self.cf(CF_META)?, KEY_TIP)? {
+            Some(data) => <[u8; 32]>::try_from(data.as_slice())
+                .map_err(|_| DbError::Corruption("invalid tip hash length"))?,
+            None => return Err(DbError::Snapshot("chain is empty")),
+        };
+        let header_at = |hash: &[u8; 32]| -> Result<StoredBlock, DbError> {
+            let data = snap.get_cf(self.cf(CF_BLOCKS)?, hash)?.ok_or(DbError::NotFound)?;
+            StoredBlock::header_only_from_bytes(&data).map_err(DbError::Corruption)
+        };
+
+        let mut state = StateSnapshot {
+            gov_params: match snap.get_cf(self.cf(CF_META)?, KEY_GOV_PARAMS)? {
+                Some(data) => crate::consensus::state::GovernanceParams::from_bytes(&data),
+                None => crate::consensus::state::GovernanceParams::default(),
+            },
+            ..Default::default()
+        };
+        for item in snap.iterator_cf(self.cf(CF_ACCOUNTS)?, rocksdb::IteratorMode::Start) {
+            let (key, value) = item?;
+            let addr = <[u8; 32]>::try_from(&key[..]).map_err(|_| DbError::Corruption("invalid address length"))?;
+            state.accounts.insert(addr, AccountState::from_bytes(&value).map_err(DbError::Corruption)?);
+        }
+        for item in snap.iterator_cf(self.cf(CF_GOV_TALLIES)?, rocksdb::IteratorMode::Start) {
+            let (key, value) = item?;
+            let prop = <[u8; 32]>::try_from(&key[..]).map_err(|_| DbError::Corruption("invalid proposal hash length"))?;
+            let tally = <[u8; 8]>::try_from(&value[..]).map_err(|_| DbError::Corruption("invalid tally length"))?;
+            state.tallies.insert(prop, u64::from_le_bytes(tally));
+        }
+        for item in snap.iterator_cf(self.cf(CF_GOV_VOTES)?, rocksdb::IteratorMode::Start) {
+            let (key, _) = item?;
+            state.votes.insert(<[u8; 64]>::try_from(&key[..]).map_err(|_| DbError::Corruption("invalid vote key length"))?);
+        }
+        for item in snap.iterator_cf(self.cf(CF_GOV_PROPOSALS)?, rocksdb::IteratorMode::Start) {
+            let (key, value) = item?;
+            let hash = <[u8; 32]>::try_from(&key[..]).map_err(|_| DbError::Corruption("invalid proposal hash length"))?;
+            state.proposals.insert(hash, value.to_vec());
+        }
+
+        let mut cur = tip;
+        let mut block = header_at(&cur)?;
+        if height > u32::from_le_bytes(block.block_height) {
+            return Err(DbError::Snapshot("height is above the chain tip"));
+        }
+        while u32::from_le_bytes(block.block_height) > height {
+            let undo = snap
+                .get_cf(self.cf(CF_UNDO)?, cur)?
+                .ok_or(DbError::Snapshot("no undo data to roll back to that height"))?;
+            state.undo_block(BlockUndo::from_bytes(&undo).map_err(DbError::Corruption)?);
+            cur = block.previous_hash;
+            block = header_at(&cur)?;
+        }
+
+        state.height = height;
+        state.tip = cur;
+        if let Some(work) = snap.get_cf(self.cf(CF_WORK)?, cur)? {
+            state.chain_work = work.try_into().map_err(|_| DbError::Corruption("invalid chain work length"))?;
+        }
+        Ok(state)
+    }
+
+    /// Write the state as of `height` to `path`; returns the snapshot hash.
+    pub fn dump_state_snapshot(&self, path: &Path, height: u32) -> Result<[u8; 32], DbError> {
+        let bytes = self.state_snapshot(height)?.to_bytes();
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, &bytes)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(crate::crypto::hash::hash_sha3_256(&bytes))
+    }
+
+    /// Replace all account and governance state with the snapshot at `path`
+    ///
+    /// The snapshot's tip must already be indexed at its height here (e.g.
+    /// from a header sync); it becomes the chain tip. Undo records are
+    /// dropped with the old state, so nothing below the snapshot can be
+    /// reorged away. Returns the snapshot's height and hash; compare the
+    /// hash with `getstatesnapshothash` from a trusted node before relying
+    /// on the result.
+    pub fn load_state_snapshot(&self, path: &Path) -> Result<(u32, [u8; 32]), DbError> {
+        let bytes = std::fs::read(path)?;
+        let state = StateSnapshot::from_bytes(&bytes).map_err(DbError::Snapshot)?;
+        if self.get_block_hash_by_height(state.height)? != Some(state.tip) {
+            return Err(DbError::Snapshot("snapshot tip is not in the block index"));
+        }
+
+        let mut batch = WriteBatch::default();
+        for name in [CF_ACCOUNTS, CF_REFERRAL_INDEX, CF_GOV_TALLIES, CF_GOV_VOTES, CF_GOV_PROPOSALS, CF_UNDO] {
+            let cf = self.cf(name)?;
+            for item in self.db.iterator_cf(cf, rocksdb::IteratorMode::Start) {
+                batch.delete_cf(cf, item?.0);
+            }
+        }
+        let cf_heights = self.cf(CF_HEIGHTS)?;
+        for item in self.db.iterator_cf(cf_heights, rocksdb::IteratorMode::Start) {
+            let (key, _) = item?;
+            if <[u8; 4]>::try_from(&key[..]).is_ok_and(|h| u32::from_le_bytes(h) > state.height) {
+                batch.delete_cf(cf_heights, key);
+            }
+        }
+
+        let cf_accounts = self.cf(CF_ACCOUNTS)?;
+        let cf_referral = self.cf(CF_REFERRAL_INDEX)?;
+        for (addr, account) in &state.accounts {
+            batch.put_cf(cf_accounts, addr, account.to_bytes());
+            let hash = crate::crypto::hash::hash_sha3_256(addr);
+            batch.put_cf(cf_referral, &hash[..8], addr);
+        }
+        let cf_tallies = self.cf(CF_GOV_TALLIES)?;
+        for (prop, tally) in &state.tallies {
+            batch.put_cf(cf_tallies, prop, tally.to_le_bytes());
+        }
+        let cf_votes = self.cf(CF_GOV_VOTES)?;
+        for key in &state.votes {
+            batch.put_cf(cf_votes, key, [1u8]);
+        }
+        let cf_proposals = self.cf(CF_GOV_PROPOSALS)?;
+        for (hash, raw) in &state.proposals {
+            batch.put_cf(cf_proposals, hash, raw);
+        }
+        let cf_meta = self.cf(CF_META)?;
+        batch.put_cf(cf_meta, KEY_GOV_PARAMS, state.gov_params.to_bytes());
+        batch.put_cf(cf_meta, KEY_TIP, state.tip);
+        // Not part of the snapshot; recomputed from the new state on first read.
+        batch.delete_cf(cf_meta, KEY_TOTAL_SUPPLY);
+        batch.delete_cf(cf_meta, KEY_ACCOUNTS_ROOT);
+        batch.delete_cf(cf_meta, KEY_ACCOUNTS_TREE_TIP);
+        if state.chain_work != [0u8; 32] {
+            batch.put_cf(self.cf(CF_WORK)?, state.tip, state.chain_work);
+        }
+
+        let mut write_opts = rocksdb::WriteOptions::default();
+        write_opts.set_sync(true);
+        self.db.write_opt(batch, &write_opts)?;
+        Ok((state.height, crate::crypto::hash::hash_sha3_256(&bytes)))
+    }
 }
 
 // Implement Send + Sync for thread safety
@@ -584,6 +1067,7 @@ mod tests {
             total_referral_bonus_earned: 25_000_000,
             governance_weight: 600,
             total_blocks_mined: 10,
+            immature: vec![(40, 7), (41, 9)],
         };
         db.put_account(&addr, &s).unwrap();
         let got = db.get_account(&addr).unwrap();
@@ -592,6 +1076,30 @@ mod tests {
         assert_eq!(got.last_mined_height, 42);
         assert_eq!(got.total_referred_miners, 5);
         assert_eq!(got.governance_weight, 600);
+        assert_eq!(got.immature, vec![(40, 7), (41, 9)]);
+
+        // Records written before immature credits existed still decode.
+        let old = &s.to_bytes()[..89];
+        assert!(AccountState::from_bytes(old).unwrap().immature.is_empty());
+        assert!(AccountState::from_bytes(&s.to_bytes()[..100]).is_err());
+    }
+
+    #[test]
+    fn test_immature_credits_mature_after_coinbase_maturity() {
+        let mut s = AccountState::empty();
+        s.credit_immature(5, 100).unwrap();
+        s.credit_immature(5, 20).unwrap();
+        s.credit_immature(6, 0).unwrap();
+        s.credit_immature(7, 30).unwrap();
+        assert_eq!(s.immature, vec![(5, 120), (7, 30)]);
+        assert_eq!(s.immature_balance(), 150);
+
+        s.mature(104).unwrap();
+        assert_eq!(s.balance, 0);
+        s.mature(105).unwrap();
+        assert_eq!((s.balance, s.immature_balance()), (120, 30));
+        s.mature(1_000).unwrap();
+        assert_eq!((s.balance, s.immature_balance()), (150, 0));
     }
 
     #[test]
@@ -625,6 +1133,69 @@ mod tests {
         assert_eq!(db.get_chain_height().unwrap(), 0);
     }
 
+    #[test]
+    fn test_prune_below_keeps_headers_and_index() {
+        let db = tmp();
+        for h in 0..5u32 {
+            let block = StoredBlock {
+                version: [0, 0, 0, 1],
+                previous_hash: [h as u8; 32],
+                merkle_root: [0u8; 32],
+                timestamp: 100u32.to_le_bytes(),
+                difficulty_target: [0xFF; 32],
+                nonce: [0u8; 8],
+                block_height: h.to_le_bytes(),
+                miner_address: [1u8; 32],
+                tx_data: vec![],
+            };
+            db.store_block(&[0x50 + h as u8; 32], &block).unwrap();
+        }
+
+        assert_eq!(db.prune_below(3).unwrap(), 2);
+        assert_eq!(db.get_prune_height().unwrap(), 3);
+        assert!(!db.is_pruned(&[0x50; 32]).unwrap());
+        assert!(db.is_pruned(&[0x51; 32]).unwrap());
+        assert!(db.is_pruned(&[0x52; 32]).unwrap());
+        assert!(!db.is_pruned(&[0x53; 32]).unwrap());
+
+        assert_eq!(db.get_block_hash_by_height(2).unwrap(), Some([0x52; 32]));
+        let header = db.get_block(&[0x52; 32]).unwrap().unwrap();
+        assert_eq!(header.previous_hash, [2u8; 32]);
+        assert!(header.tx_data.is_empty());
+
+        assert_eq!(db.prune_below(3).unwrap(), 0);
+        assert_eq!(db.prune_below(4).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_block_header_skips_transactions() {
+        let db = tmp();
+        let block = StoredBlock {
+            version: [0, 0, 0, 1],
+            previous_hash: [7u8; 32],
+            merkle_root: [8u8; 32],
+            timestamp: 100u32.to_le_bytes(),
+            difficulty_target: [0xFF; 32],
+            nonce: [9u8; 8],
+            block_height: 3u32.to_le_bytes(),
+            miner_address: [1u8; 32],
+            tx_data: vec![],
+        };
+        let hash = [0x43u8; 32];
+        db.store_block(&hash, &block).unwrap();
+        let got = db.get_block_header(&hash).unwrap().unwrap();
+        assert_eq!(got.header_bytes(), block.header_bytes());
+        assert!(db.get_block_header(&[0u8; 32]).unwrap().is_none());
+
+        // A tx count with no transactions behind it only breaks the full parse.
+        let mut raw = block.header_bytes().to_vec();
+        raw.extend_from_slice(&5u32.to_le_bytes());
+        assert!(StoredBlock::from_bytes(&raw).is_err());
+        let header = StoredBlock::header_only_from_bytes(&raw).unwrap();
+        assert_eq!(header.header_bytes(), block.header_bytes());
+        assert!(header.tx_data.is_empty());
+    }
+
     #[test]
     fn test_governance_tallying() {
         let db = tmp();
@@ -56,6 +56,7 @@ mod stress_tests {
             total_referral_bonus_earned: u64::MAX,
             governance_weight: u64::MAX,
             total_blocks_mined: u64::MAX,
+            immature: Vec::new(),
         };
         db.put_account(&addr, &state).unwrap();
         let retrieved = db.get_account(&addr).unwrap();
@@ -98,6 +99,7 @@ mod stress_tests {
             total_referral_bonus_earned: 0,
             governance_weight: 100,
             total_blocks_mined: 1,
+            immature: Vec::new(),
         };
         db.put_account(&addr, &state).unwrap();
 
@@ -137,6 +139,7 @@ mod stress_tests {
                     total_referral_bonus_earned: 0,
                     governance_weight: i as u64,
                     total_blocks_mined: 1,
+                    immature: Vec::new(),
                 };
                 db_clone.put_account(&addr, &state).unwrap();
             });
@@ -313,6 +316,7 @@ mod stress_tests {
                 total_referral_bonus_earned: 0,
                 governance_weight: i as u64,
                 total_blocks_mined: 1,
+                immature: Vec::new(),
             };
             updates.push((addr, state));
         }
@@ -390,6 +394,7 @@ mod stress_tests {
             total_referral_bonus_earned: 0,
             governance_weight: 0,
             total_blocks_mined: 0,
+            immature: Vec::new(),
         };
         db.put_account(&addr, &state1).unwrap();
 
@@ -403,6 +408,7 @@ mod stress_tests {
             total_referral_bonus_earned: 500,
             governance_weight: 100,
             total_blocks_mined: 1,
+            immature: Vec::new(),
         };
         db.put_account(&addr, &state2).unwrap();
 
@@ -429,6 +435,8 @@ mod stress_tests {
                 timestamp: i as u64,
                 referrer_address: None,
                 governance_data: None,
+                proposal: None,
+                memo: None,
                 signature: vec![0u8; 64],
             };
             txs.push(tx);
@@ -516,6 +524,7 @@ mod stress_tests {
             total_referral_bonus_earned: 5000000,
             governance_weight: 750,
             total_blocks_mined: 25,
+            immature: Vec::new(),
         };
 
         let bytes = original.to_bytes();
@@ -571,6 +580,8 @@ mod stress_tests {
             timestamp: 1234567890,
             referrer_address: Some([0x33u8; 32]),
             governance_data: Some([0x44u8; 32]),
+            proposal: None,
+            memo: None,
             signature: vec![0xBBu8; 64],
         };
 
@@ -590,6 +601,52 @@ mod stress_tests {
         assert_eq!(decoded.signature, original.signature);
     }
 
+    #[test]
+    fn test_transaction_memo_roundtrip() {
+        use crate::primitives::transaction::{MAX_MEMO_BYTES, TX_VERSION_MEMO};
+        let plain = StoredTransaction {
+            version: 1,
+            sender_address: [0x11u8; 32],
+            sender_pubkey: vec![0xAAu8; 32],
+            recipient_address: [0x22u8; 32],
+            amount: 1000,
+            fee: 1,
+            nonce: 5,
+            timestamp: 1234567890,
+            referrer_address: None,
+            governance_data: Some([0x44u8; 32]),
+            proposal: None,
+            memo: None,
+            signature: vec![0xBBu8; 64],
+        };
+        let with_memo = |memo: Vec<u8>| StoredTransaction { version: TX_VERSION_MEMO, memo: Some(memo), ..plain.clone() };
+
+        // Memo-less transactions encode exactly as before: no memo byte at all.
+        let plain_bytes = plain.to_bytes();
+        assert_eq!(plain_bytes.len(), 1 + 32 + 4 + 32 + 32 + 32 + 1 + 1 + 32 + 4 + 64);
+        assert_eq!(with_memo(vec![]).to_bytes().len(), plain_bytes.len() + 1);
+
+        for memo in [vec![], b"order #1042".to_vec(), vec![0x5Au8; MAX_MEMO_BYTES]] {
+            let tx = with_memo(memo.clone());
+            let mut bytes = tx.to_bytes();
+            let len = bytes.len();
+            // Followed by another tx, as in a block body.
+            bytes.extend_from_slice(&plain_bytes);
+            let (decoded, used) = StoredTransaction::from_bytes(&bytes).unwrap();
+            assert_eq!(used, len);
+            assert_eq!(decoded.memo, Some(memo));
+            assert_eq!(decoded.governance_data, plain.governance_data);
+            assert_eq!(decoded.signature, plain.signature);
+            let (next, _) = StoredTransaction::from_bytes(&bytes[used..]).unwrap();
+            assert_eq!(next.memo, None);
+        }
+
+        let mut long = with_memo(vec![0u8; MAX_MEMO_BYTES]).to_bytes();
+        let at = 1 + 32 + 4 + 32 + 32 + 32 + 1 + 1 + 32;
+        long[at] = MAX_MEMO_BYTES as u8 + 1;
+        assert!(StoredTransaction::from_bytes(&long).is_err());
+    }
+
     // ========== ITERATOR TESTS ==========
 
     #[test]
@@ -614,6 +671,7 @@ mod stress_tests {
                 total_referral_bonus_earned: 0,
                 governance_weight: 0,
                 total_blocks_mined: 0,
+                immature: Vec::new(),
             };
             db.put_account(&addr, &state).unwrap();
         }
@@ -637,6 +695,7 @@ mod stress_tests {
             total_referral_bonus_earned: 0,
             governance_weight: 0,
             total_blocks_mined: 0,
+            immature: Vec::new(),
         };
         
         db.put_account(&addr, &state).unwrap();
@@ -665,6 +724,7 @@ mod stress_tests {
                 total_referral_bonus_earned: 50000,
                 governance_weight: 200,
                 total_blocks_mined: 5,
+                immature: Vec::new(),
             };
             db.put_account(&addr, &state).unwrap();
             db.flush().unwrap();
@@ -0,0 +1,135 @@
+// Data directory lock
+//
+// A second knotcoind on the same data dir would fight over RocksDB, the
+// .cookie, mempool.dat and peers.json. On startup the daemon creates
+// `<data_dir>/.lock` with O_EXCL and writes its PID there; the file is
+// removed again when the lock is dropped on a clean shutdown.
+//
+// After a crash the file is left behind. On Linux a lock whose PID no longer
+// exists is treated as stale and taken over; elsewhere the user is told to
+// delete it.
+
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+pub const LOCK_FILE: &str = ".lock";
+
+#[derive(Debug)]
+pub enum LockError {
+    /// Another process holds the lock; `pid` is what it wrote, if readable.
+    Held { path: PathBuf, pid: Option<u32> },
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for LockError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LockError::Held { path, pid: Some(pid) } => write!(
+                f,
+                "data directory is in use by another knotcoind (pid {pid}); if it is not running, delete {}",
+                path.display()
+            ),
+            LockError::Held { path, pid: None } => write!(
+                f,
+                "data directory is in use by another knotcoind; if it is not running, delete {}",
+                path.display()
+            ),
+            LockError::Io(e) => write!(f, "lock file error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for LockError {}
+
+impl From<std::io::Error> for LockError {
+    fn from(e: std::io::Error) -> Self {
+        LockError::Io(e)
+    }
+}
+
+/// Exclusive hold on a data directory, released on drop.
+#[derive(Debug)]
+pub struct DataDirLock {
+    path: PathBuf,
+}
+
+impl DataDirLock {
+    pub fn acquire(data_dir: &Path) -> Result<Self, LockError> {
+        let path = data_dir.join(LOCK_FILE);
+        // One retry, after clearing a stale lock.
+        for _ in 0..2 {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut f) => {
+                    writeln!(f, "{}", std::process::id())?;
+                    f.sync_all()?;
+                    return Ok(DataDirLock { path });
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    let pid = std::fs::read_to_string(&path).ok().and_then(|s| s.trim().parse::<u32>().ok());
+                    match pid {
+                        Some(pid) if !process_alive(pid) => {
+                            log::warn!("[init] removing stale {} left by pid {pid}", path.display());
+                            std::fs::remove_file(&path)?;
+                        }
+                        _ => return Err(LockError::Held { path, pid }),
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Err(LockError::Held { path, pid: None })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for DataDirLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Whether `pid` names a running process. Without a way to tell, assume it
+/// does, so a lock is never taken from a live node.
+fn process_alive(pid: u32) -> bool {
+    if cfg!(target_os = "linux") {
+        Path::new("/proc").join(pid.to_string()).exists()
+    } else {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_is_exclusive_and_released_on_drop() {
+        let dir = tempfile::tempdir().unwrap();
+        let lock = DataDirLock::acquire(dir.path()).unwrap();
+        let written = std::fs::read_to_string(lock.path()).unwrap();
+        assert_eq!(written.trim(), std::process::id().to_string());
+
+        match DataDirLock::acquire(dir.path()) {
+            Err(LockError::Held { pid, .. }) => assert_eq!(pid, Some(std::process::id())),
+            other => panic!("expected Held, got {other:?}"),
+        }
+
+        drop(lock);
+        assert!(!dir.path().join(LOCK_FILE).exists());
+        DataDirLock::acquire(dir.path()).unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_stale_lock_is_taken_over() {
+        let dir = tempfile::tempdir().unwrap();
+        // Above the kernel's pid_max, so never a live process.
+        std::fs::write(dir.path().join(LOCK_FILE), "4294967295\n").unwrap();
+        let lock = DataDirLock::acquire(dir.path()).unwrap();
+        assert_eq!(std::fs::read_to_string(lock.path()).unwrap().trim(), std::process::id().to_string());
+    }
+}
@@ -1,6 +1,7 @@
 // Database modules
 pub mod db_common;   // Shared types (AccountState, StoredBlock, etc.)
 pub mod db_rocksdb;  // RocksDB implementation (production)
+pub mod lockfile;    // Data directory .lock (one daemon per data dir)
 // pub mod db;       // Old sled implementation (kept for reference)
 
 // Re-export main database type
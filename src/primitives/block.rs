@@ -6,7 +6,7 @@ use crate::crypto::keys::ADDRESS_BYTES;
 pub const BLOCK_HEADER_BYTES: usize = 148;
 pub const BASE_BLOCK_SIZE_KB: usize = 50;
 pub const MAX_BLOCK_SIZE_KB: usize = 500;
-pub const TARGET_BLOCK_TIME_SEC: u64 = 60;
+pub const TARGET_BLOCK_TIME_SEC: u64 = crate::consensus::chain::TARGET_BLOCK_SECS;
 
 /// Strict adherence to Section 4: Block Header (148 bytes)
 #[derive(Debug, Clone, Copy)]
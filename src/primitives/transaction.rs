@@ -1,12 +1,36 @@
 // Data Structures: Transaction
+use crate::consensus::governance::{Proposal, TX_VERSION_PROPOSAL};
 use crate::crypto::hash::hash_sha3_256;
 use crate::crypto::keys::ADDRESS_BYTES;
-use crate::crypto::dilithium::{PublicKey, Signature};
+use crate::crypto::dilithium::{PublicKey, Signature, DILITHIUM3_PUBKEY_BYTES, DILITHIUM3_SIG_BYTES};
 use crate::node::db_common::StoredTransaction;
 
 pub const KNOTS_PER_KOT: u64 = 100_000_000;
 pub const MIN_FEE_KNOTS: u64 = 1;
 
+/// Convert a KOT amount given as a float (e.g. from JSON-RPC) to knots,
+/// rounded to the nearest knot. NaN, infinities, negative amounts and
+/// anything that doesn't fit in a u64 are rejected rather than cast.
+pub fn kot_to_knots(kot: f64) -> Result<u64, &'static str> {
+    if !kot.is_finite() {
+        return Err("amount must be a finite number");
+    }
+    let knots = (kot * KNOTS_PER_KOT as f64).round();
+    if knots < 0.0 {
+        return Err("amount must not be negative");
+    }
+    // u64::MAX as f64 rounds up to 2^64, which itself doesn't fit.
+    if knots >= u64::MAX as f64 {
+        return Err("amount too large");
+    }
+    Ok(knots as u64)
+}
+
+/// Transaction version that carries a memo.
+pub const TX_VERSION_MEMO: u8 = 3;
+/// Longest memo a transaction may carry, e.g. a merchant's order reference.
+pub const MAX_MEMO_BYTES: usize = 80;
+
 /// Strict adherence to Section 3 of Knotcoin Whitepaper
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Transaction {
@@ -27,6 +51,12 @@ pub struct Transaction {
     // If set, this transaction is a signaling vote or proposal.
     pub governance_data: Option<[u8; 32]>,
 
+    // Encoded governance::Proposal; only on TX_VERSION_PROPOSAL transactions.
+    pub proposal: Option<Vec<u8>>,
+
+    // Free-form payment data, at most MAX_MEMO_BYTES; only on TX_VERSION_MEMO transactions.
+    pub memo: Option<Vec<u8>>,
+
     pub signature: Signature,
 }
 
@@ -49,6 +79,13 @@ impl Transaction {
         if let Some(gov_data) = self.governance_data {
             buffer.extend_from_slice(&gov_data);
         }
+        if let Some(proposal) = &self.proposal {
+            buffer.extend_from_slice(proposal);
+        }
+        if let Some(memo) = &self.memo {
+            buffer.push(memo.len() as u8);
+            buffer.extend_from_slice(memo);
+        }
 
         hash_sha3_256(&buffer)
     }
@@ -63,16 +100,39 @@ impl Transaction {
 
     /// Validates internal structural constraints. Does NOT validate state.
     pub fn is_structurally_valid(&self) -> bool {
+        self.has_valid_structure() && self.has_valid_signature()
+    }
+
+    /// Every check of `is_structurally_valid` except the signature.
+    pub fn has_valid_structure(&self) -> bool {
         // 1. Minimum fee check
         if self.fee < MIN_FEE_KNOTS {
             return false;
         }
 
-        // 2. Amount must be positive, UNLESS it is:
-        //    - a governance signaling transaction, OR
+        // 2. Proposal payload exactly on proposal transactions, and well formed
+        if (self.version == TX_VERSION_PROPOSAL) != self.proposal.is_some() {
+            return false;
+        }
+        if let Some(p) = &self.proposal
+            && Proposal::from_bytes(p).is_err()
+        {
+            return false;
+        }
+
+        // Memo exactly on memo transactions, and short
+        if (self.version == TX_VERSION_MEMO) != self.memo.is_some() {
+            return false;
+        }
+        if self.memo.as_ref().is_some_and(|m| m.len() > MAX_MEMO_BYTES) {
+            return false;
+        }
+
+        // 3. Amount must be positive, UNLESS it is:
+        //    - a governance signaling or proposal transaction, OR
         //    - a referral registration transaction (nonce==1, referrer set, self-recipient)
         if self.amount == 0 {
-            let is_governance_signal = self.governance_data.is_some();
+            let is_governance_signal = self.governance_data.is_some() || self.proposal.is_some();
             let is_referral_registration = self.nonce == 1
                 && self.referrer_address.is_some()
                 && self.recipient_address == self.sender_address;
@@ -87,42 +147,38 @@ impl Transaction {
             return false;
         }
 
-        // 3. Sender pubkey must match claimed address
+        // 4. Sender pubkey must match claimed address
         let derived_addr = crate::crypto::keys::derive_address(&self.sender_pubkey);
         if derived_addr != self.sender_address {
             return false;
         }
 
-        // 4. Registration rules
+        // 5. Registration rules
         if self.nonce > 1 && self.referrer_address.is_some() {
             return false; // Referrer only allowed on first outbound txn
         }
 
-        // 5. Signature verification
-        let msg = self.signing_hash();
-        if !crate::crypto::dilithium::verify(&msg, &self.signature, &self.sender_pubkey) {
-            return false;
-        }
-
         true
     }
+
+    /// 6. Signature verification
+    pub fn has_valid_signature(&self) -> bool {
+        let msg = self.signing_hash();
+        crate::crypto::dilithium::verify(&msg, &self.signature, &self.sender_pubkey)
+    }
 }
 
+/// The only way a wire or stored transaction reaches validation. The key and
+/// signature must be exactly Dilithium3-sized: a padded or truncated copy of a
+/// valid transaction would otherwise serialize (and hash) differently.
 impl TryFrom<&StoredTransaction> for Transaction {
     type Error = &'static str;
 
     fn try_from(st: &StoredTransaction) -> Result<Self, Self::Error> {
-        let mut pk = [0u8; 1952];
-        if st.sender_pubkey.len() != 1952 {
-            return Err("invalid public key length");
-        }
-        pk.copy_from_slice(&st.sender_pubkey);
-
-        let mut sig = [0u8; 3309];
-        if st.signature.len() != 3309 {
-            return Err("invalid signature length");
-        }
-        sig.copy_from_slice(&st.signature);
+        let pk: [u8; DILITHIUM3_PUBKEY_BYTES] =
+            st.sender_pubkey.as_slice().try_into().map_err(|_| "invalid public key length")?;
+        let sig: [u8; DILITHIUM3_SIG_BYTES] =
+            st.signature.as_slice().try_into().map_err(|_| "invalid signature length")?;
 
         Ok(Transaction {
             version: st.version,
@@ -135,6 +191,8 @@ impl TryFrom<&StoredTransaction> for Transaction {
             timestamp: st.timestamp,
             referrer_address: st.referrer_address,
             governance_data: st.governance_data,
+            proposal: st.proposal.clone(),
+            memo: st.memo.clone(),
             signature: Signature(sig),
         })
     }
@@ -168,6 +226,8 @@ mod tests {
             timestamp: 1700000000,
             referrer_address: None,
             governance_data: None,
+            proposal: None,
+            memo: None,
             signature: dilithium::Signature([0u8; 3309]), // placeholder
         };
 
@@ -206,6 +266,43 @@ mod tests {
         assert!(!tx.is_structurally_valid());
     }
 
+    #[test]
+    fn test_key_and_signature_lengths_are_exact() {
+        let tx = mock_tx();
+        let stored = StoredTransaction {
+            version: tx.version,
+            sender_address: tx.sender_address,
+            sender_pubkey: tx.sender_pubkey.0.to_vec(),
+            recipient_address: tx.recipient_address,
+            amount: tx.amount,
+            fee: tx.fee,
+            nonce: tx.nonce,
+            timestamp: tx.timestamp,
+            referrer_address: None,
+            governance_data: None,
+            proposal: None,
+            memo: None,
+            signature: tx.signature.0.to_vec(),
+        };
+        let back = Transaction::try_from(&stored).unwrap();
+        assert!(back.is_structurally_valid());
+        assert_eq!(back.txid(), tx.txid());
+
+        let mut short = stored.clone();
+        short.signature.pop();
+        assert_eq!(Transaction::try_from(&short).unwrap_err(), "invalid signature length");
+        let mut long = stored.clone();
+        long.signature.push(0);
+        assert_eq!(Transaction::try_from(&long).unwrap_err(), "invalid signature length");
+
+        let mut short_pk = stored.clone();
+        short_pk.sender_pubkey.pop();
+        assert_eq!(Transaction::try_from(&short_pk).unwrap_err(), "invalid public key length");
+        let mut long_pk = stored;
+        long_pk.sender_pubkey.push(0);
+        assert_eq!(Transaction::try_from(&long_pk).unwrap_err(), "invalid public key length");
+    }
+
     #[test]
     fn test_wrong_pubkey_rejected() {
         let mut tx = mock_tx();
@@ -213,6 +310,31 @@ mod tests {
         assert!(!tx.is_structurally_valid());
     }
 
+    #[test]
+    fn test_memo_is_signed_and_bounded() {
+        let (_, sk) = dilithium::generate_keypair(&[0u8; 64]);
+        let mut tx = mock_tx();
+        tx.version = TX_VERSION_MEMO;
+        tx.memo = Some(b"invoice 7".to_vec());
+        tx.signature = dilithium::sign(&tx.signing_hash(), &sk);
+        assert!(tx.is_structurally_valid());
+
+        let mut tampered = tx.clone();
+        tampered.memo = Some(b"invoice 8".to_vec());
+        assert!(!tampered.is_structurally_valid());
+
+        let mut long = tx.clone();
+        long.memo = Some(vec![b'x'; MAX_MEMO_BYTES + 1]);
+        long.signature = dilithium::sign(&long.signing_hash(), &sk);
+        assert!(!long.is_structurally_valid());
+
+        // A memo only rides on memo-version transactions.
+        let mut v1 = tx;
+        v1.version = 1;
+        v1.signature = dilithium::sign(&v1.signing_hash(), &sk);
+        assert!(!v1.is_structurally_valid());
+    }
+
     #[test]
     fn test_zero_amount_rejected() {
         let mut tx = mock_tx();
@@ -226,4 +348,19 @@ mod tests {
         
         assert!(!tx.is_structurally_valid());
     }
+
+    #[test]
+    fn test_kot_to_knots() {
+        assert_eq!(kot_to_knots(1.5), Ok(150_000_000));
+        assert_eq!(kot_to_knots(0.0), Ok(0));
+        // 0.29 * 1e8 is 28999999.999999996; truncating would lose a knot.
+        assert_eq!(kot_to_knots(0.29), Ok(29_000_000));
+        assert_eq!(kot_to_knots(0.000000004), Ok(0));
+        assert_eq!(kot_to_knots(0.000000006), Ok(1));
+        assert!(kot_to_knots(-1.0).is_err());
+        assert!(kot_to_knots(f64::NAN).is_err());
+        assert!(kot_to_knots(f64::INFINITY).is_err());
+        assert!(kot_to_knots(1e12).is_err());
+        assert_eq!(kot_to_knots(1e11), Ok(10_000_000_000_000_000_000));
+    }
 }
@@ -1 +1,3 @@
+pub mod ratelimit;
 pub mod server;
+pub mod ws;
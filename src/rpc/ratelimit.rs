@@ -0,0 +1,114 @@
+// RPC Rate Limiting
+//
+// A node whose RPC port is reachable from other hosts can be kept busy by
+// anyone holding the cookie token, or flooded with unauthenticated requests
+// that still cost a connection each. Every non-loopback source IP gets a
+// token bucket: it refills at `rate` tokens per second up to `burst`, each
+// HTTP request takes one, and an empty bucket means HTTP 429. A batch is one
+// request. Loopback is never limited so local tooling and the CLI keep working.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Instant;
+
+pub const DEFAULT_RPC_RATE_PER_SEC: u32 = 20;
+pub const DEFAULT_RPC_RATE_BURST: u32 = 50;
+
+/// Idle buckets are dropped once this many IPs are tracked.
+const MAX_TRACKED_IPS: usize = 10_000;
+
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: HashMap<IpAddr, Bucket>,
+}
+
+impl RateLimiter {
+    /// `rate` of 0 turns limiting off.
+    pub fn new(rate: u32, burst: u32) -> Self {
+        RateLimiter { rate: rate as f64, burst: burst.max(1) as f64, buckets: HashMap::new() }
+    }
+
+    /// Take a token for `ip`; false means the request should be refused.
+    pub fn check(&mut self, ip: IpAddr) -> bool {
+        self.check_at(ip, Instant::now())
+    }
+
+    fn check_at(&mut self, ip: IpAddr, now: Instant) -> bool {
+        if self.rate == 0.0 || ip.to_canonical().is_loopback() {
+            return true;
+        }
+        if self.buckets.len() >= MAX_TRACKED_IPS && !self.buckets.contains_key(&ip) {
+            self.prune(now);
+        }
+        let (rate, burst) = (self.rate, self.burst);
+        let b = self.buckets.entry(ip).or_insert(Bucket { tokens: burst, last: now });
+        let elapsed = now.saturating_duration_since(b.last).as_secs_f64();
+        b.tokens = (b.tokens + elapsed * rate).min(burst);
+        b.last = now;
+        if b.tokens < 1.0 {
+            return false;
+        }
+        b.tokens -= 1.0;
+        true
+    }
+
+    /// Forget buckets that have refilled completely; they behave like new ones.
+    fn prune(&mut self, now: Instant) {
+        let (rate, burst) = (self.rate, self.burst);
+        self.buckets.retain(|_, b| {
+            b.tokens + now.saturating_duration_since(b.last).as_secs_f64() * rate < burst
+        });
+    }
+
+    pub fn tracked(&self) -> usize {
+        self.buckets.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_burst_then_refill() {
+        let mut rl = RateLimiter::new(20, 50);
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+        let t0 = Instant::now();
+        assert!((0..50).all(|_| rl.check_at(ip, t0)));
+        assert!(!rl.check_at(ip, t0));
+
+        // Other IPs have their own bucket.
+        assert!(rl.check_at("203.0.113.8".parse().unwrap(), t0));
+
+        // 100 ms at 20/s buys two more requests.
+        let t1 = t0 + Duration::from_millis(100);
+        assert!(rl.check_at(ip, t1));
+        assert!(rl.check_at(ip, t1));
+        assert!(!rl.check_at(ip, t1));
+
+        rl.prune(t1 + Duration::from_secs(10));
+        assert_eq!(rl.tracked(), 0);
+    }
+
+    #[test]
+    fn test_loopback_and_disabled_are_unlimited() {
+        let mut rl = RateLimiter::new(1, 1);
+        let t0 = Instant::now();
+        for ip in ["127.0.0.1", "::1", "::ffff:127.0.0.1"] {
+            let ip: IpAddr = ip.parse().unwrap();
+            assert!((0..100).all(|_| rl.check_at(ip, t0)));
+        }
+        assert_eq!(rl.tracked(), 0);
+
+        let mut off = RateLimiter::new(0, 1);
+        let ip: IpAddr = "198.51.100.1".parse().unwrap();
+        assert!((0..100).all(|_| off.check_at(ip, t0)));
+    }
+}
@@ -1,5 +1,5 @@
 use std::convert::Infallible;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::{
     Arc,
     atomic::{AtomicBool, AtomicU64, Ordering},
@@ -11,15 +11,18 @@ use hyper::body::Bytes;
 use hyper::service::service_fn;
 use hyper::{Request, Response, body::Incoming};
 use hyper_util::rt::TokioIo;
+use log::{error, info, warn};
 use serde_json::{Value, json};
 use tokio::net::TcpListener;
-use tokio::time::{Duration, timeout};
 use tokio::sync::Mutex;
 
 use crate::config::{RPC_BIND_ADDRESS, RPC_COOKIE_FILE};
+use crate::rpc::ratelimit::RateLimiter;
+use crate::consensus::governance::GOVERNANCE_THRESHOLD_BPS;
 use crate::consensus::state::block_hash;
 use crate::net::mempool::Mempool;
-use crate::net::node::P2pCommand;
+use crate::net::node::{KnownPeers, P2pCommand, Peers};
+use crate::net::timedata::TimeData;
 use crate::node::ChainDB;
 
 type WalletKeyCache = std::collections::HashMap<
@@ -34,18 +37,356 @@ pub struct RpcState {
     pub db: ChainDB,
     pub mempool: Arc<Mutex<Mempool>>,
     pub shutdown: AtomicBool,
+    /// Fired once by `request_shutdown`; the RPC, P2P and mining loops listen.
+    pub shutdown_tx: tokio::sync::broadcast::Sender<()>,
     pub p2p_tx: tokio::sync::mpsc::UnboundedSender<P2pCommand>,
     pub auth_token: String,
     pub data_dir: String,
+    pub config: Arc<crate::config::Config>,
     pub mining_active: AtomicBool,
     pub mining_blocks_found: Arc<AtomicU64>,
     pub mining_start_time: Arc<AtomicU64>,
     pub mining_stop: Arc<AtomicBool>,
     pub connected_peers: Arc<std::sync::atomic::AtomicUsize>,
+    pub known_peers: KnownPeers,
+    pub peers: Peers,
+    pub time_data: Arc<Mutex<TimeData>>,
     pub wallet_keys: Arc<Mutex<WalletKeyCache>>,
     pub mining_nonces_total: Arc<AtomicU64>,
+    /// Recent `mining_nonces_total` readings, for `getmininghashps`.
+    pub mining_hashrate_samples: Arc<Mutex<crate::miner::miner::HashrateSamples>>,
     pub mining_address: Arc<Mutex<Option<[u8; 32]>>>,
     pub mining_referrer: Arc<Mutex<Option<[u8; 32]>>>,
+    pub block_events: crate::rpc::ws::BlockEvents,
+    /// `getblockstats` results by block hash; a hash always names the same block.
+    pub block_stats_cache: Arc<Mutex<std::collections::HashMap<[u8; 32], Value>>>,
+    /// Unix time our tip last changed (startup until then); see the P2P
+    /// stale-tip watchdog.
+    pub last_tip_update: Arc<AtomicU64>,
+}
+
+impl RpcState {
+    /// Stop mining and tell the RPC accept loop and the P2P loop to wind
+    /// down. `stop` and the daemon's signal handler both end up here.
+    pub fn request_shutdown(&self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        self.mining_stop.store(true, Ordering::SeqCst);
+        let _ = self.shutdown_tx.send(());
+    }
+}
+
+/// Entries kept in `block_stats_cache` before it is cleared.
+const MAX_BLOCK_STATS_CACHE: usize = 10_000;
+
+fn block_stats(block: &crate::node::db_common::StoredBlock) -> Value {
+    let height = u32::from_le_bytes(block.block_height);
+    let reward = crate::consensus::chain::calculate_block_reward(height as u64);
+    let total_fees: u64 = block.tx_data.iter().map(|tx| tx.fee).sum();
+    let total_out: u64 = block.tx_data.iter().map(|tx| tx.amount).sum();
+    let tx_bytes: usize = block.tx_data.iter().map(|tx| tx.to_bytes().len()).sum();
+    let avg_fee_per_byte = if tx_bytes == 0 { 0.0 } else { total_fees as f64 / tx_bytes as f64 };
+    json!({
+        "hash":             hex::encode(block_hash(block)),
+        "height":           height,
+        "time":             u32::from_le_bytes(block.timestamp),
+        "txs":              block.tx_data.len(),
+        "total_fees":       total_fees,
+        "total_out":        total_out,
+        "subsidy":          reward,
+        "total_size":       block.to_bytes().len(),
+        "avg_fee_per_byte": avg_fee_per_byte,
+    })
+}
+
+/// Most blocks one `getblocks` call returns.
+const MAX_GETBLOCKS_COUNT: u32 = 200;
+
+/// Most points one `getrewardschedule` call samples.
+const MAX_REWARD_SCHEDULE_POINTS: u64 = 10_000;
+
+/// `estimatefee` target when none is given, and the furthest it looks ahead.
+const DEFAULT_CONF_TARGET: u64 = 6;
+const MAX_CONF_TARGET: u64 = 1008;
+
+/// Most recipients one `sendmany` call pays.
+const MAX_SENDMANY_RECIPIENTS: usize = 100;
+
+/// Summaries of up to `count` main-chain blocks from `start` upward,
+/// stopping early at the tip.
+fn block_summaries(db: &ChainDB, start: u32, count: u32) -> Result<Value, (i32, String)> {
+    let tip = db.get_chain_height().map_err(|e| (-32603, format!("db error: {e}")))?;
+    if start > tip {
+        return Err((-32602, format!("start height {start} is above the tip ({tip})")));
+    }
+    let end = start.saturating_add(count - 1).min(tip);
+    let mut out = Vec::with_capacity((end - start + 1) as usize);
+    for h in start..=end {
+        let hash = db.get_block_hash_by_height(h)
+            .map_err(|e| (-32603, format!("db error: {e}")))?
+            .ok_or((-32603, format!("no block indexed at height {h}")))?;
+        let block = db.get_block(&hash)
+            .map_err(|e| (-32603, format!("db error: {e}")))?
+            .ok_or((-32603, format!("block {} missing", hex::encode(hash))))?;
+        let reward = crate::consensus::chain::calculate_block_reward(h as u64);
+        out.push(json!({
+            "hash":     hex::encode(hash),
+            "height":   h,
+            "time":     u32::from_le_bytes(block.timestamp),
+            "miner":    crate::crypto::keys::encode_address_string(&block.miner_address),
+            "tx_count": block.tx_data.len(),
+            "reward":   reward,
+            "size":     block.to_bytes().len(),
+        }));
+    }
+    Ok(Value::Array(out))
+}
+
+/// A KOT1 address, or 32 bytes of hex with an optional `KOT`/`KOT1` prefix.
+fn parse_address_param(s: &str) -> Option<[u8; 32]> {
+    if let Ok(a) = crate::crypto::keys::decode_address_string(s) {
+        return Some(a);
+    }
+    let hex_part = if s.to_lowercase().starts_with("kot1") {
+        &s[4..]
+    } else if s.to_lowercase().starts_with("kot") {
+        &s[3..]
+    } else {
+        s
+    };
+    hex::decode(hex_part).ok()?.try_into().ok()
+}
+
+/// A block named by height (number) or hash (hex string).
+fn block_hash_param(state: &RpcState, param: Option<&Value>) -> Result<[u8; 32], (i32, String)> {
+    match param {
+        Some(Value::Number(n)) => {
+            let h = n.as_u64().filter(|h| *h <= u32::MAX as u64)
+                .ok_or((-32602, "invalid height".to_string()))?;
+            state.db.get_block_hash_by_height(h as u32)
+                .map_err(|e| (-32603, format!("db error: {e}")))?
+                .ok_or((-32602, "block not found".to_string()))
+        }
+        Some(Value::String(s)) => {
+            let raw = hex::decode(s).map_err(|_| (-32602, "invalid hash format".to_string()))?;
+            <[u8; 32]>::try_from(raw.as_slice()).map_err(|_| (-32602, "invalid hash length".to_string()))
+        }
+        _ => Err((-32602, "height or hash required".to_string())),
+    }
+}
+
+/// Longest `optimizemining` spends on each thread count.
+const MAX_OPTIMIZE_SECS: u64 = 10;
+
+/// Deepest `getreferraltree` walk allowed.
+const MAX_REFERRAL_TREE_DEPTH: u32 = 5;
+
+fn privacy_code(addr: &[u8; 32]) -> String {
+    hex::encode(&crate::crypto::hash::hash_sha3_256(addr)[..8])
+}
+
+/// Downstream referrals of `root`. Accounts only record their referrer, so
+/// one pass over the accounts builds the referrer -> referees map and the
+/// tree is walked from that.
+fn referral_tree(db: &ChainDB, root: &[u8; 32], depth: u32) -> Result<Value, (i32, String)> {
+    let accounts = db.iter_accounts().map_err(|e| (-32603, format!("db error: {e}")))?;
+    let mut children: std::collections::HashMap<[u8; 32], Vec<(&[u8; 32], &crate::node::db_common::AccountState)>> =
+        std::collections::HashMap::new();
+    for (addr, acc) in &accounts {
+        if let Some(r) = acc.referrer {
+            children.entry(r).or_default().push((addr, acc));
+        }
+    }
+
+    fn walk(
+        children: &std::collections::HashMap<[u8; 32], Vec<(&[u8; 32], &crate::node::db_common::AccountState)>>,
+        addr: &[u8; 32],
+        depth: u32,
+        seen: &mut std::collections::HashSet<[u8; 32]>,
+    ) -> Vec<Value> {
+        let Some(kids) = children.get(addr) else {
+            return Vec::new();
+        };
+        let mut out: Vec<Value> = Vec::with_capacity(kids.len());
+        for (kid, acc) in kids {
+            // Two accounts can name each other; show each account once.
+            if !seen.insert(**kid) {
+                continue;
+            }
+            let mut node = json!({
+                "privacy_code":          privacy_code(kid),
+                "blocks_mined":          acc.total_blocks_mined,
+                "total_referred_miners": acc.total_referred_miners,
+            });
+            if depth > 1 {
+                node["referrals"] = Value::Array(walk(children, kid, depth - 1, seen));
+            }
+            out.push(node);
+        }
+        out.sort_by(|a, b| b["blocks_mined"].as_u64().cmp(&a["blocks_mined"].as_u64())
+            .then_with(|| a["privacy_code"].as_str().cmp(&b["privacy_code"].as_str())));
+        out
+    }
+
+    let mut seen = std::collections::HashSet::from([*root]);
+    let referrals = walk(&children, root, depth, &mut seen);
+    Ok(json!({
+        "privacy_code": privacy_code(root),
+        "depth": depth,
+        "total_in_tree": seen.len() - 1,
+        "referrals": referrals,
+    }))
+}
+
+/// Blocks between `verifychain` progress lines.
+const VERIFY_PROGRESS_INTERVAL: u32 = 1000;
+
+/// Walk heights 0..=tip checking that the height index, stored blocks and
+/// `previous_hash` links agree (and PoW, if asked). Stops at the first fault.
+fn verify_chain(db: &ChainDB, check_pow: bool) -> Result<Value, String> {
+    let tip = db.get_chain_height().map_err(|e| format!("db error: {e}"))?;
+    let mut prev_hash: Option<[u8; 32]> = None;
+    let mut checked = 0u32;
+
+    for h in 0..=tip {
+        let fault = match db.get_block_hash_by_height(h).map_err(|e| format!("db error: {e}"))? {
+            None => Some("no block indexed at this height".to_string()),
+            Some(hash) => match db.get_block(&hash).map_err(|e| format!("db error: {e}"))? {
+                None => Some(format!("indexed block {} missing", hex::encode(hash))),
+                Some(block) => {
+                    let fault = if block_hash(&block) != hash {
+                        Some("block hash does not match height index".to_string())
+                    } else if u32::from_le_bytes(block.block_height) != h {
+                        Some("block height field does not match".to_string())
+                    } else if prev_hash.is_some_and(|p| p != block.previous_hash) {
+                        Some("previous_hash does not link to height - 1".to_string())
+                    } else if check_pow && crate::consensus::state::verify_block_pow(&block, db).is_err() {
+                        Some("invalid proof of work".to_string())
+                    } else {
+                        None
+                    };
+                    prev_hash = Some(hash);
+                    fault
+                }
+            },
+        };
+        if let Some(err) = fault {
+            error!("[chain] verifychain: fault at height {h}: {err}");
+            return Ok(json!({ "checked": checked, "first_error_height": h, "error": err, "check_pow": check_pow }));
+        }
+        checked += 1;
+        if checked.is_multiple_of(VERIFY_PROGRESS_INTERVAL) {
+            info!("[chain] verifychain: {checked}/{} blocks checked", tip + 1);
+        }
+    }
+
+    info!("[chain] verifychain: {checked} blocks ok");
+    Ok(json!({ "checked": checked, "first_error_height": null, "error": null, "check_pow": check_pow }))
+}
+
+/// Human-readable difficulty: 2^(leading zero bits of the target), minimum 1.
+/// Also returns the bit count.
+fn difficulty_from_target(target: &[u8; 32]) -> (f64, u32) {
+    // Count leading zero bits in target (more zeros = harder)
+    let mut leading_zeros = 0u32;
+    for &b in target.iter() {
+        if b == 0x00 {
+            leading_zeros += 8;
+        } else {
+            leading_zeros += b.leading_zeros();
+            break;
+        }
+    }
+    let difficulty = if leading_zeros == 0 { 1.0 } else { 2.0f64.powi(leading_zeros as i32) };
+    (difficulty, leading_zeros)
+}
+
+/// `getaddresstransactions` stops a page after this many entries...
+const ADDRESS_TXS_PAGE_SIZE: usize = 500;
+/// ...or after scanning this many blocks, whichever comes first.
+const ADDRESS_TXS_MAX_SCAN_BLOCKS: u32 = 10_000;
+
+/// Without a tx index, `getrawtransaction` only searches this many recent blocks.
+const RAW_TX_SCAN_BLOCKS: u32 = 1_000;
+
+/// The active-chain block confirming `txid`: via `tx_index` when the node
+/// keeps one, otherwise by scanning back `RAW_TX_SCAN_BLOCKS` from the tip.
+fn find_confirmed_tx(
+    db: &ChainDB,
+    txid: &[u8; 32],
+) -> Result<Option<(u32, [u8; 32], crate::node::db_common::StoredTransaction)>, String> {
+    let in_block = |hash: &[u8; 32]| -> Result<Option<crate::node::db_common::StoredTransaction>, String> {
+        let block = db.get_block(hash).map_err(|e| e.to_string())?;
+        Ok(block.and_then(|b| {
+            b.tx_data
+                .into_iter()
+                .find(|tx| crate::net::mempool::Mempool::compute_txid_from_stored(tx) == *txid)
+        }))
+    };
+
+    if db.tx_index_enabled() {
+        let Some((height, hash)) = db.get_tx_location(txid).map_err(|e| e.to_string())? else {
+            return Ok(None);
+        };
+        // A stale entry from a block that was since reorged out doesn't count.
+        if db.get_block_hash_by_height(height).map_err(|e| e.to_string())? != Some(hash) {
+            return Ok(None);
+        }
+        return Ok(in_block(&hash)?.map(|tx| (height, hash, tx)));
+    }
+
+    let tip = db.get_chain_height().map_err(|e| e.to_string())?;
+    for height in (tip.saturating_sub(RAW_TX_SCAN_BLOCKS - 1)..=tip).rev() {
+        let Some(hash) = db.get_block_hash_by_height(height).map_err(|e| e.to_string())? else {
+            continue;
+        };
+        if let Some(tx) = in_block(&hash)? {
+            return Ok(Some((height, hash, tx)));
+        }
+    }
+    Ok(None)
+}
+
+/// History entries for `addr` in one block: its mining reward and every tx
+/// it sent or received, in block order.
+fn address_history_entries(block: &crate::node::db_common::StoredBlock, addr: &[u8; 32]) -> Vec<Value> {
+    let block_height = u32::from_le_bytes(block.block_height);
+    let block_time = u32::from_le_bytes(block.timestamp);
+    let mut txs = Vec::new();
+
+    if block.miner_address == *addr {
+        let reward = crate::consensus::chain::calculate_block_reward(block_height as u64);
+        txs.push(json!({
+            "type": "mining_reward",
+            "address": crate::crypto::keys::encode_address_string(&block.miner_address),
+            "amount_knots": reward,
+            "amount_kot": format!("{:.8}", reward as f64 / 1e8),
+            "fee_knots": 0,
+            "block_height": block_height,
+            "timestamp": block_time,
+        }));
+    }
+
+    for tx in &block.tx_data {
+        let (kind, counterparty) = if tx.sender_address == *addr {
+            ("sent", &tx.recipient_address)
+        } else if tx.recipient_address == *addr {
+            ("received", &tx.sender_address)
+        } else {
+            continue;
+        };
+        txs.push(json!({
+            "type": kind,
+            "txid": hex::encode(crate::net::mempool::Mempool::compute_txid_from_stored(tx)),
+            "address": crate::crypto::keys::encode_address_string(counterparty),
+            "amount_knots": tx.amount,
+            "amount_kot": format!("{:.8}", tx.amount as f64 / 1e8),
+            "fee_knots": tx.fee,
+            "block_height": block_height,
+            "timestamp": block_time,
+            "nonce": tx.nonce,
+        }));
+    }
+    txs
 }
 
 fn existing_wallet_hash_mismatch(data_dir: &str, mnemonic_hash: &[u8; 32]) -> bool {
@@ -117,12 +458,7 @@ async fn cached_keypair_for_mnemonic(
     state: &RpcState,
     mnemonic: &str,
 ) -> (crate::crypto::dilithium::PublicKey, crate::crypto::dilithium::SecretKey) {
-    use sha2::{Digest, Sha256};
-    let mut h = Sha256::new();
-    h.update(mnemonic.as_bytes());
-    let digest = h.finalize();
-    let mut key = [0u8; 32];
-    key.copy_from_slice(&digest[..32]);
+    let key = mnemonic_hash(mnemonic);
 
     // Single-wallet-per-profile: if a wallet already exists on disk for this profile,
     // do not silently switch identities by importing a different mnemonic.
@@ -143,8 +479,8 @@ async fn cached_keypair_for_mnemonic(
         return (pk, sk);
     }
 
-    // NOTE: Dilithium keygen is not deterministic in this version; cache ensures stability
-    // across RPC calls within the same daemon run.
+    // Keygen is deterministic, so this always reproduces the same address;
+    // the caches only skip the PBKDF2 + keygen cost.
     let (pk, sk) = crate::crypto::keys::derive_keypair_from_mnemonic(mnemonic);
     cache.insert(key, (pk, sk.clone()));
     if !existing_wallet_hash_mismatch(&state.data_dir, &key) {
@@ -153,20 +489,106 @@ async fn cached_keypair_for_mnemonic(
     (pk, sk)
 }
 
-async fn ensure_single_wallet_identity(state: &RpcState, mnemonic: &str) -> Result<(), (i32, String)> {
+/// SHA-256 of the mnemonic; identifies a profile's wallet in `wallet_keys.json`.
+fn mnemonic_hash(mnemonic: &str) -> [u8; 32] {
     use sha2::{Digest, Sha256};
-    let mut h = Sha256::new();
-    h.update(mnemonic.as_bytes());
-    let digest = h.finalize();
-    let mut key = [0u8; 32];
-    key.copy_from_slice(&digest[..32]);
+    Sha256::digest(mnemonic.as_bytes()).into()
+}
+
+async fn ensure_single_wallet_identity(state: &RpcState, mnemonic: &str) -> Result<(), (i32, String)> {
+    let key = mnemonic_hash(mnemonic);
     if existing_wallet_hash_mismatch(&state.data_dir, &key) {
         return Err((-32603, "wallet profile already initialized with a different mnemonic".to_string()));
     }
     Ok(())
 }
 
+/// Refuse to mine when peers say our clock is off and the operator opted into the guard.
+async fn ensure_clock_ok_for_mining(state: &RpcState) -> Result<(), (i32, String)> {
+    let td = state.time_data.lock().await;
+    if td.mining_blocked() {
+        let w = td.skew_warning().unwrap_or_default();
+        return Err((-32603, format!("mining disabled: {w}")));
+    }
+    Ok(())
+}
+
+/// Refuse a miner address outside the operator's `miner_allowlist`.
+fn ensure_miner_allowed(state: &RpcState, addr: &[u8; 32]) -> Result<(), (i32, String)> {
+    if !state.config.miner_allowed(addr) {
+        let a = crate::crypto::keys::encode_address_string(addr);
+        return Err((-32602, format!("miner address {a} is not in the miner allowlist")));
+    }
+    Ok(())
+}
+
+/// JSON-RPC error code for each mempool rejection reason, so wallets can
+/// react without parsing the message.
+fn mempool_error_code(e: &crate::net::mempool::MempoolError) -> i32 {
+    use crate::net::mempool::MempoolError;
+    match e {
+        MempoolError::Invalid(_) => -32010,
+        MempoolError::BadSignature => -32011,
+        MempoolError::FeeTooLow { .. } => -32012,
+        MempoolError::TimestampInFuture => -32013,
+        MempoolError::DuplicateTxid => -32014,
+        MempoolError::ReplacementUnderpriced { .. } => -32015,
+        MempoolError::TooLarge => -32016,
+        MempoolError::PoolFull => -32017,
+        MempoolError::BalanceTooLow => -32018,
+        MempoolError::NonceTooLow { .. } => -32019,
+        MempoolError::NonceGap { .. } => -32020,
+    }
+}
+
+fn mempool_rejection(e: crate::net::mempool::MempoolError) -> (i32, String) {
+    (mempool_error_code(&e), format!("mempool rejected: {e}"))
+}
+
+/// Whether an RPC method only reads, or can change the chain, mempool,
+/// wallet files, mining or the node itself. Every method is listed here;
+/// `KNOTCOIN_RPC_READONLY` turns off the `Mutating` ones.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MethodKind {
+    Query,
+    Mutating,
+}
+
+const QUERY_METHODS: &[&str] = &[
+    "decoderawtransaction", "estimatefee", "feehistogram", "get_all_miners",
+    "get_mining_status", "getaccountsroot", "getaddresstransactions", "getbalance", "getblock",
+    "getblockbyheight", "getblockchaininfo", "getblockcount", "getblockhash", "getblockheader",
+    "getblocks", "getblockstats", "getblocksubsidy", "getblocktemplate", "getblocktxids",
+    "getconfirmations", "getdifficulty", "getgovernanceinfo", "getgovernanceproposal",
+    "getgovernancetally", "getknownpeers", "getmempoolconflicts", "getmempoolentry",
+    "getmempoolinfo", "getmininghashps", "getmininginfo", "getnetworkinfo", "getpeerinfo",
+    "getrawblock", "getrawmempool", "getrawtransaction", "getreferralinfo", "getreferraltree",
+    "getrewardschedule", "getstatesnapshothash", "getsupply", "gettransactionhistory",
+    "testmempoolaccept", "verifychain",
+];
+
+const MUTATING_METHODS: &[&str] = &[
+    "addnode", "compactdb", "generatetoaddress", "getwalletinfo", "optimizemining", "prunepeers", "reindex", "sendmany",
+    "sendrawtransaction", "start_mining", "stop", "stop_mining", "submitblock", "wallet_bump_fee", "wallet_create",
+    "wallet_create_file", "wallet_derive_address", "wallet_export", "wallet_get_address",
+    "wallet_import", "wallet_propose", "wallet_register_referral", "wallet_reset",
+    "wallet_send", "wallet_unlock_file",
+];
+
+fn method_kind(method: &str) -> Option<MethodKind> {
+    if QUERY_METHODS.contains(&method) {
+        Some(MethodKind::Query)
+    } else if MUTATING_METHODS.contains(&method) {
+        Some(MethodKind::Mutating)
+    } else {
+        None
+    }
+}
+
 async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Value, (i32, String)> {
+    if state.config.rpc_readonly && method_kind(method) == Some(MethodKind::Mutating) {
+        return Err((-32601, format!("method disabled in read-only mode: {method}")));
+    }
     match method {
         "getblockcount" => Ok(json!(
             state
@@ -186,7 +608,15 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
 
         // Get block by height (convenience method)
         "getblockbyheight" => {
-            let h = params.get(0).and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+            let h = match params.get(0).and_then(|v| v.as_i64()) {
+                // Negative heights count back from the tip: -1 is the tip itself.
+                Some(rel) if rel < 0 => {
+                    let tip = state.db.get_chain_height().map_err(|e| (-32603, format!("db error: {e}")))?;
+                    u32::try_from(tip as i64 + 1 + rel)
+                        .map_err(|_| (-32602, format!("height {rel} is before genesis")))?
+                }
+                _ => params.get(0).and_then(|v| v.as_u64()).unwrap_or(0) as u32,
+            };
             let hash = match state.db.get_block_hash_by_height(h) {
                 Ok(Some(hash)) => hash,
                 Ok(None) => return Err((-32602, "block not found".to_string())),
@@ -199,22 +629,7 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
                     let base_reward = 10_0000_0000u64; // 10 KOT in knots
                     let reward = base_reward >> halvings;
                     
-                    // Calculate human-readable difficulty
-                    // Count leading zero bits in target (more zeros = harder)
-                    let target_bytes = &block.difficulty_target;
-                    let mut leading_zeros = 0u32;
-                    for &b in target_bytes.iter() {
-                        if b == 0x00 {
-                            leading_zeros += 8;
-                        } else {
-                            leading_zeros += b.leading_zeros() as u32;
-                            break;
-                        }
-                    }
-                    // Difficulty is 2^leading_zeros, minimum 1
-                    let difficulty_human = if leading_zeros == 0 { 1.0 } else { 
-                        (2.0f64).powi(leading_zeros as i32)
-                    };
+                    let (difficulty_human, leading_zeros) = difficulty_from_target(&block.difficulty_target);
                     
                     Ok(json!({
                         "hash": hex::encode(block_hash(&block)),
@@ -231,14 +646,20 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
                         "reward_knots": reward,
                         "reward_kot": format!("{:.8}", reward as f64 / 1e8),
                         "tx_count": block.tx_data.len(),
-                        "transactions": block.tx_data.iter().map(|tx| json!({
-                            "sender": crate::crypto::keys::encode_address_string(&tx.sender_address),
-                            "recipient": crate::crypto::keys::encode_address_string(&tx.recipient_address),
-                            "amount_knots": tx.amount,
-                            "amount_kot": format!("{:.8}", tx.amount as f64 / 1e8),
-                            "fee": tx.fee,
-                            "nonce": tx.nonce,
-                        })).collect::<Vec<_>>(),
+                        "transactions": block.tx_data.iter().map(|tx| {
+                            let size = tx.to_bytes().len();
+                            json!({
+                                "sender": crate::crypto::keys::encode_address_string(&tx.sender_address),
+                                "recipient": crate::crypto::keys::encode_address_string(&tx.recipient_address),
+                                "amount_knots": tx.amount,
+                                "amount_kot": format!("{:.8}", tx.amount as f64 / 1e8),
+                                "fee": tx.fee,
+                                "size": size,
+                                "fee_per_byte": tx.fee as f64 / size as f64,
+                                "nonce": tx.nonce,
+                                "memo": tx.memo.as_ref().map(hex::encode),
+                            })
+                        }).collect::<Vec<_>>(),
                     }))
                 }
                 Ok(None) => Err((-32602, "block not found".to_string())),
@@ -246,6 +667,8 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
             }
         }
 
+        // Verbosity (second param) 0 returns the serialized block in hex, as
+        // getrawblock does; 1 and 2 (default 1) return the decoded JSON.
         "getblock" => {
             let hex_str = params.get(0).and_then(|v| v.as_str()).unwrap_or("");
             let raw =
@@ -255,8 +678,13 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
             }
             let mut hash = [0u8; 32];
             hash.copy_from_slice(&raw);
+            let verbosity = match params.get(1) {
+                None | Some(Value::Null) => 1,
+                Some(v) => v.as_u64().filter(|v| *v <= 2).ok_or((-32602, "verbosity must be 0, 1 or 2".to_string()))?,
+            };
 
             match state.db.get_block(&hash) {
+                Ok(Some(block)) if verbosity == 0 => Ok(json!(hex::encode(block.to_bytes()))),
                 Ok(Some(block)) => Ok(json!({
                     "hash":              hex::encode(block_hash(&block)),
                     "height":            u32::from_le_bytes(block.block_height),
@@ -268,20 +696,199 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
                     "nonce":             hex::encode(block.nonce),
                     "miner":             crate::crypto::keys::encode_address_string(&block.miner_address),
                     "tx_count":          block.tx_data.len(),
-                    "transactions":      block.tx_data.iter().map(|tx| json!({
-                        "sender":    crate::crypto::keys::encode_address_string(&tx.sender_address),
-                        "recipient": crate::crypto::keys::encode_address_string(&tx.recipient_address),
-                        "amount":    tx.amount,
-                        "fee":       tx.fee,
-                        "nonce":     tx.nonce,
-                        "gov_data":  tx.governance_data.map(hex::encode),
-                    })).collect::<Vec<_>>(),
+                    "transactions":      block.tx_data.iter().map(|tx| {
+                        // Serialized size; the Dilithium pubkey and signature are most of it.
+                        let size = tx.to_bytes().len();
+                        json!({
+                            "sender":       crate::crypto::keys::encode_address_string(&tx.sender_address),
+                            "recipient":    crate::crypto::keys::encode_address_string(&tx.recipient_address),
+                            "amount":       tx.amount,
+                            "fee":          tx.fee,
+                            "size":         size,
+                            "fee_per_byte": tx.fee as f64 / size as f64,
+                            "nonce":        tx.nonce,
+                            "gov_data":     tx.governance_data.map(hex::encode),
+                            "memo":         tx.memo.as_ref().map(hex::encode),
+                        })
+                    }).collect::<Vec<_>>(),
+                })),
+                Ok(None) => Err((-32602, "block not found".to_string())),
+                Err(e) => Err((-32603, format!("db error: {e}"))),
+            }
+        }
+
+        // Exact stored/wire bytes of a block, to diff serialization between nodes.
+        "getrawblock" => {
+            let hex_str = params.get(0).and_then(|v| v.as_str()).unwrap_or("");
+            let raw =
+                hex::decode(hex_str).map_err(|_| (-32602, "invalid hash format".to_string()))?;
+            let hash = <[u8; 32]>::try_from(raw.as_slice()).map_err(|_| (-32602, "invalid hash length".to_string()))?;
+            match state.db.get_block(&hash) {
+                Ok(Some(block)) => Ok(json!(hex::encode(block.to_bytes()))),
+                Ok(None) => Err((-32602, "block not found".to_string())),
+                Err(e) => Err((-32603, format!("db error: {e}"))),
+            }
+        }
+
+        // Header fields only; transactions are never decoded. Pass `false`
+        // as the second param for the raw 148-byte header in hex.
+        "getblockheader" => {
+            let hex_str = params.get(0).and_then(|v| v.as_str()).unwrap_or("");
+            let raw =
+                hex::decode(hex_str).map_err(|_| (-32602, "invalid hash format".to_string()))?;
+            if raw.len() != 32 {
+                return Err((-32602, "invalid hash length".to_string()));
+            }
+            let mut hash = [0u8; 32];
+            hash.copy_from_slice(&raw);
+            let verbose = params.get(1).and_then(|v| v.as_bool()).unwrap_or(true);
+
+            match state.db.get_block_header(&hash) {
+                Ok(Some(header)) if !verbose => Ok(json!(hex::encode(header.header_bytes()))),
+                Ok(Some(header)) => Ok(json!({
+                    "hash":              hex::encode(hash),
+                    "height":            u32::from_le_bytes(header.block_height),
+                    "version":           u32::from_be_bytes(header.version),
+                    "previousblockhash": hex::encode(header.previous_hash),
+                    "merkleroot":        hex::encode(header.merkle_root),
+                    "time":              u32::from_le_bytes(header.timestamp),
+                    "difficulty":        hex::encode(header.difficulty_target),
+                    "nonce":             hex::encode(header.nonce),
+                    "miner":             crate::crypto::keys::encode_address_string(&header.miner_address),
                 })),
                 Ok(None) => Err((-32602, "block not found".to_string())),
                 Err(e) => Err((-32603, format!("db error: {e}"))),
             }
         }
 
+        // Per-block aggregates. Takes a height or a block hash.
+        "getblockstats" => {
+            let hash = block_hash_param(state, params.get(0))?;
+
+            if let Some(stats) = state.block_stats_cache.lock().await.get(&hash) {
+                return Ok(stats.clone());
+            }
+            let block = state.db.get_block(&hash)
+                .map_err(|e| (-32603, format!("db error: {e}")))?
+                .ok_or((-32602, "block not found".to_string()))?;
+            let stats = block_stats(&block);
+            let mut cache = state.block_stats_cache.lock().await;
+            if cache.len() >= MAX_BLOCK_STATS_CACHE {
+                cache.clear();
+            }
+            cache.insert(hash, stats.clone());
+            Ok(stats)
+        }
+
+        // Just the txids, for light clients checking their txs were mined.
+        "getblocktxids" => {
+            let hash = block_hash_param(state, params.get(0))?;
+            if state.db.is_pruned(&hash).map_err(|e| (-32603, format!("db error: {e}")))? {
+                return Err((-32603, "block body has been pruned".to_string()));
+            }
+            let block = state.db.get_block(&hash)
+                .map_err(|e| (-32603, format!("db error: {e}")))?
+                .ok_or((-32602, "block not found".to_string()))?;
+            let txids: Vec<String> = block.tx_data.iter()
+                .map(|tx| hex::encode(crate::net::mempool::Mempool::compute_txid_from_stored(tx)))
+                .collect();
+            Ok(json!({
+                "hash": hex::encode(hash),
+                "height": u32::from_le_bytes(block.block_height),
+                "txids": txids,
+            }))
+        }
+
+        // Block summaries for a height range, oldest first: [start_height, count].
+        // `count` is capped at MAX_GETBLOCKS_COUNT and the range stops at the tip.
+        "getblocks" => {
+            let start = params.get(0).and_then(|v| v.as_u64()).filter(|h| *h <= u32::MAX as u64)
+                .ok_or((-32602, "start_height required".to_string()))? as u32;
+            let count = match params.get(1) {
+                None | Some(Value::Null) => MAX_GETBLOCKS_COUNT,
+                Some(v) => match v.as_u64() {
+                    Some(c) if c > 0 => c.min(MAX_GETBLOCKS_COUNT as u64) as u32,
+                    _ => return Err((-32602, "count must be a positive integer".to_string())),
+                },
+            };
+            let db = state.db.clone();
+            tokio::task::spawn_blocking(move || block_summaries(&db, start, count))
+                .await
+                .map_err(|e| (-32603, format!("blocking task error: {e}")))?
+        }
+
+        // Re-check the stored chain against its own index. Optional param[0]
+        // re-runs PoW on every block, which is much slower.
+        "verifychain" => {
+            let check_pow = params.get(0).and_then(|v| v.as_bool()).unwrap_or(false);
+            let db = state.db.clone();
+            tokio::task::spawn_blocking(move || verify_chain(&db, check_pow))
+                .await
+                .map_err(|e| (-32603, format!("blocking task error: {e}")))?
+                .map_err(|e| (-32603, e))
+        }
+
+        // Rebuild tx_index (when enabled), referral_index and the supply
+        // total from the chain. Accounts are left alone; safe to re-run.
+        "reindex" => {
+            if state.mining_active.load(Ordering::SeqCst) {
+                return Err((-32603, "stop mining before reindexing".to_string()));
+            }
+            let db = state.db.clone();
+            let tx_index = db.tx_index_enabled();
+            let stats = tokio::task::spawn_blocking(move || crate::consensus::state::reindex(&db))
+                .await
+                .map_err(|e| (-32603, format!("blocking task error: {e}")))?
+                .map_err(|e| (-32603, format!("reindex failed: {e}")))?;
+            Ok(json!({
+                "tx_index": tx_index,
+                "blocks": stats.blocks,
+                "pruned_blocks": stats.pruned_blocks,
+                "txs_indexed": stats.txs_indexed,
+                "referral_entries": stats.referral_entries,
+                "total_supply_knots": stats.total_supply,
+            }))
+        }
+
+        // Manual compaction of every column family, so space freed by pruning
+        // goes back to the filesystem now rather than whenever RocksDB gets to it.
+        "compactdb" => {
+            let db = state.db.clone();
+            tokio::task::spawn_blocking(move || -> Result<Value, crate::node::db_rocksdb::DbError> {
+                db.flush()?;
+                let before = db.size_on_disk()?;
+                db.compact()?;
+                Ok(json!({ "size_before": before, "size_after": db.size_on_disk()? }))
+            })
+            .await
+            .map_err(|e| (-32603, format!("blocking task error: {e}")))?
+            .map_err(|e| (-32603, format!("db error: {e}")))
+        }
+
+        "getstatesnapshothash" => {
+            let db = state.db.clone();
+            let height = match params.get(0) {
+                Some(v) if !v.is_null() => v.as_u64().and_then(|h| u32::try_from(h).ok())
+                    .ok_or((-32602, "height must be a block height".to_string()))?,
+                _ => db.get_chain_height().map_err(|e| (-32603, e.to_string()))?,
+            };
+            let snap = tokio::task::spawn_blocking(move || db.state_snapshot(height))
+                .await
+                .map_err(|e| (-32603, format!("blocking task error: {e}")))?
+                .map_err(|e| match e {
+                    crate::node::db_rocksdb::DbError::Snapshot(msg) => (-32602, msg.to_string()),
+                    e => (-32603, e.to_string()),
+                })?;
+            let bytes = snap.to_bytes();
+            Ok(json!({
+                "height": snap.height,
+                "tip": hex::encode(snap.tip),
+                "hash": hex::encode(crate::crypto::hash::hash_sha3_256(&bytes)),
+                "accounts": snap.accounts.len(),
+                "size": bytes.len(),
+            }))
+        }
+
         "getbalance" => {
             let addr_str = params.get(0).and_then(|v| v.as_str()).unwrap_or("");
             let addr = if let Ok(a) = crate::crypto::keys::decode_address_string(addr_str) {
@@ -304,12 +911,15 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
                 }
             };
 
-            match state.db.get_account(&addr) {
+            match state.db.get_spendable_account(&addr) {
                 Ok(a) => {
                     let code = crate::crypto::hash::hash_sha3_256(&addr);
+                    let immature = a.immature_balance();
                     Ok(json!({
                         "balance_knots":    a.balance,
                         "balance_kot":      format!("{:.8}", a.balance as f64 / 1e8),
+                        "immature_knots":   immature,
+                        "immature_kot":     format!("{:.8}", immature as f64 / 1e8),
                         "nonce":            a.nonce,
                         "last_mined_height":a.last_mined_height,
                         "privacy_code":     hex::encode(&code[..8]),
@@ -319,6 +929,127 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
             }
         }
 
+        // Bitcoin-style float difficulty of the tip.
+        "getdifficulty" => {
+            let tip = state.db.get_tip()
+                .map_err(|e| (-32603, format!("db error: {e}")))?
+                .ok_or((-32603, "no chain tip".to_string()))?;
+            let header = state.db.get_block_header(&tip)
+                .map_err(|e| (-32603, format!("db error: {e}")))?
+                .ok_or((-32603, "tip block missing".to_string()))?;
+            Ok(json!(difficulty_from_target(&header.difficulty_target).0))
+        }
+
+        // Coins minted so far: block rewards plus referral bonuses.
+        // Merkle root over the sorted (address, balance, nonce) account set.
+        "getaccountsroot" => {
+            let db = state.db.clone();
+            tokio::task::spawn_blocking(move || -> Result<Value, String> {
+                let height = db.get_chain_height().map_err(|e| format!("db error: {e}"))?;
+                let tip = db.get_tip().map_err(|e| format!("db error: {e}"))?;
+                let root = crate::consensus::state::accounts_root(&db).map_err(|e| e.to_string())?;
+                Ok(json!({
+                    "height": height,
+                    "tip": tip.map(hex::encode),
+                    "root": hex::encode(root),
+                }))
+            })
+            .await
+            .map_err(|e| (-32603, format!("blocking task error: {e}")))?
+            .map_err(|e| (-32603, e))
+        }
+
+        "getsupply" => {
+            let db = state.db.clone();
+            let (height, supply) = tokio::task::spawn_blocking(move || -> Result<(u32, u64), String> {
+                let height = db.get_chain_height().map_err(|e| format!("db error: {e}"))?;
+                let supply = crate::consensus::state::total_supply(&db).map_err(|e| e.to_string())?;
+                Ok((height, supply))
+            })
+            .await
+            .map_err(|e| (-32603, format!("blocking task error: {e}")))?
+            .map_err(|e| (-32603, e))?;
+            Ok(json!({
+                "height":             height,
+                "total_supply_knots": supply,
+                "total_supply_kot":   format!("{:.8}", supply as f64 / 1e8),
+            }))
+        }
+
+        // Mining reward at one height, as `calculate_block_reward` computes it.
+        "getblocksubsidy" => {
+            let height = params.get(0).and_then(|v| v.as_u64()).ok_or((-32602, "height required".to_string()))?;
+            if height > u32::MAX as u64 {
+                return Err((-32602, format!("height must be at most {}", u32::MAX)));
+            }
+            Ok(json!(crate::consensus::chain::calculate_block_reward(height)))
+        }
+
+        // Sample the emission curve: [start_height, count, step].
+        "getrewardschedule" => {
+            let start = params.get(0).and_then(|v| v.as_u64()).ok_or((-32602, "start height required".to_string()))?;
+            let count = params.get(1).and_then(|v| v.as_u64()).ok_or((-32602, "count required".to_string()))?;
+            let step = params.get(2).and_then(|v| v.as_u64()).unwrap_or(1);
+            if count == 0 || count > MAX_REWARD_SCHEDULE_POINTS {
+                return Err((-32602, format!("count must be between 1 and {MAX_REWARD_SCHEDULE_POINTS}")));
+            }
+            if step == 0 {
+                return Err((-32602, "step must be at least 1".to_string()));
+            }
+            let last = (count - 1).checked_mul(step).and_then(|d| d.checked_add(start));
+            if last.is_none_or(|h| h > u32::MAX as u64) {
+                return Err((-32602, format!("schedule must end at or below height {}", u32::MAX)));
+            }
+            let points: Vec<Value> = (0..count)
+                .map(|i| {
+                    let height = start + i * step;
+                    json!({ "height": height, "reward_knots": crate::consensus::chain::calculate_block_reward(height) })
+                })
+                .collect();
+            Ok(json!(points))
+        }
+
+        "getblockchaininfo" => {
+            let db = state.db.clone();
+            let network = state.config.network.as_str();
+            let mut info = tokio::task::spawn_blocking(move || -> Result<Value, crate::node::db_rocksdb::DbError> {
+                let height = db.get_chain_height()?;
+                let tip = db.get_tip()?;
+                let target = match tip {
+                    Some(h) => db.get_block(&h)?.map(|b| b.difficulty_target),
+                    None => None,
+                };
+                let (difficulty, bits) = difficulty_from_target(&target.unwrap_or([0xFF; 32]));
+                let gov = db.get_governance_params()?;
+                Ok(json!({
+                    "chain":           network,
+                    "blocks":          height,
+                    "bestblockhash":   tip.map(hex::encode),
+                    "difficulty":      difficulty,
+                    "difficulty_bits": bits,
+                    // Median of the last 11 block timestamps, tip included.
+                    "mediantime":      crate::consensus::state::median_time_past(&db, height + 1),
+                    "size_on_disk":    db.size_on_disk()?,
+                    "prune_height":    db.get_prune_height()?,
+                    "cap_bps":         gov.cap_bps,
+                    "ponc_rounds":     gov.ponc_rounds,
+                    "mining_threads":  gov.mining_threads,
+                }))
+            })
+            .await
+            .map_err(|e| (-32603, format!("blocking task error: {e}")))?
+            .map_err(|e| (-32603, format!("db error: {e}")))?;
+
+            // Our height against the best height a peer has announced.
+            let height = info["blocks"].as_u64().unwrap_or(0);
+            let best_peer = state.peers.lock().await.values().map(|p| p.height as u64).max().unwrap_or(0);
+            let progress = if best_peer > height { height as f64 / best_peer as f64 } else { 1.0 };
+            info["verificationprogress"] = json!(progress);
+            info["last_tip_update"] = json!(state.last_tip_update.load(Ordering::Relaxed));
+            info["pruned"] = json!(state.config.prune_keep > 0 || info["prune_height"].as_u64().unwrap_or(0) > 0);
+            Ok(info)
+        }
+
         "getmininginfo" => {
             let height = state.db.get_chain_height().unwrap_or(0);
             let pool_size = state.mempool.lock().await.size();
@@ -341,16 +1072,19 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
                 "mempool":        pool_size,
                 "mining_threads": params.mining_threads,
                 "ponc_rounds":    params.ponc_rounds,
-                "network":        "mainnet",
+                "network":        state.config.network.as_str(),
                 "quantum_sec":    "Dilithium3 (NIST FIPS 204)",
             }))
         }
 
         "getmempoolinfo" => {
-            let pool_size = state.mempool.lock().await.size();
+            let pool = state.mempool.lock().await;
             Ok(json!({
-                "size": pool_size,
-                "bytes": 0,
+                "size": pool.size(),
+                "bytes": pool.total_bytes(),
+                "max_bytes": pool.max_bytes(),
+                // knots per byte, from the same integer-scaled rate used for ordering
+                "min_fee_per_byte": pool.min_fee_per_byte_scaled().map(|f| f as f64 / 10000.0),
             }))
         }
 
@@ -360,6 +1094,115 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
             Ok(json!(ids))
         }
 
+        "getmempoolentry" => {
+            let hex_str = params.get(0).and_then(|v| v.as_str()).ok_or((-32602, "txid required".to_string()))?;
+            let txid: [u8; 32] = hex::decode(hex_str)
+                .ok()
+                .and_then(|b| b.try_into().ok())
+                .ok_or((-32602, "txid must be 32 bytes of hex".to_string()))?;
+
+            let pool = state.mempool.lock().await;
+            let entry = pool.get_entry(&txid).ok_or((-32602, "transaction not in mempool".to_string()))?;
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            Ok(json!({
+                "txid": hex::encode(txid),
+                "fee": entry.tx.fee,
+                "size": entry.size,
+                "fee_per_byte": entry.fee_per_byte_scaled as f64 / 10000.0,
+                "sender": crate::crypto::keys::encode_address_string(&entry.tx.sender_address),
+                "recipient": crate::crypto::keys::encode_address_string(&entry.tx.recipient_address),
+                "amount": entry.tx.amount,
+                "nonce": entry.tx.nonce,
+                "time": entry.added_at,
+                "age_secs": now.saturating_sub(entry.added_at),
+            }))
+        }
+
+        // Confirmed transactions come with their block; pooled ones have
+        // `confirmations: 0` and no block.
+        // Depth of a transaction on the active chain: 0 while it is in the
+        // mempool, -1 if it can't be found (see getrawtransaction's limits).
+        "getconfirmations" => {
+            let hex_str = params.get(0).and_then(|v| v.as_str()).ok_or((-32602, "txid required".to_string()))?;
+            let txid: [u8; 32] = hex::decode(hex_str)
+                .ok()
+                .and_then(|b| b.try_into().ok())
+                .ok_or((-32602, "txid must be 32 bytes of hex".to_string()))?;
+
+            if state.mempool.lock().await.get(&txid).is_some() {
+                return Ok(json!(0));
+            }
+            let db = state.db.clone();
+            let depth = tokio::task::spawn_blocking(move || -> Result<i64, String> {
+                let Some((height, _, _)) = find_confirmed_tx(&db, &txid)? else {
+                    return Ok(-1);
+                };
+                let tip = db.get_chain_height().map_err(|e| e.to_string())?;
+                Ok((tip - height + 1) as i64)
+            })
+            .await
+            .map_err(|e| (-32603, format!("blocking task error: {e}")))?
+            .map_err(|e| (-32603, format!("db error: {e}")))?;
+            Ok(json!(depth))
+        }
+
+        "getrawtransaction" => {
+            let hex_str = params.get(0).and_then(|v| v.as_str()).ok_or((-32602, "txid required".to_string()))?;
+            let txid: [u8; 32] = hex::decode(hex_str)
+                .ok()
+                .and_then(|b| b.try_into().ok())
+                .ok_or((-32602, "txid must be 32 bytes of hex".to_string()))?;
+
+            let pooled = state.mempool.lock().await.get(&txid).cloned();
+            let (tx, block) = match pooled {
+                Some(tx) => (tx, None),
+                None => {
+                    let db = state.db.clone();
+                    let found = tokio::task::spawn_blocking(move || find_confirmed_tx(&db, &txid))
+                        .await
+                        .map_err(|e| (-32603, format!("blocking task error: {e}")))?
+                        .map_err(|e| (-32603, format!("db error: {e}")))?;
+                    match found {
+                        Some((height, hash, tx)) => (tx, Some((height, hash))),
+                        None if state.db.tx_index_enabled() => {
+                            return Err((-32602, "transaction not found".to_string()));
+                        }
+                        None => {
+                            return Err((-32602, format!(
+                                "transaction not in the mempool or the last {RAW_TX_SCAN_BLOCKS} blocks; \
+                                 restart with KNOTCOIN_TXINDEX=1 to look up older transactions"
+                            )));
+                        }
+                    }
+                }
+            };
+
+            let tip = state.db.get_chain_height().map_err(|e| (-32603, e.to_string()))?;
+            let raw = tx.to_bytes();
+            Ok(json!({
+                "txid": hex::encode(txid),
+                "hex": hex::encode(&raw),
+                "size": raw.len(),
+                "version": tx.version,
+                "sender": crate::crypto::keys::encode_address_string(&tx.sender_address),
+                "recipient": crate::crypto::keys::encode_address_string(&tx.recipient_address),
+                "amount": tx.amount,
+                "fee": tx.fee,
+                "nonce": tx.nonce,
+                "timestamp": tx.timestamp,
+                "referrer": tx.referrer_address.map(|a| crate::crypto::keys::encode_address_string(&a)),
+                "gov_data": tx.governance_data.map(hex::encode),
+                "proposal": tx.proposal.as_ref().map(hex::encode),
+                "memo": tx.memo.as_ref().map(hex::encode),
+                "block_height": block.map(|(h, _)| h),
+                "block_hash": block.map(|(_, b)| hex::encode(b)),
+                "confirmations": block.map_or(0, |(h, _)| tip - h + 1),
+            }))
+        }
+
         "sendrawtransaction" => {
             let hex_str = params.get(0).and_then(|v| v.as_str()).ok_or((-32602, "hex required".to_string()))?;
             let raw = hex::decode(hex_str).map_err(|_| (-32602, "invalid hex".to_string()))?;
@@ -369,7 +1212,10 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
             
             {
                 let mut pool = state.mempool.lock().await;
-                pool.add_transaction(stx.0.clone()).map_err(|e| (-32603, format!("mempool rejected: {e}")))?;
+                let pending_nonce = pool.highest_pending_nonce_for_sender(&stx.0.sender_address);
+                crate::consensus::state::check_transaction(&state.db, &stx.0, pending_nonce)
+                    .map_err(|e| mempool_rejection(e.into()))?;
+                pool.add_transaction(stx.0.clone()).map_err(mempool_rejection)?;
             }
 
             // Broadcast to P2P network
@@ -380,12 +1226,102 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
             Ok(json!(hex::encode(crate::net::mempool::Mempool::compute_txid_from_stored(&stx.0))))
         }
 
+        // Show what a raw transaction contains. No signature, state or
+        // mempool checks: it only has to deserialize.
+        "decoderawtransaction" => {
+            let hex_str = params.get(0).and_then(|v| v.as_str()).ok_or((-32602, "hex required".to_string()))?;
+            let raw = hex::decode(hex_str).map_err(|_| (-32602, "invalid hex".to_string()))?;
+            let (tx, _) = crate::node::db_common::StoredTransaction::from_bytes(&raw)
+                .map_err(|e| (-32602, format!("deserialization failed: {e}")))?;
+            Ok(json!({
+                "txid": hex::encode(crate::net::mempool::Mempool::compute_txid_from_stored(&tx)),
+                "size": raw.len(),
+                "version": tx.version,
+                "sender": crate::crypto::keys::encode_address_string(&tx.sender_address),
+                "recipient": crate::crypto::keys::encode_address_string(&tx.recipient_address),
+                "amount": tx.amount,
+                "fee": tx.fee,
+                "nonce": tx.nonce,
+                "timestamp": tx.timestamp,
+                "referrer": tx.referrer_address.map(|a| crate::crypto::keys::encode_address_string(&a)),
+                "gov_data": tx.governance_data.map(hex::encode),
+                "proposal": tx.proposal.as_ref().map(hex::encode),
+                "memo": tx.memo.as_ref().map(hex::encode),
+                "pubkey_len": tx.sender_pubkey.len(),
+                "signature_len": tx.signature.len(),
+            }))
+        }
+
+        // Dry run of sendrawtransaction: mempool admission plus the
+        // balance/nonce/referral rules of the next block. Nothing is stored or relayed.
+        "testmempoolaccept" => {
+            let hex_str = params.get(0).and_then(|v| v.as_str()).ok_or((-32602, "hex required".to_string()))?;
+            let raw = hex::decode(hex_str).map_err(|_| (-32602, "invalid hex".to_string()))?;
+            let (stx, _) = crate::node::db_common::StoredTransaction::from_bytes(&raw)
+                .map_err(|e| (-32602, format!("deserialization failed: {e}")))?;
+
+            let (checked, pending_nonce) = {
+                let pool = state.mempool.lock().await;
+                (pool.check_transaction(&stx), pool.highest_pending_nonce_for_sender(&stx.sender_address))
+            };
+            let reject_reason = match checked {
+                Err(e) => Some(format!("mempool rejected: {e}")),
+                Ok(_) => crate::consensus::state::check_transaction(&state.db, &stx, pending_nonce)
+                    .err()
+                    .map(|e| e.to_string()),
+            };
+
+            Ok(json!({
+                "txid": hex::encode(crate::net::mempool::Mempool::compute_txid_from_stored(&stx)),
+                "accepted": reject_reason.is_none(),
+                "reject_reason": reject_reason,
+                "fee": stx.fee,
+                "size": raw.len(),
+            }))
+        }
+
+        // Which pending transaction a raw tx collides with (same sender and
+        // nonce) and what fee would replace it. Nothing is stored or relayed.
+        "getmempoolconflicts" => {
+            let hex_str = params.get(0).and_then(|v| v.as_str()).ok_or((-32602, "hex required".to_string()))?;
+            let raw = hex::decode(hex_str).map_err(|_| (-32602, "invalid hex".to_string()))?;
+            let (stx, _) = crate::node::db_common::StoredTransaction::from_bytes(&raw)
+                .map_err(|e| (-32602, format!("deserialization failed: {e}")))?;
+
+            let pool = state.mempool.lock().await;
+            let conflicts: Vec<Value> = pool.conflicting_entry(&stx).into_iter().map(|entry| {
+                let min_fee = crate::net::mempool::Mempool::min_replacement_fee(entry.tx.fee);
+                json!({
+                    "txid": hex::encode(entry.txid),
+                    "fee": entry.tx.fee,
+                    "size": entry.size,
+                    "fee_per_byte": entry.fee_per_byte_scaled as f64 / 10000.0,
+                    "nonce": entry.tx.nonce,
+                    "min_replacement_fee": min_fee,
+                    "fee_bump_needed": min_fee.saturating_sub(stx.fee),
+                })
+            }).collect();
+            Ok(json!({
+                "txid": hex::encode(crate::net::mempool::Mempool::compute_txid_from_stored(&stx)),
+                "fee": stx.fee,
+                "conflicts": conflicts,
+                "reject_reason": pool.check_transaction(&stx).err().map(|e| e.to_string()),
+            }))
+        }
+
         "wallet_send" => {
             let mnemonic = params.get(0).and_then(|v| v.as_str()).ok_or((-32602, "mnemonic required".to_string()))?;
             ensure_single_wallet_identity(state, mnemonic).await?;
             let recipient_str = params.get(1).and_then(|v| v.as_str()).ok_or((-32602, "recipient required".to_string()))?;
             let amount_kot = params.get(2).and_then(|v| v.as_f64()).ok_or((-32602, "amount required".to_string()))?;
-            let gov_data_hex = params.get(3).and_then(|v| v.as_str());
+            let amount_knots = crate::primitives::transaction::kot_to_knots(amount_kot)
+                .map_err(|e| (-32602, format!("invalid amount: {e}")))?;
+            let gov_data_hex = params.get(3).and_then(|v| v.as_str()).filter(|s| !s.is_empty());
+            // Optional memo text (e.g. an order reference), carried as its UTF-8 bytes
+            let memo = params.get(4).and_then(|v| v.as_str()).map(|m| m.as_bytes().to_vec());
+            if memo.as_ref().is_some_and(|m| m.len() > crate::primitives::transaction::MAX_MEMO_BYTES) {
+                return Err((-32602, format!("memo must be at most {} bytes", crate::primitives::transaction::MAX_MEMO_BYTES)));
+            }
 
             // 1. Derive Keys
             let (pk, sk) = cached_keypair_for_mnemonic(state, mnemonic).await;
@@ -400,11 +1336,12 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
             let is_self_tx = sender_addr == recipient_addr;
 
             // 3. Get Nonce & Balance
-            let acc = state.db.get_account(&sender_addr).map_err(|e| (-32603, format!("db error: {e}")))?;
-            let amount_knots = (amount_kot * 1e8) as u64;
-            
-            if acc.balance < amount_knots + 1 { // 1 knot min fee
-                return Err((-32603, "insufficient balance".to_string()));
+            let acc = state.db.get_spendable_account(&sender_addr).map_err(|e| (-32603, format!("db error: {e}")))?;
+
+            let fee = state.mempool.lock().await.min_relay_fee();
+            if acc.balance < amount_knots.saturating_add(fee) {
+                let code = mempool_error_code(&crate::net::mempool::MempoolError::BalanceTooLow);
+                return Err((code, "insufficient balance".to_string()));
             }
 
             let gov_data = if let Some(hex) = gov_data_hex {
@@ -417,17 +1354,32 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
                 None
             };
 
-            // 4. Construct Transaction
-            let pending_nonce = state.mempool.lock().await.highest_pending_nonce_for_sender(&sender_addr);
-            let next_nonce = pending_nonce.unwrap_or(acc.nonce).max(acc.nonce) + 1;
+            // 4. Construct Transaction. A send behind a nonce gap would sit in
+            // the mempool until the gap is filled, so refuse it instead.
+            let next_nonce = {
+                let pool = state.mempool.lock().await;
+                let next = pool.next_contiguous_nonce(&sender_addr, acc.nonce);
+                if let Some(highest) = pool.highest_pending_nonce_for_sender(&sender_addr)
+                    && highest > next
+                {
+                    let gap = crate::net::mempool::MempoolError::NonceGap { expected: next, got: highest + 1 };
+                    return Err((mempool_error_code(&gap), format!(
+                        "nonce gap: confirmed nonce is {}, nothing is pending at nonce {next} but nonce {highest} is; \
+                         new sends would not confirm until the gap is filled. Wait for the pending txs to expire, \
+                         or use wallet_bump_fee on the ones still queued",
+                        acc.nonce
+                    )));
+                }
+                next
+            };
 
             let mut tx = crate::primitives::transaction::Transaction {
-                version: 1,
+                version: if memo.is_some() { crate::primitives::transaction::TX_VERSION_MEMO } else { 1 },
                 sender_address: sender_addr,
                 sender_pubkey: pk,
                 recipient_address: recipient_addr,
                 amount: amount_knots,
-                fee: 1, // Minimum fee
+                fee,
                 nonce: next_nonce,
                 timestamp: std::time::SystemTime::now()
                     .duration_since(std::time::UNIX_EPOCH)
@@ -435,6 +1387,8 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
                     .as_secs(),
                 referrer_address: None,
                 governance_data: gov_data,
+                proposal: None,
+                memo,
                 signature: crate::crypto::dilithium::Signature([0u8; 3309]),
             };
 
@@ -454,12 +1408,14 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
                 timestamp: tx.timestamp,
                 referrer_address: tx.referrer_address,
                 governance_data: tx.governance_data,
+                proposal: tx.proposal.clone(),
+                memo: tx.memo.clone(),
                 signature: tx.signature.0.to_vec(),
             };
             let raw = stx.to_bytes();
             {
                 let mut pool = state.mempool.lock().await;
-                pool.add_transaction(stx).map_err(|e| (-32603, format!("mempool rejected: {e}")))?;
+                pool.add_transaction(stx).map_err(mempool_rejection)?;
             }
 
             let _ = state.p2p_tx.send(crate::net::node::P2pCommand::Broadcast(
@@ -473,6 +1429,253 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
             }))
         }
 
+        // Pay several recipients at once: [mnemonic, {recipient: amount_kot, ...}].
+        // Each payment is its own transaction; nonces continue from the highest
+        // pending one. A rejected payment is reported and its nonce goes to the
+        // next recipient, so failures never leave a gap.
+        "sendmany" => {
+            let mnemonic = params.get(0).and_then(|v| v.as_str()).ok_or((-32602, "mnemonic required".to_string()))?;
+            ensure_single_wallet_identity(state, mnemonic).await?;
+            let payments = params.get(1).and_then(|v| v.as_object())
+                .ok_or((-32602, "recipients must be an object of address to amount".to_string()))?;
+            if payments.is_empty() {
+                return Err((-32602, "no recipients".to_string()));
+            }
+            if payments.len() > MAX_SENDMANY_RECIPIENTS {
+                return Err((-32602, format!("at most {MAX_SENDMANY_RECIPIENTS} recipients per call")));
+            }
+
+            let (pk, sk) = cached_keypair_for_mnemonic(state, mnemonic).await;
+            let sender_addr = crate::crypto::keys::derive_address(&pk);
+            let acc = state.db.get_spendable_account(&sender_addr).map_err(|e| (-32603, format!("db error: {e}")))?;
+
+            let mut sent = Vec::new();
+            let mut failed = Vec::new();
+            // Held for the whole batch so no other send can take one of our nonces.
+            let mut pool = state.mempool.lock().await;
+            let fee = pool.min_relay_fee();
+            let mut nonce = pool.next_contiguous_nonce(&sender_addr, acc.nonce);
+            if let Some(highest) = pool.highest_pending_nonce_for_sender(&sender_addr)
+                && highest > nonce
+            {
+                return Err((-32603, format!(
+                    "nonce gap: confirmed nonce is {}, nothing is pending at nonce {nonce} but nonce {highest} is; \
+                     new sends would not confirm until the gap is filled",
+                    acc.nonce
+                )));
+            }
+            let mut spent: u64 = 0;
+
+            for (recipient_str, amount) in payments {
+                let prepared = amount.as_f64()
+                    .ok_or_else(|| "amount must be a number".to_string())
+                    .and_then(|kot| crate::primitives::transaction::kot_to_knots(kot).map_err(|e| format!("invalid amount: {e}")))
+                    .and_then(|knots| {
+                        crate::crypto::keys::decode_address_string(recipient_str)
+                            .map(|addr| (addr, knots))
+                            .map_err(|e| format!("invalid recipient: {e}"))
+                    })
+                    .and_then(|(addr, knots)| {
+                        let total = spent.saturating_add(knots).saturating_add(fee);
+                        if acc.balance < total { Err("insufficient balance".to_string()) } else { Ok((addr, knots, total)) }
+                    });
+                let (recipient_addr, amount_knots, total) = match prepared {
+                    Ok(p) => p,
+                    Err(error) => {
+                        failed.push(json!({ "recipient": recipient_str, "error": error }));
+                        continue;
+                    }
+                };
+
+                let mut tx = crate::primitives::transaction::Transaction {
+                    version: 1,
+                    sender_address: sender_addr,
+                    sender_pubkey: pk,
+                    recipient_address: recipient_addr,
+                    amount: amount_knots,
+                    fee,
+                    nonce,
+                    timestamp: std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs(),
+                    referrer_address: None,
+                    governance_data: None,
+                    proposal: None,
+                    memo: None,
+                    signature: crate::crypto::dilithium::Signature([0u8; 3309]),
+                };
+                let hash = tx.signing_hash();
+                tx.signature = crate::crypto::dilithium::sign(&hash, &sk);
+
+                let stx = crate::node::db_common::StoredTransaction {
+                    version: tx.version,
+                    sender_address: tx.sender_address,
+                    sender_pubkey: tx.sender_pubkey.0.to_vec(),
+                    recipient_address: tx.recipient_address,
+                    amount: tx.amount,
+                    fee: tx.fee,
+                    nonce: tx.nonce,
+                    timestamp: tx.timestamp,
+                    referrer_address: None,
+                    governance_data: None,
+                    proposal: None,
+                    memo: None,
+                    signature: tx.signature.0.to_vec(),
+                };
+                let raw = stx.to_bytes();
+                if let Err(e) = pool.add_transaction(stx) {
+                    failed.push(json!({ "recipient": recipient_str, "error": format!("mempool rejected: {e}") }));
+                    continue;
+                }
+                let _ = state.p2p_tx.send(crate::net::node::P2pCommand::Broadcast(
+                    crate::net::protocol::NetworkMessage::Tx(raw)
+                ));
+                sent.push(json!({
+                    "recipient": recipient_str,
+                    "amount": format!("{:.8}", amount_knots as f64 / 1e8),
+                    "txid": hex::encode(tx.txid()),
+                    "nonce": nonce,
+                    "fee": fee
+                }));
+                spent = total;
+                nonce += 1;
+            }
+
+            Ok(json!({ "sent": sent, "failed": failed }))
+        }
+
+        // Re-sign a pending wallet tx with a higher fee so it replaces the
+        // original. Without an explicit fee, the minimum accepted bump is used.
+        "wallet_bump_fee" => {
+            let mnemonic = params.get(0).and_then(|v| v.as_str()).ok_or((-32602, "mnemonic required".to_string()))?;
+            ensure_single_wallet_identity(state, mnemonic).await?;
+            let nonce = params.get(1).and_then(|v| v.as_u64()).ok_or((-32602, "nonce required".to_string()))?;
+
+            let (pk, sk) = cached_keypair_for_mnemonic(state, mnemonic).await;
+            let sender_addr = crate::crypto::keys::derive_address(&pk);
+
+            let stuck = state.mempool.lock().await
+                .get_by_sender_nonce(&sender_addr, nonce)
+                .cloned()
+                .ok_or((-32602, format!("no pending transaction with nonce {nonce}")))?;
+            let min_fee = crate::net::mempool::Mempool::min_replacement_fee(stuck.fee);
+            let fee = params.get(2).and_then(|v| v.as_u64()).unwrap_or(min_fee);
+            if fee < min_fee {
+                return Err((-32602, format!("fee must be at least {min_fee} knots (pending tx pays {})", stuck.fee)));
+            }
+
+            let acc = state.db.get_spendable_account(&sender_addr).map_err(|e| (-32603, format!("db error: {e}")))?;
+            if acc.balance < stuck.amount.saturating_add(fee) {
+                return Err((-32603, "insufficient balance".to_string()));
+            }
+
+            let mut tx = crate::primitives::transaction::Transaction::try_from(&stuck)
+                .map_err(|e| (-32603, format!("pending tx unreadable: {e}")))?;
+            let replaced = tx.txid();
+            tx.fee = fee;
+            let hash = tx.signing_hash();
+            tx.signature = crate::crypto::dilithium::sign(&hash, &sk);
+
+            let stx = crate::node::db_common::StoredTransaction {
+                fee,
+                signature: tx.signature.0.to_vec(),
+                ..stuck
+            };
+            let raw = stx.to_bytes();
+            {
+                let mut pool = state.mempool.lock().await;
+                pool.add_transaction(stx).map_err(mempool_rejection)?;
+            }
+
+            let _ = state.p2p_tx.send(crate::net::node::P2pCommand::Broadcast(
+                crate::net::protocol::NetworkMessage::Tx(raw)
+            ));
+
+            Ok(json!({
+                "txid": hex::encode(tx.txid()),
+                "replaced": hex::encode(replaced),
+                "nonce": nonce,
+                "fee": fee
+            }))
+        }
+
+        // Publish a governance proposal: [mnemonic, title, param, value].
+        // Votes then reference the returned proposal_hash via wallet_send.
+        "wallet_propose" => {
+            use crate::consensus::governance::{GovParam, Proposal, TX_VERSION_PROPOSAL};
+            let mnemonic = params.get(0).and_then(|v| v.as_str()).ok_or((-32602, "mnemonic required".to_string()))?;
+            ensure_single_wallet_identity(state, mnemonic).await?;
+            let title = params.get(1).and_then(|v| v.as_str()).ok_or((-32602, "title required".to_string()))?;
+            let param_name = params.get(2).and_then(|v| v.as_str()).ok_or((-32602, "param required".to_string()))?;
+            let param = GovParam::from_name(param_name)
+                .ok_or((-32602, "param must be cap_bps, ponc_rounds or mining_threads".to_string()))?;
+            let value = params.get(3).and_then(|v| v.as_u64()).ok_or((-32602, "value required".to_string()))?;
+            let proposal = Proposal::new(title, param, value).map_err(|e| (-32602, e.to_string()))?;
+
+            let (pk, sk) = cached_keypair_for_mnemonic(state, mnemonic).await;
+            let sender_addr = crate::crypto::keys::derive_address(&pk);
+            let acc = state.db.get_spendable_account(&sender_addr).map_err(|e| (-32603, format!("db error: {e}")))?;
+            let fee = state.mempool.lock().await.min_relay_fee();
+            if acc.balance < fee {
+                return Err((-32603, "insufficient balance".to_string()));
+            }
+            let pending_nonce = state.mempool.lock().await.highest_pending_nonce_for_sender(&sender_addr);
+            let next_nonce = pending_nonce.unwrap_or(acc.nonce).max(acc.nonce) + 1;
+
+            let mut tx = crate::primitives::transaction::Transaction {
+                version: TX_VERSION_PROPOSAL,
+                sender_address: sender_addr,
+                sender_pubkey: pk,
+                recipient_address: sender_addr,
+                amount: 0,
+                fee,
+                nonce: next_nonce,
+                timestamp: std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+                referrer_address: None,
+                governance_data: None,
+                proposal: Some(proposal.to_bytes()),
+                memo: None,
+                signature: crate::crypto::dilithium::Signature([0u8; 3309]),
+            };
+            let hash = tx.signing_hash();
+            tx.signature = crate::crypto::dilithium::sign(&hash, &sk);
+
+            let stx = crate::node::db_common::StoredTransaction {
+                version: tx.version,
+                sender_address: tx.sender_address,
+                sender_pubkey: tx.sender_pubkey.0.to_vec(),
+                recipient_address: tx.recipient_address,
+                amount: tx.amount,
+                fee: tx.fee,
+                nonce: tx.nonce,
+                timestamp: tx.timestamp,
+                referrer_address: None,
+                governance_data: None,
+                proposal: tx.proposal.clone(),
+                memo: tx.memo.clone(),
+                signature: tx.signature.0.to_vec(),
+            };
+            let raw = stx.to_bytes();
+            {
+                let mut pool = state.mempool.lock().await;
+                pool.add_transaction(stx).map_err(mempool_rejection)?;
+            }
+
+            let _ = state.p2p_tx.send(crate::net::node::P2pCommand::Broadcast(
+                crate::net::protocol::NetworkMessage::Tx(raw)
+            ));
+
+            Ok(json!({
+                "txid": hex::encode(tx.txid()),
+                "proposal_hash": hex::encode(proposal.hash()),
+                "nonce": tx.nonce,
+            }))
+        }
+
         "wallet_register_referral" => {
             let mnemonic = params.get(0).and_then(|v| v.as_str()).ok_or((-32602, "mnemonic required".to_string()))?;
             ensure_single_wallet_identity(state, mnemonic).await?;
@@ -505,14 +1708,15 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
                     .map_err(|e| (-32602, format!("invalid referrer: {e}")))?
             };
 
-            let acc = state.db.get_account(&sender_addr).map_err(|e| (-32603, format!("db error: {e}")))?;
+            let acc = state.db.get_spendable_account(&sender_addr).map_err(|e| (-32603, format!("db error: {e}")))?;
             
             if acc.nonce != 0 {
                 return Err((-32603, "wallet already active, referral must be first tx".to_string()));
             }
 
-            if acc.balance < 1 {
-                return Err((-32603, "insufficient balance for 1 knot fee".to_string()));
+            let fee = state.mempool.lock().await.min_relay_fee();
+            if acc.balance < fee {
+                return Err((-32603, format!("insufficient balance for {fee} knot fee")));
             }
 
             let mut tx = crate::primitives::transaction::Transaction {
@@ -521,7 +1725,7 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
                 sender_pubkey: pk,
                 recipient_address: sender_addr, // send zero to self
                 amount: 0,
-                fee: 1, // Minimum fee
+                fee,
                 nonce: 1, // Must be exactly 1 to trigger state.rs referrer registration
                 timestamp: std::time::SystemTime::now()
                     .duration_since(std::time::UNIX_EPOCH)
@@ -529,6 +1733,8 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
                     .as_secs(),
                 referrer_address: Some(referrer_addr),
                 governance_data: None,
+                proposal: None,
+                memo: None,
                 signature: crate::crypto::dilithium::Signature([0u8; 3309]),
             };
 
@@ -546,13 +1752,15 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
                 timestamp: tx.timestamp,
                 referrer_address: tx.referrer_address,
                 governance_data: tx.governance_data,
+                proposal: tx.proposal.clone(),
+                memo: tx.memo.clone(),
                 signature: tx.signature.0.to_vec(),
             };
             
             let raw = stx.to_bytes();
             {
                 let mut pool = state.mempool.lock().await;
-                pool.add_transaction(stx).map_err(|e| (-32603, format!("mempool rejected: {e}")))?;
+                pool.add_transaction(stx).map_err(mempool_rejection)?;
             }
 
             let _ = state.p2p_tx.send(crate::net::node::P2pCommand::Broadcast(
@@ -570,6 +1778,7 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
             if count == 0 || count > 500 {
                 return Err((-32602, "count must be between 1 and 500".to_string()));
             }
+            ensure_clock_ok_for_mining(state).await?;
 
             let addr_str = params.get(1).and_then(|v| v.as_str()).unwrap_or("");
             let miner = if let Ok(a) = crate::crypto::keys::decode_address_string(addr_str) {
@@ -592,6 +1801,7 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
                     _ => return Err((-32602, "invalid miner address".to_string())),
                 }
             };
+            ensure_miner_allowed(state, &miner)?;
 
             let referrer = params.get(2).and_then(|v| v.as_str()).and_then(|mut s| {
                 if s.to_uppercase().starts_with("KOT") {
@@ -647,6 +1857,7 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
 
                 if let Some((block, hash)) = result
                     && crate::consensus::state::apply_block(&state.db, &block).is_ok() {
+                    crate::rpc::ws::notify_block(&state.block_events, &state.db, &block);
                     // Remove confirmed txs from mempool to avoid stale sender+nonce entries.
                     // This also prevents Replace-by-Fee checks from rejecting subsequent txs.
                     let confirmed: Vec<[u8; 32]> = block
@@ -661,27 +1872,77 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
             Ok(json!(hashes))
         }
 
+        // Work for an external miner. Optional param[0] sets the payout
+        // address (pools assign their own); it isn't covered by the merkle
+        // root, so a template can be re-targeted without being rebuilt.
+        "getblocktemplate" => {
+            let miner = match params.get(0).and_then(|v| v.as_str()) {
+                Some(s) => crate::crypto::keys::decode_address_string(s)
+                    .ok()
+                    .or_else(|| {
+                        let b = hex::decode(s).ok()?;
+                        <[u8; 32]>::try_from(b.as_slice()).ok()
+                    })
+                    .ok_or((-32602, "invalid miner address".to_string()))?,
+                None => [0u8; 32],
+            };
+
+            let txs = state.mempool.lock().await.get_top_transactions(crate::miner::miner::MAX_TXS);
+            let template = crate::miner::miner::block_template(&state.db, txs, &miner)
+                .ok_or((-32603, "no chain tip".to_string()))?;
+            let params_gov = state.db.get_governance_params()
+                .map_err(|e| (-32603, format!("db error: {e}")))?;
+            let height = u32::from_le_bytes(template.block_height);
+
+            Ok(json!({
+                "version":       u32::from_be_bytes(template.version),
+                "previous_hash": hex::encode(template.previous_hash),
+                "height":        height,
+                "target":        hex::encode(template.difficulty_target),
+                "timestamp":     u32::from_le_bytes(template.timestamp),
+                "mintime":       crate::consensus::state::median_time_past(&state.db, height).map(|m| m + 1),
+                "merkle_root":   hex::encode(template.merkle_root),
+                "ponc_rounds":   params_gov.ponc_rounds,
+                "miner_address": hex::encode(template.miner_address),
+                "transactions":  template.tx_data.iter().map(|tx| hex::encode(tx.to_bytes())).collect::<Vec<_>>(),
+                "header_prefix": hex::encode(template.header_prefix()),
+                "block":         hex::encode(template.to_bytes()),
+            }))
+        }
+
+        "submitblock" => {
+            let hex_str = params.get(0).and_then(|v| v.as_str()).ok_or((-32602, "hex required".to_string()))?;
+            let raw = hex::decode(hex_str).map_err(|_| (-32602, "invalid hex".to_string()))?;
+            let block = crate::node::db_common::StoredBlock::from_bytes(&raw)
+                .map_err(|e| (-32602, format!("deserialization failed: {e}")))?;
+            let hash = block_hash(&block);
+
+            if state.db.get_block(&hash).map_err(|e| (-32603, format!("db error: {e}")))?.is_some() {
+                return Err((-32602, "duplicate block".to_string()));
+            }
+            crate::consensus::state::verify_block_pow(&block, &state.db)
+                .map_err(|e| (-32602, format!("rejected: {e}")))?;
+            crate::consensus::state::apply_block(&state.db, &block)
+                .map_err(|e| (-32602, format!("rejected: {e}")))?;
+            crate::rpc::ws::notify_block(&state.block_events, &state.db, &block);
+
+            let confirmed: Vec<[u8; 32]> = block
+                .tx_data
+                .iter()
+                .map(crate::net::mempool::Mempool::compute_txid_from_stored)
+                .collect();
+            state.mempool.lock().await.remove_confirmed(&confirmed);
+
+            let _ = state.p2p_tx.send(crate::net::node::P2pCommand::Broadcast(
+                crate::net::protocol::NetworkMessage::Blocks(vec![raw])
+            ));
+            info!("[rpc] submitblock accepted {} at height {}", hex::encode(hash), u32::from_le_bytes(block.block_height));
+            Ok(json!(hex::encode(hash)))
+        }
+
         "getreferralinfo" => {
             let addr_str = params.get(0).and_then(|v| v.as_str()).unwrap_or("");
-            let addr = if let Ok(a) = crate::crypto::keys::decode_address_string(addr_str) {
-                a
-            } else {
-                let hex_part = if addr_str.to_lowercase().starts_with("kot1") {
-                    &addr_str[4..]
-                } else if addr_str.to_lowercase().starts_with("kot") {
-                    &addr_str[3..]
-                } else {
-                    addr_str
-                };
-                match hex::decode(hex_part) {
-                    Ok(b) if b.len() == 32 => {
-                        let mut a = [0u8; 32];
-                        a.copy_from_slice(&b);
-                        a
-                    }
-                    _ => return Err((-32602, "invalid address".to_string())),
-                }
-            };
+            let addr = parse_address_param(addr_str).ok_or((-32602, "invalid address".to_string()))?;
 
             match state.db.get_account(&addr) {
                 Ok(a) => {
@@ -706,6 +1967,24 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
             }
         }
 
+        // Who an address referred, and who they referred, down to `depth`
+        // levels (default 1). Referees are shown by privacy code only.
+        "getreferraltree" => {
+            let addr_str = params.get(0).and_then(|v| v.as_str()).unwrap_or("");
+            let addr = parse_address_param(addr_str).ok_or((-32602, "invalid address".to_string()))?;
+            let depth = match params.get(1) {
+                None | Some(Value::Null) => 1,
+                Some(v) => match v.as_u64() {
+                    Some(d) if (1..=MAX_REFERRAL_TREE_DEPTH as u64).contains(&d) => d as u32,
+                    _ => return Err((-32602, format!("depth must be 1..={MAX_REFERRAL_TREE_DEPTH}"))),
+                },
+            };
+            let db = state.db.clone();
+            tokio::task::spawn_blocking(move || referral_tree(&db, &addr, depth))
+                .await
+                .map_err(|e| (-32603, format!("blocking task error: {e}")))?
+        }
+
         "getgovernanceinfo" => {
             let addr_str = params.get(0).and_then(|v| v.as_str()).unwrap_or("");
             let addr = if let Ok(a) = crate::crypto::keys::decode_address_string(addr_str) {
@@ -762,12 +2041,12 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
 
             match state.db.get_governance_tally(&hash) {
                 Ok(tally) => {
-                    let is_passed = tally >= 5100;
+                    let is_passed = tally >= GOVERNANCE_THRESHOLD_BPS;
                     Ok(json!({
                         "proposal_hash":       hex::encode(hash),
                         "total_weight_bps":    tally,
                         "total_weight_pct":    format!("{:.2}%", tally as f64 / 100.0),
-                        "threshold_bps":       5100,
+                        "threshold_bps":       GOVERNANCE_THRESHOLD_BPS,
                         "threshold_pct":       "51.0%",
                         "is_passed":           is_passed,
                     }))
@@ -776,6 +2055,32 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
             }
         }
 
+        // A proposal published on chain, with its current tally.
+        "getgovernanceproposal" => {
+            let prop_str = params.get(0).and_then(|v| v.as_str()).unwrap_or("");
+            let raw = hex::decode(prop_str)
+                .map_err(|_| (-32602, "invalid proposal hash".to_string()))?;
+            let hash = <[u8; 32]>::try_from(raw.as_slice())
+                .map_err(|_| (-32602, "proposal hash must be 32 bytes".to_string()))?;
+
+            let stored = state.db.get_governance_proposal(&hash)
+                .map_err(|e| (-32603, format!("db error: {e}")))?
+                .ok_or((-32602, "proposal not found".to_string()))?;
+            let tally = state.db.get_governance_tally(&hash)
+                .map_err(|e| (-32603, format!("db error: {e}")))?;
+            Ok(json!({
+                "proposal_hash":    hex::encode(hash),
+                "title":            stored.proposal.title,
+                "param":            stored.proposal.param.name(),
+                "value":            stored.proposal.value,
+                "proposer":         crate::crypto::keys::encode_address_string(&stored.proposer),
+                "height":           stored.height,
+                "total_weight_bps": tally,
+                "threshold_bps":    GOVERNANCE_THRESHOLD_BPS,
+                "is_passed":        tally >= GOVERNANCE_THRESHOLD_BPS,
+            }))
+        }
+
         "get_all_miners" => {
             // Cache miners data for 5 seconds to reduce DB load (scanning blockchain is expensive)
             static MINERS_CACHE: std::sync::OnceLock<std::sync::Mutex<(serde_json::Value, u64)>> = std::sync::OnceLock::new();
@@ -826,7 +2131,7 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
                 let last_h = miner_last_height.get(addr).copied().unwrap_or(0);
                 
                 // Get balance from account state
-                let acc = state.db.get_account(addr).unwrap_or_default();
+                let acc = state.db.get_spendable_account(addr).unwrap_or_default();
                 let referrer_str = acc.referrer.map(|r| crate::crypto::keys::encode_address_string(&r));
                 
                 // Get timestamp from last mined block
@@ -890,23 +2195,50 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
             Ok(result)
         }
 
+        // Pending fee levels, best-paying first, with the block space
+        // (transactions and bytes) taken by that level and every better one.
+        "feehistogram" => {
+            let pool = state.mempool.lock().await;
+            let buckets: Vec<Value> = pool
+                .fee_histogram()
+                .into_iter()
+                .map(|b| json!({
+                    "fee_per_byte_scaled": b.fee_per_byte_scaled,
+                    "fee_per_byte": b.fee_per_byte_scaled as f64 / 10000.0,
+                    "count": b.count,
+                    "size": b.size,
+                    "cumulative_count": b.cumulative_count,
+                    "cumulative_size": b.cumulative_size,
+                }))
+                .collect();
+            Ok(json!(buckets))
+        }
+
+        // [conf_target, tx_size]: fee for a transaction to be among the
+        // first `conf_target` blocks' worth (MAX_TXS each) of the mempool.
         "estimatefee" => {
-            let tx_size = params.get(0).and_then(|v| v.as_u64()).unwrap_or(5400) as u64;
+            use crate::miner::miner::MAX_TXS;
+            let conf_target = params.get(0).and_then(|v| v.as_u64()).unwrap_or(DEFAULT_CONF_TARGET);
+            if !(1..=MAX_CONF_TARGET).contains(&conf_target) {
+                return Err((-32602, format!("conf_target must be between 1 and {MAX_CONF_TARGET}")));
+            }
+            let tx_size = params.get(1).and_then(|v| v.as_u64()).unwrap_or(5400);
             let pool = state.mempool.lock().await;
-            let pool_size = pool.size();
-            let base_fee = 1u64;
-            let congestion_fee = if pool_size > 10 {
-                (pool_size as u64 - 10) / 3
-            } else {
-                0
+            let base_fee = pool.min_relay_fee();
+            let fee_for = |rate: Option<u64>| {
+                let needed = rate.map_or(0, |r| (r as u128 * tx_size as u128).div_ceil(10000));
+                base_fee.max(needed.min(u64::MAX as u128) as u64)
             };
-            let recommended = base_fee + congestion_fee;
-            let fast = recommended + (recommended / 2).max(1);
+            let rate = pool.fee_rate_for_capacity(conf_target as usize * MAX_TXS);
+            let recommended = fee_for(rate);
+            let fast = fee_for(pool.fee_rate_for_capacity(MAX_TXS)).max(recommended);
             Ok(json!({
+                "conf_target": conf_target,
+                "fee_per_byte_scaled": rate.unwrap_or(0),
                 "recommended_fee_knots": recommended,
                 "fast_fee_knots": fast,
                 "tx_size_bytes": tx_size,
-                "mempool_size": pool_size,
+                "mempool_size": pool.size(),
             }))
         }
 
@@ -935,59 +2267,91 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
                     Ok(Some(b)) => b,
                     _ => continue,
                 };
-                let block_height = u32::from_le_bytes(block.block_height);
-                let block_time = u32::from_le_bytes(block.timestamp);
-
-                if block.miner_address == addr {
-                    let reward = crate::consensus::chain::calculate_block_reward(block_height as u64);
-                    txs.push(json!({
-                        "type": "mining_reward",
-                        "address": crate::crypto::keys::encode_address_string(&block.miner_address),
-                        "amount_knots": reward,
-                        "amount_kot": format!("{:.8}", reward as f64 / 1e8),
-                        "fee_knots": 0,
-                        "block_height": block_height,
-                        "timestamp": block_time,
-                    }));
-                }
+                let confirmations = chain_height - h + 1;
+                txs.extend(address_history_entries(&block, &addr).into_iter().map(|mut e| {
+                    e["confirmations"] = json!(confirmations);
+                    e
+                }));
+            }
 
-                for tx in &block.tx_data {
-                    if tx.sender_address == addr {
-                        txs.push(json!({
-                            "type": "sent",
-                            "address": crate::crypto::keys::encode_address_string(&tx.recipient_address),
-                            "amount_knots": tx.amount,
-                            "amount_kot": format!("{:.8}", tx.amount as f64 / 1e8),
-                            "fee_knots": tx.fee,
-                            "block_height": block_height,
-                            "timestamp": block_time,
-                            "nonce": tx.nonce,
-                        }));
-                    } else if tx.recipient_address == addr {
-                        txs.push(json!({
-                            "type": "received",
-                            "address": crate::crypto::keys::encode_address_string(&tx.sender_address),
-                            "amount_knots": tx.amount,
-                            "amount_kot": format!("{:.8}", tx.amount as f64 / 1e8),
-                            "fee_knots": tx.fee,
-                            "block_height": block_height,
-                            "timestamp": block_time,
-                            "nonce": tx.nonce,
-                        }));
-                    }
+            Ok(json!({
+                "address": addr_str,
+                "transactions": txs,
+                "count": txs.len(),
+            }))
+        }
+
+        // Full history for an address, oldest first, paged by block height.
+        // Params: [address, from_height?, cursor?]. Pass the returned
+        // `next_cursor` back to continue; it is null once the tip is reached.
+        "getaddresstransactions" => {
+            let addr_str = params.get(0).and_then(|v| v.as_str()).unwrap_or("");
+            let addr = crate::crypto::keys::decode_address_string(addr_str)
+                .map_err(|_| (-32602, "invalid address".to_string()))?;
+            let from_height = params.get(1).and_then(|v| v.as_u64()).unwrap_or(0).min(u32::MAX as u64) as u32;
+            let start = match params.get(2).and_then(|v| v.as_u64()) {
+                Some(cursor) => (cursor.min(u32::MAX as u64) as u32).saturating_add(1).max(from_height),
+                None => from_height,
+            };
+
+            let chain_height = state.db.get_chain_height().map_err(|e| (-32603, format!("db error: {e}")))?;
+            let mut txs = Vec::new();
+            let mut last_scanned = None;
+            let mut h = start;
+            // Pages end on a block boundary so the cursor never splits a block.
+            while h <= chain_height
+                && txs.len() < ADDRESS_TXS_PAGE_SIZE
+                && h - start < ADDRESS_TXS_MAX_SCAN_BLOCKS
+            {
+                if let Ok(Some(hash)) = state.db.get_block_hash_by_height(h)
+                    && let Ok(Some(block)) = state.db.get_block(&hash)
+                {
+                    txs.extend(address_history_entries(&block, &addr));
                 }
+                last_scanned = Some(h);
+                h += 1;
             }
 
+            let next_cursor = last_scanned.filter(|&h| h < chain_height);
             Ok(json!({
                 "address": addr_str,
                 "transactions": txs,
                 "count": txs.len(),
+                "next_cursor": next_cursor,
             }))
         }
 
         "addnode" => {
             let addr_str = params.get(0).and_then(|v| v.as_str()).ok_or((-32602, "address required".to_string()))?;
             let addr: SocketAddr = addr_str.parse().map_err(|_| (-32602, "invalid socket address".to_string()))?;
+            let operation = params.get(1).and_then(|v| v.as_str()).unwrap_or("onetry");
+            let data_dir = std::path::Path::new(&state.data_dir);
+            match operation {
+                "onetry" => {}
+                "add" => {
+                    let now = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .unwrap()
+                        .as_secs();
+                    state
+                        .known_peers
+                        .lock()
+                        .await
+                        .entry(addr)
+                        .or_insert_with(|| crate::net::node::KnownPeer::new(now))
+                        .manual = true;
+                    crate::net::node::save_known_peers(&state.known_peers, data_dir).await;
+                }
+                "remove" => {
+                    let removed = state.known_peers.lock().await.remove(&addr).is_some();
+                    if removed {
+                        crate::net::node::save_known_peers(&state.known_peers, data_dir).await;
+                    }
+                    state.p2p_tx.send(P2pCommand::Disconnect(addr)).map_err(|_| (-32603, "internal error".to_string()))?;
+                    return Ok(json!("removed"));
+                }
+                _ => return Err((-32602, "operation must be \"add\", \"onetry\" or \"remove\"".to_string())),
+            }
             state.p2p_tx.send(P2pCommand::Connect(addr)).map_err(|_| (-32603, "internal error".to_string()))?;
             Ok(json!("added"))
         }
@@ -1018,11 +2382,60 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
             }))
         }
 
+        // Dashboard for the profile's wallet in one call. The file checks run
+        // before deriving keys, which writes wallet_keys.json on first use.
+        "getwalletinfo" => {
+            let mnemonic = params.get(0).and_then(|v| v.as_str()).ok_or((-32602, "mnemonic required".to_string()))?;
+            ensure_single_wallet_identity(state, mnemonic).await?;
+            let has_keys_file = wallet_keys_file(&state.data_dir).exists();
+            let has_wallet_dat = PathBuf::from(&state.data_dir).join("wallet.dat").exists();
+
+            let (pk, _sk) = cached_keypair_for_mnemonic(state, mnemonic).await;
+            let addr = crate::crypto::keys::derive_address(&pk);
+            let acc = state.db.get_account(&addr).map_err(|e| (-32603, format!("db error: {e}")))?;
+            let pending_nonce = state.mempool.lock().await.highest_pending_nonce_for_sender(&addr);
+            Ok(json!({
+                "address": crate::crypto::keys::encode_address_string(&addr),
+                "balance_knots": acc.balance,
+                "balance_kot": format!("{:.8}", acc.balance as f64 / 1e8),
+                "nonce": acc.nonce,
+                "pending_nonce": pending_nonce,
+                "privacy_code": privacy_code(&addr),
+                "referred_by": acc.referrer.as_ref().map(privacy_code),
+                "total_referred_miners": acc.total_referred_miners,
+                "total_referral_bonus_earned": acc.total_referral_bonus_earned,
+                "has_wallet_keys": has_keys_file,
+                "has_wallet_dat": has_wallet_dat,
+            }))
+        }
+
+        // Fresh receiving address under the same mnemonic (e.g. one per invoice).
+        // The identity guard keys off the base mnemonic, so every index of the
+        // profile's wallet is accepted and other mnemonics are still refused.
+        "wallet_derive_address" => {
+            let mnemonic = params.get(0).and_then(|v| v.as_str()).ok_or((-32602, "mnemonic required".to_string()))?;
+            let index = params.get(1)
+                .and_then(|v| v.as_u64())
+                .and_then(|i| u32::try_from(i).ok())
+                .ok_or((-32602, "index must be a u32".to_string()))?;
+            ensure_single_wallet_identity(state, mnemonic).await?;
+            let (pk, _sk) = tokio::task::spawn_blocking({
+                let mnemonic = mnemonic.to_string();
+                move || crate::crypto::keys::derive_keypair_at_index(&mnemonic, index)
+            }).await.map_err(|e| (-32603, format!("blocking task error: {}", e)))?;
+            let addr = crate::crypto::keys::derive_address(&pk);
+            Ok(json!({
+                "index": index,
+                "address": crate::crypto::keys::encode_address_string(&addr),
+            }))
+        }
+
         "wallet_create_file" => {
             // Creates wallet.dat file with deterministic address storage
             let mnemonic = params.get(0).and_then(|v| v.as_str()).ok_or((-32602, "mnemonic required".to_string()))?;
             let password = params.get(1).and_then(|v| v.as_str()).ok_or((-32602, "password required".to_string()))?;
-            let wallet_path = params.get(2).and_then(|v| v.as_str()).unwrap_or("~/.knotcoin/mainnet/wallet.dat");
+            let wallet_path = params.get(2).and_then(|v| v.as_str()).map(str::to_string)
+                .unwrap_or_else(|| format!("~/.knotcoin/{}/wallet.dat", state.config.network));
             
             // Expand ~ to home directory
             let expanded_path = if wallet_path.starts_with("~/") {
@@ -1051,7 +2464,8 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
         "wallet_unlock_file" => {
             // Unlocks wallet.dat file and returns address
             let password = params.get(0).and_then(|v| v.as_str()).ok_or((-32602, "password required".to_string()))?;
-            let wallet_path = params.get(1).and_then(|v| v.as_str()).unwrap_or("~/.knotcoin/mainnet/wallet.dat");
+            let wallet_path = params.get(1).and_then(|v| v.as_str()).map(str::to_string)
+                .unwrap_or_else(|| format!("~/.knotcoin/{}/wallet.dat", state.config.network));
             
             // Expand ~ to home directory
             let expanded_path = if wallet_path.starts_with("~/") {
@@ -1076,6 +2490,47 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
             }))
         }
 
+        // Password-encrypted copy of the profile's keypair for wallet_import
+        // on another machine: [mnemonic, password].
+        "wallet_export" => {
+            let mnemonic = params.get(0).and_then(|v| v.as_str()).ok_or((-32602, "mnemonic required".to_string()))?;
+            let password = params.get(1).and_then(|v| v.as_str()).ok_or((-32602, "password required".to_string()))?;
+            if password.is_empty() {
+                return Err((-32602, "password must not be empty".to_string()));
+            }
+            ensure_single_wallet_identity(state, mnemonic).await?;
+            let (pk, sk) = cached_keypair_for_mnemonic(state, mnemonic).await;
+            let hash = mnemonic_hash(mnemonic);
+            let password = password.to_string();
+            let blob = tokio::task::spawn_blocking(move || crate::wallet::file::export_keys(&hash, &pk, &sk, &password))
+                .await
+                .map_err(|e| (-32603, format!("blocking task error: {e}")))?
+                .map_err(|e| (-32603, format!("export failed: {e}")))?;
+            Ok(json!({
+                "address": crate::crypto::keys::encode_address_string(&crate::crypto::keys::derive_address(&pk)),
+                "blob": blob,
+            }))
+        }
+
+        // Install a wallet_export blob into this profile: [blob, password].
+        // A profile that already holds a different wallet is left untouched.
+        "wallet_import" => {
+            let blob = params.get(0).and_then(|v| v.as_str()).ok_or((-32602, "blob required".to_string()))?.to_string();
+            let password = params.get(1).and_then(|v| v.as_str()).ok_or((-32602, "password required".to_string()))?.to_string();
+            let keys = tokio::task::spawn_blocking(move || crate::wallet::file::import_keys(&blob, &password))
+                .await
+                .map_err(|e| (-32603, format!("blocking task error: {e}")))?
+                .map_err(|e| (-32602, format!("import failed: {e}")))?;
+            if existing_wallet_hash_mismatch(&state.data_dir, &keys.mnemonic_hash) {
+                return Err((-32603, "wallet profile already initialized with a different mnemonic".to_string()));
+            }
+            save_wallet_keys_to_disk(&state.data_dir, &keys.mnemonic_hash, &keys.public_key, &keys.secret_key);
+            state.wallet_keys.lock().await.insert(keys.mnemonic_hash, (keys.public_key, keys.secret_key));
+            Ok(json!({
+                "address": crate::crypto::keys::encode_address_string(&crate::crypto::keys::derive_address(&keys.public_key)),
+            }))
+        }
+
         "wallet_reset" => {
             // Backup wallet_keys.json before deletion (allows recovery with same mnemonic)
             let wallet_path = wallet_keys_file(&state.data_dir);
@@ -1092,16 +2547,19 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
             let mnemonic = params.get(0).and_then(|v| v.as_str())
                 .ok_or((-32602, "mnemonic required".to_string()))?;
             ensure_single_wallet_identity(state, mnemonic).await?;
-            let threads = params.get(1).and_then(|v| v.as_u64()).unwrap_or(2).clamp(1, 8) as usize;
+            let threads = params.get(1).and_then(|v| v.as_u64()).unwrap_or(2)
+                .clamp(1, crate::miner::miner::MAX_MINING_THREADS as u64) as usize;
             let referrer_str = params.get(2).and_then(|v| v.as_str());
 
             if state.mining_active.load(Ordering::SeqCst) {
                 return Ok(json!({ "status": "already_mining" }));
             }
+            ensure_clock_ok_for_mining(state).await?;
 
             let (pk, _sk) = cached_keypair_for_mnemonic(state, mnemonic).await;
             let miner_addr = crate::crypto::keys::derive_address(&pk);
-            
+            ensure_miner_allowed(state, &miner_addr)?;
+
             let referrer = if let Some(r) = referrer_str {
                 let mut s = r.trim();
                 if s.to_uppercase().starts_with("KOT") {
@@ -1150,28 +2608,58 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
             let referrer_copy = referrer;
             let stop_flag = state.mining_stop.clone();
             let nonce_counter = state.mining_nonces_total.clone();
+            let hashrate_samples = state.mining_hashrate_samples.clone();
+            let time_data = state.time_data.clone();
+            let block_events = state.block_events.clone();
+            let mut shutdown_rx = state.shutdown_tx.subscribe();
             tokio::spawn(async move {
-                println!("[miner] Background mining started ({} threads)", threads);
-                loop {
+                info!("[miner] Background mining started ({} threads)", threads);
+                'mining: loop {
                     if stop_flag.load(Ordering::SeqCst) {
-                        println!("[miner] Mining stopped by user");
+                        info!("[miner] Mining stopped by user");
                         break;
                     }
 
+                    // Peers may report a skewed clock only after we started; pause instead of wasting work.
+                    if time_data.lock().await.mining_blocked() {
+                        tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
+                        continue;
+                    }
+
                     let txs = mempool.lock().await.get_top_transactions(crate::miner::miner::MAX_TXS);
                     
                     let db_clone = db.clone();
                     let inner_stop = stop_flag.clone();
                     let nonce_counter_clone = nonce_counter.clone();
-                    let result = tokio::task::spawn_blocking(move || {
+                    let mut search = tokio::task::spawn_blocking(move || {
                         crate::miner::miner::mine_block_parallel_with_counter(
                             &db_clone, txs, &addr_copy, None, &inner_stop, referrer_copy, threads,
                             Some(&nonce_counter_clone),
                         )
-                    }).await.unwrap_or(None);
+                    });
+                    // Sample the nonce counter once a second while searching.
+                    // On shutdown, drop whatever the search finds rather than
+                    // apply a block while the daemon is flushing the DB.
+                    let mut sample_tick = tokio::time::interval(tokio::time::Duration::from_secs(1));
+                    let result = loop {
+                        tokio::select! {
+                            r = &mut search => break r.unwrap_or(None),
+                            _ = sample_tick.tick() => {
+                                let now = std::time::SystemTime::now()
+                                    .duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+                                hashrate_samples.lock().await.record(now, nonce_counter.load(Ordering::Relaxed));
+                            }
+                            _ = shutdown_rx.recv() => {
+                                stop_flag.store(true, Ordering::SeqCst);
+                                info!("[miner] Mining stopped for shutdown");
+                                break 'mining;
+                            }
+                        }
+                    };
 
                     if let Some((block, hash)) = result {
                         if crate::consensus::state::apply_block_with_referrer(&db, &block, referrer_copy).is_ok() {
+                            crate::rpc::ws::notify_block(&block_events, &db, &block);
                             // Remove confirmed txs from mempool so we don't keep stale sender+nonce entries.
                             let confirmed: Vec<[u8; 32]> = block
                                 .tx_data
@@ -1180,7 +2668,7 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
                                 .collect();
                             mempool.lock().await.remove_confirmed(&confirmed);
                             blocks_counter.fetch_add(1, Ordering::SeqCst);
-                            println!("[miner] Block found: {}", hex::encode(&hash));
+                            info!("[miner] Block found: {}", hex::encode(&hash));
                             let block_bytes = block.to_bytes();
                             let _ = p2p_tx.send(crate::net::node::P2pCommand::Broadcast(
                                 crate::net::protocol::NetworkMessage::Blocks(vec![block_bytes])
@@ -1208,6 +2696,38 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
             Ok(json!({ "status": "mining_stopped" }))
         }
 
+        // Time each thread count on this machine and suggest one for start_mining.
+        "optimizemining" => {
+            static RUNNING: AtomicBool = AtomicBool::new(false);
+            let secs = match params.get(0) {
+                None | Some(Value::Null) => 2,
+                Some(v) => v.as_u64().filter(|s| (1..=MAX_OPTIMIZE_SECS).contains(s))
+                    .ok_or((-32602, format!("seconds per thread count must be 1 to {MAX_OPTIMIZE_SECS}")))?,
+            };
+            if state.mining_active.load(Ordering::SeqCst) {
+                return Err((-32603, "mining is active; stop_mining first".to_string()));
+            }
+            if RUNNING.swap(true, Ordering::SeqCst) {
+                return Err((-32603, "optimizemining is already running".to_string()));
+            }
+            let db = state.db.clone();
+            let measured = tokio::task::spawn_blocking(move || {
+                (1..=crate::miner::miner::MAX_MINING_THREADS)
+                    .map(|t| crate::miner::miner::measure_hashrate(&db, t, std::time::Duration::from_secs(secs)).map(|r| (t, r)))
+                    .collect::<Option<Vec<_>>>()
+            })
+            .await;
+            RUNNING.store(false, Ordering::SeqCst);
+            let results = measured
+                .map_err(|e| (-32603, format!("benchmark failed: {e}")))?
+                .ok_or((-32603, "no chain tip to build a template on".to_string()))?;
+            Ok(json!({
+                "threads": crate::miner::miner::pick_mining_threads(&results),
+                "seconds_per_test": secs,
+                "results": results.iter().map(|(t, r)| json!({ "threads": t, "hashrate": r })).collect::<Vec<_>>(),
+            }))
+        }
+
         "get_mining_status" => {
             let active = state.mining_active.load(Ordering::SeqCst);
             let blocks = state.mining_blocks_found.load(Ordering::SeqCst);
@@ -1223,15 +2743,8 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
             let difficulty_bits = if chain_height > 0 {
                 if let Ok(Some(hash)) = state.db.get_block_hash_by_height(chain_height) {
                     if let Ok(Some(block)) = state.db.get_block(&hash) {
-                        let target = &block.difficulty_target;
-                        // Count leading zero bytes (not 0xff) for difficulty
-                        let mut leading_zeros = 0u32;
-                        for &b in target.iter() {
-                            if b == 0x00 { leading_zeros += 8; }
-                            else { leading_zeros += b.leading_zeros() as u32; break; }
-                        }
                         // Minimum difficulty is 1
-                        leading_zeros.max(1)
+                        difficulty_from_target(&block.difficulty_target).1.max(1)
                     } else { 1 }
                 } else { 1 }
             } else { 1 };
@@ -1247,27 +2760,191 @@ async fn handle_rpc(state: &RpcState, method: &str, params: &Value) -> Result<Va
             }))
         }
 
+        "getmininghashps" => {
+            // Nonce rate of the local miner over the last `window` seconds,
+            // unlike get_mining_status's lifetime average.
+            let max = crate::miner::miner::MAX_HASHRATE_SAMPLES as u64;
+            let window = match params.get(0) {
+                None | Some(Value::Null) => 60,
+                Some(v) => match v.as_u64() {
+                    Some(w) if (1..=max).contains(&w) => w,
+                    _ => return Err((-32602, format!("window must be 1..={max} seconds"))),
+                },
+            };
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+            let samples = state.mining_hashrate_samples.lock().await;
+            Ok(json!({
+                "window_seconds": window,
+                "hashps": samples.rate(now, window),
+                "active": state.mining_active.load(Ordering::SeqCst),
+            }))
+        }
+
         "getpeerinfo" => {
             let count = state.connected_peers.load(Ordering::Relaxed);
+            let peers = state.peers.lock().await;
+            let mut list: Vec<(&SocketAddr, &crate::net::node::PeerInfo)> = peers
+                .iter()
+                .filter(|(_, p)| p.handshake_stage == crate::net::node::HandshakeStage::Done)
+                .collect();
+            list.sort_by_key(|(a, _)| **a);
+            let peers: Vec<Value> = list
+                .into_iter()
+                .map(|(a, p)| json!({
+                    "addr": a.to_string(),
+                    "height": p.height,
+                    "direction": if p.is_outbound { "outbound" } else { "inbound" },
+                    "last_ping_ms": p.last_ping_ms,
+                    // How long the current ping has gone unanswered; large on a stalled link.
+                    "ping_wait_ms": p.ping_pending.map(|(_, sent)| sent.elapsed().as_millis() as u64),
+                    "services": p.services,
+                    "version": p.protocol_version,
+                    "ban_score": p.ban_score,
+                }))
+                .collect();
             Ok(json!({
                 "connected": count > 0,
                 "peer_count": count,
+                "peers": peers,
             }))
         }
 
-        "stop" => {
-            state.shutdown.store(true, Ordering::SeqCst);
-            Ok(json!("stopping"))
+        "getknownpeers" => {
+            let known = state.known_peers.lock().await;
+            let mut list: Vec<(&SocketAddr, &crate::net::node::KnownPeer)> = known.iter().collect();
+            list.sort_by_key(|(a, _)| **a);
+            let peers: Vec<Value> = list
+                .into_iter()
+                .map(|(a, p)| json!({
+                    "addr": a.to_string(),
+                    "added": p.added,
+                    "last_seen": p.last_seen,
+                    "last_attempt": p.last_attempt,
+                    "manual": p.manual,
+                }))
+                .collect();
+            Ok(json!({
+                "count": peers.len(),
+                "peers": peers,
+            }))
         }
 
-        _ => Err((-32601, format!("method not found: {method}"))),
-    }
-}
-
-async fn handle_request(
+        "prunepeers" => {
+            let max_age_secs = params
+                .get(0)
+                .and_then(|v| v.as_u64())
+                .unwrap_or(crate::net::node::DEFAULT_PEER_PRUNE_SECS);
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            let removed = {
+                let mut known = state.known_peers.lock().await;
+                crate::net::node::prune_known_peers(&mut known, now, max_age_secs)
+            };
+            if !removed.is_empty() {
+                crate::net::node::save_known_peers(&state.known_peers, std::path::Path::new(&state.data_dir)).await;
+            }
+            let remaining = state.known_peers.lock().await.len();
+            Ok(json!({
+                "removed": removed.iter().map(|a| a.to_string()).collect::<Vec<_>>(),
+                "remaining": remaining,
+            }))
+        }
+
+        "getnetworkinfo" => {
+            use crate::primitives::transaction::KNOTS_PER_KOT;
+            let relay_fee = state.mempool.lock().await.min_relay_fee();
+            let td = state.time_data.lock().await;
+            Ok(json!({
+                "version": env!("CARGO_PKG_VERSION"),
+                "subversion": format!("/Knotcoin:{}/", env!("CARGO_PKG_VERSION")),
+                "magic": hex::encode(state.config.network.magic()),
+                "connections": state.connected_peers.load(Ordering::Relaxed),
+                "max_inbound": state.config.max_inbound,
+                "max_outbound": state.config.max_outbound,
+                "relayfee": relay_fee as f64 / KNOTS_PER_KOT as f64,
+                "relayfee_knots": relay_fee,
+                "timeoffset": td.median_offset().unwrap_or(0),
+                "time_samples": td.sample_count(),
+                "clock_skewed": td.is_skewed(),
+                "mining_blocked_by_skew": td.mining_blocked(),
+                "warnings": td.skew_warning().unwrap_or_default(),
+            }))
+        }
+
+        "stop" => {
+            state.request_shutdown();
+            Ok(json!("stopping"))
+        }
+
+        _ => Err((-32601, format!("method not found: {method}"))),
+    }
+}
+
+/// Upper bound on batch elements being processed at once.
+const MAX_CONCURRENT_BATCH_CALLS: usize = 16;
+
+/// Answer one JSON-RPC request object.
+async fn dispatch_call(state: &RpcState, v: Value) -> Value {
+    let id = v.get("id").cloned().unwrap_or(json!(null));
+    if !v.is_object() || v.get("method").is_none() {
+        return json!({
+            "jsonrpc": "2.0",
+            "error": {"code": -32600, "message": "Invalid Request"},
+            "id": id
+        });
+    }
+    let method = v["method"].as_str().unwrap_or("");
+    let params = v.get("params").cloned().unwrap_or(json!([]));
+    match handle_rpc(state, method, &params).await {
+        Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+        Err((code, message)) => json!({
+            "jsonrpc": "2.0",
+            "error": {"code": code, "message": message},
+            "id": id
+        }),
+    }
+}
+
+/// Answer a parsed request body: a single call, or a JSON-RPC 2.0 batch whose
+/// responses come back in request order.
+async fn dispatch_json(state: &RpcState, v: Value) -> Value {
+    use futures_util::StreamExt;
+
+    let Value::Array(calls) = v else {
+        return dispatch_call(state, v).await;
+    };
+    if calls.is_empty() {
+        return json!({
+            "jsonrpc": "2.0",
+            "error": {"code": -32600, "message": "Invalid Request"},
+            "id": null
+        });
+    }
+    let responses: Vec<Value> = futures_util::stream::iter(calls)
+        .map(|c| dispatch_call(state, c))
+        .buffered(MAX_CONCURRENT_BATCH_CALLS)
+        .collect()
+        .await;
+    Value::Array(responses)
+}
+
+async fn handle_request(
     state: Arc<RpcState>,
+    limiter: Arc<std::sync::Mutex<RateLimiter>>,
+    peer: SocketAddr,
     req: Request<Incoming>,
 ) -> Result<Response<Full<Bytes>>, Infallible> {
+    if !limiter.lock().unwrap().check(peer.ip()) {
+        let builder = Response::builder()
+            .status(hyper::StatusCode::TOO_MANY_REQUESTS)
+            .header("Retry-After", "1")
+            .header("Access-Control-Allow-Origin", "*");
+        return Ok(builder.body(Full::new(Bytes::from("Too Many Requests"))).unwrap());
+    }
+
     if req.method() == hyper::Method::OPTIONS {
         let builder = Response::builder()
             .header("Access-Control-Allow-Origin", "*")
@@ -1289,6 +2966,10 @@ async fn handle_request(
         return Ok(builder.body(Full::new(Bytes::from("Unauthorized"))).unwrap());
     }
 
+    if crate::rpc::ws::is_upgrade_request(&req) {
+        return Ok(crate::rpc::ws::upgrade(state, req).await);
+    }
+
     let body = match req.collect().await {
         Ok(b) => b.to_bytes(),
         Err(_) => {
@@ -1299,27 +2980,7 @@ async fn handle_request(
     };
 
     let resp = match serde_json::from_slice::<Value>(&body) {
-        Ok(v) => {
-            let id = v.get("id").cloned().unwrap_or(json!(null));
-            if !v.is_object() || v.get("method").is_none() {
-                json!({
-                    "jsonrpc": "2.0",
-                    "error": {"code": -32600, "message": "Invalid Request"},
-                    "id": id
-                })
-            } else {
-                let method = v["method"].as_str().unwrap_or("");
-                let params = v.get("params").cloned().unwrap_or(json!([]));
-                match handle_rpc(&state, method, &params).await {
-                    Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
-                    Err((code, message)) => json!({
-                        "jsonrpc": "2.0",
-                        "error": {"code": code, "message": message},
-                        "id": id
-                    }),
-                }
-            }
-        }
+        Ok(v) => dispatch_json(&state, v).await,
         Err(e) => json!({
             "jsonrpc": "2.0",
             "error": {"code": -32700, "message": format!("parse error: {e}")},
@@ -1337,27 +2998,49 @@ async fn handle_request(
     Ok(builder.body(Full::new(Bytes::from(body_bytes))).unwrap())
 }
 
+/// IP the RPC server binds: `bind` if given, else `RPC_BIND_ADDRESS`.
+pub fn rpc_bind_ip(bind: Option<IpAddr>) -> IpAddr {
+    bind.unwrap_or_else(|| RPC_BIND_ADDRESS.parse().expect("RPC_BIND_ADDRESS is a valid IP"))
+}
+
 pub async fn start_rpc_server(
     state: Arc<RpcState>,
+    bind: Option<IpAddr>,
     port: u16,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let addr: SocketAddr = format!("{RPC_BIND_ADDRESS}:{port}").parse()?;
+    let addr = SocketAddr::new(rpc_bind_ip(bind), port);
+    if !addr.ip().is_loopback() {
+        // Bearer-token auth still applies, but the port is now reachable by others.
+        warn!("[rpc] WARNING: RPC is listening on {addr}, not loopback.");
+        warn!("[rpc] WARNING: anyone who can reach this address and obtains the .cookie token controls the node and its wallets.");
+    }
     let listener = TcpListener::bind(addr).await?;
+    let mut shutdown_rx = state.shutdown_tx.subscribe();
+    let limiter = Arc::new(std::sync::Mutex::new(RateLimiter::new(
+        state.config.rpc_rate_per_sec,
+        state.config.rpc_rate_burst,
+    )));
 
     loop {
         if state.shutdown.load(Ordering::SeqCst) { break; }
-        let (stream, _) = match timeout(Duration::from_millis(250), listener.accept()).await {
-            Ok(Ok(pair)) => pair,
-            _ => continue,
+        let (stream, peer) = tokio::select! {
+            res = listener.accept() => match res {
+                Ok(pair) => pair,
+                Err(_) => continue,
+            },
+            _ = shutdown_rx.recv() => break,
         };
         let s = state.clone();
+        let l = limiter.clone();
         tokio::spawn(async move {
             let svc = service_fn(move |req| {
                 let s2 = s.clone();
-                async move { handle_request(s2, req).await }
+                let l2 = l.clone();
+                async move { handle_request(s2, l2, peer, req).await }
             });
             let _ = hyper::server::conn::http1::Builder::new()
                 .serve_connection(TokioIo::new(stream), svc)
+                .with_upgrades()
                 .await;
         });
     }
@@ -1401,3 +3084,1536 @@ pub fn generate_rpc_auth_token(data_dir: &str) -> Result<String, std::io::Error>
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::node::KnownPeer;
+
+    fn test_state(dir: &tempfile::TempDir) -> RpcState {
+        let (p2p_tx, _p2p_rx) = tokio::sync::mpsc::unbounded_channel();
+        RpcState {
+            db: ChainDB::open(&dir.path().join("chaindata")).unwrap().with_network(crate::config::Network::Testnet),
+            mempool: Arc::new(Mutex::new(Mempool::new())),
+            shutdown: AtomicBool::new(false),
+            shutdown_tx: tokio::sync::broadcast::channel(1).0,
+            p2p_tx,
+            auth_token: String::new(),
+            data_dir: dir.path().to_string_lossy().to_string(),
+            config: Arc::new(crate::config::Config {
+                data_dir: dir.path().to_string_lossy().to_string(),
+                ..Default::default()
+            }),
+            mining_active: AtomicBool::new(false),
+            mining_blocks_found: Arc::new(AtomicU64::new(0)),
+            mining_start_time: Arc::new(AtomicU64::new(0)),
+            mining_stop: Arc::new(AtomicBool::new(false)),
+            connected_peers: Arc::new(std::sync::atomic::AtomicUsize::new(0)),
+            known_peers: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            peers: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            time_data: Arc::new(Mutex::new(TimeData::new(false))),
+            wallet_keys: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            mining_nonces_total: Arc::new(AtomicU64::new(0)),
+            mining_hashrate_samples: Arc::new(Mutex::new(crate::miner::miner::HashrateSamples::new())),
+            mining_address: Arc::new(Mutex::new(None)),
+            mining_referrer: Arc::new(Mutex::new(None)),
+            block_events: crate::rpc::ws::block_events(),
+            block_stats_cache: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            last_tip_update: Arc::new(AtomicU64::new(now_secs())),
+        }
+    }
+
+    /// Make `addr`'s mining rewards spendable without mining
+    /// `COINBASE_MATURITY` more blocks.
+    fn mature_now(state: &RpcState, addr: &[u8; 32]) {
+        let mut acc = state.db.get_account(addr).unwrap();
+        acc.mature(u64::MAX).unwrap();
+        state.db.put_account(addr, &acc).unwrap();
+    }
+
+    fn now_secs() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[test]
+    fn test_every_rpc_method_is_classified() {
+        let src = include_str!("server.rs");
+        let start = src.find("async fn handle_rpc(").unwrap();
+        let body = &src[start..start + src[start..].find("\n}\n").unwrap()];
+        let arms: Vec<&str> = body
+            .lines()
+            .filter_map(|l| l.strip_prefix("        \"")?.split_once(" => ").map(|(names, _)| names))
+            .flat_map(|names| names.split(" | "))
+            .map(|n| n.trim_matches('"'))
+            .collect();
+        assert!(arms.len() > 50);
+        for name in &arms {
+            assert!(method_kind(name).is_some(), "{name} is not in QUERY_METHODS or MUTATING_METHODS");
+        }
+        for name in QUERY_METHODS.iter().chain(MUTATING_METHODS) {
+            assert!(arms.contains(name), "{name} is classified but not handled");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_optimizemining_times_each_thread_count() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        crate::consensus::state::apply_block(&state.db, &easy_genesis()).unwrap();
+        for bad in [json!([0]), json!([11]), json!(["2"])] {
+            assert_eq!(handle_rpc(&state, "optimizemining", &bad).await.unwrap_err().0, -32602);
+        }
+        state.mining_active.store(true, Ordering::SeqCst);
+        assert!(handle_rpc(&state, "optimizemining", &json!([1])).await.unwrap_err().1.contains("stop_mining"));
+        state.mining_active.store(false, Ordering::SeqCst);
+
+        let r = handle_rpc(&state, "optimizemining", &json!([1])).await.unwrap();
+        let results = r["results"].as_array().unwrap();
+        assert_eq!(results.len(), crate::miner::miner::MAX_MINING_THREADS);
+        assert!(results.iter().map(|x| x["hashrate"].as_u64().unwrap()).sum::<u64>() > 0, "{r}");
+        assert!((1..=8).contains(&r["threads"].as_u64().unwrap()));
+        assert_eq!(state.db.get_chain_height().unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_readonly_mode_refuses_mutating_methods() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = test_state(&dir);
+        state.config = Arc::new(crate::config::Config { rpc_readonly: true, ..(*state.config).clone() });
+        crate::consensus::state::apply_block(&state.db, &easy_genesis()).unwrap();
+
+        let mnemonic = "abandon ".repeat(23) + "art";
+        for (method, params) in [
+            ("stop", json!([])),
+            ("wallet_send", json!([mnemonic, "KOT1x", 1.0])),
+            ("start_mining", json!([mnemonic])),
+            ("generatetoaddress", json!([1, hex::encode([0x5A; 32])])),
+            ("addnode", json!(["8.8.8.8:9000", "add"])),
+        ] {
+            let err = handle_rpc(&state, method, &params).await.unwrap_err();
+            assert_eq!(err.0, -32601, "{method}");
+            assert!(err.1.contains("read-only"), "{}", err.1);
+        }
+        assert!(!state.shutdown.load(Ordering::SeqCst));
+        assert_eq!(state.db.get_chain_height().unwrap(), 0);
+
+        assert_eq!(handle_rpc(&state, "getblockcount", &json!([])).await.unwrap(), 0);
+        assert_eq!(handle_rpc(&state, "getblocktxids", &json!([0])).await.unwrap()["txids"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_batch_preserves_order_and_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        let mut calls: Vec<Value> = (0..40)
+            .map(|i| json!({"jsonrpc": "2.0", "method": "getbalance", "params": [hex::encode([i as u8; 32])], "id": i}))
+            .collect();
+        calls.push(json!({"jsonrpc": "2.0", "method": "nosuchmethod", "id": "x"}));
+        calls.push(json!(42));
+
+        let res = dispatch_json(&state, Value::Array(calls)).await;
+        let arr = res.as_array().unwrap();
+        assert_eq!(arr.len(), 42);
+        for (i, r) in arr[..40].iter().enumerate() {
+            assert_eq!(r["id"], i);
+            assert_eq!(r["result"]["balance_knots"], 0);
+        }
+        assert_eq!(arr[40]["id"], "x");
+        assert_eq!(arr[40]["error"]["code"], -32601);
+        assert_eq!(arr[41]["error"]["code"], -32600);
+
+        let empty = dispatch_json(&state, json!([])).await;
+        assert_eq!(empty["error"]["code"], -32600);
+        assert!(empty["id"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_getpeerinfo_lists_connected_peers() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        let peer = |is_outbound, handshake_stage, last_ping_ms| crate::net::node::PeerInfo {
+            height: 42,
+            challenge: [0u8; 32],
+            is_outbound,
+            handshake_stage,
+            services: 0,
+            ban_score: 0,
+            compact_pending: None,
+            disconnect: Arc::default(),
+            resync: Arc::default(),
+            last_ping_ms,
+            ping_pending: None,
+            node_id: [0u8; 32],
+            protocol_version: 1,
+        };
+        {
+            use crate::net::node::HandshakeStage;
+            let mut peers = state.peers.lock().await;
+            peers.insert("8.8.8.8:9000".parse().unwrap(), peer(true, HandshakeStage::Done, Some(35)));
+            peers.insert("9.9.9.9:51000".parse().unwrap(), peer(false, HandshakeStage::Done, None));
+            peers.insert("1.1.1.1:9000".parse().unwrap(), peer(true, HandshakeStage::Version, None));
+        }
+
+        let res = handle_rpc(&state, "getpeerinfo", &json!([])).await.unwrap();
+        let list = res["peers"].as_array().unwrap();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0]["addr"], "8.8.8.8:9000");
+        assert_eq!(list[0]["direction"], "outbound");
+        assert_eq!(list[0]["height"], 42);
+        assert_eq!(list[0]["version"], 1);
+        assert_eq!(list[0]["last_ping_ms"], 35);
+        assert_eq!(list[1]["direction"], "inbound");
+        assert_eq!(list[1]["last_ping_ms"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_getknownpeers_reflects_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        let a: SocketAddr = "8.8.8.8:9000".parse().unwrap();
+        state.known_peers.lock().await.insert(a, KnownPeer { added: 1, last_seen: 2, last_attempt: 3, ..Default::default() });
+
+        let res = handle_rpc(&state, "getknownpeers", &json!([])).await.unwrap();
+        assert_eq!(res["count"], 1);
+        assert_eq!(res["peers"][0]["addr"], "8.8.8.8:9000");
+        assert_eq!(res["peers"][0]["last_seen"], 2);
+        assert_eq!(res["peers"][0]["last_attempt"], 3);
+    }
+
+    #[tokio::test]
+    async fn test_prunepeers_removes_stale_keeps_active() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        let now = now_secs();
+        let stale: SocketAddr = "9.9.9.9:9000".parse().unwrap();
+        let active: SocketAddr = "8.8.8.8:9000".parse().unwrap();
+        {
+            let mut known = state.known_peers.lock().await;
+            known.insert(stale, KnownPeer { added: now - 10_000, last_seen: now - 10_000, last_attempt: now - 60, ..Default::default() });
+            known.insert(active, KnownPeer { added: now - 10_000, last_seen: now - 60, last_attempt: now - 60, ..Default::default() });
+        }
+
+        let res = handle_rpc(&state, "prunepeers", &json!([3600])).await.unwrap();
+        assert_eq!(res["removed"], json!(["9.9.9.9:9000"]));
+        assert_eq!(res["remaining"], 1);
+        assert!(state.known_peers.lock().await.contains_key(&active));
+
+        // The pruned set is persisted for the next start.
+        let on_disk = crate::net::node::load_known_peers(dir.path(), false);
+        assert_eq!(on_disk.len(), 1);
+        assert!(on_disk.contains_key(&active));
+    }
+
+    #[tokio::test]
+    async fn test_addnode_add_persists_and_remove_disconnects() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = test_state(&dir);
+        let (p2p_tx, mut p2p_rx) = tokio::sync::mpsc::unbounded_channel();
+        state.p2p_tx = p2p_tx;
+        let a: SocketAddr = "8.8.8.8:9000".parse().unwrap();
+
+        assert_eq!(handle_rpc(&state, "addnode", &json!(["8.8.8.8:9000", "add"])).await.unwrap(), "added");
+        assert!(matches!(p2p_rx.try_recv(), Ok(P2pCommand::Connect(x)) if x == a));
+        let on_disk = crate::net::node::load_known_peers(dir.path(), false);
+        assert!(on_disk[&a].manual);
+        let res = handle_rpc(&state, "getknownpeers", &json!([])).await.unwrap();
+        assert_eq!(res["peers"][0]["manual"], true);
+
+        // onetry dials without touching peers.json.
+        handle_rpc(&state, "addnode", &json!(["9.9.9.9:9000"])).await.unwrap();
+        assert!(matches!(p2p_rx.try_recv(), Ok(P2pCommand::Connect(_))));
+        assert_eq!(crate::net::node::load_known_peers(dir.path(), false).len(), 1);
+
+        assert_eq!(handle_rpc(&state, "addnode", &json!(["8.8.8.8:9000", "remove"])).await.unwrap(), "removed");
+        assert!(matches!(p2p_rx.try_recv(), Ok(P2pCommand::Disconnect(x)) if x == a));
+        assert!(crate::net::node::load_known_peers(dir.path(), false).is_empty());
+
+        let err = handle_rpc(&state, "addnode", &json!(["8.8.8.8:9000", "ban"])).await.unwrap_err();
+        assert_eq!(err.0, -32602);
+    }
+
+    #[tokio::test]
+    async fn test_clock_skew_warns_and_blocks_mining() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = test_state(&dir);
+        state.time_data = Arc::new(Mutex::new(TimeData::new(true)));
+        {
+            let mut td = state.time_data.lock().await;
+            for i in 0..5u8 {
+                td.add_sample(SocketAddr::from(([10, i, 0, 0], 9000)), 3600);
+            }
+        }
+
+        let info = handle_rpc(&state, "getnetworkinfo", &json!([])).await.unwrap();
+        assert_eq!(info["timeoffset"], 3600);
+        assert_eq!((info["max_inbound"].as_u64(), info["max_outbound"].as_u64()), (Some(128), Some(32)));
+        assert_eq!(info["clock_skewed"], true);
+        assert!(info["warnings"].as_str().unwrap().contains("3600s behind"));
+
+        let miner = hex::encode([0x11u8; 32]);
+        let err = handle_rpc(&state, "generatetoaddress", &json!([1, miner])).await.unwrap_err();
+        assert_eq!(err.0, -32603);
+        assert!(err.1.starts_with("mining disabled"));
+        assert_eq!(state.db.get_chain_height().unwrap(), 0);
+    }
+
+    fn easy_genesis() -> crate::node::db_common::StoredBlock {
+        crate::node::db_common::StoredBlock {
+            version: [0, 0, 0, 1],
+            previous_hash: [0u8; 32],
+            merkle_root: [0u8; 32],
+            timestamp: 0u32.to_le_bytes(),
+            difficulty_target: [0xFF; 32],
+            nonce: [0u8; 8],
+            block_height: 0u32.to_le_bytes(),
+            miner_address: [0x01; 32],
+            tx_data: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_getblockheader_matches_getblock() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        let genesis = easy_genesis();
+        crate::consensus::state::apply_block(&state.db, &genesis).unwrap();
+        let hash = hex::encode(block_hash(&genesis));
+
+        let header = handle_rpc(&state, "getblockheader", &json!([hash])).await.unwrap();
+        let full = handle_rpc(&state, "getblock", &json!([hash])).await.unwrap();
+        for k in ["hash", "height", "version", "previousblockhash", "merkleroot", "time", "difficulty", "nonce", "miner"] {
+            assert_eq!(header[k], full[k], "{k}");
+        }
+        assert!(header.get("transactions").is_none());
+
+        let raw = handle_rpc(&state, "getblockheader", &json!([hash, false])).await.unwrap();
+        assert_eq!(raw, json!(hex::encode(genesis.header_bytes())));
+
+        let missing = handle_rpc(&state, "getblockheader", &json!([hex::encode([0u8; 32])])).await;
+        assert_eq!(missing.unwrap_err().0, -32602);
+    }
+
+    #[tokio::test]
+    async fn test_miner_allowlist_limits_local_mining() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = test_state(&dir);
+        state.config = Arc::new(crate::config::Config {
+            miner_allowlist: vec![crate::crypto::keys::encode_address_string(&[0x5A; 32])],
+            ..(*state.config).clone()
+        });
+        crate::consensus::state::apply_block(&state.db, &easy_genesis()).unwrap();
+
+        let err = handle_rpc(&state, "generatetoaddress", &json!([1, hex::encode([0x5B; 32])])).await.unwrap_err();
+        assert_eq!(err.0, -32602);
+        assert!(err.1.contains("allowlist"), "{}", err.1);
+        let mnemonic = "abandon ".repeat(23) + "art";
+        let err = handle_rpc(&state, "start_mining", &json!([mnemonic, 1])).await.unwrap_err();
+        assert_eq!(err.0, -32602);
+        assert!(err.1.contains("allowlist"), "{}", err.1);
+        assert!(!state.mining_active.load(Ordering::SeqCst));
+        assert_eq!(state.db.get_chain_height().unwrap(), 0);
+
+        handle_rpc(&state, "generatetoaddress", &json!([1, hex::encode([0x5A; 32])])).await.unwrap();
+        assert_eq!(state.db.get_chain_height().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_getrawblock_and_getblock_verbosity() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        crate::consensus::state::apply_block(&state.db, &easy_genesis()).unwrap();
+        handle_rpc(&state, "generatetoaddress", &json!([1, hex::encode([0x5A; 32])])).await.unwrap();
+        let tip = state.db.get_tip().unwrap().unwrap();
+        let hash = hex::encode(tip);
+
+        let raw = handle_rpc(&state, "getrawblock", &json!([hash])).await.unwrap();
+        let bytes = hex::decode(raw.as_str().unwrap()).unwrap();
+        let block = crate::node::db_common::StoredBlock::from_bytes(&bytes).unwrap();
+        assert_eq!(block_hash(&block), tip);
+        assert_eq!(bytes, state.db.get_block(&tip).unwrap().unwrap().to_bytes());
+
+        assert_eq!(handle_rpc(&state, "getblock", &json!([hash, 0])).await.unwrap(), raw);
+        let json1 = handle_rpc(&state, "getblock", &json!([hash])).await.unwrap();
+        assert_eq!(json1["hash"], hash);
+        assert_eq!(handle_rpc(&state, "getblock", &json!([hash, 2])).await.unwrap(), json1);
+        assert_eq!(handle_rpc(&state, "getblock", &json!([hash, 3])).await.unwrap_err().0, -32602);
+
+        let missing = handle_rpc(&state, "getrawblock", &json!([hex::encode([0u8; 32])])).await;
+        assert_eq!(missing.unwrap_err().0, -32602);
+        assert_eq!(handle_rpc(&state, "getrawblock", &json!(["abcd"])).await.unwrap_err().0, -32602);
+    }
+
+    #[tokio::test]
+    async fn test_getblockbyheight_negative_counts_from_tip() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        let genesis = easy_genesis();
+        let mut next = easy_genesis();
+        next.previous_hash = block_hash(&genesis);
+        next.block_height = 1u32.to_le_bytes();
+        next.timestamp = 1u32.to_le_bytes();
+        crate::consensus::state::apply_block(&state.db, &genesis).unwrap();
+        crate::consensus::state::apply_block(&state.db, &next).unwrap();
+
+        let tip = handle_rpc(&state, "getblockbyheight", &json!([-1])).await.unwrap();
+        assert_eq!(tip["hash"], hex::encode(block_hash(&next)));
+        let first = handle_rpc(&state, "getblockbyheight", &json!([-2])).await.unwrap();
+        assert_eq!(first["hash"], hex::encode(block_hash(&genesis)));
+        assert_eq!(handle_rpc(&state, "getblockbyheight", &json!([0])).await.unwrap(), first);
+
+        let err = handle_rpc(&state, "getblockbyheight", &json!([-3])).await.unwrap_err();
+        assert_eq!(err.0, -32602);
+    }
+
+    #[tokio::test]
+    async fn test_getblocks_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        let mut prev = easy_genesis();
+        crate::consensus::state::apply_block(&state.db, &prev).unwrap();
+        let mut hashes = vec![block_hash(&prev)];
+        for h in 1..=3u32 {
+            let mut next = easy_genesis();
+            next.previous_hash = block_hash(&prev);
+            next.block_height = h.to_le_bytes();
+            next.timestamp = h.to_le_bytes();
+            crate::consensus::state::apply_block(&state.db, &next).unwrap();
+            hashes.push(block_hash(&next));
+            prev = next;
+        }
+
+        let r = handle_rpc(&state, "getblocks", &json!([1, 2])).await.unwrap();
+        let blocks = r.as_array().unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0]["height"], 1);
+        assert_eq!(blocks[0]["hash"], hex::encode(hashes[1]));
+        assert_eq!(blocks[0]["time"], 1);
+        assert_eq!(blocks[0]["tx_count"], 0);
+        assert_eq!(blocks[0]["reward"], crate::consensus::chain::calculate_block_reward(1));
+        assert_eq!(blocks[0]["size"], prev.to_bytes().len());
+        assert_eq!(blocks[1]["hash"], hex::encode(hashes[2]));
+
+        // The range stops at the tip, and count is capped.
+        let r = handle_rpc(&state, "getblocks", &json!([2, 1000])).await.unwrap();
+        assert_eq!(r.as_array().unwrap().len(), 2);
+        let all = handle_rpc(&state, "getblocks", &json!([0])).await.unwrap();
+        assert_eq!(all.as_array().unwrap().len(), 4);
+
+        assert_eq!(handle_rpc(&state, "getblocks", &json!([4, 1])).await.unwrap_err().0, -32602);
+        assert_eq!(handle_rpc(&state, "getblocks", &json!([0, 0])).await.unwrap_err().0, -32602);
+        assert_eq!(handle_rpc(&state, "getblocks", &json!([])).await.unwrap_err().0, -32602);
+    }
+
+    #[tokio::test]
+    async fn test_getreferraltree_walks_downstream() {
+        use crate::node::db_common::AccountState;
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        let referred = |by: u8, blocks: u64| AccountState {
+            referrer: Some([by; 32]),
+            total_blocks_mined: blocks,
+            ..AccountState::empty()
+        };
+        // 1 referred 2 and 3; 2 referred 4, which referred 5. 6 and 7 name each other.
+        for (addr, acc) in [(2u8, referred(1, 5)), (3, referred(1, 2)), (4, referred(2, 1)), (5, referred(4, 0)), (6, referred(7, 0)), (7, referred(6, 0))] {
+            state.db.put_account(&[addr; 32], &acc).unwrap();
+        }
+        let root = crate::crypto::keys::encode_address_string(&[1u8; 32]);
+        let code = |b: u8| hex::encode(&crate::crypto::hash::hash_sha3_256(&[b; 32])[..8]);
+
+        let one = handle_rpc(&state, "getreferraltree", &json!([root])).await.unwrap();
+        assert_eq!(one["privacy_code"], code(1));
+        assert_eq!(one["total_in_tree"], 2);
+        let top = one["referrals"].as_array().unwrap();
+        assert_eq!(top[0]["privacy_code"], code(2));
+        assert_eq!(top[0]["blocks_mined"], 5);
+        assert_eq!(top[1]["privacy_code"], code(3));
+        assert!(top[0].get("referrals").is_none());
+
+        let two = handle_rpc(&state, "getreferraltree", &json!([root, 2])).await.unwrap();
+        assert_eq!(two["total_in_tree"], 3);
+        assert_eq!(two["referrals"][0]["referrals"][0]["privacy_code"], code(4));
+        assert_eq!(two["referrals"][1]["referrals"], json!([]));
+        let all = handle_rpc(&state, "getreferraltree", &json!([hex::encode([1u8; 32]), 5])).await.unwrap();
+        assert_eq!(all["total_in_tree"], 4);
+
+        let cycle = handle_rpc(&state, "getreferraltree", &json!([hex::encode([6u8; 32]), 5])).await.unwrap();
+        assert_eq!(cycle["total_in_tree"], 1);
+        assert_eq!(cycle["referrals"][0]["referrals"], json!([]));
+
+        assert_eq!(handle_rpc(&state, "getreferraltree", &json!([root, 0])).await.unwrap_err().0, -32602);
+        assert_eq!(handle_rpc(&state, "getreferraltree", &json!([root, 6])).await.unwrap_err().0, -32602);
+        assert_eq!(handle_rpc(&state, "getreferraltree", &json!(["nope"])).await.unwrap_err().0, -32602);
+    }
+
+    #[tokio::test]
+    async fn test_getblocktxids_by_height_and_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        crate::consensus::state::apply_block(&state.db, &easy_genesis()).unwrap();
+        let mnemonic = "abandon ".repeat(23) + "art";
+        let (pk, sk) = crate::crypto::keys::derive_keypair_from_mnemonic(&mnemonic);
+        save_wallet_keys_to_disk(&state.data_dir, &mnemonic_hash(&mnemonic), &pk, &sk);
+        state.db.put_account(&crate::crypto::keys::derive_address(&pk), &crate::node::db_common::AccountState {
+            balance: 10_0000_0000,
+            ..crate::node::db_common::AccountState::empty()
+        }).unwrap();
+        let recipient = crate::crypto::keys::encode_address_string(&[0x22u8; 32]);
+        handle_rpc(&state, "wallet_send", &json!([mnemonic, recipient, 0.01])).await.unwrap();
+        let txid = hex::encode(state.mempool.lock().await.get_all_txids()[0]);
+        handle_rpc(&state, "generatetoaddress", &json!([1, hex::encode([0x5A; 32])])).await.unwrap();
+
+        let tip = hex::encode(state.db.get_tip().unwrap().unwrap());
+        let by_height = handle_rpc(&state, "getblocktxids", &json!([1])).await.unwrap();
+        assert_eq!(by_height, json!({ "hash": tip, "height": 1, "txids": [txid] }));
+        assert_eq!(handle_rpc(&state, "getblocktxids", &json!([tip])).await.unwrap(), by_height);
+        let genesis = handle_rpc(&state, "getblocktxids", &json!([0])).await.unwrap();
+        assert_eq!(genesis["txids"], json!([]));
+
+        for bad in [json!([]), json!([9]), json!(["zz"]), json!([hex::encode([7u8; 32])])] {
+            assert_eq!(handle_rpc(&state, "getblocktxids", &bad).await.unwrap_err().0, -32602, "{bad}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_getblockstats_by_height_and_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        let genesis = easy_genesis();
+        crate::consensus::state::apply_block(&state.db, &genesis).unwrap();
+        let hash = hex::encode(block_hash(&genesis));
+
+        let by_height = handle_rpc(&state, "getblockstats", &json!([0])).await.unwrap();
+        assert_eq!(by_height["hash"], hash);
+        assert_eq!(by_height["txs"], 0);
+        assert_eq!(by_height["total_fees"], 0);
+        assert_eq!(by_height["subsidy"], crate::consensus::chain::calculate_block_reward(0));
+        assert_eq!(by_height["total_size"], genesis.to_bytes().len());
+        assert_eq!(state.block_stats_cache.lock().await.len(), 1);
+
+        let by_hash = handle_rpc(&state, "getblockstats", &json!([hash])).await.unwrap();
+        assert_eq!(by_hash, by_height);
+
+        assert_eq!(handle_rpc(&state, "getblockstats", &json!([5])).await.unwrap_err().0, -32602);
+        assert_eq!(handle_rpc(&state, "getblockstats", &json!([])).await.unwrap_err().0, -32602);
+    }
+
+    #[tokio::test]
+    async fn test_blocktemplate_submit_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        let genesis = easy_genesis();
+        crate::consensus::state::apply_block(&state.db, &genesis).unwrap();
+
+        let pool = hex::encode([0x5A; 32]);
+        let tpl = handle_rpc(&state, "getblocktemplate", &json!([pool])).await.unwrap();
+        assert_eq!(tpl["height"], 1);
+        assert_eq!(tpl["previous_hash"], hex::encode(block_hash(&genesis)));
+        assert_eq!(tpl["miner_address"], pool);
+        assert_eq!(tpl["ponc_rounds"], crate::consensus::chain::PONC_ROUNDS_DEFAULT);
+
+        // Target is all 0xFF, so the zero nonce already satisfies PoW
+        let block = tpl["block"].as_str().unwrap().to_string();
+        let hash = handle_rpc(&state, "submitblock", &json!([block])).await.unwrap();
+        assert_eq!(state.db.get_chain_height().unwrap(), 1);
+        assert_eq!(hash, json!(hex::encode(state.db.get_tip().unwrap().unwrap())));
+        assert_eq!(state.db.get_account(&[0x5A; 32]).unwrap().total_blocks_mined, 1);
+
+        let dup = handle_rpc(&state, "submitblock", &json!([block])).await;
+        assert_eq!(dup.unwrap_err().0, -32602);
+    }
+
+    #[tokio::test]
+    async fn test_getdifficulty_and_networkinfo() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        crate::consensus::state::apply_block(&state.db, &easy_genesis()).unwrap();
+
+        // An all-0xFF target has no leading zero bits.
+        let diff = handle_rpc(&state, "getdifficulty", &json!([])).await.unwrap();
+        assert_eq!(diff.as_f64(), Some(1.0));
+        assert_eq!(difficulty_from_target(&[0u8; 32]).1, 256);
+        let mut t = [0xFFu8; 32];
+        t[0] = 0x00;
+        t[1] = 0x0F;
+        assert_eq!(difficulty_from_target(&t), (4096.0, 12));
+
+        state.connected_peers.store(3, Ordering::Relaxed);
+        let info = handle_rpc(&state, "getnetworkinfo", &json!([])).await.unwrap();
+        assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(info["magic"], "4b4e4f54");
+        assert_eq!(info["connections"], 3);
+        assert_eq!(info["relayfee_knots"], 1);
+        assert_eq!(info["timeoffset"], 0);
+
+        *state.mempool.lock().await = Mempool::new().with_min_relay_fee(10);
+        let info = handle_rpc(&state, "getnetworkinfo", &json!([])).await.unwrap();
+        assert_eq!(info["relayfee_knots"], 10);
+        let est = handle_rpc(&state, "estimatefee", &json!([])).await.unwrap();
+        assert_eq!(est["recommended_fee_knots"], 10);
+    }
+
+    #[tokio::test]
+    async fn test_estimatefee_follows_fee_histogram() {
+        use crate::miner::miner::MAX_TXS;
+        use crate::primitives::transaction::Transaction;
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+
+        // One full block's worth at fee 1000, then a second block at fee 100.
+        for i in 0..2 * MAX_TXS {
+            let (pk, sk) = crate::crypto::dilithium::generate_keypair(&[i as u8 + 1; 64]);
+            let mut tx = Transaction {
+                version: 1,
+                sender_address: crate::crypto::keys::derive_address(&pk),
+                sender_pubkey: pk,
+                recipient_address: [0x22; 32],
+                amount: 1,
+                fee: if i < MAX_TXS { 1000 } else { 100 },
+                nonce: 1,
+                timestamp: now_secs(),
+                referrer_address: None,
+                governance_data: None,
+                proposal: None,
+                memo: None,
+                signature: crate::crypto::dilithium::Signature([0u8; 3309]),
+            };
+            tx.signature = crate::crypto::dilithium::sign(&tx.signing_hash(), &sk);
+            let stx = crate::node::db_common::StoredTransaction {
+                version: tx.version,
+                sender_address: tx.sender_address,
+                sender_pubkey: tx.sender_pubkey.0.to_vec(),
+                recipient_address: tx.recipient_address,
+                amount: tx.amount,
+                fee: tx.fee,
+                nonce: tx.nonce,
+                timestamp: tx.timestamp,
+                referrer_address: None,
+                governance_data: None,
+                proposal: None,
+                memo: None,
+                signature: tx.signature.0.to_vec(),
+            };
+            state.mempool.lock().await.add_transaction(stx).unwrap();
+        }
+
+        let hist = handle_rpc(&state, "feehistogram", &json!([])).await.unwrap();
+        let hist = hist.as_array().unwrap();
+        assert_eq!(hist.len(), 2);
+        assert_eq!(hist[0]["count"], MAX_TXS);
+        assert_eq!(hist[1]["cumulative_count"], 2 * MAX_TXS);
+        let size = hist[0]["size"].as_u64().unwrap() / MAX_TXS as u64;
+
+        // Next block is full of fee-1000 txs; the one after has fee-100 ones.
+        let next = handle_rpc(&state, "estimatefee", &json!([1, size])).await.unwrap();
+        assert!(next["recommended_fee_knots"].as_u64().unwrap() > 1000, "{next}");
+        let two = handle_rpc(&state, "estimatefee", &json!([2, size])).await.unwrap();
+        let two_fee = two["recommended_fee_knots"].as_u64().unwrap();
+        assert!((101..1000).contains(&two_fee), "{two}");
+        assert_eq!(two["fast_fee_knots"], next["recommended_fee_knots"]);
+        let later = handle_rpc(&state, "estimatefee", &json!([3, size])).await.unwrap();
+        assert_eq!(later["recommended_fee_knots"], 1);
+
+        assert_eq!(handle_rpc(&state, "estimatefee", &json!([0])).await.unwrap_err().0, -32602);
+    }
+
+    #[tokio::test]
+    async fn test_decoderawtransaction_needs_no_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        // Unsigned and from an unknown sender: decoding doesn't care.
+        let stx = crate::node::db_common::StoredTransaction {
+            version: crate::primitives::transaction::TX_VERSION_MEMO,
+            sender_address: [0x11; 32],
+            sender_pubkey: vec![0u8; 10],
+            recipient_address: [0x22; 32],
+            amount: 5,
+            fee: 2,
+            nonce: 9,
+            timestamp: 1234,
+            referrer_address: None,
+            governance_data: Some([0x33; 32]),
+            proposal: None,
+            memo: Some(b"order 7".to_vec()),
+            signature: vec![0u8; 3],
+        };
+        let raw = stx.to_bytes();
+        let d = handle_rpc(&state, "decoderawtransaction", &json!([hex::encode(&raw)])).await.unwrap();
+        assert_eq!(d["txid"], hex::encode(Mempool::compute_txid_from_stored(&stx)));
+        assert_eq!(d["sender"], crate::crypto::keys::encode_address_string(&[0x11; 32]));
+        assert_eq!(d["recipient"], crate::crypto::keys::encode_address_string(&[0x22; 32]));
+        assert_eq!((d["amount"].as_u64(), d["fee"].as_u64(), d["nonce"].as_u64()), (Some(5), Some(2), Some(9)));
+        assert_eq!(d["timestamp"], 1234);
+        assert_eq!(d["referrer"], Value::Null);
+        assert_eq!(d["gov_data"], hex::encode([0x33; 32]));
+        assert_eq!(d["memo"], hex::encode(b"order 7"));
+        assert_eq!((d["pubkey_len"].as_u64(), d["signature_len"].as_u64()), (Some(10), Some(3)));
+        assert_eq!(d["size"], raw.len());
+
+        let err = handle_rpc(&state, "decoderawtransaction", &json!([hex::encode(&raw[..20])])).await.unwrap_err();
+        assert_eq!(err.0, -32602);
+        assert!(err.1.starts_with("deserialization failed"), "{}", err.1);
+        assert_eq!(handle_rpc(&state, "decoderawtransaction", &json!(["zz"])).await.unwrap_err().0, -32602);
+    }
+
+    #[tokio::test]
+    async fn test_testmempoolaccept_is_dry_run() {
+        use crate::primitives::transaction::Transaction;
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        crate::consensus::state::apply_block(&state.db, &easy_genesis()).unwrap();
+        let (pk, sk) = crate::crypto::dilithium::generate_keypair(&[3u8; 64]);
+        let addr = crate::crypto::keys::derive_address(&pk);
+        handle_rpc(&state, "generatetoaddress", &json!([1, hex::encode(addr)])).await.unwrap();
+        let fresh = handle_rpc(&state, "getbalance", &json!([hex::encode(addr)])).await.unwrap();
+        assert_eq!(fresh["balance_knots"], 0);
+        assert!(fresh["immature_knots"].as_u64().unwrap() > 0);
+        mature_now(&state, &addr);
+        let balance = state.db.get_account(&addr).unwrap().balance;
+
+        let signed = |nonce: u64, amount: u64| {
+            let mut tx = Transaction {
+                version: 1,
+                sender_address: addr,
+                sender_pubkey: pk,
+                recipient_address: [0x77; 32],
+                amount,
+                fee: 1,
+                nonce,
+                timestamp: 100,
+                referrer_address: None,
+                governance_data: None,
+                proposal: None,
+                memo: None,
+                signature: crate::crypto::dilithium::Signature([0u8; 3309]),
+            };
+            tx.signature = crate::crypto::dilithium::sign(&tx.signing_hash(), &sk);
+            crate::node::db_common::StoredTransaction {
+                version: tx.version,
+                sender_address: tx.sender_address,
+                sender_pubkey: tx.sender_pubkey.0.to_vec(),
+                recipient_address: tx.recipient_address,
+                amount: tx.amount,
+                fee: tx.fee,
+                nonce: tx.nonce,
+                timestamp: tx.timestamp,
+                referrer_address: None,
+                governance_data: None,
+                proposal: None,
+                memo: None,
+                signature: tx.signature.0.to_vec(),
+            }
+        };
+
+        let raw = signed(1, 1000).to_bytes();
+        let ok = handle_rpc(&state, "testmempoolaccept", &json!([hex::encode(&raw)])).await.unwrap();
+        assert_eq!(ok["accepted"], true);
+        assert_eq!(ok["reject_reason"], Value::Null);
+        assert_eq!(ok["fee"], 1);
+        assert_eq!(ok["size"], raw.len());
+        assert_eq!(state.mempool.lock().await.size(), 0);
+
+        let gap = handle_rpc(&state, "testmempoolaccept", &json!([hex::encode(signed(3, 1000).to_bytes())])).await.unwrap();
+        assert_eq!(gap["accepted"], false);
+        assert!(gap["reject_reason"].as_str().unwrap().contains("bad nonce"));
+
+        let broke = handle_rpc(&state, "testmempoolaccept", &json!([hex::encode(signed(1, balance).to_bytes())])).await.unwrap();
+        assert_eq!(broke["reject_reason"], "insufficient balance");
+
+        // Once nonce 1 is pending, nonce 2 is checked as its successor.
+        state.mempool.lock().await.add_transaction(signed(1, 1000)).unwrap();
+        let next = handle_rpc(&state, "testmempoolaccept", &json!([hex::encode(signed(2, 1000).to_bytes())])).await.unwrap();
+        assert_eq!(next["accepted"], true);
+    }
+
+    #[tokio::test]
+    async fn test_sendrawtransaction_rejection_codes() {
+        use crate::primitives::transaction::Transaction;
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        crate::consensus::state::apply_block(&state.db, &easy_genesis()).unwrap();
+        let (pk, sk) = crate::crypto::dilithium::generate_keypair(&[3u8; 64]);
+        let addr = crate::crypto::keys::derive_address(&pk);
+        handle_rpc(&state, "generatetoaddress", &json!([1, hex::encode(addr)])).await.unwrap();
+        mature_now(&state, &addr);
+        let balance = state.db.get_account(&addr).unwrap().balance;
+
+        let raw = |nonce: u64, amount: u64, fee: u64| {
+            let mut tx = Transaction {
+                version: 1,
+                sender_address: addr,
+                sender_pubkey: pk,
+                recipient_address: [0x77; 32],
+                amount,
+                fee,
+                nonce,
+                timestamp: 100,
+                referrer_address: None,
+                governance_data: None,
+                proposal: None,
+                memo: None,
+                signature: crate::crypto::dilithium::Signature([0u8; 3309]),
+            };
+            tx.signature = crate::crypto::dilithium::sign(&tx.signing_hash(), &sk);
+            let stx = crate::node::db_common::StoredTransaction {
+                version: tx.version,
+                sender_address: tx.sender_address,
+                sender_pubkey: tx.sender_pubkey.0.to_vec(),
+                recipient_address: tx.recipient_address,
+                amount: tx.amount,
+                fee: tx.fee,
+                nonce: tx.nonce,
+                timestamp: tx.timestamp,
+                referrer_address: None,
+                governance_data: None,
+                proposal: None,
+                memo: None,
+                signature: tx.signature.0.to_vec(),
+            };
+            json!([hex::encode(stx.to_bytes())])
+        };
+        let code = |r: Result<Value, (i32, String)>| r.unwrap_err().0;
+
+        assert_eq!(code(handle_rpc(&state, "sendrawtransaction", &raw(1, balance, 1)).await), -32018);
+        assert_eq!(code(handle_rpc(&state, "sendrawtransaction", &raw(3, 1000, 1)).await), -32020);
+        assert_eq!(code(handle_rpc(&state, "sendrawtransaction", &raw(1, 1000, 0)).await), -32012);
+
+        handle_rpc(&state, "sendrawtransaction", &raw(1, 1000, 1)).await.unwrap();
+        let dup = handle_rpc(&state, "sendrawtransaction", &raw(1, 1000, 1)).await.unwrap_err();
+        assert_eq!(dup, (-32014, "mempool rejected: duplicate transaction".to_string()));
+        assert_eq!(code(handle_rpc(&state, "sendrawtransaction", &raw(1, 2000, 1)).await), -32015);
+    }
+
+    #[tokio::test]
+    async fn test_getmempoolconflicts_reports_rbf_bump() {
+        use crate::primitives::transaction::Transaction;
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        let (pk, sk) = crate::crypto::dilithium::generate_keypair(&[4u8; 64]);
+        let addr = crate::crypto::keys::derive_address(&pk);
+        let signed = |nonce: u64, fee: u64| {
+            let mut tx = Transaction {
+                version: 1,
+                sender_address: addr,
+                sender_pubkey: pk,
+                recipient_address: [0x77; 32],
+                amount: 5,
+                fee,
+                nonce,
+                timestamp: 100,
+                referrer_address: None,
+                governance_data: None,
+                proposal: None,
+                memo: None,
+                signature: crate::crypto::dilithium::Signature([0u8; 3309]),
+            };
+            tx.signature = crate::crypto::dilithium::sign(&tx.signing_hash(), &sk);
+            crate::node::db_common::StoredTransaction {
+                version: tx.version,
+                sender_address: tx.sender_address,
+                sender_pubkey: tx.sender_pubkey.0.to_vec(),
+                recipient_address: tx.recipient_address,
+                amount: tx.amount,
+                fee: tx.fee,
+                nonce: tx.nonce,
+                timestamp: tx.timestamp,
+                referrer_address: None,
+                governance_data: None,
+                proposal: None,
+                memo: None,
+                signature: tx.signature.0.to_vec(),
+            }
+        };
+        let pending = signed(1, 100);
+        let pending_id = crate::net::mempool::Mempool::compute_txid_from_stored(&pending);
+        state.mempool.lock().await.add_transaction(pending).unwrap();
+
+        let low = handle_rpc(&state, "getmempoolconflicts", &json!([hex::encode(signed(1, 110).to_bytes())])).await.unwrap();
+        let conflicts = low["conflicts"].as_array().unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0]["txid"], hex::encode(pending_id));
+        assert_eq!(conflicts[0]["min_replacement_fee"], 125);
+        assert_eq!(conflicts[0]["fee_bump_needed"], 15);
+        assert!(low["reject_reason"].as_str().unwrap().contains("125%"));
+
+        let enough = handle_rpc(&state, "getmempoolconflicts", &json!([hex::encode(signed(1, 125).to_bytes())])).await.unwrap();
+        assert_eq!(enough["conflicts"][0]["fee_bump_needed"], 0);
+        assert_eq!(enough["reject_reason"], Value::Null);
+
+        let other = handle_rpc(&state, "getmempoolconflicts", &json!([hex::encode(signed(2, 1).to_bytes())])).await.unwrap();
+        assert!(other["conflicts"].as_array().unwrap().is_empty());
+        assert_eq!(state.mempool.lock().await.size(), 1);
+        assert!(handle_rpc(&state, "getmempoolconflicts", &json!(["zz"])).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_getsupply() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        let empty = handle_rpc(&state, "getsupply", &json!([])).await.unwrap();
+        assert_eq!(empty["total_supply_knots"], 0);
+
+        crate::consensus::state::apply_block(&state.db, &easy_genesis()).unwrap();
+        let reward = crate::consensus::chain::calculate_block_reward(0);
+        let s = handle_rpc(&state, "getsupply", &json!([])).await.unwrap();
+        assert_eq!(s["height"], 0);
+        assert_eq!(s["total_supply_knots"], reward);
+        assert_eq!(s["total_supply_kot"], format!("{:.8}", reward as f64 / 1e8));
+    }
+
+    #[tokio::test]
+    async fn test_getmininghashps_uses_recent_window() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        let idle = handle_rpc(&state, "getmininghashps", &json!([])).await.unwrap();
+        assert_eq!(idle["window_seconds"], 60);
+        assert_eq!(idle["hashps"], 0);
+
+        // A long-finished burst of work followed by 30s at 100 nonces/s.
+        let now = now_secs();
+        {
+            let mut samples = state.mining_hashrate_samples.lock().await;
+            samples.record(now - 3_000, 0);
+            samples.record(now - 2_999, 1_000_000);
+            for t in (0..=30u64).rev() {
+                samples.record(now - t, 1_000_000 + (30 - t) * 100);
+            }
+        }
+        let r = handle_rpc(&state, "getmininghashps", &json!([20])).await.unwrap();
+        assert_eq!(r["window_seconds"], 20);
+        // The clock may tick between recording and the call.
+        let hashps = r["hashps"].as_u64().unwrap();
+        assert!((90..=100).contains(&hashps), "hashps {hashps}");
+
+        assert!(handle_rpc(&state, "getmininghashps", &json!([0])).await.is_err());
+        assert!(handle_rpc(&state, "getmininghashps", &json!([100_000])).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_getmempoolentry() {
+        use crate::primitives::transaction::Transaction;
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        let (pk, sk) = crate::crypto::dilithium::generate_keypair(&[4u8; 64]);
+        let addr = crate::crypto::keys::derive_address(&pk);
+        let mut tx = Transaction {
+            version: 1,
+            sender_address: addr,
+            sender_pubkey: pk,
+            recipient_address: [0x77; 32],
+            amount: 1000,
+            fee: 5,
+            nonce: 1,
+            timestamp: now_secs(),
+            referrer_address: None,
+            governance_data: None,
+            proposal: None,
+            memo: None,
+            signature: crate::crypto::dilithium::Signature([0u8; 3309]),
+        };
+        tx.signature = crate::crypto::dilithium::sign(&tx.signing_hash(), &sk);
+        let stx = crate::node::db_common::StoredTransaction {
+            version: tx.version,
+            sender_address: tx.sender_address,
+            sender_pubkey: tx.sender_pubkey.0.to_vec(),
+            recipient_address: tx.recipient_address,
+            amount: tx.amount,
+            fee: tx.fee,
+            nonce: tx.nonce,
+            timestamp: tx.timestamp,
+            referrer_address: None,
+            governance_data: None,
+            proposal: None,
+            memo: None,
+            signature: tx.signature.0.to_vec(),
+        };
+        let txid = crate::net::mempool::Mempool::compute_txid_from_stored(&stx);
+        let before = now_secs();
+        state.mempool.lock().await.add_transaction(stx).unwrap();
+
+        let e = handle_rpc(&state, "getmempoolentry", &json!([hex::encode(txid)])).await.unwrap();
+        let size = state.mempool.lock().await.get_entry(&txid).unwrap().size;
+        assert_eq!(e["txid"], hex::encode(txid));
+        assert_eq!(e["fee"], 5);
+        assert_eq!(e["size"], size);
+        assert!(e["fee_per_byte"].as_f64().unwrap() > 0.0);
+        assert_eq!(e["sender"], crate::crypto::keys::encode_address_string(&addr));
+        assert_eq!(e["recipient"], crate::crypto::keys::encode_address_string(&[0x77; 32]));
+        assert_eq!(e["amount"], 1000);
+        assert_eq!(e["nonce"], 1);
+        assert!(e["time"].as_u64().unwrap() >= before);
+        assert!(e["age_secs"].as_u64().unwrap() <= 5);
+
+        let missing = handle_rpc(&state, "getmempoolentry", &json!([hex::encode([1u8; 32])])).await.unwrap_err();
+        assert_eq!(missing.0, -32602);
+        let bad = handle_rpc(&state, "getmempoolentry", &json!(["zz"])).await.unwrap_err();
+        assert_eq!(bad.0, -32602);
+    }
+
+    #[tokio::test]
+    async fn test_getrawtransaction_with_and_without_index() {
+        use crate::primitives::transaction::Transaction;
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = test_state(&dir);
+        state.db = state.db.clone().with_tx_index(true);
+        crate::consensus::state::apply_block(&state.db, &easy_genesis()).unwrap();
+        let (pk, sk) = crate::crypto::dilithium::generate_keypair(&[8u8; 64]);
+        let addr = crate::crypto::keys::derive_address(&pk);
+        handle_rpc(&state, "generatetoaddress", &json!([1, hex::encode(addr)])).await.unwrap();
+        mature_now(&state, &addr);
+
+        let mut tx = Transaction {
+            version: 1,
+            sender_address: addr,
+            sender_pubkey: pk,
+            recipient_address: [0x77; 32],
+            amount: 1000,
+            fee: 1,
+            nonce: 1,
+            timestamp: now_secs(),
+            referrer_address: None,
+            governance_data: None,
+            proposal: None,
+            memo: None,
+            signature: crate::crypto::dilithium::Signature([0u8; 3309]),
+        };
+        tx.signature = crate::crypto::dilithium::sign(&tx.signing_hash(), &sk);
+        let raw = crate::node::db_common::StoredTransaction {
+            version: tx.version,
+            sender_address: tx.sender_address,
+            sender_pubkey: tx.sender_pubkey.0.to_vec(),
+            recipient_address: tx.recipient_address,
+            amount: tx.amount,
+            fee: tx.fee,
+            nonce: tx.nonce,
+            timestamp: tx.timestamp,
+            referrer_address: None,
+            governance_data: None,
+            proposal: None,
+            memo: None,
+            signature: tx.signature.0.to_vec(),
+        }
+        .to_bytes();
+        let txid = handle_rpc(&state, "sendrawtransaction", &json!([hex::encode(&raw)])).await.unwrap();
+
+        let pooled = handle_rpc(&state, "getrawtransaction", &json!([txid])).await.unwrap();
+        assert_eq!(pooled["confirmations"], 0);
+        assert_eq!(handle_rpc(&state, "getconfirmations", &json!([txid])).await.unwrap(), 0);
+        assert_eq!(pooled["block_height"], Value::Null);
+        assert_eq!(pooled["hex"], hex::encode(&raw));
+
+        handle_rpc(&state, "generatetoaddress", &json!([2, hex::encode([0x5A; 32])])).await.unwrap();
+        let block2 = hex::encode(state.db.get_block_hash_by_height(2).unwrap().unwrap());
+        let indexed = handle_rpc(&state, "getrawtransaction", &json!([txid])).await.unwrap();
+        assert_eq!(indexed["block_height"], 2);
+        assert_eq!(indexed["block_hash"], block2);
+        assert_eq!(indexed["confirmations"], 2);
+        assert_eq!(indexed["amount"], 1000);
+        assert_eq!(handle_rpc(&state, "getconfirmations", &json!([txid])).await.unwrap(), 2);
+        assert_eq!(handle_rpc(&state, "getconfirmations", &json!([hex::encode([1u8; 32])])).await.unwrap(), -1);
+        let history = handle_rpc(&state, "gettransactionhistory", &json!([crate::crypto::keys::encode_address_string(&addr)])).await.unwrap();
+        let sent = history["transactions"].as_array().unwrap().iter().find(|e| e["type"] == "sent").unwrap();
+        assert_eq!(sent["confirmations"], 2);
+        assert!(history["transactions"].as_array().unwrap().iter().all(|e| e["confirmations"].is_u64()));
+        let missing = handle_rpc(&state, "getrawtransaction", &json!([hex::encode([1u8; 32])])).await.unwrap_err();
+        assert_eq!(missing.1, "transaction not found");
+
+        // Without the index, recent blocks are scanned instead.
+        state.db = state.db.clone().with_tx_index(false);
+        let scanned = handle_rpc(&state, "getrawtransaction", &json!([txid])).await.unwrap();
+        assert_eq!(scanned["block_hash"], block2);
+        let missing = handle_rpc(&state, "getrawtransaction", &json!([hex::encode([1u8; 32])])).await.unwrap_err();
+        assert!(missing.1.contains("KNOTCOIN_TXINDEX"), "{}", missing.1);
+    }
+
+    #[tokio::test]
+    async fn test_reindex_refuses_while_mining() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = test_state(&dir);
+        state.db = state.db.clone().with_tx_index(true);
+        crate::consensus::state::apply_block(&state.db, &easy_genesis()).unwrap();
+
+        state.mining_active.store(true, Ordering::SeqCst);
+        let err = handle_rpc(&state, "reindex", &json!([])).await.unwrap_err();
+        assert!(err.1.contains("stop mining"), "{}", err.1);
+
+        state.mining_active.store(false, Ordering::SeqCst);
+        let r = handle_rpc(&state, "reindex", &json!([])).await.unwrap();
+        assert_eq!(r["tx_index"], true);
+        assert_eq!(r["blocks"], 1);
+        assert_eq!(r["total_supply_knots"], crate::consensus::chain::calculate_block_reward(0));
+    }
+
+    #[tokio::test]
+    async fn test_getstatesnapshothash() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        crate::consensus::state::apply_block(&state.db, &easy_genesis()).unwrap();
+        handle_rpc(&state, "generatetoaddress", &json!([2, hex::encode([0x5A; 32])])).await.unwrap();
+
+        let tip = handle_rpc(&state, "getstatesnapshothash", &json!([])).await.unwrap();
+        assert_eq!(tip["height"], 2);
+        assert_eq!(tip["hash"], hex::encode(state.db.state_snapshot(2).unwrap().hash()));
+        let one = handle_rpc(&state, "getstatesnapshothash", &json!([1])).await.unwrap();
+        assert_ne!(one["hash"], tip["hash"]);
+        assert_eq!(one["tip"], hex::encode(state.db.get_block_hash_by_height(1).unwrap().unwrap()));
+
+        let err = handle_rpc(&state, "getstatesnapshothash", &json!([5])).await.unwrap_err();
+        assert_eq!(err.0, -32602);
+    }
+
+    #[tokio::test]
+    async fn test_getaddresstransactions_pages_by_cursor() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        crate::consensus::state::apply_block(&state.db, &easy_genesis()).unwrap();
+        let miner = hex::encode([0x5A; 32]);
+        handle_rpc(&state, "generatetoaddress", &json!([3, miner])).await.unwrap();
+        let miner = crate::crypto::keys::encode_address_string(&[0x5A; 32]);
+
+        let all = handle_rpc(&state, "getaddresstransactions", &json!([miner])).await.unwrap();
+        assert_eq!(all["count"], 3);
+        assert_eq!(all["transactions"][0]["block_height"], 1);
+        assert_eq!(all["next_cursor"], Value::Null);
+
+        let from = handle_rpc(&state, "getaddresstransactions", &json!([miner, 2])).await.unwrap();
+        assert_eq!(from["count"], 2);
+
+        let rest = handle_rpc(&state, "getaddresstransactions", &json!([miner, 0, 2])).await.unwrap();
+        assert_eq!(rest["count"], 1);
+        assert_eq!(rest["transactions"][0]["block_height"], 3);
+
+        let err = handle_rpc(&state, "getaddresstransactions", &json!(["nope"])).await.unwrap_err();
+        assert_eq!(err.0, -32602);
+    }
+
+    #[tokio::test]
+    async fn test_verifychain_reports_first_bad_height() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        crate::consensus::state::apply_block(&state.db, &easy_genesis()).unwrap();
+        let pool = hex::encode([0x5A; 32]);
+        handle_rpc(&state, "generatetoaddress", &json!([2, pool])).await.unwrap();
+
+        let ok = handle_rpc(&state, "verifychain", &json!([true])).await.unwrap();
+        assert_eq!(ok["checked"], 3);
+        assert_eq!(ok["first_error_height"], Value::Null);
+
+        // Point height 2 at the genesis block.
+        let cf = state.db.db.cf_handle("heights").unwrap();
+        let genesis = state.db.get_block_hash_by_height(0).unwrap().unwrap();
+        state.db.db.put_cf(cf, 2u32.to_le_bytes(), genesis).unwrap();
+
+        let bad = handle_rpc(&state, "verifychain", &json!([])).await.unwrap();
+        assert_eq!(bad["checked"], 2);
+        assert_eq!(bad["first_error_height"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_stop_signals_shutdown() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        let mut rx = state.shutdown_tx.subscribe();
+        assert_eq!(handle_rpc(&state, "stop", &json!([])).await.unwrap(), "stopping");
+        assert!(state.shutdown.load(Ordering::SeqCst));
+        assert!(state.mining_stop.load(Ordering::SeqCst));
+        assert!(rx.try_recv().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_getblockchaininfo_summarizes_tip() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        crate::consensus::state::apply_block(&state.db, &easy_genesis()).unwrap();
+        handle_rpc(&state, "generatetoaddress", &json!([2, hex::encode([0x5A; 32])])).await.unwrap();
+        state.db.flush().unwrap();
+
+        let info = handle_rpc(&state, "getblockchaininfo", &json!([])).await.unwrap();
+        let tip = state.db.get_tip().unwrap().unwrap();
+        assert_eq!(info["chain"], "mainnet");
+        assert_eq!(info["blocks"], 2);
+        assert_eq!(info["last_tip_update"], state.last_tip_update.load(Ordering::Relaxed));
+        assert_eq!(info["bestblockhash"], hex::encode(tip));
+        assert_eq!(info["mediantime"], crate::consensus::state::median_time_past(&state.db, 3).unwrap());
+        assert_eq!(info["verificationprogress"], 1.0);
+        assert_eq!(info["pruned"], false);
+        assert!(info["size_on_disk"].as_u64().unwrap() > 0);
+        assert_eq!(info["ponc_rounds"], crate::consensus::chain::PONC_ROUNDS_DEFAULT);
+    }
+
+    #[tokio::test]
+    async fn test_regtest_reports_network_and_keeps_trivial_target() {
+        use crate::config::Network;
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = test_state(&dir);
+        state.db = state.db.clone().with_network(Network::Regtest);
+        state.config = Arc::new(crate::config::Config { network: Network::Regtest, ..(*state.config).clone() });
+        let genesis = crate::consensus::genesis::genesis_block(Network::Regtest);
+        crate::consensus::state::apply_block(&state.db, &genesis).unwrap();
+        handle_rpc(&state, "generatetoaddress", &json!([1, hex::encode([0x5A; 32])])).await.unwrap();
+
+        let tip = state.db.get_block(&state.db.get_tip().unwrap().unwrap()).unwrap().unwrap();
+        assert_eq!(tip.difficulty_target, [0xFF; 32]);
+        let info = handle_rpc(&state, "getblockchaininfo", &json!([])).await.unwrap();
+        assert_eq!(info["chain"], "regtest");
+        let mining = handle_rpc(&state, "getmininginfo", &json!([])).await.unwrap();
+        assert_eq!(mining["network"], "regtest");
+        let net = handle_rpc(&state, "getnetworkinfo", &json!([])).await.unwrap();
+        assert_eq!(net["magic"], hex::encode(Network::Regtest.magic()));
+    }
+
+    #[tokio::test]
+    async fn test_submitblock_rejects_garbage() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        crate::consensus::state::apply_block(&state.db, &easy_genesis()).unwrap();
+        assert!(handle_rpc(&state, "submitblock", &json!(["zz"])).await.is_err());
+        assert!(handle_rpc(&state, "submitblock", &json!(["00ff"])).await.is_err());
+        assert!(handle_rpc(&state, "getblocktemplate", &json!(["not-an-address"])).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_wallet_send_memo_shows_in_block() {
+        use sha2::{Digest, Sha256};
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        crate::consensus::state::apply_block(&state.db, &easy_genesis()).unwrap();
+        let mnemonic = "abandon ".repeat(23) + "art";
+        let (pk, sk) = crate::crypto::keys::derive_keypair_from_mnemonic(&mnemonic);
+        let hash: [u8; 32] = Sha256::digest(mnemonic.as_bytes()).into();
+        save_wallet_keys_to_disk(&state.data_dir, &hash, &pk, &sk);
+        let sender = crate::crypto::keys::derive_address(&pk);
+        state.db.put_account(&sender, &crate::node::db_common::AccountState {
+            balance: 10_0000_0000,
+            ..crate::node::db_common::AccountState::empty()
+        }).unwrap();
+
+        let recipient = crate::crypto::keys::encode_address_string(&[0x22u8; 32]);
+        for bad in [json!(-1.0), json!(1e12)] {
+            let err = handle_rpc(&state, "wallet_send", &json!([mnemonic, recipient, bad])).await.unwrap_err();
+            assert_eq!(err.0, -32602, "{}", err.1);
+        }
+        let long = "x".repeat(81);
+        let err = handle_rpc(&state, "wallet_send", &json!([mnemonic, recipient, 1.0, null, long])).await.unwrap_err();
+        assert_eq!(err.0, -32602);
+        // Two early blocks issue about 0.2 KOT, the most one payment can move.
+        handle_rpc(&state, "wallet_send", &json!([mnemonic, recipient, 0.01, null, "order 1042"])).await.unwrap();
+
+        handle_rpc(&state, "generatetoaddress", &json!([1, hex::encode([0x5A; 32])])).await.unwrap();
+        let tip = hex::encode(state.db.get_tip().unwrap().unwrap());
+        let block = handle_rpc(&state, "getblock", &json!([tip])).await.unwrap();
+        assert_eq!(block["transactions"][0]["memo"], hex::encode("order 1042"));
+
+        let stored = state.db.get_block(&state.db.get_tip().unwrap().unwrap()).unwrap().unwrap();
+        let size = stored.tx_data[0].to_bytes().len();
+        let tx = &block["transactions"][0];
+        assert_eq!(tx["size"], size);
+        assert_eq!(tx["fee_per_byte"].as_f64().unwrap(), stored.tx_data[0].fee as f64 / size as f64);
+        let by_height = handle_rpc(&state, "getblockbyheight", &json!([-1])).await.unwrap();
+        assert_eq!(by_height["transactions"][0]["size"], size);
+        assert_eq!(by_height["transactions"][0]["fee_per_byte"], tx["fee_per_byte"]);
+    }
+
+    #[tokio::test]
+    async fn test_wallet_export_import_moves_keys() {
+        let (dir_a, dir_b) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let (a, b) = (test_state(&dir_a), test_state(&dir_b));
+        let mnemonic = "abandon ".repeat(23) + "art";
+        let (pk, sk) = crate::crypto::keys::derive_keypair_from_mnemonic(&mnemonic);
+        save_wallet_keys_to_disk(&a.data_dir, &mnemonic_hash(&mnemonic), &pk, &sk);
+        let address = crate::crypto::keys::encode_address_string(&crate::crypto::keys::derive_address(&pk));
+
+        let err = handle_rpc(&a, "wallet_export", &json!([mnemonic, ""])).await.unwrap_err();
+        assert_eq!(err.0, -32602);
+        let exported = handle_rpc(&a, "wallet_export", &json!([mnemonic, "hunter2"])).await.unwrap();
+        assert_eq!(exported["address"], address);
+        let blob = exported["blob"].as_str().unwrap();
+
+        let err = handle_rpc(&b, "wallet_import", &json!([blob, "wrong"])).await.unwrap_err();
+        assert_eq!(err.0, -32602);
+        assert!(!wallet_keys_file(&b.data_dir).exists());
+
+        let imported = handle_rpc(&b, "wallet_import", &json!([blob, "hunter2"])).await.unwrap();
+        assert_eq!(imported["address"], address);
+        let got = handle_rpc(&b, "wallet_get_address", &json!([mnemonic])).await.unwrap();
+        assert_eq!(got["address"], address);
+
+        // A profile holding another wallet refuses the import.
+        let other = "zoo ".repeat(23) + "wrong";
+        let (pk2, sk2) = crate::crypto::keys::derive_keypair_from_mnemonic(&other);
+        save_wallet_keys_to_disk(&a.data_dir, &mnemonic_hash(&other), &pk2, &sk2);
+        let err = handle_rpc(&a, "wallet_import", &json!([blob, "hunter2"])).await.unwrap_err();
+        assert!(err.1.contains("different mnemonic"), "{}", err.1);
+    }
+
+    #[tokio::test]
+    async fn test_wallet_bump_fee_replaces_pending() {
+        use sha2::{Digest, Sha256};
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        let mnemonic = "abandon ".repeat(23) + "art";
+        let (pk, sk) = crate::crypto::keys::derive_keypair_from_mnemonic(&mnemonic);
+        let hash: [u8; 32] = Sha256::digest(mnemonic.as_bytes()).into();
+        save_wallet_keys_to_disk(&state.data_dir, &hash, &pk, &sk);
+        let sender = crate::crypto::keys::derive_address(&pk);
+        state.db.put_account(&sender, &crate::node::db_common::AccountState {
+            balance: 10_0000_0000,
+            ..crate::node::db_common::AccountState::empty()
+        }).unwrap();
+
+        let recipient = crate::crypto::keys::encode_address_string(&[0x22u8; 32]);
+        let sent = handle_rpc(&state, "wallet_send", &json!([mnemonic, recipient, 1.0])).await.unwrap();
+        assert_eq!(sent["fee"], 1);
+
+        let err = handle_rpc(&state, "wallet_bump_fee", &json!([mnemonic, 1, 1])).await.unwrap_err();
+        assert_eq!(err.0, -32602);
+
+        let bumped = handle_rpc(&state, "wallet_bump_fee", &json!([mnemonic, 1])).await.unwrap();
+        assert_eq!(bumped["fee"], 2);
+        assert_eq!(bumped["replaced"], sent["txid"]);
+        let pool = state.mempool.lock().await;
+        assert_eq!(pool.size(), 1);
+        assert_eq!(pool.get_by_sender_nonce(&sender, 1).unwrap().fee, 2);
+        drop(pool);
+
+        assert!(handle_rpc(&state, "wallet_bump_fee", &json!([mnemonic, 9])).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_accounts_root_tracks_connects_and_reorgs() {
+        use crate::consensus::state::{accounts_merkle_root, accounts_root};
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        crate::consensus::state::apply_block(&state.db, &easy_genesis()).unwrap();
+        handle_rpc(&state, "generatetoaddress", &json!([2, hex::encode([0x5A; 32])])).await.unwrap();
+
+        let expected = |db: &ChainDB| {
+            let accounts = db.iter_accounts().unwrap();
+            accounts_merkle_root(accounts.iter().map(|(a, acc)| (a, acc)))
+        };
+        let stored = state.db.get_accounts_root().unwrap().unwrap();
+        assert_eq!(stored.root, expected(&state.db));
+        assert_eq!(stored.tip, state.db.get_tip().unwrap().unwrap());
+
+        let r = handle_rpc(&state, "getaccountsroot", &json!([])).await.unwrap();
+        assert_eq!(r["height"], 2);
+        assert_eq!(r["root"], hex::encode(stored.root));
+        assert_eq!(r["tip"], hex::encode(stored.tip));
+
+        // A stale entry (tip moved without it) is ignored and recomputed.
+        let mut acc = state.db.get_account(&[0x5A; 32]).unwrap();
+        acc.nonce += 1;
+        state.db.put_account(&[0x5A; 32], &acc).unwrap();
+        state.db.set_tip(&[0x77; 32]).unwrap();
+        assert_eq!(accounts_root(&state.db).unwrap(), expected(&state.db));
+        assert_ne!(accounts_root(&state.db).unwrap(), stored.root);
+
+        assert_eq!(accounts_merkle_root(std::iter::empty()), [0u8; 32]);
+    }
+
+    #[tokio::test]
+    async fn test_reward_schedule_matches_consensus() {
+        use crate::consensus::chain::calculate_block_reward;
+        const PHASE_1_END: u64 = 262_800;
+        const PHASE_2_END: u64 = 525_600;
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+
+        let points = handle_rpc(&state, "getrewardschedule", &json!([0, 5, PHASE_1_END])).await.unwrap();
+        let points = points.as_array().unwrap();
+        assert_eq!(points.len(), 5);
+        for (i, p) in points.iter().enumerate() {
+            let h = i as u64 * PHASE_1_END;
+            assert_eq!(p["height"], h);
+            assert_eq!(p["reward_knots"], calculate_block_reward(h));
+        }
+        assert_eq!(points[0]["reward_knots"], 10_000_000);
+        assert_eq!(points[1]["reward_knots"], 100_000_000);
+
+        // The curve is continuous into phase 3.
+        let subsidy = handle_rpc(&state, "getblocksubsidy", &json!([PHASE_2_END + 1])).await.unwrap();
+        assert_eq!(subsidy, 100_000_000);
+        assert_eq!(handle_rpc(&state, "getblocksubsidy", &json!([7])).await.unwrap(), calculate_block_reward(7));
+
+        for bad in [json!([0, 0, 1]), json!([0, 10, 0]), json!([0, 10_001, 1]), json!([u32::MAX, 2, 1])] {
+            assert_eq!(handle_rpc(&state, "getrewardschedule", &bad).await.unwrap_err().0, -32602, "{bad}");
+        }
+        assert!(handle_rpc(&state, "getblocksubsidy", &json!([u64::MAX])).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sendmany_reports_failures_without_nonce_gaps() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        let mnemonic = "abandon ".repeat(23) + "art";
+        let (pk, sk) = crate::crypto::keys::derive_keypair_from_mnemonic(&mnemonic);
+        save_wallet_keys_to_disk(&state.data_dir, &mnemonic_hash(&mnemonic), &pk, &sk);
+        let sender = crate::crypto::keys::derive_address(&pk);
+        state.db.put_account(&sender, &crate::node::db_common::AccountState {
+            balance: 10_0000_0000,
+            ..crate::node::db_common::AccountState::empty()
+        }).unwrap();
+
+        let a = crate::crypto::keys::encode_address_string(&[0x22u8; 32]);
+        let b = crate::crypto::keys::encode_address_string(&[0x33u8; 32]);
+        let c = crate::crypto::keys::encode_address_string(&[0x44u8; 32]);
+        handle_rpc(&state, "wallet_send", &json!([mnemonic, a, 1.0])).await.unwrap();
+
+        let mut payments = serde_json::Map::new();
+        payments.insert(a.clone(), json!(1.0));
+        payments.insert("not-an-address".to_string(), json!(1.0));
+        payments.insert(b.clone(), json!(100.0));
+        payments.insert(c.clone(), json!(2.5));
+        let out = handle_rpc(&state, "sendmany", &json!([mnemonic, payments])).await.unwrap();
+
+        let sent = out["sent"].as_array().unwrap();
+        let failed = out["failed"].as_array().unwrap();
+        assert_eq!(sent.len(), 2, "{out}");
+        assert_eq!(failed.len(), 2, "{out}");
+        assert!(failed.iter().any(|f| f["recipient"] == b && f["error"] == "insufficient balance"));
+        assert!(failed.iter().any(|f| f["recipient"] == "not-an-address"));
+        // Nonce 1 was already pending; the batch continues at 2 and 3.
+        let mut nonces: Vec<u64> = sent.iter().map(|s| s["nonce"].as_u64().unwrap()).collect();
+        nonces.sort();
+        assert_eq!(nonces, vec![2, 3]);
+        assert!(sent.iter().any(|s| s["recipient"] == c && s["amount"] == "2.50000000"));
+        assert_eq!(state.mempool.lock().await.highest_pending_nonce_for_sender(&sender), Some(3));
+
+        assert_eq!(handle_rpc(&state, "sendmany", &json!([mnemonic, {}])).await.unwrap_err().0, -32602);
+        assert_eq!(handle_rpc(&state, "sendmany", &json!([mnemonic, [a]])).await.unwrap_err().0, -32602);
+    }
+
+    #[tokio::test]
+    async fn test_wallet_send_refuses_behind_nonce_gap() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        let mnemonic = "abandon ".repeat(23) + "art";
+        let (pk, sk) = crate::crypto::keys::derive_keypair_from_mnemonic(&mnemonic);
+        save_wallet_keys_to_disk(&state.data_dir, &mnemonic_hash(&mnemonic), &pk, &sk);
+        let sender = crate::crypto::keys::derive_address(&pk);
+        state.db.put_account(&sender, &crate::node::db_common::AccountState {
+            balance: 10_0000_0000,
+            ..crate::node::db_common::AccountState::empty()
+        }).unwrap();
+
+        let recipient = crate::crypto::keys::encode_address_string(&[0x22u8; 32]);
+        for _ in 0..3 {
+            handle_rpc(&state, "wallet_send", &json!([mnemonic, recipient, 1.0])).await.unwrap();
+        }
+
+        // Dropping nonce 2 leaves 3 stranded behind a gap.
+        let txid2 = Mempool::compute_txid_from_stored(state.mempool.lock().await.get_by_sender_nonce(&sender, 2).unwrap());
+        state.mempool.lock().await.remove_confirmed(&[txid2]);
+        let err = handle_rpc(&state, "wallet_send", &json!([mnemonic, recipient, 1.0])).await.unwrap_err();
+        assert_eq!(err.0, -32020);
+        assert!(err.1.contains("nonce gap"), "{}", err.1);
+        assert!(err.1.contains("wallet_bump_fee"));
+
+        // Without the stranded tx, the next send fills nonce 2.
+        let txid3 = Mempool::compute_txid_from_stored(state.mempool.lock().await.get_by_sender_nonce(&sender, 3).unwrap());
+        state.mempool.lock().await.remove_confirmed(&[txid3]);
+        let next = handle_rpc(&state, "wallet_send", &json!([mnemonic, recipient, 1.0])).await.unwrap();
+        assert_eq!(next["nonce"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_wallet_propose_records_proposal_on_chain() {
+        use sha2::{Digest, Sha256};
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        crate::consensus::state::apply_block(&state.db, &easy_genesis()).unwrap();
+        let mnemonic = "abandon ".repeat(23) + "art";
+        let (pk, sk) = crate::crypto::keys::derive_keypair_from_mnemonic(&mnemonic);
+        let hash: [u8; 32] = Sha256::digest(mnemonic.as_bytes()).into();
+        save_wallet_keys_to_disk(&state.data_dir, &hash, &pk, &sk);
+        let sender = crate::crypto::keys::derive_address(&pk);
+        handle_rpc(&state, "generatetoaddress", &json!([1, hex::encode(sender)])).await.unwrap();
+        mature_now(&state, &sender);
+
+        let bad = handle_rpc(&state, "wallet_propose", &json!([mnemonic, "x", "block_size", 1])).await;
+        assert_eq!(bad.unwrap_err().0, -32602);
+        let bad = handle_rpc(&state, "wallet_propose", &json!([mnemonic, "x", "mining_threads", 99])).await;
+        assert_eq!(bad.unwrap_err().0, -32602);
+
+        let res = handle_rpc(&state, "wallet_propose", &json!([mnemonic, "Six threads", "mining_threads", 6])).await.unwrap();
+        let prop = res["proposal_hash"].as_str().unwrap().to_string();
+        assert_eq!(handle_rpc(&state, "getgovernanceproposal", &json!([prop])).await.unwrap_err().0, -32602);
+
+        handle_rpc(&state, "generatetoaddress", &json!([1, hex::encode([0x5A; 32])])).await.unwrap();
+        let got = handle_rpc(&state, "getgovernanceproposal", &json!([prop])).await.unwrap();
+        assert_eq!(got["title"], "Six threads");
+        assert_eq!(got["param"], "mining_threads");
+        assert_eq!(got["value"], 6);
+        assert_eq!(got["height"], 2);
+        assert_eq!(got["proposer"], crate::crypto::keys::encode_address_string(&sender));
+        assert_eq!(got["is_passed"], false);
+    }
+
+    #[tokio::test]
+    async fn test_compactdb_reports_sizes() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        crate::consensus::state::apply_block(&state.db, &easy_genesis()).unwrap();
+        handle_rpc(&state, "generatetoaddress", &json!([3, hex::encode([0x5A; 32])])).await.unwrap();
+        let out = handle_rpc(&state, "compactdb", &json!([])).await.unwrap();
+        assert!(out["size_before"].as_u64().unwrap() > 0);
+        assert!(out["size_after"].as_u64().unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_getwalletinfo_summarizes_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        crate::consensus::state::apply_block(&state.db, &easy_genesis()).unwrap();
+        let mnemonic = "abandon ".repeat(23) + "art";
+
+        let fresh = handle_rpc(&state, "getwalletinfo", &json!([mnemonic])).await.unwrap();
+        assert_eq!(fresh["has_wallet_keys"], false);
+        assert_eq!(fresh["has_wallet_dat"], false);
+        assert_eq!(fresh["balance_knots"], 0);
+        assert_eq!(fresh["nonce"], 0);
+        assert_eq!(fresh["pending_nonce"], Value::Null);
+        assert_eq!(fresh["referred_by"], Value::Null);
+
+        let addr = handle_rpc(&state, "wallet_get_address", &json!([mnemonic])).await.unwrap();
+        assert_eq!(fresh["address"], addr["address"]);
+        let raw_addr = crate::crypto::keys::decode_address_string(addr["address"].as_str().unwrap()).unwrap();
+        handle_rpc(&state, "generatetoaddress", &json!([1, hex::encode(raw_addr)])).await.unwrap();
+        mature_now(&state, &raw_addr);
+        handle_rpc(&state, "wallet_send", &json!([mnemonic, crate::crypto::keys::encode_address_string(&[0x22u8; 32]), 0.01])).await.unwrap();
+
+        let info = handle_rpc(&state, "getwalletinfo", &json!([mnemonic])).await.unwrap();
+        assert_eq!(info["has_wallet_keys"], true);
+        assert!(info["balance_knots"].as_u64().unwrap() > 0);
+        assert_eq!(info["pending_nonce"], 1);
+        assert_eq!(info["privacy_code"], privacy_code(&raw_addr));
+
+        let other = "zoo ".repeat(23) + "wrong";
+        assert!(handle_rpc(&state, "getwalletinfo", &json!([other])).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_wallet_derive_address_indices_share_identity() {
+        use sha2::{Digest, Sha256};
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir);
+        let mnemonic = "abandon ".repeat(23) + "art";
+        let (pk, sk) = crate::crypto::keys::derive_keypair_from_mnemonic(&mnemonic);
+        let hash: [u8; 32] = Sha256::digest(mnemonic.as_bytes()).into();
+        save_wallet_keys_to_disk(&state.data_dir, &hash, &pk, &sk);
+
+        let base = handle_rpc(&state, "wallet_get_address", &json!([mnemonic])).await.unwrap();
+        let idx0 = handle_rpc(&state, "wallet_derive_address", &json!([mnemonic, 0])).await.unwrap();
+        let idx7 = handle_rpc(&state, "wallet_derive_address", &json!([mnemonic, 7])).await.unwrap();
+        assert_eq!(idx0["address"], base["address"]);
+        assert_ne!(idx7["address"], base["address"]);
+        assert_eq!(idx7["index"], 7);
+
+        let other = "zoo ".repeat(23) + "wrong";
+        assert!(handle_rpc(&state, "wallet_derive_address", &json!([other, 1])).await.is_err());
+        assert!(handle_rpc(&state, "wallet_derive_address", &json!([mnemonic, -1])).await.is_err());
+    }
+}
@@ -0,0 +1,242 @@
+// WebSocket Notifications
+//
+// `GET /ws` on the RPC port upgrades to a WebSocket after the same bearer
+// token check as plain HTTP. Clients send JSON-RPC `subscribe` /
+// `unsubscribe` requests naming topics (`newblock`, `newtx`) and from then
+// on receive notifications shaped like
+// `{"jsonrpc":"2.0","method":"newblock","params":{"hash":..,"height":..}}`
+// as blocks become our tip and transactions enter the mempool.
+
+use std::sync::Arc;
+
+use futures_util::{SinkExt, StreamExt};
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::{Request, Response};
+use hyper_util::rt::TokioIo;
+use log::warn;
+use serde_json::{Value, json};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::{Message, Role};
+
+use crate::consensus::state::block_hash;
+use crate::node::ChainDB;
+use crate::node::db_common::StoredBlock;
+use crate::rpc::server::RpcState;
+
+pub const TOPIC_NEW_BLOCK: &str = "newblock";
+pub const TOPIC_NEW_TX: &str = "newtx";
+
+/// Tip changes as (block hash, height).
+pub type BlockEvents = broadcast::Sender<([u8; 32], u32)>;
+
+pub fn block_events() -> BlockEvents {
+    broadcast::channel(64).0
+}
+
+/// Publish `block` if applying it made it our tip. A side-branch block that
+/// didn't trigger a reorg is not news to subscribers.
+pub fn notify_block(events: &BlockEvents, db: &ChainDB, block: &StoredBlock) {
+    let hash = block_hash(block);
+    if db.get_tip().ok().flatten() == Some(hash) {
+        let _ = events.send((hash, u32::from_le_bytes(block.block_height)));
+    }
+}
+
+pub fn is_upgrade_request(req: &Request<Incoming>) -> bool {
+    req.uri().path() == "/ws"
+        && req
+            .headers()
+            .get(hyper::header::UPGRADE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.eq_ignore_ascii_case("websocket"))
+}
+
+/// Answer the upgrade handshake and hand the connection to a notification
+/// task once hyper releases the socket.
+pub async fn upgrade(state: Arc<RpcState>, mut req: Request<Incoming>) -> Response<Full<Bytes>> {
+    let Some(key) = req.headers().get("sec-websocket-key").map(|k| derive_accept_key(k.as_bytes())) else {
+        return Response::builder()
+            .status(hyper::StatusCode::BAD_REQUEST)
+            .body(Full::new(Bytes::from("missing Sec-WebSocket-Key")))
+            .unwrap();
+    };
+
+    let blocks = state.block_events.subscribe();
+    let txs = state.mempool.lock().await.subscribe();
+    tokio::spawn(async move {
+        match hyper::upgrade::on(&mut req).await {
+            Ok(upgraded) => {
+                let ws = WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Server, None).await;
+                serve(ws, blocks, txs).await;
+            }
+            Err(e) => warn!("[rpc] websocket upgrade failed: {e}"),
+        }
+    });
+
+    Response::builder()
+        .status(hyper::StatusCode::SWITCHING_PROTOCOLS)
+        .header(hyper::header::UPGRADE, "websocket")
+        .header(hyper::header::CONNECTION, "Upgrade")
+        .header("Sec-WebSocket-Accept", key)
+        .body(Full::new(Bytes::new()))
+        .unwrap()
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Subscriptions {
+    blocks: bool,
+    txs: bool,
+}
+
+/// Apply one client request to `subs` and build the JSON-RPC reply.
+fn handle_control(text: &str, subs: &mut Subscriptions) -> Value {
+    let v: Value = match serde_json::from_str(text) {
+        Ok(v) => v,
+        Err(e) => {
+            return json!({
+                "jsonrpc": "2.0",
+                "error": {"code": -32700, "message": format!("parse error: {e}")},
+                "id": null,
+            });
+        }
+    };
+    let id = v.get("id").cloned().unwrap_or(json!(null));
+    let on = match v.get("method").and_then(|m| m.as_str()) {
+        Some("subscribe") => true,
+        Some("unsubscribe") => false,
+        _ => {
+            return json!({
+                "jsonrpc": "2.0",
+                "error": {"code": -32601, "message": "expected subscribe or unsubscribe"},
+                "id": id,
+            });
+        }
+    };
+
+    let topics: Vec<&str> = v
+        .get("params")
+        .and_then(|p| p.as_array())
+        .map(|a| a.iter().filter_map(|t| t.as_str()).collect())
+        .unwrap_or_default();
+    for t in &topics {
+        match *t {
+            TOPIC_NEW_BLOCK => subs.blocks = on,
+            TOPIC_NEW_TX => subs.txs = on,
+            other => {
+                return json!({
+                    "jsonrpc": "2.0",
+                    "error": {"code": -32602, "message": format!("unknown topic: {other}")},
+                    "id": id,
+                });
+            }
+        }
+    }
+
+    let mut active = Vec::new();
+    if subs.blocks {
+        active.push(TOPIC_NEW_BLOCK);
+    }
+    if subs.txs {
+        active.push(TOPIC_NEW_TX);
+    }
+    json!({ "jsonrpc": "2.0", "result": active, "id": id })
+}
+
+fn notification(topic: &str, params: Value) -> Message {
+    Message::Text(json!({ "jsonrpc": "2.0", "method": topic, "params": params }).to_string())
+}
+
+async fn serve<S>(
+    mut ws: WebSocketStream<S>,
+    mut blocks: broadcast::Receiver<([u8; 32], u32)>,
+    mut txs: broadcast::Receiver<[u8; 32]>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut subs = Subscriptions::default();
+    loop {
+        let out = tokio::select! {
+            biased;
+            msg = ws.next() => match msg {
+                Some(Ok(Message::Text(t))) => Some(Message::Text(handle_control(&t, &mut subs).to_string())),
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => None,
+            },
+            ev = blocks.recv() => match ev {
+                Ok((hash, height)) if subs.blocks => {
+                    Some(notification(TOPIC_NEW_BLOCK, json!({ "hash": hex::encode(hash), "height": height })))
+                }
+                Err(RecvError::Closed) => break,
+                _ => None,
+            },
+            ev = txs.recv() => match ev {
+                Ok(txid) if subs.txs => Some(notification(TOPIC_NEW_TX, json!({ "txid": hex::encode(txid) }))),
+                Err(RecvError::Closed) => break,
+                _ => None,
+            },
+        };
+        if let Some(m) = out
+            && ws.send(m).await.is_err()
+        {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscribe_and_unsubscribe_topics() {
+        let mut subs = Subscriptions::default();
+        let r = handle_control(r#"{"method":"subscribe","params":["newblock","newtx"],"id":1}"#, &mut subs);
+        assert_eq!(r["result"], json!(["newblock", "newtx"]));
+        assert_eq!(subs, Subscriptions { blocks: true, txs: true });
+
+        let r = handle_control(r#"{"method":"unsubscribe","params":["newtx"],"id":2}"#, &mut subs);
+        assert_eq!(r["result"], json!(["newblock"]));
+        assert_eq!(r["id"], 2);
+
+        let r = handle_control(r#"{"method":"subscribe","params":["blocks"]}"#, &mut subs);
+        assert_eq!(r["error"]["code"], -32602);
+        let r = handle_control("not json", &mut subs);
+        assert_eq!(r["error"]["code"], -32700);
+    }
+
+    #[tokio::test]
+    async fn test_notifications_only_for_subscribed_topics() {
+        let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+        let (block_tx, _) = broadcast::channel(8);
+        let (tx_tx, _) = broadcast::channel(8);
+        let server = WebSocketStream::from_raw_socket(server_io, Role::Server, None).await;
+        tokio::spawn(serve(server, block_tx.subscribe(), tx_tx.subscribe()));
+        let mut client = WebSocketStream::from_raw_socket(client_io, Role::Client, None).await;
+
+        async fn next_json<S: AsyncRead + AsyncWrite + Unpin>(c: &mut WebSocketStream<S>) -> Value {
+            let m = tokio::time::timeout(std::time::Duration::from_secs(5), c.next()).await.unwrap().unwrap().unwrap();
+            serde_json::from_str(m.to_text().unwrap()).unwrap()
+        }
+
+        client.send(Message::Text(r#"{"method":"subscribe","params":["newtx"],"id":1}"#.into())).await.unwrap();
+        assert_eq!(next_json(&mut client).await["result"], json!(["newtx"]));
+
+        // Not subscribed to blocks: this one is dropped.
+        block_tx.send(([1u8; 32], 5)).unwrap();
+        tx_tx.send([2u8; 32]).unwrap();
+        let n = next_json(&mut client).await;
+        assert_eq!(n["method"], TOPIC_NEW_TX);
+        assert_eq!(n["params"]["txid"], hex::encode([2u8; 32]));
+
+        client.send(Message::Text(r#"{"method":"subscribe","params":["newblock"],"id":2}"#.into())).await.unwrap();
+        assert_eq!(next_json(&mut client).await["id"], 2);
+        block_tx.send(([3u8; 32], 6)).unwrap();
+        let n = next_json(&mut client).await;
+        assert_eq!(n["method"], TOPIC_NEW_BLOCK);
+        assert_eq!(n["params"]["height"], 6);
+    }
+}
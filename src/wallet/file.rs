@@ -2,7 +2,7 @@
 // Implements wallet.dat file format for persistent key storage
 
 use aes_gcm::{
-    aead::{Aead, KeyInit},
+    aead::{Aead, KeyInit, Payload},
     Aes256Gcm, Nonce,
 };
 use argon2::{Argon2, PasswordHasher};
@@ -30,6 +30,102 @@ pub enum WalletFileError {
     NotFound,
     #[error("Wallet file corrupted")]
     Corrupted,
+    #[error("Unsupported export version {0}")]
+    UnsupportedVersion(u8),
+}
+
+/// Version byte leading every `export_keys` blob.
+pub const EXPORT_VERSION: u8 = 1;
+
+/// AES-256-GCM cipher keyed by Argon2 over `password` and `salt`.
+fn password_cipher(password: &str, salt: &SaltString) -> Result<Aes256Gcm, WalletFileError> {
+    let password_hash = Argon2::default()
+        .hash_password(password.as_bytes(), salt)
+        .map_err(|_| WalletFileError::Encryption)?;
+    let key_material = password_hash.hash.ok_or(WalletFileError::Encryption)?;
+    let key_bytes = key_material.as_bytes();
+    if key_bytes.len() < 32 {
+        return Err(WalletFileError::Encryption);
+    }
+    Aes256Gcm::new_from_slice(&key_bytes[..32]).map_err(|_| WalletFileError::Encryption)
+}
+
+/// Keypair recovered by `import_keys`, with the SHA-256 of the mnemonic it
+/// was derived from so the importer can match it against its profile.
+pub struct ExportedKeys {
+    pub mnemonic_hash: [u8; 32],
+    pub public_key: PublicKey,
+    pub secret_key: SecretKey,
+}
+
+/// Encrypt a keypair for moving a wallet between machines. Returns base64 of
+/// `[version][mnemonic_hash 32][salt len u8][salt][nonce 12][ciphertext]`;
+/// the version and mnemonic hash are authenticated along with the keys.
+pub fn export_keys(
+    mnemonic_hash: &[u8; 32],
+    pk: &PublicKey,
+    sk: &SecretKey,
+    password: &str,
+) -> Result<String, WalletFileError> {
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let cipher = password_cipher(password, &salt)?;
+    let nonce_bytes: [u8; 12] = rand::random();
+
+    let mut header = vec![EXPORT_VERSION];
+    header.extend_from_slice(mnemonic_hash);
+    let mut keys = pk.0.to_vec();
+    keys.extend_from_slice(&sk.0);
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce_bytes), Payload { msg: &keys, aad: &header })
+        .map_err(|_| WalletFileError::Encryption)?;
+
+    let mut blob = header;
+    blob.push(salt.as_str().len() as u8);
+    blob.extend_from_slice(salt.as_str().as_bytes());
+    blob.extend_from_slice(&nonce_bytes);
+    blob.extend_from_slice(&ciphertext);
+    Ok(data_encoding::BASE64.encode(&blob))
+}
+
+/// Decrypt a blob made by `export_keys`.
+pub fn import_keys(blob: &str, password: &str) -> Result<ExportedKeys, WalletFileError> {
+    use crate::crypto::dilithium::{DILITHIUM3_PRIVKEY_BYTES, DILITHIUM3_PUBKEY_BYTES};
+
+    let raw = data_encoding::BASE64.decode(blob.trim().as_bytes()).map_err(|_| WalletFileError::Corrupted)?;
+    let version = *raw.first().ok_or(WalletFileError::Corrupted)?;
+    if version != EXPORT_VERSION {
+        return Err(WalletFileError::UnsupportedVersion(version));
+    }
+    if raw.len() < 34 {
+        return Err(WalletFileError::Corrupted);
+    }
+    let (header, rest) = raw.split_at(33);
+    let salt_len = rest[0] as usize;
+    if rest.len() < 1 + salt_len + 12 {
+        return Err(WalletFileError::Corrupted);
+    }
+    let salt = std::str::from_utf8(&rest[1..1 + salt_len])
+        .ok()
+        .and_then(|s| SaltString::from_b64(s).ok())
+        .ok_or(WalletFileError::Corrupted)?;
+    let nonce = Nonce::from_slice(&rest[1 + salt_len..1 + salt_len + 12]);
+    let ciphertext = &rest[1 + salt_len + 12..];
+
+    let cipher = password_cipher(password, &salt).map_err(|_| WalletFileError::Decryption)?;
+    let keys = cipher
+        .decrypt(nonce, Payload { msg: ciphertext, aad: header })
+        .map_err(|_| WalletFileError::InvalidPassword)?;
+    if keys.len() != DILITHIUM3_PUBKEY_BYTES + DILITHIUM3_PRIVKEY_BYTES {
+        return Err(WalletFileError::Corrupted);
+    }
+
+    let mut mnemonic_hash = [0u8; 32];
+    mnemonic_hash.copy_from_slice(&header[1..]);
+    let mut pk = [0u8; DILITHIUM3_PUBKEY_BYTES];
+    pk.copy_from_slice(&keys[..DILITHIUM3_PUBKEY_BYTES]);
+    let mut sk = [0u8; DILITHIUM3_PRIVKEY_BYTES];
+    sk.copy_from_slice(&keys[DILITHIUM3_PUBKEY_BYTES..]);
+    Ok(ExportedKeys { mnemonic_hash, public_key: PublicKey(pk), secret_key: SecretKey(sk) })
 }
 
 #[derive(Serialize, Deserialize)]
@@ -58,21 +154,7 @@ impl WalletFile {
         let salt = SaltString::generate(&mut rand::thread_rng());
 
         // Derive encryption key from password using Argon2
-        let argon2 = Argon2::default();
-        let password_hash = argon2
-            .hash_password(password.as_bytes(), &salt)
-            .map_err(|_| WalletFileError::Encryption)?;
-
-        // Extract key material (first 32 bytes of hash)
-        let key_material = password_hash.hash.ok_or(WalletFileError::Encryption)?;
-        let key_bytes = key_material.as_bytes();
-        if key_bytes.len() < 32 {
-            return Err(WalletFileError::Encryption);
-        }
-
-        // Create AES-256-GCM cipher
-        let cipher = Aes256Gcm::new_from_slice(&key_bytes[..32])
-            .map_err(|_| WalletFileError::Encryption)?;
+        let cipher = password_cipher(password, &salt)?;
 
         // Generate random nonce
         let nonce_bytes: [u8; 12] = rand::random();
@@ -112,21 +194,7 @@ impl WalletFile {
         let salt = SaltString::new(&self.salt).map_err(|_| WalletFileError::Corrupted)?;
 
         // Derive key from password
-        let argon2 = Argon2::default();
-        let password_hash = argon2
-            .hash_password(password.as_bytes(), &salt)
-            .map_err(|_| WalletFileError::InvalidPassword)?;
-
-        // Extract key material
-        let key_material = password_hash.hash.ok_or(WalletFileError::Decryption)?;
-        let key_bytes = key_material.as_bytes();
-        if key_bytes.len() < 32 {
-            return Err(WalletFileError::Decryption);
-        }
-
-        // Create cipher
-        let cipher = Aes256Gcm::new_from_slice(&key_bytes[..32])
-            .map_err(|_| WalletFileError::Decryption)?;
+        let cipher = password_cipher(password, &salt).map_err(|_| WalletFileError::Decryption)?;
 
         // Decrypt
         let nonce = Nonce::from_slice(&self.nonce);
@@ -226,4 +294,28 @@ mod tests {
         
         assert_eq!(wallet.mnemonic_hint, Some("word1 word2 word3...".to_string()));
     }
+
+    #[test]
+    fn test_export_import_keys() {
+        let (pk, sk) = keys::derive_keypair_from_mnemonic("word1 word2 word3");
+        let hash = [7u8; 32];
+        let blob = export_keys(&hash, &pk, &sk, "pass").unwrap();
+
+        let got = import_keys(&blob, "pass").unwrap();
+        assert_eq!(got.mnemonic_hash, hash);
+        assert_eq!(got.public_key.0, pk.0);
+        assert_eq!(got.secret_key.0, sk.0);
+        assert!(matches!(import_keys(&blob, "wrong"), Err(WalletFileError::InvalidPassword)));
+
+        // The mnemonic hash is authenticated, and the version is checked first.
+        let mut raw = data_encoding::BASE64.decode(blob.as_bytes()).unwrap();
+        raw[1] ^= 1;
+        assert!(import_keys(&data_encoding::BASE64.encode(&raw), "pass").is_err());
+        raw[0] = 9;
+        assert!(matches!(
+            import_keys(&data_encoding::BASE64.encode(&raw), "pass"),
+            Err(WalletFileError::UnsupportedVersion(9))
+        ));
+        assert!(matches!(import_keys("not base64!", "pass"), Err(WalletFileError::Corrupted)));
+    }
 }
@@ -12,7 +12,8 @@ fn tmp_db() -> ChainDB {
     let id = CTR.fetch_add(1, Ordering::SeqCst);
     let p = PathBuf::from(format!("/tmp/knot_integration_{}_{}", std::process::id(), id));
     let _ = std::fs::remove_dir_all(&p);
-    ChainDB::open(&p).unwrap()
+    // Testnet: mainnet retargeting, but no floor under the trivial test targets.
+    ChainDB::open(&p).unwrap().with_network(knotcoin::config::Network::Testnet)
 }
 
 fn create_signed_tx(seed_val: u8, nonce: u64, amount: u64, fee: u64) -> (StoredTransaction, [u8; 32], [u8; 32]) {
@@ -35,6 +36,8 @@ fn create_signed_tx(seed_val: u8, nonce: u64, amount: u64, fee: u64) -> (StoredT
         timestamp: 1000,
         referrer_address: None,
         governance_data: None,
+        proposal: None,
+        memo: None,
         signature: dilithium::Signature([0u8; 3309]),
     };
 
@@ -52,6 +55,8 @@ fn create_signed_tx(seed_val: u8, nonce: u64, amount: u64, fee: u64) -> (StoredT
         timestamp: tx.timestamp,
         referrer_address: tx.referrer_address,
         governance_data: tx.governance_data,
+        proposal: tx.proposal.clone(),
+        memo: tx.memo.clone(),
         signature: tx.signature.0.to_vec(),
     };
 
@@ -81,7 +86,8 @@ fn test_genesis_block_application() {
     
     // Verify database state after consensus processing
     let acc = db.get_account(&miner).unwrap();
-    assert_eq!(acc.balance, 10_000_000); // Genesis reward
+    assert_eq!(acc.balance, 0);
+    assert_eq!(acc.immature_balance(), 10_000_000); // Genesis reward, not yet mature
     assert_eq!(acc.last_mined_height, 0);
     assert_eq!(acc.total_blocks_mined, 1);
     
@@ -171,6 +177,7 @@ fn test_transaction_processing_updates_accounts() {
         total_referral_bonus_earned: 0,
         governance_weight: 0,
         total_blocks_mined: 0,
+        immature: Vec::new(),
     };
     db.put_account(&sender, &sender_state).unwrap();
     
@@ -228,6 +235,7 @@ fn test_referral_system_integration() {
         total_referral_bonus_earned: 0,
         governance_weight: 0,
         total_blocks_mined: 0,
+        immature: Vec::new(),
     };
     db.put_account(&referee, &referee_state).unwrap();
     
@@ -272,6 +280,7 @@ fn test_governance_voting_integration() {
         total_referral_bonus_earned: 0,
         governance_weight: 500,
         total_blocks_mined: 5,
+        immature: Vec::new(),
     };
     db.put_account(&voter1, &state1).unwrap();
     
@@ -284,6 +293,7 @@ fn test_governance_voting_integration() {
         total_referral_bonus_earned: 0,
         governance_weight: 300,
         total_blocks_mined: 3,
+        immature: Vec::new(),
     };
     db.put_account(&voter2, &state2).unwrap();
     
@@ -325,12 +335,14 @@ fn test_rapid_block_sequence() {
     
     let mut prev_hash = [0u8; 32];
     
+    // Slightly slower than the 60s target so the height-60 retarget keeps
+    // the easiest target and every block stays valid at [0xFF; 32].
     for i in 0..100 {
         let block = StoredBlock {
             version: [0, 0, 0, 1],
             previous_hash: prev_hash,
             merkle_root: [0u8; 32],
-            timestamp: (i as u32 * 60).to_le_bytes(),
+            timestamp: (i as u32 * 65).to_le_bytes(),
             difficulty_target: [0xFF; 32],
             nonce: [i as u8; 8],
             block_height: (i as u32).to_le_bytes(),
@@ -368,6 +380,7 @@ fn test_many_accounts_creation() {
             total_referral_bonus_earned: 0,
             governance_weight: i as u64,
             total_blocks_mined: 0,
+            immature: Vec::new(),
         };
         
         db.put_account(&addr, &state).unwrap();
@@ -402,6 +415,7 @@ fn test_block_with_max_transactions() {
             total_referral_bonus_earned: 0,
             governance_weight: 0,
             total_blocks_mined: 0,
+            immature: Vec::new(),
         };
         db.put_account(&addr, &state).unwrap();
     }
@@ -478,6 +492,7 @@ fn test_account_state_persistence() {
         total_referral_bonus_earned: 250_000,
         governance_weight: 400,
         total_blocks_mined: 8,
+        immature: Vec::new(),
     };
     
     // Write and close
@@ -123,7 +123,8 @@ async fn smoke_rpc_mainnet_features() {
 
     let ref_addr = synthetic_referrer_address();
 
-    // Mine 2 blocks to miner so it has funds for fees (genesis already exists)
+    // Mine 2 blocks to miner so it has funds for fees (genesis already exists),
+    // then enough on top for those rewards to pass coinbase maturity.
     let _ = rpc_call(
         rpc_port,
         &token,
@@ -131,6 +132,16 @@ async fn smoke_rpc_mainnet_features() {
         json!([2, miner_addr]),
     )
     .await;
+    let _ = rpc_call(
+        rpc_port,
+        &token,
+        "generatetoaddress",
+        json!([
+            knotcoin::consensus::chain::COINBASE_MATURITY,
+            knotcoin::crypto::keys::encode_address_string(&[7u8; 32]),
+        ]),
+    )
+    .await;
 
     // Referral registration MUST be first outgoing tx (nonce==1).
     // This should now pass structural validation and consensus rules.